@@ -1,12 +1,30 @@
-use chrono::{DateTime, Utc, Duration};
+use ch352_test::{
+    metrics, BacktestConfig, BenchmarkReport, ExitReason, MetricsConfig, MonteCarlo, MonteCarloError, ParamSet,
+    Side,
+};
+use chrono::{DateTime, Datelike, Duration, Utc};
+use plotters::coord::Shift;
 use plotters::prelude::*;
+use std::fmt;
+use std::path::Path;
+
+type ChartResult = Result<(), Box<dyn std::error::Error>>;
+
+const CHART_SIZE: (u32, u32) = (1024, 768);
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
 
 #[derive(Debug, Clone)]
 struct Trade {
+    entry_timestamp: DateTime<Utc>,
+    entry_price: f64,
+    /// Exit time
     timestamp: DateTime<Utc>,
     symbol: String,
     side: TradeSide,
     quantity: f64,
+    /// Exit price
     price: f64,
     profit: f64,
 }
@@ -24,12 +42,62 @@ struct EquityPoint {
     drawdown: f64,
 }
 
+#[derive(Debug, Clone)]
+struct Candle {
+    timestamp: DateTime<Utc>,
+    open: f64,
+    high: f64,
+    low: f64,
+    close: f64,
+}
+
+/// Indicator line drawn over the candles
+#[derive(Debug, Clone)]
+struct Overlay {
+    name: String,
+    values: Vec<(DateTime<Utc>, f64)>,
+}
+
+/// Series the strategy is measured against, e.g. buy-and-hold of the
+/// traded market or an index
+#[derive(Debug, Clone)]
+struct Benchmark {
+    name: String,
+    /// Value at the start of the backtest
+    initial: f64,
+    values: Vec<(DateTime<Utc>, f64)>,
+}
+
+impl Benchmark {
+    /// Buying at the first open and marking at every close
+    fn buy_and_hold(name: &str, candles: &[Candle]) -> Self {
+        Benchmark {
+            name: name.to_string(),
+            initial: candles.first().map_or(0.0, |c| c.open),
+            values: candles.iter().map(|c| (c.timestamp, c.close)).collect(),
+        }
+    }
+
+    /// Last value at or before `timestamp`
+    fn value_at(&self, timestamp: DateTime<Utc>) -> f64 {
+        match self.values.partition_point(|v| v.0 <= timestamp) {
+            0 => self.initial,
+            n => self.values[n - 1].1,
+        }
+    }
+}
+
 #[derive(Debug)]
 struct BacktestResults {
     trades: Vec<Trade>,
     equity_curve: Vec<EquityPoint>,
     initial_balance: f64,
     final_balance: f64,
+    candles: Vec<Candle>,
+    overlays: Vec<Overlay>,
+    benchmark: Option<Benchmark>,
+    /// Annual rate the benchmark comparison measures excess returns against
+    risk_free_rate: f64,
 }
 
 impl BacktestResults {
@@ -39,6 +107,10 @@ impl BacktestResults {
             equity_curve: Vec::new(),
             initial_balance,
             final_balance: initial_balance,
+            candles: Vec::new(),
+            overlays: Vec::new(),
+            benchmark: None,
+            risk_free_rate: 0.0,
         }
     }
 
@@ -96,19 +168,302 @@ impl BacktestResults {
             .map(|ep| ep.drawdown)
             .fold(0.0, f64::max)
     }
+
+    /// Annualized Sharpe ratio and volatility (%) over a trailing window of
+    /// `window` equity points, under `metrics_config`
+    fn rolling_sharpe_volatility(&self, window: usize) -> Vec<(DateTime<Utc>, f64, f64)> {
+        rolling_sharpe_volatility(self.initial_balance, &self.equity_curve, window, &self.metrics_config())
+    }
+
+    /// The same for the benchmark, over the same equity points
+    fn benchmark_rolling_sharpe_volatility(&self, window: usize) -> Vec<(DateTime<Utc>, f64, f64)> {
+        rolling_sharpe_volatility(self.initial_balance, &self.benchmark_equity(), window, &self.metrics_config())
+    }
+
+    /// Return of every calendar month in percent, from the last balance of
+    /// the month before
+    fn monthly_returns(&self) -> Vec<(i32, u32, f64)> {
+        let points: Vec<(DateTime<Utc>, f64)> = self.equity_curve.iter().map(|ep| (ep.timestamp, ep.balance)).collect();
+        monthly_returns(self.initial_balance, &points)
+    }
+
+    /// The benchmark scaled to the initial balance, at every equity point
+    fn benchmark_equity(&self) -> Vec<EquityPoint> {
+        let Some(benchmark) = &self.benchmark else {
+            return Vec::new();
+        };
+        let mut peak = self.initial_balance;
+        self.equity_curve
+            .iter()
+            .map(|ep| {
+                let balance = self.initial_balance * benchmark.value_at(ep.timestamp) / benchmark.initial;
+                peak = peak.max(balance);
+                EquityPoint {
+                    timestamp: ep.timestamp,
+                    balance,
+                    drawdown: (peak - balance) / peak * 100.0,
+                }
+            })
+            .collect()
+    }
+
+    /// Conventions for the shared metrics: one return period per equity
+    /// point, as many periods a year as the trades averaged from the first
+    /// entry to the last exit (daily if they span no time), and the
+    /// configured risk-free rate
+    fn metrics_config(&self) -> MetricsConfig {
+        let start = self.trades.iter().map(|t| t.entry_timestamp).min();
+        let periods_per_year = match (start, self.equity_curve.last()) {
+            (Some(start), Some(last)) if last.timestamp > start => {
+                let years = (last.timestamp - start).num_seconds() as f64 / (365.25 * 86_400.0);
+                self.equity_curve.len() as f64 / years
+            }
+            _ => 252.0,
+        };
+        MetricsConfig::per_bar(periods_per_year).with_risk_free_rate(self.risk_free_rate)
+    }
+
+    /// The same results in the form the shared ch352 crate works with
+    fn to_shared(&self) -> ch352_test::BacktestResults {
+        let bar_of = |time: DateTime<Utc>| self.candles.partition_point(|c| c.timestamp < time);
+        ch352_test::BacktestResults {
+            strategy: "Chapter 289 strategy".to_string(),
+            symbol: self.trades.first().map_or_else(String::new, |t| t.symbol.clone()),
+            parameters: ParamSet::new(),
+            config: BacktestConfig::new(self.initial_balance),
+            trades: self
+                .trades
+                .iter()
+                .map(|t| ch352_test::Trade {
+                    side: match t.side {
+                        TradeSide::Buy => Side::Buy,
+                        TradeSide::Sell => Side::Sell,
+                    },
+                    entry_bar: bar_of(t.entry_timestamp),
+                    exit_bar: bar_of(t.timestamp),
+                    entry_time: t.entry_timestamp.timestamp(),
+                    exit_time: t.timestamp.timestamp(),
+                    entry_price: t.entry_price,
+                    exit_price: t.price,
+                    quantity: t.quantity,
+                    notional: t.quantity * t.entry_price,
+                    profit: t.profit,
+                    commission: 0.0,
+                    financing: 0.0,
+                    funding: 0.0,
+                    exit_reason: ExitReason::Signal,
+                })
+                .collect(),
+            fills: Vec::new(),
+            equity_curve: self
+                .equity_curve
+                .iter()
+                .map(|ep| ch352_test::EquityPoint {
+                    timestamp: ep.timestamp.timestamp(),
+                    balance: ep.balance,
+                    drawdown: ep.drawdown,
+                })
+                .collect(),
+            initial_balance: self.initial_balance,
+            final_balance: self.final_balance,
+            rejected_orders: 0,
+        }
+    }
+
+    /// Strategy and benchmark metrics, alpha, beta, tracking error and
+    /// capture under `metrics_config`, or `None` without a benchmark or with
+    /// fewer than three equity points
+    fn benchmark_report(&self) -> Option<BenchmarkReport> {
+        let benchmark = self.benchmark.as_ref()?;
+        let curve = self
+            .benchmark_equity()
+            .iter()
+            .map(|ep| (ep.timestamp.timestamp(), ep.balance))
+            .collect();
+        ch352_test::Benchmark::new(&benchmark.name, self.initial_balance, curve)
+            .compare(&self.to_shared(), &self.metrics_config())
+            .ok()
+    }
+
+    /// Percentiles 5, 25, 50, 75 and 95 of the balance after each trade over
+    /// `paths` bootstrap resamples of the trade returns, from the seeded
+    /// Monte Carlo of the shared crate
+    fn monte_carlo_bands(&self, paths: usize, seed: u64) -> Result<Vec<[f64; 5]>, MonteCarloError> {
+        let profits: Vec<f64> = self.trades.iter().map(|t| t.profit).collect();
+        let report = MonteCarlo::from_trade_pnls(&profits, self.initial_balance, seed)
+            .with_paths(paths)
+            .run()?;
+        // Bands are relative to a start of 1
+        Ok(report
+            .bands
+            .iter()
+            .map(|b| [b.p5, b.p25, b.median, b.p75, b.p95].map(|v| v * self.initial_balance))
+            .collect())
+    }
 }
 
-fn plot_equity_curve(results: &BacktestResults, filename: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let root = BitMapBackend::new(filename, (1024, 768)).into_drawing_area();
+/// Annualized Sharpe ratio and volatility (%) over every window of `window`
+/// consecutive equity points, at the timestamp of the window's last point
+fn rolling_sharpe_volatility(
+    initial: f64,
+    points: &[EquityPoint],
+    window: usize,
+    config: &MetricsConfig,
+) -> Vec<(DateTime<Utc>, f64, f64)> {
+    let curve: Vec<(i64, f64)> = points.iter().map(|ep| (ep.timestamp.timestamp(), ep.balance)).collect();
+    let sharpe = metrics::rolling(initial, &curve, window, config, |m| m.sharpe);
+    let volatility = metrics::rolling(initial, &curve, window, config, |m| m.volatility * 100.0);
+    points
+        .iter()
+        .skip(window.saturating_sub(1))
+        .zip(sharpe.into_iter().zip(volatility))
+        .map(|(ep, ((_, sharpe), (_, volatility)))| (ep.timestamp, sharpe, volatility))
+        .collect()
+}
+
+/// Return of every calendar month of `points` in percent, from the last
+/// value of the month before
+fn monthly_returns(initial: f64, points: &[(DateTime<Utc>, f64)]) -> Vec<(i32, u32, f64)> {
+    let mut months: Vec<(i32, u32, f64)> = Vec::new();
+    let (mut start, mut last) = (initial, initial);
+    for &(timestamp, value) in points {
+        let month = (timestamp.year(), timestamp.month());
+        match months.last_mut() {
+            Some(current) if (current.0, current.1) == month => {
+                current.2 = (value / start - 1.0) * 100.0
+            }
+            _ => {
+                start = last;
+                months.push((month.0, month.1, (value / start - 1.0) * 100.0));
+            }
+        }
+        last = value;
+    }
+    months
+}
+
+/// Every chart the suite draws
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ChartKind {
+    Equity,
+    Drawdown,
+    Underwater,
+    ProfitDistribution,
+    Candlestick,
+    RollingSharpe,
+    MonthlyHeatmap,
+    MonteCarloFan,
+}
+
+impl ChartKind {
+    const ALL: [ChartKind; 8] = [
+        ChartKind::Equity,
+        ChartKind::Drawdown,
+        ChartKind::Underwater,
+        ChartKind::ProfitDistribution,
+        ChartKind::Candlestick,
+        ChartKind::RollingSharpe,
+        ChartKind::MonthlyHeatmap,
+        ChartKind::MonteCarloFan,
+    ];
+
+    fn file_stem(&self) -> &'static str {
+        match self {
+            ChartKind::Equity => "equity_curve",
+            ChartKind::Drawdown => "drawdown",
+            ChartKind::Underwater => "underwater",
+            ChartKind::ProfitDistribution => "profit_distribution",
+            ChartKind::Candlestick => "candlestick",
+            ChartKind::RollingSharpe => "rolling_sharpe",
+            ChartKind::MonthlyHeatmap => "monthly_returns",
+            ChartKind::MonteCarloFan => "monte_carlo_fan",
+        }
+    }
+}
+
+impl fmt::Display for ChartKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ChartKind::Equity => "Equity curve",
+            ChartKind::Drawdown => "Drawdown",
+            ChartKind::Underwater => "Underwater plot",
+            ChartKind::ProfitDistribution => "Profit distribution",
+            ChartKind::Candlestick => "Candlestick chart",
+            ChartKind::RollingSharpe => "Rolling Sharpe and volatility",
+            ChartKind::MonthlyHeatmap => "Monthly returns",
+            ChartKind::MonteCarloFan => "Monte Carlo fan",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+const ROLLING_WINDOW: usize = 5;
+const MONTE_CARLO_PATHS: usize = 1000;
+
+/// Draws `kind` on any plotters backend, so the same code makes PNG and SVG
+fn draw_chart<DB: DrawingBackend>(
+    results: &BacktestResults,
+    kind: ChartKind,
+    root: &DrawingArea<DB, Shift>,
+) -> ChartResult
+where
+    DB::ErrorType: 'static,
+{
     root.fill(&WHITE)?;
+    match kind {
+        ChartKind::Equity => draw_equity_curve(results, root)?,
+        ChartKind::Drawdown => draw_drawdown(results, root)?,
+        ChartKind::Underwater => draw_underwater(results, root)?,
+        ChartKind::ProfitDistribution => draw_profit_distribution(results, root)?,
+        ChartKind::Candlestick => draw_candlesticks(results, root)?,
+        ChartKind::RollingSharpe => draw_rolling(results, root)?,
+        ChartKind::MonthlyHeatmap => draw_monthly_heatmap(results, root)?,
+        ChartKind::MonteCarloFan => draw_monte_carlo_fan(results, root)?,
+    }
+    root.present()?;
+    Ok(())
+}
 
-    let min_balance = results.equity_curve.iter().map(|ep| ep.balance).fold(f64::INFINITY, f64::min);
-    let max_balance = results.equity_curve.iter().map(|ep| ep.balance).fold(f64::NEG_INFINITY, f64::max);
+/// Writes `kind` as SVG or PNG, picked by the extension of `filename`
+fn save_chart(results: &BacktestResults, kind: ChartKind, filename: &str) -> ChartResult {
+    match Path::new(filename).extension().and_then(|e| e.to_str()) {
+        Some("svg") => draw_chart(results, kind, &SVGBackend::new(filename, CHART_SIZE).into_drawing_area())?,
+        _ => draw_chart(results, kind, &BitMapBackend::new(filename, CHART_SIZE).into_drawing_area())?,
+    }
+    println!("{} saved to {}", kind, filename);
+    Ok(())
+}
+
+/// Every chart as both PNG and SVG in `dir`; a chart that cannot be
+/// rendered, as happens on servers without fonts, goes to the terminal
+fn save_all_charts(results: &BacktestResults, dir: &str) -> ChartResult {
+    std::fs::create_dir_all(dir)?;
+    for kind in ChartKind::ALL {
+        for extension in ["png", "svg"] {
+            let filename = format!("{}/{}.{}", dir, kind.file_stem(), extension);
+            if let Err(e) = save_chart(results, kind, &filename) {
+                println!("Could not render {} ({}), showing it in the terminal", filename, e);
+                print_terminal_chart(results, kind);
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn draw_equity_curve<DB: DrawingBackend>(results: &BacktestResults, root: &DrawingArea<DB, Shift>) -> ChartResult
+where
+    DB::ErrorType: 'static,
+{
+    let benchmark = results.benchmark_equity();
+    let balances = || results.equity_curve.iter().chain(&benchmark).map(|ep| ep.balance);
+    let min_balance = balances().fold(f64::INFINITY, f64::min);
+    let max_balance = balances().fold(f64::NEG_INFINITY, f64::max);
 
     let min_time = results.equity_curve.first().unwrap().timestamp;
     let max_time = results.equity_curve.last().unwrap().timestamp;
 
-    let mut chart = ChartBuilder::on(&root)
+    let mut chart = ChartBuilder::on(root)
         .caption("Equity Curve", ("sans-serif", 50).into_font())
         .margin(10)
         .x_label_area_size(40)
@@ -140,33 +495,37 @@ fn plot_equity_curve(results: &BacktestResults, filename: &str) -> Result<(), Bo
     .label("Initial Balance")
     .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &RED));
 
+    if let Some(b) = &results.benchmark {
+        chart.draw_series(LineSeries::new(benchmark.iter().map(|ep| (ep.timestamp, ep.balance)), &GREEN))?
+            .label(b.name.as_str())
+            .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &GREEN));
+    }
+
     chart.configure_series_labels()
         .background_style(&WHITE.mix(0.8))
         .border_style(&BLACK)
         .draw()?;
 
-    root.present()?;
-    println!("Equity curve saved to {}", filename);
-
     Ok(())
 }
 
-fn plot_drawdown(results: &BacktestResults, filename: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let root = BitMapBackend::new(filename, (1024, 768)).into_drawing_area();
-    root.fill(&WHITE)?;
-
-    let max_dd = results.max_drawdown();
+fn draw_drawdown<DB: DrawingBackend>(results: &BacktestResults, root: &DrawingArea<DB, Shift>) -> ChartResult
+where
+    DB::ErrorType: 'static,
+{
+    let benchmark = results.benchmark_equity();
+    let max_dd = benchmark.iter().map(|ep| ep.drawdown).fold(results.max_drawdown(), f64::max);
     let min_time = results.equity_curve.first().unwrap().timestamp;
     let max_time = results.equity_curve.last().unwrap().timestamp;
 
-    let mut chart = ChartBuilder::on(&root)
+    let mut chart = ChartBuilder::on(root)
         .caption("Drawdown", ("sans-serif", 50).into_font())
         .margin(10)
         .x_label_area_size(40)
         .y_label_area_size(60)
         .build_cartesian_2d(
             min_time..max_time,
-            0.0..(max_dd * 1.1)
+            0.0..(max_dd * 1.1).max(1.0)
         )?;
 
     chart.configure_mesh()
@@ -184,24 +543,111 @@ fn plot_drawdown(results: &BacktestResults, filename: &str) -> Result<(), Box<dy
         results.equity_curve.iter().map(|ep| (ep.timestamp, ep.drawdown)),
         &RED,
     ))?
-    .label(format!("Max DD: {:.2}%", max_dd))
+    .label(format!("Max DD: {:.2}%", results.max_drawdown()))
     .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &RED));
 
+    if let Some(b) = &results.benchmark {
+        chart.draw_series(LineSeries::new(benchmark.iter().map(|ep| (ep.timestamp, ep.drawdown)), &GREEN))?
+            .label(b.name.as_str())
+            .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &GREEN));
+    }
+
     chart.configure_series_labels()
         .background_style(&WHITE.mix(0.8))
         .border_style(&BLACK)
         .draw()?;
 
-    root.present()?;
-    println!("Drawdown chart saved to {}", filename);
-
     Ok(())
 }
 
-fn plot_profit_distribution(results: &BacktestResults, filename: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let root = BitMapBackend::new(filename, (1024, 768)).into_drawing_area();
-    root.fill(&WHITE)?;
+/// Periods spent below the previous peak, as (start, end) indices into the
+/// equity curve
+fn underwater_periods(results: &BacktestResults) -> Vec<(usize, usize)> {
+    let mut periods = Vec::new();
+    let mut start = None;
+    for (i, ep) in results.equity_curve.iter().enumerate() {
+        match (start, ep.drawdown > 0.0) {
+            (None, true) => start = Some(i),
+            (Some(s), false) => {
+                periods.push((s, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        periods.push((s, results.equity_curve.len() - 1));
+    }
+    periods
+}
+
+/// Drawdown below zero with every stretch under water shaded, so the time
+/// to recover is as visible as the depth
+fn draw_underwater<DB: DrawingBackend>(results: &BacktestResults, root: &DrawingArea<DB, Shift>) -> ChartResult
+where
+    DB::ErrorType: 'static,
+{
+    let benchmark = results.benchmark_equity();
+    let max_dd = benchmark.iter().map(|ep| ep.drawdown).fold(results.max_drawdown(), f64::max);
+    let curve = &results.equity_curve;
+    let min_time = curve.first().unwrap().timestamp;
+    let max_time = curve.last().unwrap().timestamp;
+    let periods = underwater_periods(results);
+    let longest = periods
+        .iter()
+        .map(|&(s, e)| curve[e].timestamp - curve[s].timestamp)
+        .max()
+        .unwrap_or_else(Duration::zero);
+
+    let mut chart = ChartBuilder::on(root)
+        .caption("Underwater Plot", ("sans-serif", 50).into_font())
+        .margin(10)
+        .x_label_area_size(40)
+        .y_label_area_size(60)
+        .build_cartesian_2d(min_time..max_time, -(max_dd * 1.1).max(1.0)..0.0)?;
+
+    chart.configure_mesh()
+        .x_desc("Time")
+        .y_desc("Below peak (%)")
+        .draw()?;
+
+    chart.draw_series(periods.iter().map(|&(s, e)| {
+        Rectangle::new(
+            [(curve[s].timestamp, -(max_dd * 1.1).max(1.0)), (curve[e].timestamp, 0.0)],
+            BLUE.mix(0.08).filled(),
+        )
+    }))?;
+
+    chart.draw_series(AreaSeries::new(
+        curve.iter().map(|ep| (ep.timestamp, -ep.drawdown)),
+        0.0,
+        &BLUE.mix(0.4),
+    ))?
+    .label(format!(
+        "Max DD: {:.2}%, longest under water: {} days",
+        results.max_drawdown(),
+        longest.num_days()
+    ))
+    .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &BLUE));
+
+    if let Some(b) = &results.benchmark {
+        chart.draw_series(LineSeries::new(benchmark.iter().map(|ep| (ep.timestamp, -ep.drawdown)), &GREEN))?
+            .label(b.name.as_str())
+            .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &GREEN));
+    }
+
+    chart.configure_series_labels()
+        .background_style(&WHITE.mix(0.8))
+        .border_style(&BLACK)
+        .draw()?;
+
+    Ok(())
+}
 
+fn draw_profit_distribution<DB: DrawingBackend>(results: &BacktestResults, root: &DrawingArea<DB, Shift>) -> ChartResult
+where
+    DB::ErrorType: 'static,
+{
     let profits: Vec<f64> = results.trades.iter().map(|t| t.profit).collect();
 
     if profits.is_empty() {
@@ -213,17 +659,11 @@ fn plot_profit_distribution(results: &BacktestResults, filename: &str) -> Result
 
     let bin_count = 20;
     let bin_width = (max_profit - min_profit) / bin_count as f64;
-    let mut bins = vec![0u32; bin_count];
-
-    for &profit in &profits {
-        let bin_index = ((profit - min_profit) / bin_width).floor() as usize;
-        let bin_index = bin_index.min(bin_count - 1);
-        bins[bin_index] += 1;
-    }
+    let bins = histogram(&profits, bin_count);
 
     let max_count = *bins.iter().max().unwrap();
 
-    let mut chart = ChartBuilder::on(&root)
+    let mut chart = ChartBuilder::on(root)
         .caption("Profit/Loss Distribution", ("sans-serif", 50).into_font())
         .margin(10)
         .x_label_area_size(40)
@@ -253,46 +693,444 @@ fn plot_profit_distribution(results: &BacktestResults, filename: &str) -> Result
         &BLACK,
     ))?;
 
-    root.present()?;
-    println!("Profit distribution saved to {}", filename);
+    Ok(())
+}
+
+fn histogram(values: &[f64], bin_count: usize) -> Vec<u32> {
+    let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let bin_width = (max - min) / bin_count as f64;
+    let mut bins = vec![0u32; bin_count];
+
+    for &value in values {
+        let bin_index = if bin_width > 0.0 { ((value - min) / bin_width).floor() as usize } else { 0 };
+        let bin_index = bin_index.min(bin_count - 1);
+        bins[bin_index] += 1;
+    }
+    bins
+}
+
+/// Price candles with indicator overlays; entries are triangles pointing in
+/// the trade's direction, exits are crosses joined to their entry
+fn draw_candlesticks<DB: DrawingBackend>(results: &BacktestResults, root: &DrawingArea<DB, Shift>) -> ChartResult
+where
+    DB::ErrorType: 'static,
+{
+    let candles = &results.candles;
+    if candles.is_empty() {
+        return Ok(());
+    }
+    let low = candles.iter().map(|c| c.low).fold(f64::INFINITY, f64::min);
+    let high = candles.iter().map(|c| c.high).fold(f64::NEG_INFINITY, f64::max);
+    let pad = (high - low) * 0.05;
+    let min_time = candles.first().unwrap().timestamp - Duration::days(1);
+    let max_time = candles.last().unwrap().timestamp + Duration::days(1);
+
+    let mut chart = ChartBuilder::on(root)
+        .caption("Price and Trades", ("sans-serif", 50).into_font())
+        .margin(10)
+        .x_label_area_size(40)
+        .y_label_area_size(60)
+        .build_cartesian_2d(min_time..max_time, (low - pad)..(high + pad))?;
+
+    chart.configure_mesh()
+        .x_desc("Time")
+        .y_desc("Price ($)")
+        .draw()?;
+
+    let width = (900 / candles.len().max(1)).clamp(1, 15) as u32;
+    chart.draw_series(candles.iter().map(|c| {
+        CandleStick::new(c.timestamp, c.open, c.high, c.low, c.close, GREEN.filled(), RED.filled(), width)
+    }))?;
+
+    let palette = [&BLUE, &MAGENTA, &CYAN, &BLACK];
+    for (overlay, color) in results.overlays.iter().zip(palette.iter().cycle()) {
+        let color = *color;
+        chart.draw_series(LineSeries::new(overlay.values.iter().copied(), color))?
+            .label(overlay.name.clone())
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+    }
+
+    for trade in &results.trades {
+        let won = trade.profit > 0.0;
+        chart.draw_series(LineSeries::new(
+            vec![(trade.entry_timestamp, trade.entry_price), (trade.timestamp, trade.price)],
+            if won { GREEN.mix(0.6) } else { RED.mix(0.6) },
+        ))?;
+    }
+    chart.draw_series(results.trades.iter().map(|t| {
+        let tip = match t.side {
+            TradeSide::Buy => vec![(-6, 6), (6, 6), (0, -6)],
+            TradeSide::Sell => vec![(-6, -6), (6, -6), (0, 6)],
+        };
+        let color = match t.side {
+            TradeSide::Buy => GREEN,
+            TradeSide::Sell => RED,
+        };
+        EmptyElement::at((t.entry_timestamp, t.entry_price)) + Polygon::new(tip, color.filled())
+    }))?;
+    chart.draw_series(results.trades.iter().map(|t| Cross::new((t.timestamp, t.price), 5, BLACK.stroke_width(2))))?;
+
+    chart.configure_series_labels()
+        .background_style(&WHITE.mix(0.8))
+        .border_style(&BLACK)
+        .draw()?;
+
+    Ok(())
+}
+
+/// Rolling annualized Sharpe ratio above rolling volatility
+fn draw_rolling<DB: DrawingBackend>(results: &BacktestResults, root: &DrawingArea<DB, Shift>) -> ChartResult
+where
+    DB::ErrorType: 'static,
+{
+    let rolling = results.rolling_sharpe_volatility(ROLLING_WINDOW);
+    if rolling.len() < 2 {
+        return Ok(());
+    }
+    let benchmark = results.benchmark_rolling_sharpe_volatility(ROLLING_WINDOW);
+    let both = || rolling.iter().chain(&benchmark);
+    let min_time = rolling.first().unwrap().0;
+    let max_time = rolling.last().unwrap().0;
+    let (upper, lower) = root.split_vertically(CHART_SIZE.1 as i32 / 2);
+
+    let sharpe_min = both().map(|r| r.1).fold(0.0, f64::min);
+    let sharpe_max = both().map(|r| r.1).fold(0.0, f64::max);
+    let mut chart = ChartBuilder::on(&upper)
+        .caption(format!("Rolling Sharpe ({} periods)", ROLLING_WINDOW), ("sans-serif", 30).into_font())
+        .margin(10)
+        .x_label_area_size(30)
+        .y_label_area_size(60)
+        .build_cartesian_2d(min_time..max_time, (sharpe_min - 0.5)..(sharpe_max + 0.5))?;
+    chart.configure_mesh().y_desc("Sharpe").draw()?;
+    chart.draw_series(LineSeries::new(vec![(min_time, 0.0), (max_time, 0.0)], &BLACK.mix(0.4)))?;
+    chart.draw_series(LineSeries::new(rolling.iter().map(|r| (r.0, r.1)), &BLUE))?;
+    chart.draw_series(LineSeries::new(benchmark.iter().map(|r| (r.0, r.1)), &GREEN))?;
+
+    let vol_max = both().map(|r| r.2).fold(0.0, f64::max);
+    let mut chart = ChartBuilder::on(&lower)
+        .caption(format!("Rolling Volatility ({} periods)", ROLLING_WINDOW), ("sans-serif", 30).into_font())
+        .margin(10)
+        .x_label_area_size(40)
+        .y_label_area_size(60)
+        .build_cartesian_2d(min_time..max_time, 0.0..(vol_max * 1.1).max(1.0))?;
+    chart.configure_mesh().x_desc("Time").y_desc("Annualized (%)").draw()?;
+    chart.draw_series(AreaSeries::new(rolling.iter().map(|r| (r.0, r.2)), 0.0, &RED.mix(0.3)))?;
+    chart.draw_series(LineSeries::new(benchmark.iter().map(|r| (r.0, r.2)), &GREEN))?;
+
+    Ok(())
+}
+
+/// Years down, months across, green for gains and red for losses, with the
+/// benchmark's return for the month in grey below the strategy's
+fn draw_monthly_heatmap<DB: DrawingBackend>(results: &BacktestResults, root: &DrawingArea<DB, Shift>) -> ChartResult
+where
+    DB::ErrorType: 'static,
+{
+    let months = results.monthly_returns();
+    if months.is_empty() {
+        return Ok(());
+    }
+    let first_year = months.first().unwrap().0;
+    let years = (months.last().unwrap().0 - first_year + 1) as f64;
+    let scale = months.iter().map(|m| m.2.abs()).fold(0.0, f64::max).max(1e-9);
+
+    let mut chart = ChartBuilder::on(root)
+        .caption("Monthly Returns (%)", ("sans-serif", 50).into_font())
+        .margin(10)
+        .x_label_area_size(40)
+        .y_label_area_size(60)
+        .build_cartesian_2d(0.0..12.0, 0.0..years)?;
+
+    chart.configure_mesh()
+        .disable_mesh()
+        .x_labels(12)
+        .x_label_formatter(&|x| MONTHS.get(*x as usize).copied().unwrap_or("").to_string())
+        .y_labels(years as usize)
+        .y_label_formatter(&|y| format!("{}", first_year + *y as i32))
+        .draw()?;
+
+    chart.draw_series(months.iter().map(|&(year, month, r)| {
+        let (x, y) = ((month - 1) as f64, (year - first_year) as f64);
+        let base = if r >= 0.0 { RGBColor(46, 125, 50) } else { RGBColor(198, 40, 40) };
+        Rectangle::new([(x, y), (x + 1.0, y + 1.0)], base.mix(0.15 + 0.75 * r.abs() / scale).filled())
+    }))?;
+    chart.draw_series(months.iter().map(|&(year, month, r)| {
+        let (x, y) = ((month - 1) as f64 + 0.3, (year - first_year) as f64 + 0.6);
+        Text::new(format!("{:.1}", r), (x, y), ("sans-serif", 18))
+    }))?;
+    let benchmark: Vec<(DateTime<Utc>, f64)> =
+        results.benchmark_equity().iter().map(|ep| (ep.timestamp, ep.balance)).collect();
+    chart.draw_series(monthly_returns(results.initial_balance, &benchmark).into_iter().map(|(year, month, r)| {
+        let (x, y) = ((month - 1) as f64 + 0.3, (year - first_year) as f64 + 0.3);
+        Text::new(format!("{:.1}", r), (x, y), ("sans-serif", 14).into_font().color(&BLACK.mix(0.5)))
+    }))?;
+
+    Ok(())
+}
+
+/// Median balance after each trade with the 25-75 and 5-95 percentile bands
+fn draw_monte_carlo_fan<DB: DrawingBackend>(results: &BacktestResults, root: &DrawingArea<DB, Shift>) -> ChartResult
+where
+    DB::ErrorType: 'static,
+{
+    let bands = match results.monte_carlo_bands(MONTE_CARLO_PATHS, 42) {
+        Ok(bands) => bands,
+        // Too few trades to resample: no fan
+        Err(MonteCarloError::NotEnoughData { .. }) => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    let low = bands.iter().map(|b| b[0]).fold(f64::INFINITY, f64::min);
+    let high = bands.iter().map(|b| b[4]).fold(f64::NEG_INFINITY, f64::max);
+    let steps = (bands.len() - 1) as f64;
+
+    let mut chart = ChartBuilder::on(root)
+        .caption(format!("Monte Carlo ({} resampled paths)", MONTE_CARLO_PATHS), ("sans-serif", 40).into_font())
+        .margin(10)
+        .x_label_area_size(40)
+        .y_label_area_size(60)
+        .build_cartesian_2d(0.0..steps.max(1.0), (low * 0.98)..(high * 1.02))?;
+
+    chart.configure_mesh()
+        .x_desc("Trade")
+        .y_desc("Balance ($)")
+        .draw()?;
+
+    for ((lower, upper), label) in [((0, 4), "5-95%"), ((1, 3), "25-75%")] {
+        let outline: Vec<(f64, f64)> = bands
+            .iter()
+            .enumerate()
+            .map(|(i, b)| (i as f64, b[upper]))
+            .chain(bands.iter().enumerate().rev().map(|(i, b)| (i as f64, b[lower])))
+            .collect();
+        chart.draw_series(std::iter::once(Polygon::new(outline, BLUE.mix(0.15).filled())))?
+            .label(label)
+            .legend(|(x, y)| Rectangle::new([(x, y - 5), (x + 20, y + 5)], BLUE.mix(0.3).filled()));
+    }
+    chart.draw_series(LineSeries::new(bands.iter().enumerate().map(|(i, b)| (i as f64, b[2])), &BLUE))?
+        .label("Median")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &BLUE));
+    chart.draw_series(LineSeries::new(
+        std::iter::once(results.initial_balance)
+            .chain(results.equity_curve.iter().map(|ep| ep.balance))
+            .enumerate()
+            .map(|(i, b)| (i as f64, b)),
+        &BLACK,
+    ))?
+    .label("Actual")
+    .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &BLACK));
+    if let Some(b) = &results.benchmark {
+        chart.draw_series(LineSeries::new(
+            std::iter::once(results.initial_balance)
+                .chain(results.benchmark_equity().iter().map(|ep| ep.balance))
+                .enumerate()
+                .map(|(i, b)| (i as f64, b)),
+            &GREEN,
+        ))?
+        .label(b.name.as_str())
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &GREEN));
+    }
+
+    chart.configure_series_labels()
+        .background_style(&WHITE.mix(0.8))
+        .border_style(&BLACK)
+        .draw()?;
 
     Ok(())
 }
 
+/// ASCII version of a chart for terminals without a display
+fn print_terminal_chart(results: &BacktestResults, kind: ChartKind) {
+    use textplots::{Chart, Plot, Shape};
+
+    let indexed = |values: Vec<f64>| -> Vec<(f32, f32)> {
+        values.iter().enumerate().map(|(i, &v)| (i as f32, v as f32)).collect()
+    };
+    let plot = |lines: &[Vec<(f32, f32)>]| {
+        let x_max = lines.iter().map(|l| l.len()).max().unwrap_or(0).max(2) as f32;
+        // No benchmark leaves its line empty
+        let shapes: Vec<Shape> = lines.iter().filter(|l| !l.is_empty()).map(|l| Shape::Lines(l)).collect();
+        let mut chart = Chart::new(180, 60, 0.0, x_max - 1.0);
+        let mut chart = &mut chart;
+        for shape in &shapes {
+            chart = chart.lineplot(shape);
+        }
+        chart.display();
+    };
+
+    println!("\n=== {} ===\n", kind);
+    match kind {
+        ChartKind::Equity => plot(&[
+            indexed(results.equity_curve.iter().map(|ep| ep.balance).collect()),
+            indexed(results.benchmark_equity().iter().map(|ep| ep.balance).collect()),
+        ]),
+        ChartKind::Drawdown | ChartKind::Underwater => plot(&[
+            indexed(results.equity_curve.iter().map(|ep| -ep.drawdown).collect()),
+            indexed(results.benchmark_equity().iter().map(|ep| -ep.drawdown).collect()),
+        ]),
+        ChartKind::ProfitDistribution => {
+            let profits: Vec<f64> = results.trades.iter().map(|t| t.profit).collect();
+            if profits.is_empty() {
+                return;
+            }
+            let min = profits.iter().cloned().fold(f64::INFINITY, f64::min);
+            let max = profits.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+            let bin_count = 10;
+            let width = (max - min) / bin_count as f64;
+            for (i, count) in histogram(&profits, bin_count).iter().enumerate() {
+                println!("{:>10.2} | {}", min + (i as f64 + 0.5) * width, "#".repeat(*count as usize));
+            }
+        }
+        ChartKind::Candlestick => {
+            let mut lines = vec![indexed(results.candles.iter().map(|c| c.close).collect())];
+            for overlay in &results.overlays {
+                // Overlays start later than the candles; align them on the right
+                let offset = results.candles.len().saturating_sub(overlay.values.len());
+                lines.push(
+                    overlay.values.iter().enumerate().map(|(i, v)| ((i + offset) as f32, v.1 as f32)).collect(),
+                );
+            }
+            plot(&lines);
+        }
+        ChartKind::RollingSharpe => {
+            let rolling = results.rolling_sharpe_volatility(ROLLING_WINDOW);
+            let benchmark = results.benchmark_rolling_sharpe_volatility(ROLLING_WINDOW);
+            println!("Sharpe:");
+            plot(&[
+                indexed(rolling.iter().map(|r| r.1).collect()),
+                indexed(benchmark.iter().map(|r| r.1).collect()),
+            ]);
+            println!("Volatility (%):");
+            plot(&[
+                indexed(rolling.iter().map(|r| r.2).collect()),
+                indexed(benchmark.iter().map(|r| r.2).collect()),
+            ]);
+        }
+        ChartKind::MonthlyHeatmap => {
+            print!("{:<6}", "");
+            for name in MONTHS {
+                print!("{:>7}", name);
+            }
+            println!();
+            let months = results.monthly_returns();
+            let benchmark: Vec<(DateTime<Utc>, f64)> =
+                results.benchmark_equity().iter().map(|ep| (ep.timestamp, ep.balance)).collect();
+            let benchmark_months = monthly_returns(results.initial_balance, &benchmark);
+            let print_row = |label: String, returns: &[(i32, u32, f64)], year: i32| {
+                print!("{:<6}", label);
+                for month in 1..=12 {
+                    match returns.iter().find(|m| m.0 == year && m.1 == month) {
+                        Some(m) => print!("{:>6.1}%", m.2),
+                        None => print!("{:>7}", ""),
+                    }
+                }
+                println!();
+            };
+            let mut years: Vec<i32> = months.iter().map(|m| m.0).collect();
+            years.dedup();
+            for year in years {
+                print_row(year.to_string(), &months, year);
+                if !benchmark_months.is_empty() {
+                    print_row("bench".to_string(), &benchmark_months, year);
+                }
+            }
+        }
+        ChartKind::MonteCarloFan => {
+            let Ok(bands) = results.monte_carlo_bands(MONTE_CARLO_PATHS, 42) else {
+                println!("Not enough trades to resample");
+                return;
+            };
+            let lines: Vec<Vec<(f32, f32)>> =
+                (0..5).map(|k| indexed(bands.iter().map(|b| b[k]).collect())).collect();
+            plot(&lines);
+        }
+    }
+}
+
+/// Daily candles of a noisy uptrend, reproducible from `seed`
+fn synthetic_candles(start: DateTime<Utc>, days: usize, seed: u64) -> Vec<Candle> {
+    let mut state = seed;
+    let mut uniform = || {
+        state = state
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (state >> 11) as f64 / (1u64 << 53) as f64
+    };
+    let mut close = 42000.0;
+    (0..days)
+        .map(|day| {
+            let open = close;
+            close = open * (1.0 + 0.002 + 0.03 * (uniform() - 0.5));
+            Candle {
+                timestamp: start + Duration::days(day as i64),
+                open,
+                high: open.max(close) * (1.0 + 0.01 * uniform()),
+                low: open.min(close) * (1.0 - 0.01 * uniform()),
+                close,
+            }
+        })
+        .collect()
+}
+
+fn sma(candles: &[Candle], period: usize) -> Overlay {
+    Overlay {
+        name: format!("SMA({})", period),
+        values: candles
+            .windows(period)
+            .map(|w| {
+                let last = w.last().unwrap();
+                (last.timestamp, w.iter().map(|c| c.close).sum::<f64>() / period as f64)
+            })
+            .collect(),
+    }
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("=== Chapter 289: Results Visualization Test ===\n");
 
     let mut results = BacktestResults::new(10_000.0);
 
-    let start_date = Utc::now() - Duration::days(30);
+    let start_date = Utc::now() - Duration::days(120);
+    results.candles = synthetic_candles(start_date, 120, 7);
+    results.overlays = vec![sma(&results.candles, 10), sma(&results.candles, 30)];
+    results.benchmark = Some(Benchmark::buy_and_hold("BTC buy & hold", &results.candles));
+    results.risk_free_rate = 0.02;
 
+    // (entry day, days held, long?); each trade moves 0.1 BTC
     let trade_data = vec![
-        (1, 250.0),
-        (2, -100.0),
-        (3, 300.0),
-        (4, 150.0),
-        (5, -200.0),
-        (6, 400.0),
-        (7, 100.0),
-        (8, -150.0),
-        (9, 500.0),
-        (10, 200.0),
-        (11, -100.0),
-        (12, 350.0),
-        (13, 150.0),
-        (14, -250.0),
-        (15, 600.0),
+        (3, 4, true),
+        (10, 3, false),
+        (16, 6, true),
+        (25, 2, true),
+        (30, 5, false),
+        (38, 7, true),
+        (47, 3, true),
+        (52, 4, false),
+        (60, 8, true),
+        (70, 3, true),
+        (76, 5, false),
+        (84, 6, true),
+        (93, 4, true),
+        (99, 3, false),
+        (105, 10, true),
     ];
 
-    for (day, profit) in trade_data {
-        results.add_trade(Trade {
-            timestamp: start_date + Duration::days(day),
+    for (day, held, long) in trade_data {
+        let entry = &results.candles[day];
+        let exit = &results.candles[day + held];
+        let quantity = 0.1;
+        let direction = if long { 1.0 } else { -1.0 };
+        let trade = Trade {
+            entry_timestamp: entry.timestamp,
+            entry_price: entry.open,
+            timestamp: exit.timestamp,
             symbol: "BTC".to_string(),
-            side: if profit > 0.0 { TradeSide::Buy } else { TradeSide::Sell },
-            quantity: 0.1,
-            price: 42000.0,
-            profit,
-        });
+            side: if long { TradeSide::Buy } else { TradeSide::Sell },
+            quantity,
+            price: exit.close,
+            profit: (exit.close - entry.open) * quantity * direction,
+        };
+        results.add_trade(trade);
     }
 
     results.calculate_equity_curve();
@@ -307,10 +1145,44 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("Initial balance:   ${:.2}", results.initial_balance);
     println!("Final balance:     ${:.2}", results.final_balance);
 
-    println!("\n=== Generating Charts ===");
-    plot_equity_curve(&results, "equity_curve.png")?;
-    plot_drawdown(&results, "drawdown.png")?;
-    plot_profit_distribution(&results, "profit_distribution.png")?;
+    let config = results.metrics_config();
+    let comparison = results.benchmark_report().expect("benchmark and trades are set");
+    println!("\nEquity periods per year: {:.1}", config.periods_per_year);
+    comparison.print();
+
+    // The benchmark is marked at each equity point, from the first open
+    let benchmark = results.benchmark_equity();
+    let last_exit = results.equity_curve.last().unwrap().timestamp;
+    let last_close = results.candles.iter().rfind(|c| c.timestamp <= last_exit).unwrap().close;
+    let expected = results.initial_balance * last_close / results.candles[0].open;
+    assert!((benchmark.last().unwrap().balance - expected).abs() < 1e-6);
+    assert!((comparison.benchmark_metrics.total_return - (expected / results.initial_balance - 1.0)).abs() < 1e-9);
+    // 15 trades from day 3 to day 115: about 49 periods a year, not 252
+    assert!((config.periods_per_year - 15.0 / (112.0 / 365.25)).abs() < 1.0);
+    assert_eq!(comparison.relative.periods, results.trades.len());
+
+    // One rolling window ends at every equity point from the window-th on
+    let rolling = results.rolling_sharpe_volatility(ROLLING_WINDOW);
+    assert_eq!(rolling.len(), results.equity_curve.len() + 1 - ROLLING_WINDOW);
+    assert_eq!(rolling.last().map(|r| r.0), results.equity_curve.last().map(|ep| ep.timestamp));
+
+    // The fan starts at the initial balance and its median ends near the actual result
+    let bands = results.monte_carlo_bands(MONTE_CARLO_PATHS, 42)?;
+    assert_eq!(bands.len(), results.trades.len() + 1);
+    assert!(bands[0].iter().all(|&b| b == results.initial_balance));
+    assert!(bands.iter().all(|b| b.windows(2).all(|w| w[0] <= w[1])));
+    assert_eq!(bands, results.monte_carlo_bands(MONTE_CARLO_PATHS, 42)?);
+    let monthly: f64 = results.monthly_returns().iter().map(|m| 1.0 + m.2 / 100.0).product();
+    assert!((monthly - results.final_balance / results.initial_balance).abs() < 1e-9);
+
+    if std::env::args().any(|a| a == "--terminal") {
+        for kind in ChartKind::ALL {
+            print_terminal_chart(&results, kind);
+        }
+    } else {
+        println!("\n=== Generating Charts ===");
+        save_all_charts(&results, "charts")?;
+    }
 
     println!("\nAll charts generated successfully!");
     println!("Test completed - code compiles and runs correctly!");
@@ -1,3 +1,4 @@
+use ch352_test::{Metrics, MetricsConfig};
 use rand::seq::SliceRandom;
 use rand::thread_rng;
 
@@ -57,6 +58,8 @@ impl StrategyParams {
 
 #[derive(Debug, Clone)]
 struct Trade {
+    entry_bar: usize,
+    exit_bar: usize,
     #[allow(dead_code)]
     entry_price: f64,
     #[allow(dead_code)]
@@ -73,67 +76,62 @@ fn backtest_strategy(prices: &[f64], params: &StrategyParams) -> Vec<Trade> {
 
     let mut position_open = false;
     let mut entry_price = 0.0;
+    let mut entry_bar = 0;
 
-    // Start from ma_long + 1 to ensure we have enough data for both current and previous MAs
-    for i in (params.ma_long + 1)..prices.len() {
-        let short_ma: f64 = prices[i - params.ma_short..i].iter().sum::<f64>()
-            / params.ma_short as f64;
-        let long_ma: f64 = prices[i - params.ma_long..i].iter().sum::<f64>()
-            / params.ma_long as f64;
+    let mean = |window: &[f64]| window.iter().sum::<f64>() / window.len() as f64;
 
-        let prev_short_ma: f64 = prices[i - params.ma_short - 1..i - 1].iter().sum::<f64>()
-            / params.ma_short as f64;
-        let prev_long_ma: f64 = prices[i - params.ma_long - 1..i - 1].iter().sum::<f64>()
-            / params.ma_long as f64;
+    // Signals use the close of bar i, so orders fill at the price of bar i + 1
+    for i in params.ma_long..prices.len().saturating_sub(1) {
+        let short_ma = mean(&prices[i + 1 - params.ma_short..=i]);
+        let long_ma = mean(&prices[i + 1 - params.ma_long..=i]);
 
-        if !position_open && prev_short_ma <= prev_long_ma && short_ma > long_ma {
-            position_open = true;
-            entry_price = prices[i];
-        }
+        let prev_short_ma = mean(&prices[i - params.ma_short..i]);
+        let prev_long_ma = mean(&prices[i - params.ma_long..i]);
 
         if position_open {
             let current_pnl = (prices[i] - entry_price) / entry_price;
+            let hit_exit = current_pnl <= -params.stop_loss || current_pnl >= params.take_profit;
+            let crossed_down = prev_short_ma >= prev_long_ma && short_ma < long_ma;
 
-            if current_pnl <= -params.stop_loss || current_pnl >= params.take_profit {
-                trades.push(Trade {
-                    entry_price,
-                    exit_price: prices[i],
-                    pnl: current_pnl,
-                });
-                position_open = false;
-            } else if prev_short_ma >= prev_long_ma && short_ma < long_ma {
+            if hit_exit || crossed_down {
+                let exit_price = prices[i + 1];
                 trades.push(Trade {
+                    entry_bar,
+                    exit_bar: i + 1,
                     entry_price,
-                    exit_price: prices[i],
-                    pnl: current_pnl,
+                    exit_price,
+                    pnl: (exit_price - entry_price) / entry_price,
                 });
                 position_open = false;
             }
+        } else if prev_short_ma <= prev_long_ma && short_ma > long_ma {
+            position_open = true;
+            entry_price = prices[i + 1];
+            entry_bar = i + 1;
         }
     }
 
     trades
 }
 
-fn calculate_sharpe(trades: &[Trade]) -> f64 {
-    if trades.is_empty() {
-        return 0.0;
-    }
-
-    let returns: Vec<f64> = trades.iter().map(|t| t.pnl).collect();
-    let mean_return = returns.iter().sum::<f64>() / returns.len() as f64;
-
-    let variance = returns.iter()
-        .map(|r| (r - mean_return).powi(2))
-        .sum::<f64>() / returns.len() as f64;
-
-    let std_dev = variance.sqrt();
-
-    if std_dev == 0.0 {
-        return 0.0;
+/// Sharpe ratio of the daily equity curve of holding `trades` over `prices`,
+/// under the conventions of the shared metrics module
+fn calculate_sharpe(prices: &[f64], trades: &[Trade]) -> f64 {
+    let mut equity = 1.0;
+    let mut curve = Vec::with_capacity(prices.len());
+    let mut trades = trades.iter().peekable();
+    for i in 1..prices.len() {
+        if let Some(trade) = trades.peek() {
+            if i > trade.entry_bar && i <= trade.exit_bar {
+                equity *= prices[i] / prices[i - 1];
+            }
+            if i == trade.exit_bar {
+                trades.next();
+            }
+        }
+        curve.push((i as i64, equity));
     }
-
-    mean_return / std_dev * (252.0_f64).sqrt()
+    Metrics::compute(1.0, &curve, &[], &MetricsConfig::per_bar(252.0)).sharpe
 }
 
 fn walk_forward_analysis(prices: &[f64], params: &StrategyParams) -> BacktestResult {
@@ -143,11 +141,11 @@ fn walk_forward_analysis(prices: &[f64], params: &StrategyParams) -> BacktestRes
     let test_prices = &prices[split_point..];
 
     let train_trades = backtest_strategy(train_prices, params);
-    let train_sharpe = calculate_sharpe(&train_trades);
+    let train_sharpe = calculate_sharpe(train_prices, &train_trades);
     let train_profit: f64 = train_trades.iter().map(|t| t.pnl).sum();
 
     let test_trades = backtest_strategy(test_prices, params);
-    let test_sharpe = calculate_sharpe(&test_trades);
+    let test_sharpe = calculate_sharpe(test_prices, &test_trades);
     let test_profit: f64 = test_trades.iter().map(|t| t.pnl).sum();
 
     BacktestResult {
@@ -27,6 +27,7 @@ struct BacktestResult {
 struct TimeSeriesFold<'a, T> {
     train: &'a [T],
     test: &'a [T],
+    test_start: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -37,7 +38,10 @@ struct StrategyConfig {
 }
 
 // K-Fold Cross-Validation for time series
-fn time_series_k_fold<T>(data: &[T], k: usize) -> Vec<TimeSeriesFold<T>> {
+//
+// Training data ends `purge` bars before the test period, because the label
+// of a bar (its forward return) overlaps the bars that follow it.
+fn time_series_k_fold<T>(data: &[T], k: usize, purge: usize) -> Vec<TimeSeriesFold<T>> {
     let mut folds = Vec::new();
     let fold_size = data.len() / (k + 1);
 
@@ -50,30 +54,91 @@ fn time_series_k_fold<T>(data: &[T], k: usize) -> Vec<TimeSeriesFold<T>> {
         }
 
         folds.push(TimeSeriesFold {
-            train: &data[0..test_start],
+            train: &data[0..test_start.saturating_sub(purge)],
             test: &data[test_start..test_end],
+            test_start,
         });
     }
 
     folds
 }
 
-// Backtest simulation
-fn run_backtest(data: &[Candle], config: &StrategyConfig) -> (f64, f64, f64, f64) {
-    use std::collections::hash_map::DefaultHasher;
-    use std::hash::{Hash, Hasher};
+// Backtest of a moving-average filter, scored on the bars `test`
+//
+// Goes long at a close above the average of the `ma_period` closes before
+// it, or of all earlier closes while there are fewer, and exits at the stop
+// loss or take profit from the entry price. The strategy runs from the first
+// bar, so a position opened before the test period is carried into it; only
+// test bars count towards the metrics. A position still open at the end is
+// counted as a trade at the last close.
+fn run_backtest(
+    data: &[Candle],
+    test: std::ops::Range<usize>,
+    config: &StrategyConfig,
+) -> (f64, f64, f64, f64) {
+    let stop = config.stop_loss_pct / 100.0;
+    let target = config.take_profit_pct / 100.0;
+
+    let mut entry: Option<f64> = None;
+    let mut equity = 1.0f64;
+    let mut peak = 1.0f64;
+    let mut max_drawdown = 0.0f64;
+    let mut returns = Vec::new();
+    let mut wins = 0;
+    let mut trades = 0;
+
+    for i in 2..test.end {
+        let prev = data[i - 1].close;
+        let history = &data[(i - 1).saturating_sub(config.ma_period)..i - 1];
+        let ma = history.iter().map(|c| c.close).sum::<f64>() / history.len() as f64;
+        if entry.is_none() && prev > ma {
+            entry = Some(prev);
+        }
+
+        let mut r = 0.0;
+        if let Some(entry_price) = entry {
+            let low = entry_price * (1.0 - stop);
+            let high = entry_price * (1.0 + target);
+            let exit = data[i].close.clamp(low, high);
+            r = exit / prev - 1.0;
+            if exit <= low || exit >= high {
+                if i >= test.start {
+                    trades += 1;
+                    if exit > entry_price {
+                        wins += 1;
+                    }
+                }
+                entry = None;
+            }
+        }
 
-    let mut hasher = DefaultHasher::new();
-    config.ma_period.hash(&mut hasher);
-    data.len().hash(&mut hasher);
-    let seed = (hasher.finish() % 10000) as f64 / 10000.0;
+        if i < test.start {
+            continue;
+        }
+        returns.push(r);
+        equity *= 1.0 + r;
+        peak = peak.max(equity);
+        max_drawdown = max_drawdown.max((peak - equity) / peak * 100.0);
+    }
+    if let Some(entry_price) = entry {
+        trades += 1;
+        if data[test.end - 1].close > entry_price {
+            wins += 1;
+        }
+    }
 
-    let total_return = 5.0 + seed * 30.0;
-    let sharpe_ratio = 0.8 + seed * 1.5;
-    let max_drawdown = 5.0 + seed * 10.0;
-    let win_rate = 50.0 + seed * 25.0;
+    let n = returns.len().max(1) as f64;
+    let mean = returns.iter().sum::<f64>() / n;
+    let std = (returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / n).sqrt();
+    // Monthly bars
+    let sharpe_ratio = if std > 0.0 { mean / std * 12f64.sqrt() } else { 0.0 };
+    let win_rate = if trades > 0 {
+        wins as f64 / trades as f64 * 100.0
+    } else {
+        0.0
+    };
 
-    (total_return, sharpe_ratio, max_drawdown, win_rate)
+    ((equity - 1.0) * 100.0, sharpe_ratio, max_drawdown, win_rate)
 }
 
 fn cross_validate_strategy(
@@ -81,12 +146,13 @@ fn cross_validate_strategy(
     config: &StrategyConfig,
     k: usize,
 ) -> Vec<BacktestResult> {
-    let folds = time_series_k_fold(data, k);
+    let folds = time_series_k_fold(data, k, 1);
     let mut results = Vec::new();
 
     for (i, fold) in folds.iter().enumerate() {
+        let test = fold.test_start..fold.test_start + fold.test.len();
         let (total_return, sharpe_ratio, max_drawdown, win_rate) =
-            run_backtest(fold.test, config);
+            run_backtest(data, test, config);
 
         results.push(BacktestResult {
             fold_number: i + 1,
@@ -121,7 +187,7 @@ fn main() {
     // Test 1: Basic K-Fold
     println!("Test 1: Time Series K-Fold");
     let k = 3;
-    let folds = time_series_k_fold(&candles, k);
+    let folds = time_series_k_fold(&candles, k, 1);
     println!("Created {} folds", folds.len());
     for (i, fold) in folds.iter().enumerate() {
         println!("  Fold {}: train={}, test={}", i + 1, fold.train.len(), fold.test.len());
@@ -138,13 +204,16 @@ fn main() {
 
     let results = cross_validate_strategy(&candles, &config, k);
 
-    let mut sharpe_sum = 0.0;
     for result in &results {
-        println!("  Fold {}: Sharpe={:.2}, Return={:.2}%",
-            result.fold_number, result.sharpe_ratio, result.total_return);
-        sharpe_sum += result.sharpe_ratio;
+        println!("  Fold {}: Sharpe={:.2}, Return={:.2}%, MaxDD={:.2}%, WinRate={:.0}%",
+            result.fold_number, result.sharpe_ratio, result.total_return,
+            result.max_drawdown, result.win_rate);
     }
-    println!("  Average Sharpe: {:.2}", sharpe_sum / results.len() as f64);
+    let mut sharpes: Vec<f64> = results.iter().map(|r| r.sharpe_ratio).collect();
+    sharpes.sort_by(f64::total_cmp);
+    let mean = sharpes.iter().sum::<f64>() / sharpes.len() as f64;
+    println!("  Sharpe: min={:.2}, median={:.2}, max={:.2}, mean={:.2}",
+        sharpes[0], sharpes[sharpes.len() / 2], sharpes[sharpes.len() - 1], mean);
     println!();
 
     println!("All tests passed!");
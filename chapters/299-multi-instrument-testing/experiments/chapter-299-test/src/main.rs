@@ -1,8 +1,12 @@
 // Test code from Chapter 299: Multi-Instrument Testing
 
-use std::collections::HashMap;
+use ch352_test::{Metrics, MetricsConfig};
+
+/// The synthetic bars are daily
+const BARS_PER_YEAR: f64 = 252.0;
 
 #[derive(Debug, Clone)]
+#[allow(dead_code)]
 struct OHLCV {
     timestamp: u64,
     open: f64,
@@ -17,7 +21,10 @@ struct Trade {
     entry_price: f64,
     exit_price: f64,
     profit_pct: f64,
-    holding_bars: usize,
+    /// Bar whose open filled the entry
+    entry_bar: usize,
+    /// Bar whose open (or, for the last bar, close) filled the exit
+    exit_bar: usize,
 }
 
 #[derive(Debug)]
@@ -44,7 +51,7 @@ impl BacktestResult {
         }
     }
 
-    fn calculate_metrics(&mut self, trades: &[Trade]) {
+    fn calculate_metrics(&mut self, data: &[OHLCV], trades: &[Trade]) {
         self.total_trades = trades.len();
         self.winning_trades = trades.iter().filter(|t| t.profit_pct > 0.0).count();
         self.win_rate = if self.total_trades > 0 {
@@ -53,42 +60,37 @@ impl BacktestResult {
             0.0
         };
 
-        // Total return
-        self.total_return = trades.iter().map(|t| t.profit_pct).sum();
-
-        // Maximum drawdown (simplified)
-        let mut peak = 0.0;
-        let mut current = 0.0;
-        let mut max_dd = 0.0;
+        // Return, drawdown and annualized Sharpe of the marked-to-market
+        // equity, under the conventions shared with the other backtesters
+        let config = MetricsConfig::per_bar(BARS_PER_YEAR);
+        let metrics = Metrics::compute(1.0, &equity_curve(data, trades), &[], &config);
+        self.total_return = metrics.total_return * 100.0;
+        self.max_drawdown = metrics.max_drawdown * 100.0;
+        self.sharpe_ratio = metrics.sharpe;
+    }
+}
 
-        for trade in trades {
-            current += trade.profit_pct;
-            if current > peak {
-                peak = current;
-            }
-            let dd = peak - current;
-            if dd > max_dd {
-                max_dd = dd;
+/// Equity at every close, starting from 1.0, with trades compounding on
+/// the equity left by the previous ones
+fn equity_curve(data: &[OHLCV], trades: &[Trade]) -> Vec<(i64, f64)> {
+    let mut closed = 1.0;
+    let mut trades = trades.iter().peekable();
+    data.iter()
+        .enumerate()
+        .map(|(i, bar)| {
+            let mut equity = closed;
+            if let Some(trade) = trades.peek() {
+                if i == trade.exit_bar {
+                    closed *= trade.exit_price / trade.entry_price;
+                    equity = closed;
+                    trades.next();
+                } else if i >= trade.entry_bar {
+                    equity = closed * bar.close / trade.entry_price;
+                }
             }
-        }
-        self.max_drawdown = max_dd;
-
-        // Sharpe Ratio (simplified)
-        if !trades.is_empty() {
-            let mean = self.total_return / trades.len() as f64;
-            let variance: f64 = trades
-                .iter()
-                .map(|t| (t.profit_pct - mean).powi(2))
-                .sum::<f64>()
-                / trades.len() as f64;
-            let std_dev = variance.sqrt();
-            self.sharpe_ratio = if std_dev > 0.0 {
-                mean / std_dev
-            } else {
-                0.0
-            };
-        }
-    }
+            (bar.timestamp as i64, equity)
+        })
+        .collect()
 }
 
 fn simple_moving_average(prices: &[f64], period: usize) -> Vec<f64> {
@@ -116,7 +118,8 @@ fn backtest_sma_crossover(data: &[OHLCV], fast_period: usize, slow_period: usize
     let mut trades = Vec::new();
     let mut position: Option<(f64, usize)> = None; // (entry_price, entry_index)
 
-    for i in slow_period..data.len() {
+    // Signals use bar i's close, so orders fill at the open of bar i + 1
+    for i in slow_period..data.len().saturating_sub(1) {
         if fast_sma[i] == 0.0 || slow_sma[i] == 0.0 {
             continue;
         }
@@ -126,18 +129,19 @@ fn backtest_sma_crossover(data: &[OHLCV], fast_period: usize, slow_period: usize
             && fast_sma[i] > slow_sma[i]
             && fast_sma[i - 1] <= slow_sma[i - 1]
         {
-            position = Some((data[i].close, i));
+            position = Some((data[i + 1].open, i + 1));
         }
         // Sell signal: fast MA crosses below slow MA
         else if let Some((entry_price, entry_idx)) = position {
             if fast_sma[i] < slow_sma[i] && fast_sma[i - 1] >= slow_sma[i - 1] {
-                let exit_price = data[i].close;
+                let exit_price = data[i + 1].open;
                 let profit_pct = ((exit_price - entry_price) / entry_price) * 100.0;
                 trades.push(Trade {
                     entry_price,
                     exit_price,
                     profit_pct,
-                    holding_bars: i - entry_idx,
+                    entry_bar: entry_idx,
+                    exit_bar: i + 1,
                 });
                 position = None;
             }
@@ -152,7 +156,8 @@ fn backtest_sma_crossover(data: &[OHLCV], fast_period: usize, slow_period: usize
             entry_price,
             exit_price,
             profit_pct,
-            holding_bars: data.len() - 1 - entry_idx,
+            entry_bar: entry_idx,
+            exit_bar: data.len() - 1,
         });
     }
 
@@ -199,7 +204,7 @@ impl MultiInstrumentTester {
         for instrument in &self.instruments {
             let trades = backtest_sma_crossover(&instrument.data, self.fast_period, self.slow_period);
             let mut result = BacktestResult::new(instrument.symbol.clone());
-            result.calculate_metrics(&trades);
+            result.calculate_metrics(&instrument.data, &trades);
             results.push(result);
         }
 
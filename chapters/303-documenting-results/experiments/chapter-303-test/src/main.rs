@@ -0,0 +1,1318 @@
+// Test script for Chapter 303 code examples
+// This combines all the code examples to verify they compile
+
+use ch352_test::MetricsConfig;
+use chrono::{DateTime, Datelike, NaiveDateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::Command;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BacktestMetadata {
+    test_id: String,
+    strategy_name: String,
+    code_version: String,
+    timestamp: DateTime<Utc>,
+    author: String,
+    description: String,
+}
+
+impl BacktestMetadata {
+    fn new(strategy_name: &str, description: &str) -> Self {
+        Self {
+            test_id: uuid::Uuid::new_v4().to_string(),
+            strategy_name: strategy_name.to_string(),
+            code_version: git_commit(),
+            timestamp: Utc::now(),
+            author: "trading-bot".to_string(),
+            description: description.to_string(),
+        }
+    }
+
+    fn print(&self) {
+        println!("=== Backtest Metadata ===");
+        println!("ID: {}", self.test_id);
+        println!("Strategy: {}", self.strategy_name);
+        println!("Code Version: {}", self.code_version);
+        println!("Time: {}", self.timestamp.format("%Y-%m-%d %H:%M:%S UTC"));
+        println!("Author: {}", self.author);
+        println!("Description: {}", self.description);
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct StrategyParameters {
+    ma_short_period: usize,
+    ma_long_period: usize,
+    stop_loss_pct: f64,
+    take_profit_pct: f64,
+    max_position_size: f64,
+}
+
+impl StrategyParameters {
+    fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    fn print(&self) {
+        println!("\n=== Strategy Parameters ===");
+        println!("MA Short: {}", self.ma_short_period);
+        println!("MA Long: {}", self.ma_long_period);
+        println!("Stop Loss: {:.2}%", self.stop_loss_pct * 100.0);
+        println!("Take Profit: {:.2}%", self.take_profit_pct * 100.0);
+        println!("Max Position Size: {:.2}%", self.max_position_size * 100.0);
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PerformanceMetrics {
+    total_return: f64,
+    annual_return: f64,
+    sharpe_ratio: f64,
+    max_drawdown: f64,
+    win_rate: f64,
+    profit_factor: f64,
+    total_trades: usize,
+    winning_trades: usize,
+    losing_trades: usize,
+    avg_profit_per_trade: f64,
+    max_profit: f64,
+    max_loss: f64,
+}
+
+impl PerformanceMetrics {
+    fn print(&self) {
+        println!("\n=== Performance Metrics ===");
+        println!("Total Return: {:.2}%", self.total_return * 100.0);
+        println!("Annual Return: {:.2}%", self.annual_return * 100.0);
+        println!("Sharpe Ratio: {:.2}", self.sharpe_ratio);
+        println!("Max Drawdown: {:.2}%", self.max_drawdown * 100.0);
+        println!("Win Rate: {:.2}%", self.win_rate * 100.0);
+        println!("Profit Factor: {:.2}", self.profit_factor);
+    }
+
+    fn grade(&self) -> &str {
+        if self.sharpe_ratio > 2.0 && self.max_drawdown.abs() < 0.15 {
+            "Excellent ✅"
+        } else if self.sharpe_ratio > 1.0 && self.max_drawdown.abs() < 0.25 {
+            "Good 👍"
+        } else if self.sharpe_ratio > 0.5 {
+            "Acceptable ⚠️"
+        } else {
+            "Unsatisfactory ❌"
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Trade {
+    entry_time: String,
+    exit_time: String,
+    symbol: String,
+    side: String,
+    entry_price: f64,
+    exit_price: f64,
+    quantity: f64,
+    pnl: f64,
+    pnl_pct: f64,
+    /// Maximum adverse excursion, fraction of the entry price
+    #[serde(default)]
+    mae: Option<f64>,
+    /// Maximum favorable excursion, fraction of the entry price
+    #[serde(default)]
+    mfe: Option<f64>,
+}
+
+/// What the strategy is measured against, e.g. buy-and-hold of the traded
+/// market, valued at every equity point
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Benchmark {
+    name: String,
+    /// Scaled to start from the strategy's initial equity
+    equity: Vec<f64>,
+}
+
+/// Strategy returns against the benchmark's, per equity period, as computed
+/// by the shared benchmark module: alpha and beta regress returns in excess
+/// of the risk-free rate, everything is annualized by the report's periods
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+struct RelativeMetrics {
+    benchmark_return: f64,
+    /// Annualized Jensen's alpha
+    alpha: f64,
+    /// t-statistic of the alpha
+    #[serde(default)]
+    alpha_t: f64,
+    beta: f64,
+    correlation: f64,
+    #[serde(default)]
+    r_squared: f64,
+    /// Annualized mean of strategy minus benchmark returns
+    #[serde(default)]
+    active_return: f64,
+    /// Annualized deviation of strategy minus benchmark returns
+    tracking_error: f64,
+    information_ratio: f64,
+    /// Average return over the benchmark's when it rose
+    up_capture: f64,
+    /// Average return over the benchmark's when it fell
+    down_capture: f64,
+}
+
+impl RelativeMetrics {
+    /// `None` with fewer than three periods or a flat benchmark
+    fn compute(returns: &[f64], benchmark: &[f64], config: &MetricsConfig) -> Option<Self> {
+        let shared = ch352_test::RelativeMetrics::compute(returns, benchmark, config).ok()?;
+        Some(RelativeMetrics {
+            benchmark_return: benchmark.iter().map(|b| 1.0 + b).product::<f64>() - 1.0,
+            alpha: shared.alpha,
+            alpha_t: shared.alpha_t,
+            beta: shared.beta,
+            correlation: shared.correlation,
+            r_squared: shared.r_squared,
+            active_return: shared.active_return,
+            tracking_error: shared.tracking_error,
+            information_ratio: shared.information_ratio,
+            up_capture: shared.up_capture,
+            down_capture: shared.down_capture,
+        })
+    }
+}
+
+/// Returns between consecutive points of `values`
+fn period_returns(values: &[f64]) -> Vec<f64> {
+    values
+        .windows(2)
+        .map(|w| if w[0] != 0.0 { w[1] / w[0] - 1.0 } else { 0.0 })
+        .collect()
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BacktestReport {
+    metadata: BacktestMetadata,
+    parameters: StrategyParameters,
+    metrics: PerformanceMetrics,
+    trades: Vec<Trade>,
+    equity_curve: Vec<f64>,
+    /// Time of each equity point; reports saved without it still load
+    #[serde(default)]
+    equity_times: Vec<DateTime<Utc>>,
+    #[serde(default)]
+    benchmark: Option<Benchmark>,
+    /// Annual rate the benchmark comparison measures excess returns against
+    #[serde(default)]
+    risk_free_rate: f64,
+}
+
+impl BacktestReport {
+    fn new(
+        metadata: BacktestMetadata,
+        parameters: StrategyParameters,
+        metrics: PerformanceMetrics,
+        trades: Vec<Trade>,
+        equity_curve: Vec<f64>,
+    ) -> Self {
+        Self {
+            metadata,
+            parameters,
+            metrics,
+            trades,
+            equity_curve,
+            equity_times: Vec::new(),
+            benchmark: None,
+            risk_free_rate: 0.0,
+        }
+    }
+
+    fn with_equity_times(mut self, times: Vec<DateTime<Utc>>) -> Self {
+        self.equity_times = times;
+        self
+    }
+
+    /// Benchmark values at each equity point, in any unit: they are scaled
+    /// to start from the initial equity
+    fn with_benchmark(mut self, name: &str, values: &[f64]) -> Self {
+        let start = self.equity_curve.first().copied().unwrap_or(1.0);
+        let base = values.first().copied().unwrap_or(1.0);
+        self.benchmark = Some(Benchmark {
+            name: name.to_string(),
+            equity: values.iter().map(|v| v / base * start).collect(),
+        });
+        self
+    }
+
+    fn with_risk_free_rate(mut self, rate: f64) -> Self {
+        self.risk_free_rate = rate;
+        self
+    }
+
+    /// Equity periods in a year: from the timestamps when there are any,
+    /// otherwise daily
+    fn periods_per_year(&self) -> f64 {
+        match (self.equity_times.first(), self.equity_times.last()) {
+            (Some(first), Some(last)) if last > first && self.equity_times.len() > 1 => {
+                let years = (*last - *first).num_seconds() as f64 / (365.25 * 86_400.0);
+                (self.equity_times.len() - 1) as f64 / years
+            }
+            _ => 252.0,
+        }
+    }
+
+    /// Conventions of the benchmark comparison: one return period per
+    /// equity point, `periods_per_year` of them, and the report's risk-free rate
+    fn metrics_config(&self) -> MetricsConfig {
+        MetricsConfig::per_bar(self.periods_per_year()).with_risk_free_rate(self.risk_free_rate)
+    }
+
+    /// `None` without a benchmark of the same length as the equity curve
+    fn relative_metrics(&self) -> Option<RelativeMetrics> {
+        let benchmark = self.benchmark.as_ref()?;
+        if benchmark.equity.len() != self.equity_curve.len() {
+            return None;
+        }
+        RelativeMetrics::compute(
+            &period_returns(&self.equity_curve),
+            &period_returns(&benchmark.equity),
+            &self.metrics_config(),
+        )
+    }
+
+    fn load_from_file(filename: &str) -> std::io::Result<Self> {
+        let json = std::fs::read_to_string(filename)?;
+        serde_json::from_str(&json)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    fn save_to_file(&self, filename: &str) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self).unwrap();
+        std::fs::write(filename, json)?;
+        println!("✅ Report saved to: {}", filename);
+        Ok(())
+    }
+
+    fn generate_text_report(&self) -> String {
+        let mut report = String::new();
+        report.push_str("═══════════════════════════════════════════════════\n");
+        report.push_str("           BACKTEST STRATEGY REPORT\n");
+        report.push_str("═══════════════════════════════════════════════════\n\n");
+        report.push_str(&format!("Test ID: {}\n", self.metadata.test_id));
+        report.push_str(&format!("Strategy: {}\n", self.metadata.strategy_name));
+        if let (Some(benchmark), Some(r)) = (&self.benchmark, self.relative_metrics()) {
+            report.push_str(&format!("\nVersus {}:\n", benchmark.name));
+            report.push_str(&format!("  Benchmark Return: {:.2}%\n", r.benchmark_return * 100.0));
+            report.push_str(&format!(
+                "  Alpha: {:.2}% (risk-free {:.2}%), Beta: {:.2}\n",
+                r.alpha * 100.0,
+                self.risk_free_rate * 100.0,
+                r.beta
+            ));
+            report.push_str(&format!(
+                "  Tracking Error: {:.2}%, Information Ratio: {:.2}\n",
+                r.tracking_error * 100.0,
+                r.information_ratio
+            ));
+            report.push_str(&format!(
+                "  Capture: {:.0}% up / {:.0}% down\n",
+                r.up_capture * 100.0,
+                r.down_capture * 100.0
+            ));
+        }
+        report
+    }
+
+    fn print_summary(&self) {
+        println!("{}", self.generate_text_report());
+    }
+
+    fn generate_markdown_report(&self) -> String {
+        let mut md = String::new();
+        md.push_str(&format!("# Backtest Report: {}\n\n", self.metadata.strategy_name));
+        md.push_str("## Metadata\n\n");
+        md.push_str(&format!("- **Test ID**: `{}`\n", self.metadata.test_id));
+        if let (Some(benchmark), Some(r)) = (&self.benchmark, self.relative_metrics()) {
+            md.push_str(&format!("\n## Versus {}\n\n", benchmark.name));
+            md.push_str("| Metric | Value |\n|---|---|\n");
+            md.push_str(&format!("| Benchmark Return | {:.2}% |\n", r.benchmark_return * 100.0));
+            md.push_str(&format!("| Alpha | {:.2}% |\n", r.alpha * 100.0));
+            md.push_str(&format!("| Risk-Free Rate | {:.2}% |\n", self.risk_free_rate * 100.0));
+            md.push_str(&format!("| Beta | {:.2} |\n", r.beta));
+            md.push_str(&format!("| Correlation | {:.2} |\n", r.correlation));
+            md.push_str(&format!("| Tracking Error | {:.2}% |\n", r.tracking_error * 100.0));
+            md.push_str(&format!("| Information Ratio | {:.2} |\n", r.information_ratio));
+            md.push_str(&format!(
+                "| Up / Down Capture | {:.0}% / {:.0}% |\n",
+                r.up_capture * 100.0,
+                r.down_capture * 100.0
+            ));
+        }
+        md
+    }
+
+    /// Self-contained HTML page: inline CSS, SVG charts and a few lines of
+    /// JavaScript for sorting, so it opens offline
+    fn generate_html_report(&self) -> String {
+        let m = &self.metrics;
+        let mut html = String::new();
+        html.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+        html.push_str(&format!(
+            "<title>Backtest Report: {}</title>\n<style>{}</style>\n</head>\n<body>\n",
+            escape_html(&self.metadata.strategy_name),
+            HTML_STYLE
+        ));
+        html.push_str(&format!(
+            "<h1>Backtest Report: {}</h1>\n",
+            escape_html(&self.metadata.strategy_name)
+        ));
+
+        html.push_str("<section><h2>Metadata</h2>\n<table>\n");
+        for (name, value) in [
+            ("Test ID", self.metadata.test_id.clone()),
+            ("Code Version", self.metadata.code_version.clone()),
+            ("Time", self.metadata.timestamp.format("%Y-%m-%d %H:%M:%S UTC").to_string()),
+            ("Author", self.metadata.author.clone()),
+            ("Description", self.metadata.description.clone()),
+        ] {
+            html.push_str(&format!(
+                "<tr><th>{}</th><td>{}</td></tr>\n",
+                name,
+                escape_html(&value)
+            ));
+        }
+        html.push_str("</table></section>\n");
+
+        // Parameters go through serde so new fields show up without changes here
+        html.push_str("<section><h2>Parameters</h2>\n<table>\n");
+        if let Ok(serde_json::Value::Object(params)) = serde_json::to_value(&self.parameters) {
+            for (name, value) in params {
+                html.push_str(&format!(
+                    "<tr><th>{}</th><td>{}</td></tr>\n",
+                    escape_html(&name),
+                    escape_html(&value.to_string())
+                ));
+            }
+        }
+        html.push_str("</table></section>\n");
+
+        html.push_str("<section><h2>Metrics</h2>\n<table>\n");
+        for (name, value) in [
+            ("Total Return", format!("{:.2}%", m.total_return * 100.0)),
+            ("Annual Return", format!("{:.2}%", m.annual_return * 100.0)),
+            ("Sharpe Ratio", format!("{:.2}", m.sharpe_ratio)),
+            ("Max Drawdown", format!("{:.2}%", m.max_drawdown * 100.0)),
+            ("Win Rate", format!("{:.2}%", m.win_rate * 100.0)),
+            ("Profit Factor", format!("{:.2}", m.profit_factor)),
+            ("Total Trades", m.total_trades.to_string()),
+            ("Winning / Losing", format!("{} / {}", m.winning_trades, m.losing_trades)),
+            ("Avg Profit per Trade", format!("{:.2}%", m.avg_profit_per_trade * 100.0)),
+            ("Best / Worst Trade", format!("{:.2}% / {:.2}%", m.max_profit * 100.0, m.max_loss * 100.0)),
+            ("Grade", m.grade().to_string()),
+        ] {
+            html.push_str(&format!("<tr><th>{}</th><td>{}</td></tr>\n", name, escape_html(&value)));
+        }
+        html.push_str("</table></section>\n");
+
+        if let (Some(benchmark), Some(r)) = (&self.benchmark, self.relative_metrics()) {
+            html.push_str(&format!(
+                "<section><h2>Benchmark</h2>\n<p>Versus {}</p>\n<table>\n",
+                escape_html(&benchmark.name)
+            ));
+            for (name, value) in [
+                ("Benchmark Return", format!("{:.2}%", r.benchmark_return * 100.0)),
+                ("Alpha", format!("{:.2}%", r.alpha * 100.0)),
+                ("Risk-Free Rate", format!("{:.2}%", self.risk_free_rate * 100.0)),
+                ("Beta", format!("{:.2}", r.beta)),
+                ("Correlation", format!("{:.2}", r.correlation)),
+                ("Tracking Error", format!("{:.2}%", r.tracking_error * 100.0)),
+                ("Information Ratio", format!("{:.2}", r.information_ratio)),
+                ("Up / Down Capture", format!("{:.0}% / {:.0}%", r.up_capture * 100.0, r.down_capture * 100.0)),
+            ] {
+                html.push_str(&format!("<tr><th>{}</th><td>{}</td></tr>\n", name, escape_html(&value)));
+            }
+            html.push_str("</table></section>\n");
+        }
+
+        html.push_str("<section><h2>Equity and Drawdown</h2>\n");
+        html.push_str(&svg_equity_chart(&self.equity_curve, self.benchmark.as_ref()));
+        html.push_str("</section>\n");
+
+        html.push_str("<section><h2>Monthly Returns</h2>\n");
+        if self.equity_times.len() == self.equity_curve.len() && !self.equity_times.is_empty() {
+            html.push_str(&monthly_heatmap(&monthly_returns(&self.equity_times, &self.equity_curve)));
+            if let Some(benchmark) = self.benchmark.as_ref().filter(|b| b.equity.len() == self.equity_times.len()) {
+                html.push_str(&format!("<h3>{}</h3>\n", escape_html(&benchmark.name)));
+                html.push_str(&monthly_heatmap(&monthly_returns(&self.equity_times, &benchmark.equity)));
+            }
+        } else {
+            html.push_str("<p class=\"note\">No equity timestamps in this report.</p>\n");
+        }
+        html.push_str("</section>\n");
+
+        html.push_str("<section><h2>MAE / MFE</h2>\n");
+        html.push_str(&svg_mae_mfe(&self.trades));
+        html.push_str("</section>\n");
+
+        html.push_str("<section><h2>Trades</h2>\n<p class=\"note\">Click a column to sort.</p>\n");
+        html.push_str("<table class=\"sortable\">\n<thead><tr>");
+        for column in [
+            "Entry", "Exit", "Symbol", "Side", "Entry Price", "Exit Price", "Quantity", "P&L",
+            "P&L %", "MAE %", "MFE %",
+        ] {
+            html.push_str(&format!("<th>{}</th>", escape_html(column)));
+        }
+        html.push_str("</tr></thead>\n<tbody>\n");
+        let percent = |v: Option<f64>| match v {
+            Some(v) => format!("<td data-value=\"{}\">{:.2}%</td>", v, v * 100.0),
+            None => "<td data-value=\"\">-</td>".to_string(),
+        };
+        for t in &self.trades {
+            let class = if t.pnl > 0.0 { "win" } else if t.pnl < 0.0 { "loss" } else { "" };
+            html.push_str(&format!(
+                "<tr class=\"{}\">{}{}<td>{}</td><td>{}</td>\
+                 <td data-value=\"{}\">{:.2}</td><td data-value=\"{}\">{:.2}</td>\
+                 <td data-value=\"{}\">{}</td><td data-value=\"{}\">{:.2}</td>{}{}{}</tr>\n",
+                class,
+                time_cell(&t.entry_time),
+                time_cell(&t.exit_time),
+                escape_html(&t.symbol),
+                escape_html(&t.side),
+                t.entry_price,
+                t.entry_price,
+                t.exit_price,
+                t.exit_price,
+                t.quantity,
+                t.quantity,
+                t.pnl,
+                t.pnl,
+                percent(Some(t.pnl_pct)),
+                percent(t.mae),
+                percent(t.mfe)
+            ));
+        }
+        html.push_str("</tbody></table></section>\n");
+
+        html.push_str(&format!("<script>{}</script>\n</body>\n</html>\n", SORT_SCRIPT));
+        html
+    }
+
+    fn save_html_report(&self, filename: &str) -> std::io::Result<()> {
+        std::fs::write(filename, self.generate_html_report())?;
+        println!("✅ HTML report saved: {}", filename);
+        Ok(())
+    }
+}
+
+const HTML_STYLE: &str = "
+body { font-family: -apple-system, 'Segoe UI', sans-serif; margin: 2em auto; max-width: 960px; color: #222; }
+h1 { font-size: 1.6em; } h2 { font-size: 1.2em; border-bottom: 1px solid #ddd; padding-bottom: 0.2em; }
+table { border-collapse: collapse; font-size: 0.9em; }
+th, td { padding: 0.25em 0.7em; border-bottom: 1px solid #eee; text-align: right; }
+th:first-child, td:first-child { text-align: left; }
+table.sortable th { cursor: pointer; background: #f6f6f6; }
+tr.win td:nth-child(8) { color: #1a7f37; } tr.loss td:nth-child(8) { color: #c62828; }
+.note { color: #777; font-size: 0.85em; }
+svg { background: #fcfcfc; border: 1px solid #eee; }
+";
+
+// Sorts a table body by the clicked column. A cell sorts by its data-value,
+// else its text: as a number only when the whole value is one, otherwise as
+// a string after the numbers
+const SORT_SCRIPT: &str = r#"
+document.querySelectorAll('table.sortable th').forEach((th, col) => {
+  th.addEventListener('click', () => {
+    const body = th.closest('table').tBodies[0];
+    const asc = th.dataset.order !== 'asc';
+    th.dataset.order = asc ? 'asc' : 'desc';
+    const key = row => {
+      const cell = row.cells[col];
+      const text = (cell.dataset.value ?? cell.textContent).trim();
+      const number = Number(text);
+      return text !== '' && !isNaN(number) ? number : text;
+    };
+    const compare = (x, y) => typeof x !== typeof y
+      ? (typeof x === 'number' ? -1 : 1)
+      : (x < y ? -1 : x > y ? 1 : 0);
+    Array.from(body.rows)
+      .sort((a, b) => compare(key(a), key(b)) * (asc ? 1 : -1))
+      .forEach(row => body.appendChild(row));
+  });
+});
+"#;
+
+/// Table cell of a trade time, sorted by its Unix timestamp when it parses
+/// as "YYYY-MM-DD HH:MM:SS" (UTC) and by its text otherwise
+fn time_cell(time: &str) -> String {
+    match NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S") {
+        Ok(parsed) => format!(
+            "<td data-value=\"{}\">{}</td>",
+            parsed.and_utc().timestamp(),
+            escape_html(time)
+        ),
+        Err(_) => format!("<td>{}</td>", escape_html(time)),
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Polyline points scaling `values` into a `width` x `height` box
+fn svg_points(values: &[f64], width: f64, height: f64, min: f64, max: f64) -> String {
+    let range = if max > min { max - min } else { 1.0 };
+    let step = width / (values.len().max(2) - 1) as f64;
+    values
+        .iter()
+        .enumerate()
+        .map(|(i, v)| format!("{:.1},{:.1}", i as f64 * step, height - (v - min) / range * height))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Equity line, with the benchmark's in grey, above an underwater
+/// (drawdown) area
+fn svg_equity_chart(equity: &[f64], benchmark: Option<&Benchmark>) -> String {
+    if equity.is_empty() {
+        return "<p class=\"note\">No equity curve in this report.</p>\n".to_string();
+    }
+    let (width, top, bottom) = (900.0, 260.0, 110.0);
+    let benchmark = benchmark.filter(|b| b.equity.len() == equity.len());
+    let all = || equity.iter().chain(benchmark.map_or(&[][..], |b| &b.equity[..])).copied();
+    let min = all().fold(f64::INFINITY, f64::min);
+    let max = all().fold(f64::NEG_INFINITY, f64::max);
+    let mut peak = f64::NEG_INFINITY;
+    let drawdown: Vec<f64> = equity
+        .iter()
+        .map(|&e| {
+            peak = peak.max(e);
+            if peak > 0.0 { e / peak - 1.0 } else { 0.0 }
+        })
+        .collect();
+    let deepest = drawdown.iter().copied().fold(0.0, f64::min);
+
+    let mut svg = format!(
+        "<svg viewBox=\"-60 -10 {} {}\" width=\"100%\">\n",
+        width + 70.0,
+        top + bottom + 40.0
+    );
+    if let Some(b) = benchmark {
+        svg.push_str(&format!(
+            "<polyline fill=\"none\" stroke=\"#888\" stroke-width=\"1.2\" stroke-dasharray=\"4 3\" points=\"{}\">\
+             <title>{}</title></polyline>\n",
+            svg_points(&b.equity, width, top, min, max),
+            escape_html(&b.name)
+        ));
+    }
+    svg.push_str(&format!(
+        "<polyline fill=\"none\" stroke=\"#1565c0\" stroke-width=\"1.5\" points=\"{}\"/>\n",
+        svg_points(equity, width, top, min, max)
+    ));
+    svg.push_str(&format!(
+        "<text x=\"-5\" y=\"5\" text-anchor=\"end\" font-size=\"11\">{:.0}</text>\
+         <text x=\"-5\" y=\"{}\" text-anchor=\"end\" font-size=\"11\">{:.0}</text>\n",
+        max, top, min
+    ));
+    let offset = top + 30.0;
+    svg.push_str(&format!(
+        "<g transform=\"translate(0,{})\"><polygon fill=\"#e57373\" fill-opacity=\"0.6\" points=\"0,0 {} {:.1},0\"/>\
+         <text x=\"-5\" y=\"5\" text-anchor=\"end\" font-size=\"11\">0%</text>\
+         <text x=\"-5\" y=\"{}\" text-anchor=\"end\" font-size=\"11\">{:.1}%</text></g>\n",
+        offset,
+        svg_points(&drawdown, width, bottom, deepest, 0.0),
+        width,
+        bottom,
+        deepest * 100.0
+    ));
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Return of every calendar month, from the last equity of the month before
+fn monthly_returns(times: &[DateTime<Utc>], equity: &[f64]) -> Vec<(i32, u32, f64)> {
+    let mut months: Vec<(i32, u32, f64)> = Vec::new();
+    let mut start = equity[0];
+    let mut last = equity[0];
+    for (time, &value) in times.iter().zip(equity) {
+        let month = (time.year(), time.month());
+        match months.last_mut() {
+            Some(current) if (current.0, current.1) == month => current.2 = value / start - 1.0,
+            _ => {
+                start = last;
+                months.push((month.0, month.1, value / start - 1.0));
+            }
+        }
+        last = value;
+    }
+    months
+}
+
+/// Years down, months across, green for gains and red for losses
+fn monthly_heatmap(months: &[(i32, u32, f64)]) -> String {
+    const NAMES: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let scale = months.iter().map(|m| m.2.abs()).fold(0.0, f64::max).max(1e-9);
+    let cell = |r: f64| {
+        let (red, green) = if r >= 0.0 { (46, 125) } else { (198, 40) };
+        format!(
+            "<td style=\"background: rgba({}, {}, 50, {:.2})\">{:.1}%</td>",
+            red,
+            green,
+            (0.15 + 0.75 * r.abs() / scale).min(0.9),
+            r * 100.0
+        )
+    };
+
+    let mut html = String::from("<table class=\"heatmap\">\n<tr><th>Year</th>");
+    for name in NAMES {
+        html.push_str(&format!("<th>{}</th>", name));
+    }
+    html.push_str("<th>Year</th></tr>\n");
+    let mut years: Vec<i32> = months.iter().map(|m| m.0).collect();
+    years.dedup();
+    for year in years {
+        html.push_str(&format!("<tr><th>{}</th>", year));
+        let mut compounded = 1.0;
+        for month in 1..=12 {
+            match months.iter().find(|m| m.0 == year && m.1 == month) {
+                Some(m) => {
+                    compounded *= 1.0 + m.2;
+                    html.push_str(&cell(m.2));
+                }
+                None => html.push_str("<td></td>"),
+            }
+        }
+        html.push_str(&cell(compounded - 1.0));
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>\n");
+    html
+}
+
+/// One dot per trade: how far it went against (x) and for (y) the position
+fn svg_mae_mfe(trades: &[Trade]) -> String {
+    let points: Vec<(f64, f64, &Trade)> = trades
+        .iter()
+        .filter_map(|t| Some((t.mae?, t.mfe?, t)))
+        .collect();
+    if points.is_empty() {
+        return "<p class=\"note\">No excursions recorded for these trades.</p>\n".to_string();
+    }
+    let size = 360.0;
+    let max_x = points.iter().map(|p| p.0).fold(0.0, f64::max).max(1e-9);
+    let max_y = points.iter().map(|p| p.1).fold(0.0, f64::max).max(1e-9);
+
+    let mut svg = format!(
+        "<svg viewBox=\"-50 -10 {} {}\" width=\"480\">\n\
+         <line x1=\"0\" y1=\"{s}\" x2=\"{s}\" y2=\"{s}\" stroke=\"#999\"/>\
+         <line x1=\"0\" y1=\"0\" x2=\"0\" y2=\"{s}\" stroke=\"#999\"/>\n\
+         <text x=\"{}\" y=\"{}\" text-anchor=\"middle\" font-size=\"11\">MAE (max {:.2}%)</text>\
+         <text x=\"-35\" y=\"{}\" font-size=\"11\" transform=\"rotate(-90 -35 {})\" text-anchor=\"middle\">MFE (max {:.2}%)</text>\n",
+        size + 70.0,
+        size + 50.0,
+        size / 2.0,
+        size + 30.0,
+        max_x * 100.0,
+        size / 2.0,
+        size / 2.0,
+        max_y * 100.0,
+        s = size
+    );
+    for (mae, mfe, trade) in points {
+        svg.push_str(&format!(
+            "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"4\" fill=\"{}\" fill-opacity=\"0.7\">\
+             <title>{} {}: P&amp;L {:.2}, MAE {:.2}%, MFE {:.2}%</title></circle>\n",
+            mae / max_x * size,
+            size - mfe / max_y * size,
+            if trade.pnl > 0.0 { "#2e7d32" } else { "#c62828" },
+            escape_html(&trade.entry_time),
+            escape_html(&trade.side),
+            trade.pnl,
+            mae * 100.0,
+            mfe * 100.0
+        ));
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Commit of the working tree, suffixed with `-dirty` when it has
+/// uncommitted changes; "unknown" outside a git checkout
+fn git_commit() -> String {
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .args(args)
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+    };
+    match git(&["rev-parse", "HEAD"]) {
+        Some(commit) if git(&["status", "--porcelain"]).is_some_and(|s| !s.is_empty()) => {
+            format!("{}-dirty", commit)
+        }
+        Some(commit) => commit,
+        None => "unknown".to_string(),
+    }
+}
+
+/// SHA-256 of a file's contents, in hex
+fn file_sha256(path: &Path) -> std::io::Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// How the engine charged for trading
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct EngineSettings {
+    initial_capital: f64,
+    commission_model: String,
+    commission_rate: f64,
+    slippage_model: String,
+    slippage: f64,
+}
+
+/// Everything needed to explain a backtest result later
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct RunRecord {
+    run_id: String,
+    created_at: DateTime<Utc>,
+    strategy_name: String,
+    git_commit: String,
+    data_file: String,
+    data_hash: String,
+    seed: u64,
+    parameters: serde_json::Value,
+    engine: EngineSettings,
+    metrics: serde_json::Value,
+}
+
+impl RunRecord {
+    /// Provenance of `report`, run on `data_file` with `seed` and `engine`
+    fn from_report(
+        report: &BacktestReport,
+        data_file: &Path,
+        seed: u64,
+        engine: EngineSettings,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        // Benchmark-relative metrics sit next to the others so diffs show them
+        let mut metrics = serde_json::to_value(&report.metrics)?;
+        if let (Some(fields), Some(relative)) = (metrics.as_object_mut(), report.relative_metrics()) {
+            if let serde_json::Value::Object(extra) = serde_json::to_value(relative)? {
+                fields.extend(extra);
+            }
+        }
+        Ok(RunRecord {
+            run_id: report.metadata.test_id.clone(),
+            created_at: report.metadata.timestamp,
+            strategy_name: report.metadata.strategy_name.clone(),
+            git_commit: report.metadata.code_version.clone(),
+            data_file: data_file.display().to_string(),
+            data_hash: file_sha256(data_file)?,
+            seed,
+            parameters: serde_json::to_value(&report.parameters)?,
+            engine,
+            metrics,
+        })
+    }
+
+    /// Every input of the run as flat `name -> value` pairs
+    fn inputs(&self) -> BTreeMap<String, String> {
+        let mut inputs = BTreeMap::new();
+        inputs.insert("strategy_name".to_string(), self.strategy_name.clone());
+        inputs.insert("git_commit".to_string(), self.git_commit.clone());
+        inputs.insert("data_file".to_string(), self.data_file.clone());
+        inputs.insert("data_hash".to_string(), self.data_hash.clone());
+        inputs.insert("seed".to_string(), self.seed.to_string());
+        flatten_json("parameters", &self.parameters, &mut inputs);
+        if let Ok(engine) = serde_json::to_value(&self.engine) {
+            flatten_json("engine", &engine, &mut inputs);
+        }
+        inputs
+    }
+}
+
+fn flatten_json(prefix: &str, value: &serde_json::Value, out: &mut BTreeMap<String, String>) {
+    match value {
+        serde_json::Value::Object(fields) => {
+            for (name, field) in fields {
+                flatten_json(&format!("{}.{}", prefix, name), field, out);
+            }
+        }
+        serde_json::Value::String(text) => {
+            out.insert(prefix.to_string(), text.clone());
+        }
+        other => {
+            out.insert(prefix.to_string(), other.to_string());
+        }
+    }
+}
+
+/// One input that differs between two runs; `None` where a run lacks it
+#[derive(Debug, Clone, PartialEq)]
+struct InputChange {
+    name: String,
+    before: Option<String>,
+    after: Option<String>,
+}
+
+/// One metric of either run; `None` where a run does not report it
+#[derive(Debug, Clone, PartialEq)]
+struct MetricDelta {
+    name: String,
+    before: Option<f64>,
+    after: Option<f64>,
+}
+
+impl MetricDelta {
+    /// Change in value, when both runs report the metric
+    fn delta(&self) -> Option<f64> {
+        Some(self.after? - self.before?)
+    }
+
+    fn changed(&self) -> bool {
+        self.delta().map_or(self.before.is_some() || self.after.is_some(), |d| d.abs() > 1e-12)
+    }
+}
+
+/// What changed from one run to another
+#[derive(Debug, Clone, PartialEq)]
+struct RunDiff {
+    before: String,
+    after: String,
+    changes: Vec<InputChange>,
+    metrics: Vec<MetricDelta>,
+}
+
+impl RunDiff {
+    fn between(before: &RunRecord, after: &RunRecord) -> Self {
+        let (old, new) = (before.inputs(), after.inputs());
+        let mut names: Vec<&String> = old.keys().chain(new.keys()).collect();
+        names.sort();
+        names.dedup();
+        let changes = names
+            .into_iter()
+            .filter(|name| old.get(*name) != new.get(*name))
+            .map(|name| InputChange {
+                name: name.clone(),
+                before: old.get(name).cloned(),
+                after: new.get(name).cloned(),
+            })
+            .collect();
+
+        // Every metric of either run, so added and removed ones show up too
+        let numbers = |record: &RunRecord| -> BTreeMap<String, f64> {
+            record
+                .metrics
+                .as_object()
+                .into_iter()
+                .flatten()
+                .filter_map(|(name, value)| Some((name.clone(), value.as_f64()?)))
+                .collect()
+        };
+        let (old, new) = (numbers(before), numbers(after));
+        let mut names: Vec<&String> = old.keys().chain(new.keys()).collect();
+        names.sort();
+        names.dedup();
+        let metrics = names
+            .into_iter()
+            .map(|name| MetricDelta {
+                name: name.clone(),
+                before: old.get(name).copied(),
+                after: new.get(name).copied(),
+            })
+            .collect();
+
+        RunDiff {
+            before: before.run_id.clone(),
+            after: after.run_id.clone(),
+            changes,
+            metrics,
+        }
+    }
+
+    fn metrics_changed(&self) -> bool {
+        self.metrics.iter().any(MetricDelta::changed)
+    }
+
+    fn print(&self) {
+        println!("\n=== Run Diff: {} -> {} ===", self.before, self.after);
+        if self.changes.is_empty() {
+            println!("Inputs: identical");
+        } else {
+            println!("Inputs:");
+            let show = |v: &Option<String>| v.clone().unwrap_or_else(|| "(none)".to_string());
+            for change in &self.changes {
+                println!("  {:<28} {} -> {}", change.name, show(&change.before), show(&change.after));
+            }
+        }
+        println!("Metrics:");
+        for m in self.metrics.iter().filter(|m| m.changed()) {
+            match (m.before, m.after) {
+                (Some(b), Some(a)) => println!("  {:<28} {:>10.4} -> {:>10.4} ({:+.4})", m.name, b, a, a - b),
+                (None, Some(a)) => println!("  {:<28} {:>10} -> {:>10.4} (added)", m.name, "-", a),
+                (Some(b), None) => println!("  {:<28} {:>10.4} -> {:>10} (removed)", m.name, b, "-"),
+                (None, None) => {}
+            }
+        }
+        if !self.metrics_changed() {
+            println!("  unchanged");
+        } else if self.changes.is_empty() {
+            println!("⚠️  Same inputs, different results: the run is not deterministic");
+        }
+    }
+}
+
+/// Local SQLite database of backtest runs
+struct RunRegistry {
+    conn: Connection,
+}
+
+impl RunRegistry {
+    fn open(path: &Path) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS runs (
+                run_id        TEXT PRIMARY KEY,
+                created_at    TEXT NOT NULL,
+                strategy_name TEXT NOT NULL,
+                git_commit    TEXT NOT NULL,
+                data_file     TEXT NOT NULL,
+                data_hash     TEXT NOT NULL,
+                seed          INTEGER NOT NULL,
+                parameters    TEXT NOT NULL,
+                engine        TEXT NOT NULL,
+                metrics       TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS runs_by_strategy ON runs (strategy_name, created_at);",
+        )?;
+        Ok(Self { conn })
+    }
+
+    fn record(&self, run: &RunRecord) -> Result<(), Box<dyn std::error::Error>> {
+        self.conn.execute(
+            "INSERT INTO runs (run_id, created_at, strategy_name, git_commit, data_file,
+                               data_hash, seed, parameters, engine, metrics)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                run.run_id,
+                run.created_at.to_rfc3339(),
+                run.strategy_name,
+                run.git_commit,
+                run.data_file,
+                run.data_hash,
+                // SQLite integers are signed; the bits round-trip
+                run.seed as i64,
+                run.parameters.to_string(),
+                serde_json::to_string(&run.engine)?,
+                run.metrics.to_string(),
+            ],
+        )?;
+        println!("✅ Run {} recorded", run.run_id);
+        Ok(())
+    }
+
+    fn get(&self, run_id: &str) -> Result<Option<RunRecord>, Box<dyn std::error::Error>> {
+        let row = self
+            .conn
+            .query_row(
+                &format!("SELECT {} FROM runs WHERE run_id = ?1", RUN_COLUMNS),
+                params![run_id],
+                RawRun::from_row,
+            )
+            .optional()?;
+        row.map(RawRun::parse).transpose()
+    }
+
+    /// Runs of `strategy_name`, newest first
+    fn history(&self, strategy_name: &str) -> Result<Vec<RunRecord>, Box<dyn std::error::Error>> {
+        let mut statement = self.conn.prepare(&format!(
+            "SELECT {} FROM runs WHERE strategy_name = ?1 ORDER BY created_at DESC, rowid DESC",
+            RUN_COLUMNS
+        ))?;
+        let rows = statement.query_map(params![strategy_name], RawRun::from_row)?;
+        let runs = rows.map(|row| row?.parse()).collect();
+        runs
+    }
+
+    fn diff(&self, before: &str, after: &str) -> Result<RunDiff, Box<dyn std::error::Error>> {
+        let find = |id: &str| -> Result<RunRecord, Box<dyn std::error::Error>> {
+            self.get(id)?.ok_or_else(|| format!("no run {}", id).into())
+        };
+        let (before, after) = (find(before)?, find(after)?);
+        Ok(RunDiff::between(&before, &after))
+    }
+}
+
+const RUN_COLUMNS: &str = "run_id, created_at, strategy_name, git_commit, data_file, data_hash, \
+                           seed, parameters, engine, metrics";
+
+/// A row as stored, before the JSON columns are parsed
+struct RawRun {
+    run_id: String,
+    created_at: String,
+    strategy_name: String,
+    git_commit: String,
+    data_file: String,
+    data_hash: String,
+    seed: i64,
+    parameters: String,
+    engine: String,
+    metrics: String,
+}
+
+impl RawRun {
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        Ok(RawRun {
+            run_id: row.get(0)?,
+            created_at: row.get(1)?,
+            strategy_name: row.get(2)?,
+            git_commit: row.get(3)?,
+            data_file: row.get(4)?,
+            data_hash: row.get(5)?,
+            seed: row.get(6)?,
+            parameters: row.get(7)?,
+            engine: row.get(8)?,
+            metrics: row.get(9)?,
+        })
+    }
+
+    fn parse(self) -> Result<RunRecord, Box<dyn std::error::Error>> {
+        Ok(RunRecord {
+            run_id: self.run_id,
+            created_at: DateTime::parse_from_rfc3339(&self.created_at)?.with_timezone(&Utc),
+            strategy_name: self.strategy_name,
+            git_commit: self.git_commit,
+            data_file: self.data_file,
+            data_hash: self.data_hash,
+            seed: self.seed as u64,
+            parameters: serde_json::from_str(&self.parameters)?,
+            engine: serde_json::from_str(&self.engine)?,
+            metrics: serde_json::from_str(&self.metrics)?,
+        })
+    }
+}
+
+fn main() {
+    println!("Testing Chapter 303 code examples...\n");
+
+    // Test 1: Metadata
+    let metadata = BacktestMetadata::new(
+        "MA Crossover v2.1",
+        "Test with new stop-loss parameters"
+    );
+    metadata.print();
+
+    // Test 2: Parameters
+    let params = StrategyParameters {
+        ma_short_period: 10,
+        ma_long_period: 50,
+        stop_loss_pct: 0.02,
+        take_profit_pct: 0.05,
+        max_position_size: 0.10,
+    };
+    params.print();
+
+    // Test 3: Metrics
+    let metrics = PerformanceMetrics {
+        total_return: 0.45,
+        annual_return: 0.18,
+        sharpe_ratio: 1.6,
+        max_drawdown: -0.12,
+        win_rate: 0.58,
+        profit_factor: 1.8,
+        total_trades: 150,
+        winning_trades: 87,
+        losing_trades: 63,
+        avg_profit_per_trade: 0.003,
+        max_profit: 0.08,
+        max_loss: -0.05,
+    };
+    metrics.print();
+    println!("\nGrade: {}", metrics.grade());
+
+    // Test 4: Full report
+    let trades = vec![
+        Trade {
+            entry_time: "2024-01-15 10:30:00".to_string(),
+            exit_time: "2024-01-15 14:20:00".to_string(),
+            symbol: "BTC/USDT".to_string(),
+            side: "LONG".to_string(),
+            entry_price: 42000.0,
+            exit_price: 43500.0,
+            quantity: 0.1,
+            pnl: 150.0,
+            pnl_pct: 0.0357,
+            mae: Some(0.008),
+            mfe: Some(0.041),
+        },
+        Trade {
+            entry_time: "2024-02-20 09:00:00".to_string(),
+            exit_time: "2024-02-21 16:45:00".to_string(),
+            symbol: "BTC/USDT".to_string(),
+            side: "SHORT".to_string(),
+            entry_price: 51000.0,
+            exit_price: 51500.0,
+            quantity: 0.1,
+            pnl: -50.0,
+            pnl_pct: -0.0098,
+            mae: Some(0.015),
+            mfe: Some(0.004),
+        },
+    ];
+
+    let equity_curve = vec![10000.0, 10150.0, 10300.0, 10250.0, 10500.0];
+    let equity_times = ["2024-01-15", "2024-01-31", "2024-02-15", "2024-02-29", "2024-03-15"]
+        .iter()
+        .map(|day| format!("{}T00:00:00Z", day).parse::<DateTime<Utc>>().unwrap())
+        .collect();
+
+    let report = BacktestReport::new(
+        metadata,
+        params.clone(),
+        metrics,
+        trades,
+        equity_curve
+    )
+    .with_equity_times(equity_times)
+    .with_benchmark("BTC buy & hold", &[42000.0, 42840.0, 43260.0, 42840.0, 44100.0])
+    .with_risk_free_rate(0.02);
+
+    report.print_summary();
+
+    // Benchmark +2%, +0.98%, -0.97%, +2.94%: 5% in all, as the strategy
+    let relative = report.relative_metrics().unwrap();
+    assert!((relative.benchmark_return - 0.05).abs() < 1e-9);
+    // Alpha regresses excess returns: against a zero rate, beta is the same and alpha
+    // differs by the per-period risk-free return times (1 - beta), annualized
+    let config = report.metrics_config();
+    let rf = config.risk_free_per_period();
+    let zero_rate = {
+        let mut report = serde_json::from_value::<BacktestReport>(serde_json::to_value(&report).unwrap()).unwrap();
+        report.risk_free_rate = 0.0;
+        report.relative_metrics().unwrap()
+    };
+    assert!((zero_rate.beta - relative.beta).abs() < 1e-9);
+    let shift = (zero_rate.alpha - relative.alpha) / config.periods_per_year;
+    assert!((shift - rf * (1.0 - relative.beta)).abs() < 1e-12);
+    assert_eq!(report.benchmark.as_ref().unwrap().equity[0], 10000.0);
+    assert!(relative.beta > 0.0 && relative.correlation > 0.0);
+    assert!(report.generate_markdown_report().contains("## Versus BTC buy & hold"));
+
+    // Test 5: HTML report, rendered from the saved JSON
+    let dir = std::env::temp_dir();
+    let json_path = dir.join("backtest_report.json");
+    let json_path = json_path.to_str().unwrap();
+    report.save_to_file(json_path).unwrap();
+    let saved = BacktestReport::load_from_file(json_path).unwrap();
+    let html = saved.generate_html_report();
+    assert_eq!(html, report.generate_html_report());
+    for section in ["Metadata", "Parameters", "Metrics", "Benchmark", "Equity and Drawdown", "Monthly Returns", "MAE / MFE", "Trades"] {
+        assert!(html.contains(&format!("<h2>{}</h2>", section)), "missing {}", section);
+    }
+    assert!(html.contains("ma_short_period"));
+    // Trade times sort by timestamp, not by the year parseFloat would read
+    assert!(html.contains("<td data-value=\"1705314600\">2024-01-15 10:30:00</td>"));
+    // Jan 10000 -> 10150, Feb 10150 -> 10250, Mar 10250 -> 10500, 5% over the year
+    for month in [">1.5%<", ">1.0%<", ">2.4%<", ">5.0%<"] {
+        assert!(html.contains(month), "missing {}", month);
+    }
+    assert_eq!(html.matches("<circle").count(), 2);
+    assert!(!html.contains("http"), "the report must not load anything");
+    assert_eq!(html.matches("<polyline").count(), 2);
+    saved.save_html_report(dir.join("backtest_report.html").to_str().unwrap()).unwrap();
+
+    // Reports saved before timestamps and excursions were recorded still render
+    let mut legacy: serde_json::Value = serde_json::to_value(&report).unwrap();
+    legacy.as_object_mut().unwrap().remove("equity_times");
+    legacy.as_object_mut().unwrap().remove("benchmark");
+    for trade in legacy["trades"].as_array_mut().unwrap() {
+        trade.as_object_mut().unwrap().remove("mae");
+        trade.as_object_mut().unwrap().remove("mfe");
+    }
+    let legacy: BacktestReport = serde_json::from_value(legacy).unwrap();
+    let html = legacy.generate_html_report();
+    assert!(html.contains("No equity timestamps") && html.contains("No excursions recorded"));
+    assert!(!html.contains("<h2>Benchmark</h2>"));
+
+    // Test 6: Run registry
+    let db_path = dir.join("backtest_runs.db");
+    let _ = std::fs::remove_file(&db_path);
+    let registry = RunRegistry::open(&db_path).unwrap();
+    let data_path = dir.join("btc_usdt_1h.csv");
+    let candles = "timestamp,open,high,low,close,volume\n1705312800,42000,42100,41900,42050,12.5\n";
+    std::fs::write(&data_path, candles).unwrap();
+    let engine = EngineSettings {
+        initial_capital: 10_000.0,
+        commission_model: "percent".to_string(),
+        commission_rate: 0.001,
+        slippage_model: "fixed_bps".to_string(),
+        slippage: 5.0,
+    };
+
+    let first = RunRecord::from_report(&report, &data_path, 42, engine.clone()).unwrap();
+    assert_eq!(first.metrics["beta"].as_f64(), Some(relative.beta));
+    println!("\nRun {} at commit {}", first.run_id, first.git_commit);
+    assert_ne!(first.run_id, "test-uuid-12345");
+    registry.record(&first).unwrap();
+    assert_eq!(registry.get(&first.run_id).unwrap(), Some(first.clone()));
+    assert!(registry.record(&first).is_err(), "run ids are unique");
+
+    // A wider stop and costlier fills: the diff names exactly those two inputs
+    let mut second = first.clone();
+    second.run_id = uuid::Uuid::new_v4().to_string();
+    second.parameters["stop_loss_pct"] = serde_json::json!(0.03);
+    second.engine.slippage = 10.0;
+    second.metrics["sharpe_ratio"] = serde_json::json!(1.2);
+    registry.record(&second).unwrap();
+    let diff = registry.diff(&first.run_id, &second.run_id).unwrap();
+    diff.print();
+    let changed: Vec<&str> = diff.changes.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(changed, vec!["engine.slippage", "parameters.stop_loss_pct"]);
+    let sharpe = diff.metrics.iter().find(|m| m.name == "sharpe_ratio").unwrap();
+    assert!((sharpe.delta().unwrap() + 0.4).abs() < 1e-9);
+    let changed: Vec<&str> = diff.metrics.iter().filter(|m| m.changed()).map(|m| m.name.as_str()).collect();
+    assert_eq!(changed, vec!["sharpe_ratio"]);
+
+    // Same code and parameters, but the data file was edited in place
+    std::fs::write(&data_path, format!("{}1705316400,42050,42200,42000,42150,9.8\n", candles)).unwrap();
+    let mut third = RunRecord::from_report(&report, &data_path, 42, engine).unwrap();
+    third.run_id = uuid::Uuid::new_v4().to_string();
+    registry.record(&third).unwrap();
+    let diff = registry.diff(&first.run_id, &third.run_id).unwrap();
+    diff.print();
+    assert_eq!(diff.changes.len(), 1);
+    assert_eq!(diff.changes[0].name, "data_hash");
+    assert!(!diff.metrics_changed());
+
+    // A run without a benchmark: its relative metrics show up as removed, not skipped
+    let mut fourth = first.clone();
+    fourth.run_id = uuid::Uuid::new_v4().to_string();
+    fourth.metrics.as_object_mut().unwrap().remove("beta");
+    registry.record(&fourth).unwrap();
+    let diff = registry.diff(&first.run_id, &fourth.run_id).unwrap();
+    diff.print();
+    assert!(diff.metrics_changed());
+    let beta = diff.metrics.iter().find(|m| m.name == "beta").unwrap();
+    assert_eq!((beta.before, beta.after, beta.delta()), (Some(relative.beta), None, None));
+    let diff = registry.diff(&fourth.run_id, &first.run_id).unwrap();
+    assert!(diff.metrics.iter().any(|m| m.name == "beta" && m.before.is_none() && m.after.is_some()));
+
+    let unchanged = registry.diff(&first.run_id, &first.run_id).unwrap();
+    assert!(unchanged.changes.is_empty() && !unchanged.metrics_changed());
+    assert!(registry.diff(&first.run_id, "missing").is_err());
+    let history: Vec<String> = registry
+        .history("MA Crossover v2.1")
+        .unwrap()
+        .into_iter()
+        .map(|run| run.run_id)
+        .collect();
+    assert_eq!(history, vec![fourth.run_id, third.run_id, second.run_id, first.run_id]);
+
+    println!("\n✅ All code examples compiled and ran successfully!");
+}
@@ -93,10 +93,15 @@ impl RiskMetrics {
             losses.iter().map(|t| t.pnl()).sum::<f64>() / losses.len() as f64
         };
 
-        let profit_factor = if avg_loss == 0.0 {
+        // Gross profit over gross loss, not average win over average loss
+        let gross_profit: f64 = wins.iter().map(|t| t.pnl()).sum();
+        let gross_loss: f64 = losses.iter().map(|t| t.pnl().abs()).sum();
+        let profit_factor = if gross_loss > 0.0 {
+            gross_profit / gross_loss
+        } else if gross_profit > 0.0 {
             f64::INFINITY
         } else {
-            avg_win / avg_loss.abs()
+            0.0
         };
 
         let loss_rate = (losses.len() as f64 / total) * 100.0;
@@ -0,0 +1,1012 @@
+//! Event-driven backtest engine for a single instrument
+//!
+//! Each bar is processed in a fixed order:
+//!
+//! 1. market orders decided on the previous close fill at this bar's open;
+//! 2. resting limit/stop orders and the position's bracket are checked
+//!    against the bar, walking its prices in the configured [`IntrabarPath`];
+//! 3. the position is marked to the close and `on_bar` is called.
+//!
+//! Intents returned by `on_bar` can therefore only affect later bars, so a
+//! strategy never trades at a price it used to make its decision.
+//!
+//! Positions are signed, so shorts are marked to market like longs. Orders
+//! that add exposure must be covered by initial margin, and a position whose
+//! equity falls below maintenance margin is liquidated inside the bar.
+//!
+//! Profit and loss follow the configured [`ContractSpec`]. For perpetual
+//! swaps, [`Backtester::run_perpetual`] takes mark prices, which drive
+//! unrealized P&L and liquidation, and a funding schedule.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::contracts::{ContractSpec, FundingSchedule, PerpetualData};
+use crate::data::{Instrument, OHLCV};
+use crate::metrics::{Metrics, MetricsConfig};
+use crate::params::ParamSet;
+use crate::strategy::{
+    Account, Bracket, Fill, MarketData, OrderIntent, OrderType, Position, Side, Strategy,
+    StrategyContext,
+};
+
+/// Order in which prices are assumed to be visited inside a bar
+///
+/// Bars only record four prices, so when a bar touches several order levels
+/// the engine has to guess which came first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum IntrabarPath {
+    /// Open, high, low, close
+    #[default]
+    Ohlc,
+    /// Open, low, high, close
+    Olhc,
+    /// Move against the open position first, so a bar touching both the
+    /// stop-loss and the take-profit always fills the stop-loss
+    WorstCase,
+}
+
+impl IntrabarPath {
+    fn points(self, bar: &OHLCV, position: &Position) -> [f64; 4] {
+        let low_first = match self {
+            IntrabarPath::Ohlc => false,
+            IntrabarPath::Olhc => true,
+            IntrabarPath::WorstCase => position.is_long(),
+        };
+        if low_first {
+            [bar.open, bar.low, bar.high, bar.close]
+        } else {
+            [bar.open, bar.high, bar.low, bar.close]
+        }
+    }
+}
+
+impl fmt::Display for IntrabarPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntrabarPath::Ohlc => write!(f, "OHLC"),
+            IntrabarPath::Olhc => write!(f, "OLHC"),
+            IntrabarPath::WorstCase => write!(f, "worst-case"),
+        }
+    }
+}
+
+/// Margin requirements and short financing
+///
+/// Margins are fractions of position notional: an initial margin of 0.2
+/// allows 5x leverage.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MarginConfig {
+    /// Equity required to add exposure
+    pub initial_margin: f64,
+    /// Equity below which the position is liquidated
+    pub maintenance_margin: f64,
+    /// Annual fee for borrowing the asset, charged on short notional
+    pub borrow_rate: f64,
+}
+
+impl MarginConfig {
+    /// Unleveraged account: exposure limited to equity, liquidated only at zero equity
+    pub fn cash() -> Self {
+        MarginConfig {
+            initial_margin: 1.0,
+            maintenance_margin: 0.0,
+            borrow_rate: 0.0,
+        }
+    }
+
+    pub fn leveraged(leverage: f64, maintenance_margin: f64) -> Self {
+        MarginConfig {
+            initial_margin: 1.0 / leverage,
+            maintenance_margin,
+            borrow_rate: 0.0,
+        }
+    }
+
+    pub fn with_borrow_rate(mut self, borrow_rate: f64) -> Self {
+        self.borrow_rate = borrow_rate;
+        self
+    }
+
+    pub fn max_leverage(&self) -> f64 {
+        1.0 / self.initial_margin
+    }
+
+    /// Price at which equity equals the maintenance margin of `position`
+    pub fn liquidation_price(
+        &self,
+        contract: &ContractSpec,
+        position: &Position,
+        cash: f64,
+    ) -> Option<f64> {
+        contract.liquidation_price(position, cash, self.maintenance_margin)
+    }
+}
+
+impl Default for MarginConfig {
+    fn default() -> Self {
+        MarginConfig::cash()
+    }
+}
+
+/// Execution settings of a backtest
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BacktestConfig {
+    pub initial_capital: f64,
+    /// Commission as a fraction of traded notional
+    pub commission_rate: f64,
+    /// Adverse price move applied to market and stop fills, as a fraction of price
+    pub slippage: f64,
+    pub intrabar_path: IntrabarPath,
+    pub margin: MarginConfig,
+    pub contract: ContractSpec,
+    /// Bars in a year, used to accrue annual financing rates per bar
+    pub bars_per_year: f64,
+}
+
+impl BacktestConfig {
+    pub fn new(initial_capital: f64) -> Self {
+        BacktestConfig {
+            initial_capital,
+            commission_rate: 0.0,
+            slippage: 0.0,
+            intrabar_path: IntrabarPath::default(),
+            margin: MarginConfig::default(),
+            contract: ContractSpec::default(),
+            bars_per_year: 252.0,
+        }
+    }
+
+    pub fn with_commission(mut self, commission_rate: f64) -> Self {
+        self.commission_rate = commission_rate;
+        self
+    }
+
+    pub fn with_slippage(mut self, slippage: f64) -> Self {
+        self.slippage = slippage;
+        self
+    }
+
+    pub fn with_intrabar_path(mut self, intrabar_path: IntrabarPath) -> Self {
+        self.intrabar_path = intrabar_path;
+        self
+    }
+
+    pub fn with_margin(mut self, margin: MarginConfig) -> Self {
+        self.margin = margin;
+        self
+    }
+
+    pub fn with_contract(mut self, contract: ContractSpec) -> Self {
+        self.contract = contract;
+        self
+    }
+
+    pub fn with_bars_per_year(mut self, bars_per_year: f64) -> Self {
+        self.bars_per_year = bars_per_year;
+        self
+    }
+}
+
+impl Default for BacktestConfig {
+    fn default() -> Self {
+        BacktestConfig::new(10_000.0)
+    }
+}
+
+/// Why a trade was closed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ExitReason {
+    /// An order emitted by the strategy
+    Signal,
+    StopLoss,
+    TakeProfit,
+    /// Equity fell below maintenance margin
+    Liquidation,
+    /// Still open after the last bar
+    EndOfData,
+}
+
+impl fmt::Display for ExitReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExitReason::Signal => write!(f, "signal"),
+            ExitReason::StopLoss => write!(f, "stop-loss"),
+            ExitReason::TakeProfit => write!(f, "take-profit"),
+            ExitReason::Liquidation => write!(f, "liquidation"),
+            ExitReason::EndOfData => write!(f, "end of data"),
+        }
+    }
+}
+
+/// A round trip from flat to flat (or to a flip of direction)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Trade {
+    pub side: Side,
+    pub entry_bar: usize,
+    pub exit_bar: usize,
+    pub entry_time: i64,
+    pub exit_time: i64,
+    /// Average price of the opening fills
+    pub entry_price: f64,
+    /// Volume-weighted price of the closing fills
+    pub exit_price: f64,
+    /// Largest absolute quantity held during the trade
+    pub quantity: f64,
+    /// Notional of `quantity` at the entry price, in account currency
+    pub notional: f64,
+    /// Realized P&L net of commission and financing, plus funding
+    pub profit: f64,
+    pub commission: f64,
+    /// Borrow fees paid while the trade was open
+    pub financing: f64,
+    /// Funding received (positive) or paid (negative) while the trade was open
+    pub funding: f64,
+    pub exit_reason: ExitReason,
+}
+
+impl Trade {
+    pub fn bars_held(&self) -> usize {
+        self.exit_bar - self.entry_bar
+    }
+
+    /// Net profit relative to the entry notional, in percent
+    pub fn return_pct(&self) -> f64 {
+        if self.notional > 0.0 {
+            self.profit / self.notional * 100.0
+        } else {
+            0.0
+        }
+    }
+
+    /// Profit from price moves, net of commission
+    pub fn trading_pnl(&self) -> f64 {
+        self.profit - self.funding + self.financing
+    }
+}
+
+/// Mark-to-market equity at one bar's close
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EquityPoint {
+    pub timestamp: i64,
+    pub balance: f64,
+    /// Distance below the running peak, in percent
+    pub drawdown: f64,
+}
+
+/// Everything a backtest run produced
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BacktestResults {
+    pub strategy: String,
+    pub symbol: String,
+    pub parameters: ParamSet,
+    pub config: BacktestConfig,
+    pub trades: Vec<Trade>,
+    pub fills: Vec<Fill>,
+    pub equity_curve: Vec<EquityPoint>,
+    pub initial_balance: f64,
+    pub final_balance: f64,
+    /// Orders dropped because they needed more margin than was available
+    pub rejected_orders: usize,
+}
+
+impl BacktestResults {
+    pub fn total_return(&self) -> f64 {
+        ((self.final_balance - self.initial_balance) / self.initial_balance) * 100.0
+    }
+
+    pub fn winning_trades(&self) -> usize {
+        self.trades.iter().filter(|t| t.profit > 0.0).count()
+    }
+
+    pub fn losing_trades(&self) -> usize {
+        self.trades.iter().filter(|t| t.profit < 0.0).count()
+    }
+
+    pub fn win_rate(&self) -> f64 {
+        let total = self.trades.len() as f64;
+        if total == 0.0 {
+            return 0.0;
+        }
+        (self.winning_trades() as f64 / total) * 100.0
+    }
+
+    /// Simple return of every bar in the equity curve, as fractions
+    pub fn returns(&self) -> Vec<f64> {
+        let mut previous = self.initial_balance;
+        self.equity_curve
+            .iter()
+            .map(|point| {
+                let r = if previous != 0.0 {
+                    point.balance / previous - 1.0
+                } else {
+                    0.0
+                };
+                previous = point.balance;
+                r
+            })
+            .collect()
+    }
+
+    pub fn max_drawdown(&self) -> f64 {
+        self.equity_curve
+            .iter()
+            .map(|ep| ep.drawdown)
+            .fold(0.0, f64::max)
+    }
+
+    pub fn total_commission(&self) -> f64 {
+        self.fills.iter().map(|f| f.commission).sum()
+    }
+
+    pub fn total_financing(&self) -> f64 {
+        self.trades.iter().map(|t| t.financing).sum()
+    }
+
+    /// Profit from price moves net of commission, excluding funding and borrow fees
+    pub fn trading_pnl(&self) -> f64 {
+        self.trades.iter().map(Trade::trading_pnl).sum()
+    }
+
+    pub fn funding_pnl(&self) -> f64 {
+        self.trades.iter().map(|t| t.funding).sum()
+    }
+
+    pub fn liquidations(&self) -> usize {
+        self.trades
+            .iter()
+            .filter(|t| t.exit_reason == ExitReason::Liquidation)
+            .count()
+    }
+
+    /// Metrics of bar returns, annualized with the run's `bars_per_year`
+    pub fn metrics(&self) -> Metrics {
+        self.metrics_with(&MetricsConfig::per_bar(self.config.bars_per_year))
+    }
+
+    pub fn metrics_with(&self, config: &MetricsConfig) -> Metrics {
+        let curve: Vec<(i64, f64)> = self
+            .equity_curve
+            .iter()
+            .map(|p| (p.timestamp, p.balance))
+            .collect();
+        let pnls: Vec<f64> = self.trades.iter().map(|t| t.profit).collect();
+        Metrics::compute(self.initial_balance, &curve, &pnls, config)
+    }
+}
+
+/// Runs one strategy over one instrument
+#[derive(Debug, Clone, Default)]
+pub struct Backtester {
+    config: BacktestConfig,
+}
+
+impl Backtester {
+    pub fn new(config: BacktestConfig) -> Self {
+        Backtester { config }
+    }
+
+    pub fn config(&self) -> &BacktestConfig {
+        &self.config
+    }
+
+    pub fn run(&self, strategy: &mut dyn Strategy, instrument: &Instrument) -> BacktestResults {
+        self.run_inner(
+            strategy,
+            instrument,
+            &PerpetualData::default(),
+            &HashMap::new(),
+            0,
+        )
+    }
+
+    /// Runs with the first `warmup` bars shown to the strategy but not traded
+    ///
+    /// Intents returned during warm-up are discarded and the results (equity
+    /// curve, trades) start at bar `warmup`.
+    pub fn run_with_warmup(
+        &self,
+        strategy: &mut dyn Strategy,
+        instrument: &Instrument,
+        warmup: usize,
+    ) -> BacktestResults {
+        self.run_inner(
+            strategy,
+            instrument,
+            &PerpetualData::default(),
+            &HashMap::new(),
+            warmup,
+        )
+    }
+
+    /// Runs with mark prices and funding payments
+    pub fn run_perpetual(
+        &self,
+        strategy: &mut dyn Strategy,
+        instrument: &Instrument,
+        perpetual: &PerpetualData,
+    ) -> BacktestResults {
+        self.run_inner(strategy, instrument, perpetual, &HashMap::new(), 0)
+    }
+
+    /// Runs with slippage multiplied by `slippage_scale[timestamp]` on the
+    /// bars it lists, e.g. during a liquidity drought
+    pub fn run_with_slippage_scale(
+        &self,
+        strategy: &mut dyn Strategy,
+        instrument: &Instrument,
+        slippage_scale: &HashMap<i64, f64>,
+    ) -> BacktestResults {
+        self.run_inner(
+            strategy,
+            instrument,
+            &PerpetualData::default(),
+            slippage_scale,
+            0,
+        )
+    }
+
+    fn run_inner(
+        &self,
+        strategy: &mut dyn Strategy,
+        instrument: &Instrument,
+        perpetual: &PerpetualData,
+        slippage_scale: &HashMap<i64, f64>,
+        warmup: usize,
+    ) -> BacktestResults {
+        let mut run = Run::new(self.config);
+        run.funding = perpetual.funding.as_ref();
+        let marks: HashMap<i64, &OHLCV> = perpetual.mark.iter().map(|b| (b.timestamp, b)).collect();
+        let mut data = MarketData::new(&instrument.symbol);
+
+        strategy.on_start(&run.ctx);
+        for (t, bar) in instrument.data.iter().enumerate() {
+            run.ctx.bar_index = t;
+            if t < warmup {
+                data.add_bar(bar);
+                strategy.on_bar(&data, &run.ctx);
+                continue;
+            }
+            run.mark = marks.get(&bar.timestamp).copied().cloned();
+            run.slippage_scale = slippage_scale.get(&bar.timestamp).copied().unwrap_or(1.0);
+            run.step(strategy, &mut data, bar, t);
+        }
+
+        // Close whatever is still open at the last close; orders decided on
+        // the last bar never execute
+        if let Some(last) = instrument.data.last() {
+            let open_qty = run.ctx.position.quantity;
+            if open_qty != 0.0 {
+                let side = if open_qty > 0.0 {
+                    Side::Sell
+                } else {
+                    Side::Buy
+                };
+                let at = BarTime {
+                    index: instrument.data.len() - 1,
+                    timestamp: last.timestamp,
+                };
+                run.execute(
+                    strategy,
+                    side,
+                    open_qty.abs(),
+                    last.close,
+                    at,
+                    ExitReason::EndOfData,
+                );
+                run.mark_to_market(last);
+                if let Some(point) = run.equity_curve.pop() {
+                    run.record_equity(point.timestamp);
+                }
+            }
+        }
+        strategy.on_end(&run.ctx);
+        run.into_results(strategy, &instrument.symbol)
+    }
+}
+
+/// An order waiting in the book
+#[derive(Debug, Clone, PartialEq)]
+struct PendingOrder {
+    side: Side,
+    quantity: f64,
+    order_type: OrderType,
+    bracket: Option<Bracket>,
+}
+
+impl PendingOrder {
+    /// Price level and whether it triggers on an upward move
+    fn trigger(&self) -> Option<(f64, bool)> {
+        match (self.order_type, self.side) {
+            (OrderType::Market, _) => None,
+            (OrderType::Limit(price), side) => Some((price, side == Side::Sell)),
+            (OrderType::Stop(price), side) => Some((price, side == Side::Buy)),
+        }
+    }
+}
+
+/// Something that can fill inside a bar
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Trigger {
+    Resting(usize),
+    StopLoss,
+    TakeProfit,
+    Liquidation,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BarTime {
+    index: usize,
+    timestamp: i64,
+}
+
+/// Trade being built from fills
+#[derive(Debug, Clone)]
+struct OpenTrade {
+    trade: Trade,
+    exit_qty: f64,
+    realized: f64,
+}
+
+/// Mutable state of one backtest or paper-trading run
+#[derive(Debug, Clone)]
+pub(crate) struct Run<'a> {
+    pub(crate) config: BacktestConfig,
+    funding: Option<&'a FundingSchedule>,
+    /// Mark price bar of the current bar, when it differs from the traded prices
+    mark: Option<OHLCV>,
+    /// Multiplier on the configured slippage for the current bar
+    slippage_scale: f64,
+    last_timestamp: Option<i64>,
+    pub(crate) ctx: StrategyContext,
+    bracket: Option<Bracket>,
+    market_orders: Vec<PendingOrder>,
+    resting: Vec<PendingOrder>,
+    open_trade: Option<OpenTrade>,
+    trades: Vec<Trade>,
+    fills: Vec<Fill>,
+    equity_curve: Vec<EquityPoint>,
+    peak: f64,
+    rejected_orders: usize,
+}
+
+impl<'a> Run<'a> {
+    pub(crate) fn new(config: BacktestConfig) -> Self {
+        Run {
+            config,
+            funding: None,
+            mark: None,
+            slippage_scale: 1.0,
+            last_timestamp: None,
+            ctx: StrategyContext::new(Account::new(config.initial_capital)),
+            bracket: None,
+            market_orders: Vec::new(),
+            resting: Vec::new(),
+            open_trade: None,
+            trades: Vec::new(),
+            fills: Vec::new(),
+            equity_curve: Vec::new(),
+            peak: config.initial_capital,
+            rejected_orders: 0,
+        }
+    }
+
+    /// Trades one bar, then shows it to the strategy and queues its orders
+    pub(crate) fn step(
+        &mut self,
+        strategy: &mut dyn Strategy,
+        data: &mut MarketData,
+        bar: &OHLCV,
+        index: usize,
+    ) {
+        self.ctx.bar_index = index;
+        self.pay_funding(bar);
+        self.execute_bar(strategy, bar, index);
+        self.accrue_financing(bar);
+
+        data.add_bar(bar);
+        self.mark_to_market(bar);
+        self.record_equity(bar.timestamp);
+
+        let intents = strategy.on_bar(data, &self.ctx);
+        self.submit(intents);
+    }
+
+    pub(crate) fn into_results(self, strategy: &dyn Strategy, symbol: &str) -> BacktestResults {
+        BacktestResults {
+            strategy: strategy.name().to_string(),
+            symbol: symbol.to_string(),
+            parameters: strategy.parameters(),
+            config: self.config,
+            trades: self.trades,
+            fills: self.fills,
+            equity_curve: self.equity_curve,
+            initial_balance: self.config.initial_capital,
+            final_balance: self.ctx.account.equity,
+            rejected_orders: self.rejected_orders,
+        }
+    }
+
+    /// Market orders at the open, then resting orders and brackets along the bar's path
+    fn execute_bar(&mut self, strategy: &mut dyn Strategy, bar: &OHLCV, index: usize) {
+        let at = BarTime {
+            index,
+            timestamp: bar.timestamp,
+        };
+
+        for order in std::mem::take(&mut self.market_orders) {
+            let price = self.slipped(bar.open, order.side);
+            let filled = self.execute(
+                strategy,
+                order.side,
+                order.quantity,
+                price,
+                at,
+                ExitReason::Signal,
+            );
+            if let (true, Some(bracket)) = (filled, order.bracket) {
+                self.bracket = Some(bracket);
+            }
+        }
+
+        let points = self.config.intrabar_path.points(bar, &self.ctx.position);
+
+        // Levels already crossed by the open fill at the open
+        while let Some(trigger) = self.triggered_at(points[0]) {
+            self.fill_trigger(strategy, trigger, points[0], at);
+        }
+
+        let mut current = points[0];
+        for &target in &points[1..] {
+            while let Some((trigger, level)) = self.next_trigger(current, target) {
+                self.fill_trigger(strategy, trigger, level, at);
+                current = level;
+            }
+            current = target;
+        }
+
+        if let Some(mark) = self.mark.clone() {
+            self.check_mark_liquidation(strategy, &mark, at);
+        }
+    }
+
+    /// Liquidation when the mark price, rather than the traded price, reaches the level
+    fn check_mark_liquidation(&mut self, strategy: &mut dyn Strategy, mark: &OHLCV, at: BarTime) {
+        let position = self.ctx.position;
+        let Some(level) = self.config.margin.liquidation_price(
+            &self.config.contract,
+            &position,
+            self.ctx.account.cash,
+        ) else {
+            return;
+        };
+        let (at_open, touched) = if position.is_long() {
+            (mark.open <= level, mark.low <= level)
+        } else {
+            (mark.open >= level, mark.high >= level)
+        };
+        if at_open {
+            self.fill_trigger(strategy, Trigger::Liquidation, mark.open, at);
+        } else if touched {
+            self.fill_trigger(strategy, Trigger::Liquidation, level, at);
+        }
+    }
+
+    /// Funding payments due since the previous bar, on the position held over that time
+    fn pay_funding(&mut self, bar: &OHLCV) {
+        let previous = self.last_timestamp.replace(bar.timestamp);
+        let (Some(funding), Some(previous)) = (self.funding, previous) else {
+            return;
+        };
+        let quantity = self.ctx.position.quantity;
+        if quantity == 0.0 {
+            return;
+        }
+        let mark = self.mark.as_ref().map_or(bar.open, |m| m.open);
+        for time in funding.payments_between(previous, bar.timestamp) {
+            let Some(rate) = funding.rate_at(time) else {
+                continue;
+            };
+            let payment = funding.payment(&self.config.contract, quantity, mark, rate);
+            self.ctx.account.cash += payment;
+            if let Some(open) = self.open_trade.as_mut() {
+                open.trade.funding += payment;
+            }
+        }
+    }
+
+    fn mark_price(&self, traded: f64) -> f64 {
+        self.mark.as_ref().map_or(traded, |m| m.close)
+    }
+
+    fn triggers(&self) -> Vec<(Trigger, f64, bool)> {
+        let mut triggers = Vec::new();
+        let position = self.ctx.position;
+        let margin = &self.config.margin;
+        let liquidation =
+            margin.liquidation_price(&self.config.contract, &position, self.ctx.account.cash);
+        if let (Some(price), None) = (liquidation, &self.mark) {
+            // Without separate marks the traded price is the mark
+            triggers.push((Trigger::Liquidation, price, position.is_short()));
+        }
+        if let (Some(bracket), false) = (self.bracket, position.is_flat()) {
+            // Exits of a long trigger on the way down (stop) or up (target)
+            if let Some(stop) = bracket.stop_loss {
+                triggers.push((Trigger::StopLoss, stop, position.is_short()));
+            }
+            if let Some(target) = bracket.take_profit {
+                triggers.push((Trigger::TakeProfit, target, position.is_long()));
+            }
+        }
+        for (i, order) in self.resting.iter().enumerate() {
+            if let Some((level, up)) = order.trigger() {
+                triggers.push((Trigger::Resting(i), level, up));
+            }
+        }
+        triggers
+    }
+
+    fn triggered_at(&self, price: f64) -> Option<Trigger> {
+        self.triggers()
+            .into_iter()
+            .find(|&(_, level, up)| if up { price >= level } else { price <= level })
+            .map(|(trigger, _, _)| trigger)
+    }
+
+    /// First level touched while price moves from `from` to `to`
+    fn next_trigger(&self, from: f64, to: f64) -> Option<(Trigger, f64)> {
+        let rising = to > from;
+        self.triggers()
+            .into_iter()
+            .filter(|&(_, level, up)| {
+                up == rising && level >= from.min(to) && level <= from.max(to)
+            })
+            .min_by(|a, b| (a.1 - from).abs().total_cmp(&(b.1 - from).abs()))
+            .map(|(trigger, level, _)| (trigger, level))
+    }
+
+    fn fill_trigger(
+        &mut self,
+        strategy: &mut dyn Strategy,
+        trigger: Trigger,
+        price: f64,
+        at: BarTime,
+    ) {
+        match trigger {
+            Trigger::Resting(i) => {
+                let order = self.resting.remove(i);
+                let price = match order.order_type {
+                    OrderType::Stop(_) => self.slipped(price, order.side),
+                    _ => price,
+                };
+                let filled = self.execute(
+                    strategy,
+                    order.side,
+                    order.quantity,
+                    price,
+                    at,
+                    ExitReason::Signal,
+                );
+                if let (true, Some(bracket)) = (filled, order.bracket) {
+                    self.bracket = Some(bracket);
+                }
+            }
+            Trigger::StopLoss | Trigger::TakeProfit | Trigger::Liquidation => {
+                let quantity = self.ctx.position.quantity;
+                let side = if quantity > 0.0 {
+                    Side::Sell
+                } else {
+                    Side::Buy
+                };
+                let (price, reason) = match trigger {
+                    Trigger::StopLoss => (self.slipped(price, side), ExitReason::StopLoss),
+                    Trigger::TakeProfit => (price, ExitReason::TakeProfit),
+                    _ => (price, ExitReason::Liquidation),
+                };
+                self.execute(strategy, side, quantity.abs(), price, at, reason);
+            }
+        }
+    }
+
+    fn slipped(&self, price: f64, side: Side) -> f64 {
+        price * (1.0 + side.sign() * self.config.slippage * self.slippage_scale)
+    }
+
+    fn execute(
+        &mut self,
+        strategy: &mut dyn Strategy,
+        side: Side,
+        quantity: f64,
+        price: f64,
+        at: BarTime,
+        reason: ExitReason,
+    ) -> bool {
+        let contract = self.config.contract;
+        let price = contract.round_price(price);
+        let fill = Fill {
+            timestamp: at.timestamp,
+            side,
+            price,
+            quantity,
+            commission: contract.notional(quantity, price) * self.config.commission_rate,
+        };
+
+        let before = self.ctx.position.quantity;
+        let after = before + side.sign() * quantity;
+        if after.abs() > before.abs() {
+            // Adding exposure: the resulting position must be covered by initial margin
+            let equity = self.ctx.account.cash + contract.unrealized_pnl(&self.ctx.position, price)
+                - fill.commission;
+            if contract.notional(after, price) * self.config.margin.initial_margin > equity {
+                self.rejected_orders += 1;
+                return false;
+            }
+        }
+
+        let realized = contract.apply_fill(&mut self.ctx.position, &fill);
+        let after = self.ctx.position.quantity;
+        self.ctx.account.cash += realized - fill.commission;
+        self.track_trade(&fill, before, after, realized, at, reason);
+
+        // A bracket belongs to one direction of exposure
+        if after == 0.0 || before * after < 0.0 {
+            self.bracket = None;
+        }
+
+        self.fills.push(fill.clone());
+        strategy.on_fill(&fill, &self.ctx);
+        true
+    }
+
+    /// Charges borrow fees for a short held through the bar
+    fn accrue_financing(&mut self, bar: &OHLCV) {
+        let position = self.ctx.position;
+        let rate = self.config.margin.borrow_rate;
+        if !position.is_short() || rate == 0.0 {
+            return;
+        }
+        let notional = self
+            .config
+            .contract
+            .notional(position.quantity, self.mark_price(bar.close));
+        let fee = notional * rate / self.config.bars_per_year;
+        self.ctx.account.cash -= fee;
+        if let Some(open) = self.open_trade.as_mut() {
+            open.trade.financing += fee;
+        }
+    }
+
+    fn track_trade(
+        &mut self,
+        fill: &Fill,
+        before: f64,
+        after: f64,
+        realized: f64,
+        at: BarTime,
+        reason: ExitReason,
+    ) {
+        let signed = fill.side.sign() * fill.quantity;
+        let opening = if before == 0.0 || before * signed > 0.0 {
+            fill.quantity
+        } else {
+            // Only the part beyond the old position opens a new trade
+            (signed.abs() - before.abs()).max(0.0)
+        };
+        let closing = fill.quantity - opening;
+
+        if closing > 0.0 {
+            if let Some(open) = self.open_trade.as_mut() {
+                open.exit_qty += closing;
+                open.realized += realized;
+                open.trade.exit_price +=
+                    (fill.price - open.trade.exit_price) * closing / open.exit_qty;
+                open.trade.commission += fill.commission * closing / fill.quantity;
+            }
+            if (after == 0.0 || before * after < 0.0)
+                && let Some(mut open) = self.open_trade.take()
+            {
+                open.trade.exit_bar = at.index;
+                open.trade.exit_time = at.timestamp;
+                open.trade.exit_reason = reason;
+                open.trade.notional = self
+                    .config
+                    .contract
+                    .notional(open.trade.quantity, open.trade.entry_price);
+                open.trade.profit = open.realized - open.trade.commission - open.trade.financing
+                    + open.trade.funding;
+                self.trades.push(open.trade);
+            }
+        }
+
+        if opening > 0.0 {
+            let commission = fill.commission * opening / fill.quantity;
+            let open = self.open_trade.get_or_insert(OpenTrade {
+                trade: Trade {
+                    side: fill.side,
+                    entry_bar: at.index,
+                    exit_bar: at.index,
+                    entry_time: at.timestamp,
+                    exit_time: at.timestamp,
+                    entry_price: fill.price,
+                    exit_price: 0.0,
+                    quantity: 0.0,
+                    notional: 0.0,
+                    profit: 0.0,
+                    commission: 0.0,
+                    financing: 0.0,
+                    funding: 0.0,
+                    exit_reason: ExitReason::Signal,
+                },
+                exit_qty: 0.0,
+                realized: 0.0,
+            });
+            // Opening fills only ever add to the position, so its average is the entry price
+            open.trade.entry_price = self.ctx.position.avg_price;
+            open.trade.quantity = open.trade.quantity.max(after.abs());
+            open.trade.commission += commission;
+        }
+    }
+
+    fn mark_to_market(&mut self, bar: &OHLCV) {
+        let mark = self.mark_price(bar.close);
+        self.ctx.account.equity = self.ctx.account.cash
+            + self
+                .config
+                .contract
+                .unrealized_pnl(&self.ctx.position, mark);
+    }
+
+    fn record_equity(&mut self, timestamp: i64) {
+        let balance = self.ctx.account.equity;
+        self.peak = self.peak.max(balance);
+        self.equity_curve.push(EquityPoint {
+            timestamp,
+            balance,
+            drawdown: (self.peak - balance) / self.peak * 100.0,
+        });
+    }
+
+    /// Turns the intents of one `on_bar` call into orders for the next bars
+    fn submit(&mut self, intents: Vec<OrderIntent>) {
+        let mut projected = self.ctx.position;
+        for intent in intents {
+            match intent {
+                OrderIntent::TargetPosition { .. } | OrderIntent::ClosePosition => {
+                    let delta = intent.position_delta(&projected);
+                    if delta != 0.0 {
+                        projected.quantity += delta;
+                        self.market_orders.push(PendingOrder {
+                            side: if delta > 0.0 { Side::Buy } else { Side::Sell },
+                            quantity: delta.abs(),
+                            order_type: OrderType::Market,
+                            bracket: None,
+                        });
+                    }
+                }
+                OrderIntent::Entry {
+                    side,
+                    quantity,
+                    order_type,
+                    bracket,
+                } => {
+                    let order = PendingOrder {
+                        side,
+                        quantity,
+                        order_type,
+                        bracket,
+                    };
+                    if order_type == OrderType::Market {
+                        projected.quantity += side.sign() * quantity;
+                        self.market_orders.push(order);
+                    } else {
+                        self.resting.push(order);
+                    }
+                }
+                OrderIntent::SetBracket(bracket) => self.bracket = Some(bracket),
+                OrderIntent::CancelPending => self.resting.clear(),
+            }
+        }
+    }
+}
@@ -0,0 +1,472 @@
+//! Performance relative to a benchmark
+//!
+//! Absolute metrics cannot tell a strategy that rode a rising market from one
+//! that added something to it. Against a [`Benchmark`], typically holding the
+//! traded instrument or an index, [`RelativeMetrics`] reports:
+//!
+//! - Jensen's alpha and beta, from regressing the strategy's returns in excess
+//!   of the risk-free rate on the benchmark's;
+//! - tracking error and information ratio of the active return, strategy
+//!   minus benchmark;
+//! - up and down capture: the strategy's average return over the benchmark's,
+//!   in the periods the benchmark rose and in those it fell.
+//!
+//! [`FactorModel`] extends the regression to any number of factor return
+//! series, such as market, momentum or volatility, and splits the strategy's
+//! average annual excess return into each factor's contribution plus alpha.
+//!
+//! A benchmark is valued at or before each timestamp of the strategy's equity
+//! curve, and both are turned into returns with the same [`MetricsConfig`].
+//! Alpha, active return and contributions are annualized arithmetically, by
+//! `periods_per_year`; volatilities by its square root.
+
+use std::fmt;
+
+use crate::backtest::BacktestResults;
+use crate::data::Instrument;
+use crate::metrics::{Metrics, MetricsConfig, period_returns};
+use crate::stats;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BenchmarkError {
+    NoFactors,
+    LengthMismatch {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    NotEnoughData {
+        needed: usize,
+        available: usize,
+    },
+    /// The regressors are constant or collinear
+    Singular,
+}
+
+impl fmt::Display for BenchmarkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoFactors => write!(f, "Factor model needs at least one factor"),
+            Self::LengthMismatch {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "'{}' has {} returns, expected {} aligned with the strategy",
+                name, found, expected
+            ),
+            Self::NotEnoughData { needed, available } => write!(
+                f,
+                "Regression needs at least {} periods, got {}",
+                needed, available
+            ),
+            Self::Singular => write!(f, "Benchmark or factor returns are constant or collinear"),
+        }
+    }
+}
+
+impl std::error::Error for BenchmarkError {}
+
+/// A value series to measure a strategy against
+#[derive(Debug, Clone, PartialEq)]
+pub struct Benchmark {
+    pub name: String,
+    /// Value before the first point of the curve
+    pub initial: f64,
+    /// `(timestamp, value)` points in time order
+    pub curve: Vec<(i64, f64)>,
+}
+
+impl Benchmark {
+    pub fn new(name: &str, initial: f64, curve: Vec<(i64, f64)>) -> Self {
+        Benchmark {
+            name: name.to_string(),
+            initial,
+            curve,
+        }
+    }
+
+    /// Holding `instrument` from the open of its first bar, marked at each close
+    pub fn buy_and_hold(instrument: &Instrument) -> Self {
+        Benchmark {
+            name: format!("{} buy & hold", instrument.symbol),
+            initial: instrument.data.first().map_or(0.0, |bar| bar.open),
+            curve: instrument
+                .data
+                .iter()
+                .map(|bar| (bar.timestamp, bar.close))
+                .collect(),
+        }
+    }
+
+    /// Last value at or before `timestamp`, or the initial value
+    pub fn value_at(&self, timestamp: i64) -> f64 {
+        match self.curve.partition_point(|&(t, _)| t <= timestamp) {
+            0 => self.initial,
+            n => self.curve[n - 1].1,
+        }
+    }
+
+    /// The value just before `timestamps` and the value at each of them,
+    /// ready to be compared with an equity curve over the same timestamps
+    pub fn aligned(&self, timestamps: &[i64]) -> (f64, Vec<(i64, f64)>) {
+        let before = match timestamps.first() {
+            Some(&first) => match self.curve.partition_point(|&(t, _)| t < first) {
+                0 => self.initial,
+                n => self.curve[n - 1].1,
+            },
+            None => self.initial,
+        };
+        let curve = timestamps.iter().map(|&t| (t, self.value_at(t))).collect();
+        (before, curve)
+    }
+
+    /// Period returns over `timestamps`, aligned with a strategy's
+    pub fn returns_along(&self, timestamps: &[i64], config: &MetricsConfig) -> Vec<f64> {
+        let (before, curve) = self.aligned(timestamps);
+        period_returns(before, &curve, config.frequency)
+    }
+
+    /// Measures a backtest against the benchmark over the backtest's timestamps
+    pub fn compare(
+        &self,
+        results: &BacktestResults,
+        config: &MetricsConfig,
+    ) -> Result<BenchmarkReport, BenchmarkError> {
+        let curve: Vec<(i64, f64)> = results
+            .equity_curve
+            .iter()
+            .map(|p| (p.timestamp, p.balance))
+            .collect();
+        let timestamps: Vec<i64> = curve.iter().map(|&(t, _)| t).collect();
+        let (before, benchmark_curve) = self.aligned(&timestamps);
+
+        let returns = period_returns(results.initial_balance, &curve, config.frequency);
+        let benchmark_returns = period_returns(before, &benchmark_curve, config.frequency);
+        Ok(BenchmarkReport {
+            strategy: results.strategy.clone(),
+            benchmark: self.name.clone(),
+            strategy_metrics: results.metrics_with(config),
+            benchmark_metrics: Metrics::compute(before, &benchmark_curve, &[], config),
+            relative: RelativeMetrics::compute(&returns, &benchmark_returns, config)?,
+        })
+    }
+}
+
+/// How a strategy's returns relate to a benchmark's over the same periods
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RelativeMetrics {
+    pub periods: usize,
+    /// Annual Jensen's alpha
+    pub alpha: f64,
+    /// t-statistic of the alpha
+    pub alpha_t: f64,
+    pub beta: f64,
+    pub correlation: f64,
+    pub r_squared: f64,
+    /// Annual mean of strategy minus benchmark returns
+    pub active_return: f64,
+    /// Annualized deviation of the active returns
+    pub tracking_error: f64,
+    /// Active return over tracking error
+    pub information_ratio: f64,
+    /// Average return over the benchmark's, in periods the benchmark rose
+    pub up_capture: f64,
+    /// Average return over the benchmark's, in periods the benchmark fell
+    pub down_capture: f64,
+}
+
+impl RelativeMetrics {
+    /// Relates aligned per-period `returns` to `benchmark` returns
+    pub fn compute(
+        returns: &[f64],
+        benchmark: &[f64],
+        config: &MetricsConfig,
+    ) -> Result<Self, BenchmarkError> {
+        let periods = returns.len();
+        if benchmark.len() != periods {
+            return Err(BenchmarkError::LengthMismatch {
+                name: "benchmark".to_string(),
+                expected: periods,
+                found: benchmark.len(),
+            });
+        }
+        if periods < 3 {
+            return Err(BenchmarkError::NotEnoughData {
+                needed: 3,
+                available: periods,
+            });
+        }
+
+        let rf = config.risk_free_per_period();
+        let excess: Vec<f64> = returns.iter().map(|r| r - rf).collect();
+        let benchmark_excess: Vec<f64> = benchmark.iter().map(|r| r - rf).collect();
+        let fit = stats::ols(&[vec![1.0; periods], benchmark_excess], &excess)
+            .ok_or(BenchmarkError::Singular)?;
+
+        let active: Vec<f64> = returns.iter().zip(benchmark).map(|(r, b)| r - b).collect();
+        let active_return = stats::mean(&active) * config.periods_per_year;
+        let tracking_error = stats::std_dev(&active) * config.periods_per_year.sqrt();
+
+        Ok(RelativeMetrics {
+            periods,
+            alpha: fit.coefficients[0] * config.periods_per_year,
+            alpha_t: fit.t_stat(0),
+            beta: fit.coefficients[1],
+            correlation: stats::correlation(returns, benchmark),
+            r_squared: fit.r_squared,
+            active_return,
+            tracking_error,
+            information_ratio: if tracking_error > 0.0 {
+                active_return / tracking_error
+            } else {
+                0.0
+            },
+            up_capture: capture(returns, benchmark, |b| b > 0.0),
+            down_capture: capture(returns, benchmark, |b| b < 0.0),
+        })
+    }
+}
+
+impl fmt::Display for RelativeMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Alpha {:.2}% (t {:.2}), beta {:.2}, correlation {:.2}, R² {:.2} over {} periods",
+            self.alpha * 100.0,
+            self.alpha_t,
+            self.beta,
+            self.correlation,
+            self.r_squared,
+            self.periods
+        )?;
+        write!(
+            f,
+            "Active return {:.2}%, tracking error {:.2}%, information ratio {:.2}, capture up {:.0}% / down {:.0}%",
+            self.active_return * 100.0,
+            self.tracking_error * 100.0,
+            self.information_ratio,
+            self.up_capture * 100.0,
+            self.down_capture * 100.0
+        )
+    }
+}
+
+/// Mean return over mean benchmark return, in the periods `select` keeps
+fn capture(returns: &[f64], benchmark: &[f64], select: impl Fn(f64) -> bool) -> f64 {
+    let (strategy, market): (Vec<f64>, Vec<f64>) = returns
+        .iter()
+        .zip(benchmark)
+        .filter(|&(_, &b)| select(b))
+        .map(|(&r, &b)| (r, b))
+        .unzip();
+    let market_mean = stats::mean(&market);
+    if market.is_empty() || market_mean == 0.0 {
+        0.0
+    } else {
+        stats::mean(&strategy) / market_mean
+    }
+}
+
+/// A backtest next to its benchmark
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchmarkReport {
+    pub strategy: String,
+    pub benchmark: String,
+    pub strategy_metrics: Metrics,
+    /// The benchmark's own metrics over the backtest's periods
+    pub benchmark_metrics: Metrics,
+    pub relative: RelativeMetrics,
+}
+
+impl BenchmarkReport {
+    pub fn print(&self) {
+        println!("\n=== {} vs {} ===\n", self.strategy, self.benchmark);
+        println!("{:<16} {:>12} {:>12}", "", "Strategy", "Benchmark");
+        println!("{}", "-".repeat(42));
+        let (s, b) = (&self.strategy_metrics, &self.benchmark_metrics);
+        let rows = [
+            (
+                "Return",
+                s.total_return * 100.0,
+                b.total_return * 100.0,
+                "%",
+            ),
+            (
+                "Annual return",
+                s.annual_return * 100.0,
+                b.annual_return * 100.0,
+                "%",
+            ),
+            (
+                "Volatility",
+                s.volatility * 100.0,
+                b.volatility * 100.0,
+                "%",
+            ),
+            (
+                "Max drawdown",
+                s.max_drawdown * 100.0,
+                b.max_drawdown * 100.0,
+                "%",
+            ),
+            ("Sharpe", s.sharpe, b.sharpe, ""),
+            ("Sortino", s.sortino, b.sortino, ""),
+        ];
+        for (label, strategy, benchmark, unit) in rows {
+            let strategy = format!("{:.2}{}", strategy, unit);
+            let benchmark = format!("{:.2}{}", benchmark, unit);
+            println!("{:<16} {:>12} {:>12}", label, strategy, benchmark);
+        }
+        println!("\n{}", self.relative);
+    }
+}
+
+/// Regression of a strategy's excess returns on factor return series
+#[derive(Debug, Clone, Default)]
+pub struct FactorModel {
+    factors: Vec<(String, Vec<f64>)>,
+}
+
+impl FactorModel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A factor's per-period returns, aligned with the strategy's
+    pub fn with_factor(mut self, name: &str, returns: Vec<f64>) -> Self {
+        self.factors.push((name.to_string(), returns));
+        self
+    }
+
+    /// Fits `returns - rf = alpha + sum(beta_i * factor_i)`
+    ///
+    /// Factors are taken as given, so market factors should already be in
+    /// excess of the risk-free rate.
+    pub fn fit(
+        &self,
+        returns: &[f64],
+        config: &MetricsConfig,
+    ) -> Result<FactorAttribution, BenchmarkError> {
+        if self.factors.is_empty() {
+            return Err(BenchmarkError::NoFactors);
+        }
+        let periods = returns.len();
+        if let Some((name, series)) = self.factors.iter().find(|(_, s)| s.len() != periods) {
+            return Err(BenchmarkError::LengthMismatch {
+                name: name.clone(),
+                expected: periods,
+                found: series.len(),
+            });
+        }
+        let needed = self.factors.len() + 2;
+        if periods < needed {
+            return Err(BenchmarkError::NotEnoughData {
+                needed,
+                available: periods,
+            });
+        }
+
+        let rf = config.risk_free_per_period();
+        let excess: Vec<f64> = returns.iter().map(|r| r - rf).collect();
+        let mut columns = vec![vec![1.0; periods]];
+        columns.extend(self.factors.iter().map(|(_, series)| series.clone()));
+        let fit = stats::ols(&columns, &excess).ok_or(BenchmarkError::Singular)?;
+
+        let exposures = self
+            .factors
+            .iter()
+            .enumerate()
+            .map(|(i, (name, series))| FactorExposure {
+                name: name.clone(),
+                beta: fit.coefficients[i + 1],
+                t_stat: fit.t_stat(i + 1),
+                contribution: fit.coefficients[i + 1]
+                    * stats::mean(series)
+                    * config.periods_per_year,
+            })
+            .collect();
+        Ok(FactorAttribution {
+            periods,
+            excess_return: stats::mean(&excess) * config.periods_per_year,
+            alpha: fit.coefficients[0] * config.periods_per_year,
+            alpha_t: fit.t_stat(0),
+            exposures,
+            r_squared: fit.r_squared,
+            residual_volatility: stats::std_dev(&fit.residuals) * config.periods_per_year.sqrt(),
+        })
+    }
+}
+
+/// Loading on one factor and the return it explains
+#[derive(Debug, Clone, PartialEq)]
+pub struct FactorExposure {
+    pub name: String,
+    pub beta: f64,
+    pub t_stat: f64,
+    /// Annual return from the exposure: beta times the factor's mean return
+    pub contribution: f64,
+}
+
+/// Where a strategy's excess return came from
+///
+/// The contributions and the alpha add up to the excess return.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FactorAttribution {
+    pub periods: usize,
+    /// Annual mean return over the risk-free rate
+    pub excess_return: f64,
+    /// Annual return no factor explains
+    pub alpha: f64,
+    pub alpha_t: f64,
+    pub exposures: Vec<FactorExposure>,
+    pub r_squared: f64,
+    /// Annualized deviation of what no factor explains
+    pub residual_volatility: f64,
+}
+
+impl FactorAttribution {
+    pub fn exposure(&self, name: &str) -> Option<&FactorExposure> {
+        self.exposures.iter().find(|e| e.name == name)
+    }
+
+    pub fn print(&self) {
+        println!(
+            "\n=== Factor Attribution ({} periods, R² {:.2}) ===\n",
+            self.periods, self.r_squared
+        );
+        println!(
+            "{:<16} {:>8} {:>8} {:>14}",
+            "Factor", "Beta", "t", "Contribution"
+        );
+        println!("{}", "-".repeat(49));
+        for e in &self.exposures {
+            println!(
+                "{:<16} {:>8.2} {:>8.2} {:>13.2}%",
+                e.name,
+                e.beta,
+                e.t_stat,
+                e.contribution * 100.0
+            );
+        }
+        println!(
+            "{:<16} {:>8} {:>8.2} {:>13.2}%",
+            "Alpha",
+            "",
+            self.alpha_t,
+            self.alpha * 100.0
+        );
+        println!(
+            "{:<16} {:>8} {:>8} {:>13.2}%",
+            "Excess return",
+            "",
+            "",
+            self.excess_return * 100.0
+        );
+        println!(
+            "\nResidual volatility {:.2}%",
+            self.residual_volatility * 100.0
+        );
+    }
+}
@@ -0,0 +1,482 @@
+//! Statistical comparison of strategies (from Chapter 302)
+//!
+//! Two Sharpe ratios side by side say nothing about whether the gap is
+//! real. A [`StrategyComparison`] takes aligned per-period returns of each
+//! strategy and reports, for a ranking by Sharpe ratio:
+//!
+//! - bootstrap confidence intervals of each Sharpe ratio and of its gap to
+//!   the leader, from a stationary bootstrap of the periods shared by all
+//!   strategies, so cross-correlation and autocorrelation are kept;
+//! - the Jobson-Korkie test of the gap, with Memmel's correction;
+//! - a one-sided bootstrap p-value of each strategy beating the benchmark;
+//! - White's Reality Check and Hansen's SPA test of whether the best strategy
+//!   beats the benchmark once the search over all of them is accounted for;
+//! - the correlation of returns between strategies.
+//!
+//! The benchmark is the risk-free rate unless another return series is given.
+
+use std::fmt;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rayon::prelude::*;
+
+use crate::metrics::MetricsConfig;
+use crate::stats;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ComparisonError {
+    NotEnoughStrategies,
+    LengthMismatch {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    NotEnoughData {
+        needed: usize,
+        available: usize,
+    },
+    InvalidConfig(String),
+}
+
+impl fmt::Display for ComparisonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotEnoughStrategies => write!(f, "Comparison needs at least two strategies"),
+            Self::LengthMismatch {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "'{}' has {} returns, expected {} aligned with the others",
+                name, found, expected
+            ),
+            Self::NotEnoughData { needed, available } => write!(
+                f,
+                "Comparison needs at least {} periods, got {}",
+                needed, available
+            ),
+            Self::InvalidConfig(reason) => write!(f, "Invalid comparison setup: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ComparisonError {}
+
+/// Sharpe ratio per period of returns in excess of `risk_free`
+fn sharpe(returns: &[f64], risk_free: f64) -> f64 {
+    let sd = stats::std_dev(returns);
+    if sd > 0.0 {
+        (stats::mean(returns) - risk_free) / sd
+    } else {
+        0.0
+    }
+}
+
+/// Jobson-Korkie test with Memmel's correction that two strategies have the
+/// same Sharpe ratio: the z statistic and its two-sided p-value
+pub fn jobson_korkie(a: &[f64], b: &[f64], risk_free: f64) -> (f64, f64) {
+    let periods = a.len().min(b.len());
+    if periods < 2 {
+        return (0.0, 1.0);
+    }
+    let (sa, sb) = (sharpe(a, risk_free), sharpe(b, risk_free));
+    let rho = stats::correlation(a, b);
+    let variance =
+        (2.0 - 2.0 * rho + 0.5 * (sa * sa + sb * sb - 2.0 * sa * sb * rho * rho)) / periods as f64;
+    if variance <= 0.0 {
+        return (0.0, 1.0);
+    }
+    let z = (sa - sb) / variance.sqrt();
+    (z, 2.0 * (1.0 - stats::normal_cdf(z.abs())))
+}
+
+/// Gap between two annualized Sharpe ratios
+#[derive(Debug, Clone, PartialEq)]
+pub struct SharpeDifference {
+    pub first: String,
+    pub second: String,
+    /// First minus second
+    pub difference: f64,
+    /// Bootstrap confidence interval of the difference
+    pub low: f64,
+    pub high: f64,
+    /// Jobson-Korkie-Memmel statistic
+    pub z: f64,
+    pub p_value: f64,
+}
+
+/// Data-snooping tests of the best strategy against the benchmark
+#[derive(Debug, Clone, PartialEq)]
+pub struct RealityCheck {
+    /// Strategy with the highest mean return over the benchmark
+    pub best: String,
+    /// White's statistic: the best scaled mean excess over the benchmark
+    pub statistic: f64,
+    pub p_value: f64,
+    /// Hansen's studentized statistic
+    pub spa_statistic: f64,
+    pub spa_p_value: f64,
+}
+
+/// One strategy in the ranking
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComparisonRow {
+    pub name: String,
+    /// Annualized
+    pub sharpe: f64,
+    pub sharpe_low: f64,
+    pub sharpe_high: f64,
+    /// Annualized mean return over the benchmark
+    pub excess_return: f64,
+    /// One-sided bootstrap p-value of beating the benchmark, not adjusted
+    /// for the number of strategies
+    pub p_value: f64,
+    /// Against the top of the ranking; `None` for the top itself
+    pub versus_best: Option<SharpeDifference>,
+}
+
+/// Strategies ranked by Sharpe ratio, with significance
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComparisonReport {
+    /// Best first
+    pub rows: Vec<ComparisonRow>,
+    /// Strategy names in the order they were added
+    pub names: Vec<String>,
+    /// Return correlations, in the order of `names`
+    pub correlations: Vec<Vec<f64>>,
+    pub reality_check: RealityCheck,
+    pub confidence: f64,
+    pub resamples: usize,
+}
+
+impl ComparisonReport {
+    pub fn row(&self, name: &str) -> Option<&ComparisonRow> {
+        self.rows.iter().find(|r| r.name == name)
+    }
+
+    pub fn correlation(&self, a: &str, b: &str) -> Option<f64> {
+        let i = self.names.iter().position(|n| n == a)?;
+        let j = self.names.iter().position(|n| n == b)?;
+        Some(self.correlations[i][j])
+    }
+
+    /// Whether the leader beats the benchmark at `alpha` after accounting
+    /// for every strategy tried (Hansen's SPA)
+    pub fn best_is_significant(&self, alpha: f64) -> bool {
+        self.reality_check.spa_p_value < alpha
+    }
+
+    pub fn print(&self) {
+        println!(
+            "\n=== Strategy Comparison ({} resamples, {:.0}% intervals) ===\n",
+            self.resamples,
+            self.confidence * 100.0
+        );
+        println!(
+            "{:<4} {:<20} {:>7} {:>18} {:>9} {:>8} {:>9} {:>18} {:>8}",
+            "#", "Strategy", "Sharpe", "Interval", "Excess", "p", "vs best", "Interval", "JK p"
+        );
+        println!("{}", "-".repeat(109));
+        for (i, row) in self.rows.iter().enumerate() {
+            let interval = format!("[{:.2}, {:.2}]", row.sharpe_low, row.sharpe_high);
+            let (gap, gap_interval, gap_p) = match &row.versus_best {
+                Some(d) => (
+                    format!("{:.2}", d.difference),
+                    format!("[{:.2}, {:.2}]", d.low, d.high),
+                    format!("{:.3}", d.p_value),
+                ),
+                None => ("-".to_string(), "-".to_string(), "-".to_string()),
+            };
+            println!(
+                "{:<4} {:<20} {:>7.2} {:>18} {:>8.2}% {:>8.3} {:>9} {:>18} {:>8}",
+                i + 1,
+                row.name,
+                row.sharpe,
+                interval,
+                row.excess_return * 100.0,
+                row.p_value,
+                gap,
+                gap_interval,
+                gap_p
+            );
+        }
+
+        let rc = &self.reality_check;
+        println!(
+            "\nBest vs benchmark ({}): Reality Check p = {:.3}, SPA p = {:.3}",
+            rc.best, rc.p_value, rc.spa_p_value
+        );
+
+        println!("\nReturn correlation:");
+        print!("{:<20}", "");
+        for name in &self.names {
+            print!(" {:>10.10}", name);
+        }
+        println!();
+        for (name, row) in self.names.iter().zip(&self.correlations) {
+            print!("{:<20.20}", name);
+            for c in row {
+                print!(" {:>10.2}", c);
+            }
+            println!();
+        }
+    }
+}
+
+/// Compares strategies from their per-period returns over the same periods
+pub struct StrategyComparison {
+    strategies: Vec<(String, Vec<f64>)>,
+    benchmark: Option<Vec<f64>>,
+    config: MetricsConfig,
+    seed: u64,
+    resamples: usize,
+    mean_block: f64,
+    confidence: f64,
+}
+
+impl StrategyComparison {
+    /// `config` gives the annualization and risk-free rate of the returns
+    pub fn new(config: MetricsConfig, seed: u64) -> Self {
+        StrategyComparison {
+            strategies: Vec::new(),
+            benchmark: None,
+            config,
+            seed,
+            resamples: 1000,
+            mean_block: 10.0,
+            confidence: 0.95,
+        }
+    }
+
+    pub fn with_strategy(mut self, name: &str, returns: Vec<f64>) -> Self {
+        self.strategies.push((name.to_string(), returns));
+        self
+    }
+
+    /// Returns to beat instead of the risk-free rate
+    pub fn with_benchmark(mut self, returns: Vec<f64>) -> Self {
+        self.benchmark = Some(returns);
+        self
+    }
+
+    pub fn with_resamples(mut self, resamples: usize) -> Self {
+        self.resamples = resamples;
+        self
+    }
+
+    /// Mean block length of the stationary bootstrap; 1 resamples periods
+    /// independently
+    pub fn with_mean_block(mut self, mean_block: f64) -> Self {
+        self.mean_block = mean_block.max(1.0);
+        self
+    }
+
+    pub fn with_confidence(mut self, confidence: f64) -> Self {
+        self.confidence = confidence;
+        self
+    }
+
+    fn validate(&self) -> Result<usize, ComparisonError> {
+        let Some((_, first)) = self.strategies.first() else {
+            return Err(ComparisonError::NotEnoughStrategies);
+        };
+        if self.strategies.len() < 2 {
+            return Err(ComparisonError::NotEnoughStrategies);
+        }
+        let periods = first.len();
+        let benchmark = self.benchmark.iter().map(|b| ("benchmark", b));
+        for (name, returns) in self
+            .strategies
+            .iter()
+            .map(|(n, r)| (n.as_str(), r))
+            .chain(benchmark)
+        {
+            if returns.len() != periods {
+                return Err(ComparisonError::LengthMismatch {
+                    name: name.to_string(),
+                    expected: periods,
+                    found: returns.len(),
+                });
+            }
+        }
+        if periods < 10 {
+            return Err(ComparisonError::NotEnoughData {
+                needed: 10,
+                available: periods,
+            });
+        }
+        let invalid = |reason: &str| Err(ComparisonError::InvalidConfig(reason.to_string()));
+        if self.resamples == 0 {
+            return invalid("at least one resample is needed for p-values");
+        }
+        if !(self.confidence > 0.0 && self.confidence < 1.0) {
+            return invalid("confidence must be between 0 and 1");
+        }
+        Ok(periods)
+    }
+
+    /// Period indices of resample `sample`
+    fn indices(&self, sample: usize, periods: usize) -> Vec<usize> {
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        rng.set_stream(sample as u64);
+        let restart = 1.0 / self.mean_block;
+        let mut i = rng.gen_range(0..periods);
+        (0..periods)
+            .map(|step| {
+                if step > 0 {
+                    i = if rng.r#gen::<f64>() < restart {
+                        rng.gen_range(0..periods)
+                    } else {
+                        (i + 1) % periods
+                    };
+                }
+                i
+            })
+            .collect()
+    }
+
+    pub fn run(&self) -> Result<ComparisonReport, ComparisonError> {
+        let periods = self.validate()?;
+        let rf = self.config.risk_free_per_period();
+        let annualize = self.config.periods_per_year.sqrt();
+        let scale = (periods as f64).sqrt();
+        let excess: Vec<Vec<f64>> = self
+            .strategies
+            .iter()
+            .map(|(_, returns)| match &self.benchmark {
+                Some(b) => returns.iter().zip(b).map(|(r, b)| r - b).collect(),
+                None => returns.iter().map(|r| r - rf).collect(),
+            })
+            .collect();
+        let means: Vec<f64> = excess.iter().map(|d| stats::mean(d)).collect();
+        let sharpes: Vec<f64> = self.strategies.iter().map(|(_, r)| sharpe(r, rf)).collect();
+
+        // Per resample and strategy: bootstrap Sharpe ratio and mean excess
+        let boot: Vec<(Vec<f64>, Vec<f64>)> = (0..self.resamples)
+            .into_par_iter()
+            .map(|sample| {
+                let idx = self.indices(sample, periods);
+                let pick = |series: &[f64]| idx.iter().map(|&i| series[i]).collect::<Vec<f64>>();
+                let sharpes = self
+                    .strategies
+                    .iter()
+                    .map(|(_, r)| sharpe(&pick(r), rf))
+                    .collect();
+                let means = excess.iter().map(|d| stats::mean(&pick(d))).collect();
+                (sharpes, means)
+            })
+            .collect();
+        let column = |k: usize, of_means: bool| -> Vec<f64> {
+            boot.iter()
+                .map(|(s, m)| if of_means { m[k] } else { s[k] })
+                .collect()
+        };
+        let tail = (1.0 - self.confidence) / 2.0;
+        let resamples = self.resamples as f64;
+
+        // White's Reality Check and Hansen's SPA over the mean excess returns
+        let omegas: Vec<f64> = (0..excess.len())
+            .map(|k| stats::std_dev(&column(k, true)) * scale)
+            .collect();
+        let statistic = means
+            .iter()
+            .fold(f64::NEG_INFINITY, |a, m| a.max(scale * m));
+        let studentized: Vec<f64> = means
+            .iter()
+            .zip(&omegas)
+            .map(|(m, w)| if *w > 0.0 { scale * m / w } else { 0.0 })
+            .collect();
+        let spa_statistic = studentized.iter().fold(0.0f64, |a, t| a.max(*t));
+        let threshold = -(2.0 * (periods as f64).ln().ln()).sqrt();
+        let recentred: Vec<f64> = means
+            .iter()
+            .zip(&studentized)
+            .map(|(m, t)| if *t >= threshold { *m } else { 0.0 })
+            .collect();
+        let (mut rc_hits, mut spa_hits) = (0usize, 0usize);
+        for (_, boot_means) in &boot {
+            let v = (0..means.len())
+                .map(|k| scale * (boot_means[k] - means[k]))
+                .fold(f64::NEG_INFINITY, f64::max);
+            rc_hits += (v >= statistic) as usize;
+            let t = (0..means.len())
+                .filter(|&k| omegas[k] > 0.0)
+                .map(|k| scale * (boot_means[k] - recentred[k]) / omegas[k])
+                .fold(0.0f64, f64::max);
+            spa_hits += (t >= spa_statistic) as usize;
+        }
+        let best_mean = (0..means.len())
+            .max_by(|&a, &b| means[a].total_cmp(&means[b]))
+            .expect("at least two strategies");
+        let reality_check = RealityCheck {
+            best: self.strategies[best_mean].0.clone(),
+            statistic,
+            p_value: rc_hits as f64 / resamples,
+            spa_statistic,
+            spa_p_value: spa_hits as f64 / resamples,
+        };
+
+        let mut order: Vec<usize> = (0..self.strategies.len()).collect();
+        order.sort_by(|&a, &b| sharpes[b].total_cmp(&sharpes[a]));
+        let leader = order[0];
+        let rows = order
+            .iter()
+            .map(|&k| {
+                let boot_sharpes = column(k, false);
+                let boot_means = column(k, true);
+                let versus_best = (k != leader).then(|| {
+                    let gaps: Vec<f64> = boot
+                        .iter()
+                        .map(|(s, _)| (s[leader] - s[k]) * annualize)
+                        .collect();
+                    let (z, p_value) =
+                        jobson_korkie(&self.strategies[leader].1, &self.strategies[k].1, rf);
+                    SharpeDifference {
+                        first: self.strategies[leader].0.clone(),
+                        second: self.strategies[k].0.clone(),
+                        difference: (sharpes[leader] - sharpes[k]) * annualize,
+                        low: stats::quantile(&gaps, tail),
+                        high: stats::quantile(&gaps, 1.0 - tail),
+                        z,
+                        p_value,
+                    }
+                });
+                ComparisonRow {
+                    name: self.strategies[k].0.clone(),
+                    sharpe: sharpes[k] * annualize,
+                    sharpe_low: stats::quantile(&boot_sharpes, tail) * annualize,
+                    sharpe_high: stats::quantile(&boot_sharpes, 1.0 - tail) * annualize,
+                    excess_return: means[k] * self.config.periods_per_year,
+                    p_value: boot_means
+                        .iter()
+                        .filter(|&&m| m - means[k] >= means[k])
+                        .count() as f64
+                        / resamples,
+                    versus_best,
+                }
+            })
+            .collect();
+
+        let correlations = self
+            .strategies
+            .iter()
+            .map(|(_, a)| {
+                self.strategies
+                    .iter()
+                    .map(|(_, b)| stats::correlation(a, b))
+                    .collect()
+            })
+            .collect();
+
+        Ok(ComparisonReport {
+            rows,
+            names: self.strategies.iter().map(|(n, _)| n.clone()).collect(),
+            correlations,
+            reality_check,
+            confidence: self.confidence,
+            resamples: self.resamples,
+        })
+    }
+}
@@ -0,0 +1,200 @@
+//! Contract specifications and perpetual swap funding
+
+use serde::{Deserialize, Serialize};
+
+use crate::data::OHLCV;
+use crate::strategy::{Fill, Position};
+
+/// Currency in which a contract settles its profit and loss
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ContractKind {
+    /// Settled in the quote currency (spot, USDT-margined swaps)
+    #[default]
+    Linear,
+    /// Settled in the base currency (coin-margined swaps)
+    Inverse,
+}
+
+/// How price moves translate into account currency
+///
+/// Linear P&L is `q * m * (exit - entry)`; inverse P&L is
+/// `q * m * (1 / entry - 1 / exit)`, where `m` is the multiplier. For an
+/// inverse contract the account balance is held in the base currency.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ContractSpec {
+    pub kind: ContractKind,
+    /// Base units (linear) or quote units (inverse) per contract
+    pub multiplier: f64,
+    /// Minimum price increment; 0 disables rounding
+    pub tick_size: f64,
+}
+
+impl ContractSpec {
+    pub fn linear(multiplier: f64, tick_size: f64) -> Self {
+        ContractSpec {
+            kind: ContractKind::Linear,
+            multiplier,
+            tick_size,
+        }
+    }
+
+    pub fn inverse(multiplier: f64, tick_size: f64) -> Self {
+        ContractSpec {
+            kind: ContractKind::Inverse,
+            multiplier,
+            tick_size,
+        }
+    }
+
+    /// Absolute value of `quantity` contracts at `price`, in account currency
+    pub fn notional(&self, quantity: f64, price: f64) -> f64 {
+        match self.kind {
+            ContractKind::Linear => quantity.abs() * self.multiplier * price,
+            ContractKind::Inverse => quantity.abs() * self.multiplier / price,
+        }
+    }
+
+    /// Profit of holding signed `quantity` from `entry` to `exit`
+    pub fn pnl(&self, quantity: f64, entry: f64, exit: f64) -> f64 {
+        match self.kind {
+            ContractKind::Linear => quantity * self.multiplier * (exit - entry),
+            ContractKind::Inverse => quantity * self.multiplier * (1.0 / entry - 1.0 / exit),
+        }
+    }
+
+    pub fn unrealized_pnl(&self, position: &Position, mark: f64) -> f64 {
+        if position.is_flat() {
+            0.0
+        } else {
+            self.pnl(position.quantity, position.avg_price, mark)
+        }
+    }
+
+    /// Applies `fill` to `position` and returns the realized P&L
+    ///
+    /// Inverse positions keep the harmonic average of their entry prices,
+    /// which is what makes their P&L add up across partial fills.
+    pub fn apply_fill(&self, position: &mut Position, fill: &Fill) -> f64 {
+        match self.kind {
+            ContractKind::Linear => position.apply_fill(fill) * self.multiplier,
+            ContractKind::Inverse => {
+                // Inverse P&L is linear in 1 / price with the sign flipped
+                let mut inverted = Position {
+                    quantity: position.quantity,
+                    avg_price: reciprocal(position.avg_price),
+                };
+                let realized = inverted.apply_fill(&Fill {
+                    price: 1.0 / fill.price,
+                    ..fill.clone()
+                });
+                position.quantity = inverted.quantity;
+                position.avg_price = reciprocal(inverted.avg_price);
+                -realized * self.multiplier
+            }
+        }
+    }
+
+    /// Nearest valid price
+    pub fn round_price(&self, price: f64) -> f64 {
+        if self.tick_size > 0.0 {
+            (price / self.tick_size).round() * self.tick_size
+        } else {
+            price
+        }
+    }
+
+    /// Mark price at which equity equals `maintenance_margin` times notional
+    ///
+    /// `None` when flat or when no positive price can bring the account there.
+    pub fn liquidation_price(
+        &self,
+        position: &Position,
+        cash: f64,
+        maintenance_margin: f64,
+    ) -> Option<f64> {
+        let q = position.quantity;
+        if q == 0.0 {
+            return None;
+        }
+        let m = self.multiplier;
+        let price = match self.kind {
+            // cash + q m (p - avg) = mm |q| m p
+            ContractKind::Linear => {
+                (q * m * position.avg_price - cash)
+                    / (q * m * (1.0 - maintenance_margin * q.signum()))
+            }
+            // cash + q m (1 / avg - 1 / p) = mm |q| m / p
+            ContractKind::Inverse => {
+                m * (q + maintenance_margin * q.abs()) / (cash + q * m / position.avg_price)
+            }
+        };
+        (price > 0.0 && price.is_finite()).then_some(price)
+    }
+}
+
+impl Default for ContractSpec {
+    fn default() -> Self {
+        ContractSpec::linear(1.0, 0.0)
+    }
+}
+
+fn reciprocal(price: f64) -> f64 {
+    if price == 0.0 { 0.0 } else { 1.0 / price }
+}
+
+/// Historical funding rates of a perpetual swap
+///
+/// Payments happen at every multiple of `interval`. Each one uses the latest
+/// rate published at or before it; with a positive rate longs pay shorts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FundingSchedule {
+    /// Time between payments, in timestamp units
+    pub interval: i64,
+    /// `(timestamp, rate per interval)`, sorted by timestamp
+    pub rates: Vec<(i64, f64)>,
+}
+
+impl FundingSchedule {
+    pub fn new(interval: i64, rates: Vec<(i64, f64)>) -> Self {
+        FundingSchedule { interval, rates }
+    }
+
+    pub fn rate_at(&self, timestamp: i64) -> Option<f64> {
+        let published = self.rates.partition_point(|&(t, _)| t <= timestamp);
+        published.checked_sub(1).map(|i| self.rates[i].1)
+    }
+
+    /// Payment times in `(from, to]`
+    pub fn payments_between(&self, from: i64, to: i64) -> Vec<i64> {
+        if self.interval <= 0 || to <= from {
+            return Vec::new();
+        }
+        let first = (from.div_euclid(self.interval) + 1) * self.interval;
+        (0..)
+            .map(|k| first + k * self.interval)
+            .take_while(|&t| t <= to)
+            .collect()
+    }
+
+    /// Amount received by signed `quantity` at `mark`; negative when paid
+    pub fn payment(&self, contract: &ContractSpec, quantity: f64, mark: f64, rate: f64) -> f64 {
+        -quantity.signum() * contract.notional(quantity, mark) * rate
+    }
+}
+
+/// Perpetual swap data that accompanies the traded bars
+#[derive(Debug, Clone, Default)]
+pub struct PerpetualData {
+    /// Mark price bars; a traded bar without a mark at its timestamp uses its own prices
+    pub mark: Vec<OHLCV>,
+    pub funding: Option<FundingSchedule>,
+}
+
+impl PerpetualData {
+    pub fn new(mark: Vec<OHLCV>, funding: FundingSchedule) -> Self {
+        PerpetualData {
+            mark,
+            funding: Some(funding),
+        }
+    }
+}
@@ -0,0 +1,391 @@
+//! Purged and embargoed cross-validation for trading strategies
+//!
+//! The bars are cut into contiguous groups. Each split tests on some groups
+//! and trains (picks parameters from a grid) on the rest, after two
+//! adjustments that stop test information from leaking into training:
+//!
+//! - **purge**: a training bar's outcome depends on the `label_horizon`
+//!   bars after it, so bars whose horizon reaches into a test range are
+//!   dropped;
+//! - **embargo**: the `embargo` bars right after a test range are dropped,
+//!   since their features still reflect test-period prices.
+//!
+//! With one test group per split this is purged k-fold; with several it is
+//! combinatorial purged CV, whose test groups recombine into many backtest
+//! paths instead of one.
+
+use std::fmt;
+use std::ops::Range;
+
+use crate::backtest::{BacktestResults, Backtester};
+use crate::data::Instrument;
+use crate::params::{FromParams, ParamError, ParamSet};
+use crate::stats::Summary;
+use crate::strategy::Strategy;
+
+/// How the data is split
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CvScheme {
+    /// `folds` groups, each tested once
+    KFold { folds: usize },
+    /// Every choice of `test_groups` out of `groups`
+    Combinatorial { groups: usize, test_groups: usize },
+}
+
+impl CvScheme {
+    fn groups(&self) -> (usize, usize) {
+        match *self {
+            CvScheme::KFold { folds } => (folds, 1),
+            CvScheme::Combinatorial {
+                groups,
+                test_groups,
+            } => (groups, test_groups),
+        }
+    }
+
+    /// Number of backtest paths the test groups recombine into
+    pub fn paths(&self) -> usize {
+        let (n, k) = self.groups();
+        binomial(n.saturating_sub(1), k.saturating_sub(1))
+    }
+}
+
+/// Bar ranges of one split
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CvSplit {
+    /// Group indices tested in this split
+    pub test_groups: Vec<usize>,
+    pub test: Vec<Range<usize>>,
+    /// Remaining contiguous training ranges after purge and embargo
+    pub train: Vec<Range<usize>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FoldResult {
+    pub split: CvSplit,
+    /// Parameters chosen on the training ranges
+    pub params: ParamSet,
+    pub train_score: f64,
+    /// Objective over the test ranges, weighted by their length
+    pub test_score: f64,
+    /// Compounded return over the test ranges, in percent
+    pub test_return: f64,
+    /// Return of each test group, in `split.test_groups` order
+    pub group_returns: Vec<f64>,
+    pub test_trades: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct CvReport {
+    pub scheme: CvScheme,
+    pub folds: Vec<FoldResult>,
+    /// Compounded return of every recombined backtest path, in percent
+    pub path_returns: Vec<f64>,
+}
+
+impl CvReport {
+    pub fn test_scores(&self) -> Summary {
+        Summary::of(&self.folds.iter().map(|f| f.test_score).collect::<Vec<_>>())
+    }
+
+    pub fn test_returns(&self) -> Summary {
+        Summary::of(&self.folds.iter().map(|f| f.test_return).collect::<Vec<_>>())
+    }
+
+    pub fn path_summary(&self) -> Summary {
+        Summary::of(&self.path_returns)
+    }
+
+    pub fn print(&self) {
+        println!("\n=== Purged CV ({:?}) ===\n", self.scheme);
+        for (i, fold) in self.folds.iter().enumerate() {
+            println!(
+                "Fold {:>2}: test groups {:?}, train bars {:>4}, score {:>8.3}, return {:>7.2}%  {}",
+                i + 1,
+                fold.split.test_groups,
+                fold.split.train.iter().map(|r| r.len()).sum::<usize>(),
+                fold.test_score,
+                fold.test_return,
+                fold.params
+            );
+        }
+        println!("\nTest score:  {}", self.test_scores());
+        println!("Test return: {}", self.test_returns());
+        println!(
+            "Paths ({}):   {}",
+            self.path_returns.len(),
+            self.path_summary()
+        );
+    }
+}
+
+/// Why cross-validation could not be carried out
+#[derive(Debug, Clone, PartialEq)]
+pub enum CvError {
+    InvalidScheme(String),
+    NotEnoughData { needed: usize, available: usize },
+    EmptyGrid,
+    InvalidParams(ParamError),
+}
+
+impl fmt::Display for CvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidScheme(reason) => write!(f, "Invalid CV scheme: {}", reason),
+            Self::NotEnoughData { needed, available } => write!(
+                f,
+                "Cross-validation needs at least {} bars, got {}",
+                needed, available
+            ),
+            Self::EmptyGrid => write!(f, "Parameter grid is empty"),
+            Self::InvalidParams(e) => write!(f, "Invalid parameters: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CvError {}
+
+impl From<ParamError> for CvError {
+    fn from(e: ParamError) -> Self {
+        CvError::InvalidParams(e)
+    }
+}
+
+/// Cross-validates a strategy over a parameter grid
+pub struct PurgedCv {
+    engine: Backtester,
+    scheme: CvScheme,
+    label_horizon: usize,
+    embargo: usize,
+    warmup: usize,
+}
+
+impl PurgedCv {
+    pub fn new(engine: Backtester, scheme: CvScheme) -> Self {
+        PurgedCv {
+            engine,
+            scheme,
+            label_horizon: 0,
+            embargo: 0,
+            warmup: 0,
+        }
+    }
+
+    /// Bars after a training bar that its outcome depends on
+    pub fn with_purge(mut self, label_horizon: usize) -> Self {
+        self.label_horizon = label_horizon;
+        self
+    }
+
+    /// Bars dropped from training after each test range
+    pub fn with_embargo(mut self, embargo: usize) -> Self {
+        self.embargo = embargo;
+        self
+    }
+
+    /// Bars before each test range shown to the strategy without trading
+    pub fn with_warmup(mut self, warmup: usize) -> Self {
+        self.warmup = warmup;
+        self
+    }
+
+    pub fn splits(&self, bars: usize) -> Result<Vec<CvSplit>, CvError> {
+        let (n, k) = self.scheme.groups();
+        if n < 2 || k == 0 || k >= n {
+            return Err(CvError::InvalidScheme(format!(
+                "need 0 < test groups < groups, got {} of {}",
+                k, n
+            )));
+        }
+        if bars < 2 * n {
+            return Err(CvError::NotEnoughData {
+                needed: 2 * n,
+                available: bars,
+            });
+        }
+
+        let size = bars / n;
+        let groups: Vec<Range<usize>> = (0..n)
+            .map(|g| g * size..if g + 1 == n { bars } else { (g + 1) * size })
+            .collect();
+
+        Ok(combinations(n, k)
+            .into_iter()
+            .map(|test_groups| {
+                let test: Vec<Range<usize>> =
+                    test_groups.iter().map(|&g| groups[g].clone()).collect();
+                let mut usable = vec![true; bars];
+                for range in &test {
+                    let purge_from = range.start.saturating_sub(self.label_horizon);
+                    let embargo_to = (range.end + self.embargo).min(bars);
+                    usable[purge_from..embargo_to].fill(false);
+                }
+                CvSplit {
+                    test_groups,
+                    test,
+                    train: runs(&usable),
+                }
+            })
+            .collect())
+    }
+
+    pub fn run<S, F>(
+        &self,
+        instrument: &Instrument,
+        grid: &[ParamSet],
+        objective: F,
+    ) -> Result<CvReport, CvError>
+    where
+        S: Strategy + FromParams,
+        F: Fn(&BacktestResults) -> f64,
+    {
+        let splits = self.splits(instrument.data.len())?;
+        if grid.is_empty() {
+            return Err(CvError::EmptyGrid);
+        }
+        let grid = grid
+            .iter()
+            .map(|p| S::schema().validate(p))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut folds = Vec::new();
+        for split in splits {
+            let mut best: Option<(f64, &ParamSet)> = None;
+            for params in &grid {
+                let runs: Vec<(usize, BacktestResults)> = split
+                    .train
+                    .iter()
+                    .filter(|r| r.len() >= 2)
+                    .map(|r| {
+                        let mut strategy = S::from_validated(params);
+                        (
+                            r.len(),
+                            self.engine
+                                .run(&mut strategy, &slice(instrument, r.clone())),
+                        )
+                    })
+                    .collect();
+                let score = weighted_score(&runs, &objective);
+                if best.is_none_or(|(s, _)| score > s) {
+                    best = Some((score, params));
+                }
+            }
+            let (train_score, params) = best.expect("grid is not empty");
+
+            let runs: Vec<(usize, BacktestResults)> = split
+                .test
+                .iter()
+                .map(|r| {
+                    let start = r.start.saturating_sub(self.warmup);
+                    let mut strategy = S::from_validated(params);
+                    let results = self.engine.run_with_warmup(
+                        &mut strategy,
+                        &slice(instrument, start..r.end),
+                        r.start - start,
+                    );
+                    (r.len(), results)
+                })
+                .collect();
+            let group_returns: Vec<f64> = runs.iter().map(|(_, r)| r.total_return()).collect();
+
+            folds.push(FoldResult {
+                params: params.clone(),
+                train_score,
+                test_score: weighted_score(&runs, &objective),
+                test_return: compound(&group_returns),
+                test_trades: runs.iter().map(|(_, r)| r.trades.len()).sum(),
+                group_returns,
+                split,
+            });
+        }
+
+        Ok(CvReport {
+            scheme: self.scheme,
+            path_returns: path_returns(&folds, self.scheme),
+            folds,
+        })
+    }
+}
+
+fn slice(instrument: &Instrument, range: Range<usize>) -> Instrument {
+    Instrument::new(&instrument.symbol, instrument.data[range].to_vec())
+}
+
+fn weighted_score<F>(runs: &[(usize, BacktestResults)], objective: &F) -> f64
+where
+    F: Fn(&BacktestResults) -> f64,
+{
+    let bars: usize = runs.iter().map(|(n, _)| n).sum();
+    if bars == 0 {
+        return 0.0;
+    }
+    runs.iter()
+        .map(|(n, r)| *n as f64 * objective(r))
+        .sum::<f64>()
+        / bars as f64
+}
+
+/// Chains percent returns
+fn compound(returns: &[f64]) -> f64 {
+    (returns.iter().fold(1.0, |acc, r| acc * (1.0 + r / 100.0)) - 1.0) * 100.0
+}
+
+/// Assigns each group's results to paths: path `p` takes the `p`-th split testing that group
+fn path_returns(folds: &[FoldResult], scheme: CvScheme) -> Vec<f64> {
+    let (groups, _) = scheme.groups();
+    let mut per_group: Vec<Vec<f64>> = vec![Vec::new(); groups];
+    for fold in folds {
+        for (&g, &ret) in fold.split.test_groups.iter().zip(&fold.group_returns) {
+            per_group[g].push(ret);
+        }
+    }
+    (0..scheme.paths())
+        .map(|p| {
+            let returns: Vec<f64> = per_group.iter().filter_map(|r| r.get(p).copied()).collect();
+            compound(&returns)
+        })
+        .collect()
+}
+
+/// Contiguous runs of `true`
+fn runs(mask: &[bool]) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start = None;
+    for (i, &keep) in mask.iter().chain(std::iter::once(&false)).enumerate() {
+        match (keep, start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                ranges.push(s..i);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    ranges
+}
+
+/// All `k`-element subsets of `0..n`, in lexicographic order
+pub(crate) fn combinations(n: usize, k: usize) -> Vec<Vec<usize>> {
+    let mut result = Vec::new();
+    let mut current: Vec<usize> = (0..k).collect();
+    if k == 0 || k > n {
+        return result;
+    }
+    loop {
+        result.push(current.clone());
+        let Some(i) = (0..k).rev().find(|&i| current[i] < n - k + i) else {
+            return result;
+        };
+        current[i] += 1;
+        for j in i + 1..k {
+            current[j] = current[j - 1] + 1;
+        }
+    }
+}
+
+fn binomial(n: usize, k: usize) -> usize {
+    if k > n {
+        return 0;
+    }
+    (0..k).fold(1, |acc, i| acc * (n - i) / (i + 1))
+}
@@ -0,0 +1,54 @@
+//! Bar data shared by the backtesting tools
+
+use serde::{Deserialize, Serialize};
+
+use crate::strategy::MarketData;
+
+/// One OHLCV bar
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OHLCV {
+    pub timestamp: i64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+}
+
+impl OHLCV {
+    pub fn new(timestamp: i64, open: f64, high: f64, low: f64, close: f64, volume: f64) -> Self {
+        OHLCV {
+            timestamp,
+            open,
+            high,
+            low,
+            close,
+            volume,
+        }
+    }
+}
+
+/// A named series of bars
+#[derive(Debug, Clone)]
+pub struct Instrument {
+    pub symbol: String,
+    pub data: Vec<OHLCV>,
+}
+
+impl Instrument {
+    pub fn new(symbol: &str, data: Vec<OHLCV>) -> Self {
+        Instrument {
+            symbol: symbol.to_string(),
+            data,
+        }
+    }
+
+    /// Column view of the bars, as consumed by strategies
+    pub fn to_market_data(&self) -> MarketData {
+        let mut market = MarketData::new(&self.symbol);
+        for bar in &self.data {
+            market.add_bar(bar);
+        }
+        market
+    }
+}
@@ -0,0 +1,214 @@
+//! Combining the signals of several strategies into one decision
+
+use crate::strategy::Signal;
+
+/// How the signals of several strategies are combined
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AggregationPolicy {
+    /// A side wins when more than half of all strategies vote for it
+    MajorityVote,
+    /// Weighted sum of votes normalized to [-1, 1]; trades when it exceeds `threshold`
+    WeightedVote { threshold: f64 },
+    /// Trades only when every strategy votes for the same side
+    Unanimous,
+    /// Takes the first non-hold signal in registration order
+    Priority,
+    /// Sums the signed, weighted quantities of all strategies
+    NetPosition,
+}
+
+/// One strategy's part in a combined decision
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attribution {
+    pub strategy: String,
+    pub signal: Signal,
+    pub weight: f64,
+    /// Signed amount this strategy added to the decision (vote or quantity)
+    pub contribution: f64,
+    /// Whether the strategy points the same way as the final decision
+    pub agreed: bool,
+}
+
+/// Result of combining all strategy signals for one bar
+#[derive(Debug, Clone, PartialEq)]
+pub struct CombinedDecision {
+    pub policy: AggregationPolicy,
+    pub signal: Signal,
+    pub attribution: Vec<Attribution>,
+}
+
+impl CombinedDecision {
+    /// Strategies that voted with the decision
+    pub fn supporters(&self) -> Vec<&str> {
+        self.attribution
+            .iter()
+            .filter(|a| a.agreed)
+            .map(|a| a.strategy.as_str())
+            .collect()
+    }
+
+    pub fn print(&self) {
+        println!("Decision ({:?}): {:?}", self.policy, self.signal);
+        for a in &self.attribution {
+            println!(
+                "  {:<20} w={:<5.2} contrib={:>+8.3} {} {:?}",
+                a.strategy,
+                a.weight,
+                a.contribution,
+                if a.agreed { "✓" } else { " " },
+                a.signal
+            );
+        }
+    }
+}
+
+/// Signed direction of a signal: +1 buy, -1 sell, 0 hold
+fn direction(signal: &Signal) -> f64 {
+    match signal {
+        Signal::Buy { .. } => 1.0,
+        Signal::Sell { .. } => -1.0,
+        Signal::Hold => 0.0,
+    }
+}
+
+fn quantity(signal: &Signal) -> f64 {
+    match signal {
+        Signal::Buy { quantity, .. } | Signal::Sell { quantity, .. } => *quantity,
+        Signal::Hold => 0.0,
+    }
+}
+
+/// Builds a signal pointing in `dir` with the average quantity of the strategies that agree
+fn consensus_signal(dir: f64, signals: &[(&str, Signal, f64)], price: f64) -> Signal {
+    let agreeing: Vec<f64> = signals
+        .iter()
+        .filter(|(_, s, _)| direction(s) == dir)
+        .map(|(_, s, _)| quantity(s))
+        .collect();
+
+    if dir == 0.0 || agreeing.is_empty() {
+        return Signal::Hold;
+    }
+
+    let quantity = agreeing.iter().sum::<f64>() / agreeing.len() as f64;
+    if dir > 0.0 {
+        Signal::Buy { price, quantity }
+    } else {
+        Signal::Sell { price, quantity }
+    }
+}
+
+/// Combines `(name, signal, weight)` triples under `policy`
+///
+/// `price` is used for the combined signal when the policy creates a new one.
+pub fn combine_signals(
+    signals: &[(&str, Signal, f64)],
+    policy: AggregationPolicy,
+    price: f64,
+) -> CombinedDecision {
+    let total = signals.len() as f64;
+    let buys = signals
+        .iter()
+        .filter(|(_, s, _)| direction(s) > 0.0)
+        .count() as f64;
+    let sells = signals
+        .iter()
+        .filter(|(_, s, _)| direction(s) < 0.0)
+        .count() as f64;
+
+    let (signal, contributions): (Signal, Vec<f64>) = match policy {
+        AggregationPolicy::MajorityVote => {
+            let dir = if buys > total / 2.0 {
+                1.0
+            } else if sells > total / 2.0 {
+                -1.0
+            } else {
+                0.0
+            };
+            let votes = signals.iter().map(|(_, s, _)| direction(s)).collect();
+            (consensus_signal(dir, signals, price), votes)
+        }
+        AggregationPolicy::WeightedVote { threshold } => {
+            let total_weight: f64 = signals.iter().map(|(_, _, w)| w.abs()).sum();
+            let votes: Vec<f64> = signals.iter().map(|(_, s, w)| direction(s) * w).collect();
+            let score = if total_weight > 0.0 {
+                votes.iter().sum::<f64>() / total_weight
+            } else {
+                0.0
+            };
+            let dir = if score > threshold {
+                1.0
+            } else if score < -threshold {
+                -1.0
+            } else {
+                0.0
+            };
+            (consensus_signal(dir, signals, price), votes)
+        }
+        AggregationPolicy::Unanimous => {
+            let dir = if total > 0.0 && buys == total {
+                1.0
+            } else if total > 0.0 && sells == total {
+                -1.0
+            } else {
+                0.0
+            };
+            let votes = signals.iter().map(|(_, s, _)| direction(s)).collect();
+            (consensus_signal(dir, signals, price), votes)
+        }
+        AggregationPolicy::Priority => {
+            let first = signals.iter().position(|(_, s, _)| *s != Signal::Hold);
+            let votes = (0..signals.len())
+                .map(|i| {
+                    if Some(i) == first {
+                        direction(&signals[i].1)
+                    } else {
+                        0.0
+                    }
+                })
+                .collect();
+            let signal = first.map(|i| signals[i].1.clone()).unwrap_or(Signal::Hold);
+            (signal, votes)
+        }
+        AggregationPolicy::NetPosition => {
+            let sized: Vec<f64> = signals
+                .iter()
+                .map(|(_, s, w)| direction(s) * quantity(s) * w)
+                .collect();
+            let net: f64 = sized.iter().sum();
+            let signal = if net > 0.0 {
+                Signal::Buy {
+                    price,
+                    quantity: net,
+                }
+            } else if net < 0.0 {
+                Signal::Sell {
+                    price,
+                    quantity: -net,
+                }
+            } else {
+                Signal::Hold
+            };
+            (signal, sized)
+        }
+    };
+
+    let decision_dir = direction(&signal);
+    let attribution = signals
+        .iter()
+        .zip(contributions)
+        .map(|((name, s, w), contribution)| Attribution {
+            strategy: name.to_string(),
+            signal: s.clone(),
+            weight: *w,
+            contribution,
+            agreed: decision_dir != 0.0 && direction(s) == decision_dir,
+        })
+        .collect();
+
+    CombinedDecision {
+        policy,
+        signal,
+        attribution,
+    }
+}
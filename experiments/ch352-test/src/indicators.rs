@@ -0,0 +1,164 @@
+//! Technical indicators shared by the strategies
+
+/// Trait for all trading indicators
+pub trait TradingIndicator {
+    /// Calculates indicator values from prices
+    fn calculate(&self, prices: &[f64]) -> Vec<f64>;
+
+    /// Returns the indicator name
+    fn name(&self) -> &str;
+
+    /// Minimum number of data points required
+    fn min_periods(&self) -> usize;
+}
+
+/// Simple Moving Average (SMA)
+#[derive(Debug, Clone)]
+pub struct SMA {
+    period: usize,
+}
+
+impl SMA {
+    pub fn new(period: usize) -> Self {
+        assert!(period > 0, "Period must be greater than 0");
+        SMA { period }
+    }
+
+    pub fn period(&self) -> usize {
+        self.period
+    }
+}
+
+impl TradingIndicator for SMA {
+    fn calculate(&self, prices: &[f64]) -> Vec<f64> {
+        if prices.len() < self.period {
+            return vec![];
+        }
+
+        prices
+            .windows(self.period)
+            .map(|window| window.iter().sum::<f64>() / self.period as f64)
+            .collect()
+    }
+
+    fn name(&self) -> &str {
+        "SMA"
+    }
+
+    fn min_periods(&self) -> usize {
+        self.period
+    }
+}
+
+/// Exponential Moving Average (EMA)
+#[derive(Debug, Clone)]
+pub struct EMA {
+    period: usize,
+    multiplier: f64,
+}
+
+impl EMA {
+    pub fn new(period: usize) -> Self {
+        assert!(period > 0, "Period must be greater than 0");
+        let multiplier = 2.0 / (period as f64 + 1.0);
+        EMA { period, multiplier }
+    }
+
+    pub fn period(&self) -> usize {
+        self.period
+    }
+}
+
+impl TradingIndicator for EMA {
+    fn calculate(&self, prices: &[f64]) -> Vec<f64> {
+        if prices.len() < self.period {
+            return vec![];
+        }
+
+        let mut result = Vec::with_capacity(prices.len() - self.period + 1);
+
+        let first_ema: f64 = prices[..self.period].iter().sum::<f64>() / self.period as f64;
+        result.push(first_ema);
+
+        let mut prev_ema = first_ema;
+        for price in &prices[self.period..] {
+            let ema = (price - prev_ema) * self.multiplier + prev_ema;
+            result.push(ema);
+            prev_ema = ema;
+        }
+
+        result
+    }
+
+    fn name(&self) -> &str {
+        "EMA"
+    }
+
+    fn min_periods(&self) -> usize {
+        self.period
+    }
+}
+
+/// RSI indicator
+#[derive(Debug, Clone)]
+pub struct RSI {
+    period: usize,
+}
+
+impl RSI {
+    pub fn new(period: usize) -> Self {
+        assert!(period > 0, "Period must be greater than 0");
+        RSI { period }
+    }
+}
+
+impl TradingIndicator for RSI {
+    fn calculate(&self, prices: &[f64]) -> Vec<f64> {
+        if prices.len() < self.period + 1 {
+            return vec![];
+        }
+
+        let changes: Vec<f64> = prices.windows(2).map(|w| w[1] - w[0]).collect();
+
+        let mut gains: Vec<f64> = Vec::new();
+        let mut losses: Vec<f64> = Vec::new();
+
+        for change in &changes {
+            if *change > 0.0 {
+                gains.push(*change);
+                losses.push(0.0);
+            } else {
+                gains.push(0.0);
+                losses.push(-change);
+            }
+        }
+
+        let mut result = Vec::new();
+
+        let mut avg_gain: f64 = gains[..self.period].iter().sum::<f64>() / self.period as f64;
+        let mut avg_loss: f64 = losses[..self.period].iter().sum::<f64>() / self.period as f64;
+
+        for i in self.period..gains.len() {
+            avg_gain = (avg_gain * (self.period - 1) as f64 + gains[i]) / self.period as f64;
+            avg_loss = (avg_loss * (self.period - 1) as f64 + losses[i]) / self.period as f64;
+
+            let rs = if avg_loss != 0.0 {
+                avg_gain / avg_loss
+            } else {
+                100.0
+            };
+
+            result.push(100.0 - (100.0 / (1.0 + rs)));
+        }
+
+        result
+    }
+
+    fn name(&self) -> &str {
+        "RSI"
+    }
+
+    fn min_periods(&self) -> usize {
+        self.period + 1
+    }
+}
@@ -0,0 +1,84 @@
+//! # Trading Strategy Library
+//!
+//! Code from Chapter 352: Publishing to crates.io, grown into a small
+//! library of indicators and strategies.
+
+pub mod backtest;
+pub mod benchmark;
+pub mod comparison;
+pub mod contracts;
+pub mod cross_validation;
+pub mod data;
+pub mod ensemble;
+pub mod indicators;
+pub mod metrics;
+pub mod monte_carlo;
+pub mod multi_instrument;
+pub mod optimizer;
+pub mod overfitting;
+pub mod pairs;
+pub mod paper;
+pub mod params;
+pub mod portfolio;
+pub mod stats;
+pub mod strategies;
+pub mod strategy;
+pub mod stress;
+pub mod sweep;
+pub mod trade_analysis;
+pub mod trial_store;
+pub mod walk_forward;
+
+pub use backtest::{
+    BacktestConfig, BacktestResults, Backtester, EquityPoint, ExitReason, IntrabarPath,
+    MarginConfig, Trade,
+};
+pub use benchmark::{
+    Benchmark, BenchmarkError, BenchmarkReport, FactorAttribution, FactorExposure, FactorModel,
+    RelativeMetrics,
+};
+pub use comparison::{
+    ComparisonError, ComparisonReport, ComparisonRow, RealityCheck, SharpeDifference,
+    StrategyComparison,
+};
+pub use contracts::{ContractKind, ContractSpec, FundingSchedule, PerpetualData};
+pub use cross_validation::{CvError, CvReport, CvScheme, CvSplit, FoldResult, PurgedCv};
+pub use data::{Instrument, OHLCV};
+pub use ensemble::{AggregationPolicy, Attribution, CombinedDecision, combine_signals};
+pub use indicators::{EMA, RSI, SMA, TradingIndicator};
+pub use metrics::{Drawdowns, Metrics, MetricsConfig, ReturnFrequency};
+pub use monte_carlo::{EquityBand, MonteCarlo, MonteCarloError, MonteCarloReport, Resampling};
+pub use multi_instrument::{
+    BacktestResult, LegIntent, LegPnl, MultiInstrumentTester, MultiLegContext, MultiLegStrategy,
+    SingleLeg, TesterError,
+};
+pub use optimizer::{Evaluated, Genetic, GridSearch, Optimizer, RandomSearch, TreeParzen};
+pub use overfitting::{DeflatedSharpe, OverfittingReport, Pbo};
+pub use pairs::{
+    CointegrationResult, HedgeMethod, KalmanHedge, PairsTradingStrategy, engle_granger,
+};
+pub use paper::{
+    ExecutionDrift, LineFeed, PaperError, PaperTrader, PriceFeed, StubServer, WebSocketFeed,
+};
+pub use params::{
+    Constraint, FromParams, ParamError, ParamKind, ParamSchema, ParamSet, ParamSpec, ParamValue,
+};
+pub use portfolio::{
+    Allocation, InstrumentAttribution, MissingBars, PortfolioBacktester, PortfolioResults,
+};
+pub use stats::Summary;
+pub use strategies::{BollingerReversion, DonchianBreakout, RiskExits, TimeSeriesMomentum};
+pub use strategy::{
+    Account, Bracket, CrossoverStrategy, Fill, MarketData, OrderIntent, OrderType, Position, Side,
+    Signal, Strategy, StrategyContext, StrategyManager,
+};
+pub use stress::{
+    Episode, Recovery, Scenario, Shock, StressMetrics, StressReport, StressRow, StressTest,
+    StressedMarket,
+};
+pub use sweep::{Heatmap, ParamSweep, SweepError, SweepMetric, SweepReport, Trial};
+pub use trade_analysis::{Breakdown, Excursion, Streaks, TradeAnalyzer, TradeReport};
+pub use trial_store::{SweepKey, TrialStore};
+pub use walk_forward::{
+    ParamStability, WalkForward, WalkForwardError, WalkForwardReport, WalkForwardWindow, WindowMode,
+};
@@ -1,377 +1,3022 @@
 // Test code from Chapter 352: Publishing to crates.io
 
-use std::collections::HashMap;
+use ch352_test::{
+    Account, AggregationPolicy, Allocation, BacktestConfig, Backtester, Benchmark, BenchmarkError,
+    BollingerReversion, Bracket, ComparisonError, Constraint, ContractSpec, CrossoverStrategy,
+    CvError, CvScheme, DonchianBreakout, EMA, Episode, ExecutionDrift, ExitReason, FactorModel,
+    Fill, FromParams, FundingSchedule, Genetic, GridSearch, Instrument, IntrabarPath, KalmanHedge,
+    LegIntent, LineFeed, MarginConfig, MarketData, Metrics, MetricsConfig, MissingBars, MonteCarlo,
+    MonteCarloError, MultiInstrumentTester, MultiLegContext, MultiLegStrategy, OHLCV, OrderIntent,
+    OrderType, PairsTradingStrategy, PaperError, PaperTrader, ParamError, ParamSchema, ParamSet,
+    ParamSpec, ParamSweep, ParamValue, PerpetualData, PortfolioBacktester, PurgedCv, RSI,
+    RandomSearch, Recovery, RelativeMetrics, Resampling, SMA, Scenario, Shock, Side, Signal,
+    Strategy, StrategyComparison, StrategyContext, StrategyManager, StressTest, StubServer,
+    SweepError, SweepKey, SweepMetric, TesterError, TimeSeriesMomentum, TradeAnalyzer,
+    TradingIndicator, TreeParzen, TrialStore, WalkForward, WalkForwardError, WebSocketFeed,
+    WindowMode, combine_signals, engle_granger,
+};
 
 // ============================================
-// Part 1: Trading Indicator Trait and SMA/EMA
+// Main function to test everything
 // ============================================
 
-/// Trait for all trading indicators
-pub trait TradingIndicator {
-    /// Calculates indicator values from prices
-    fn calculate(&self, prices: &[f64]) -> Vec<f64>;
+fn main() {
+    println!("=== Testing Chapter 352 Code Examples ===\n");
 
-    /// Returns the indicator name
-    fn name(&self) -> &str;
+    // Test indicators
+    let prices = vec![
+        100.0, 102.0, 101.0, 103.0, 105.0, 104.0, 106.0, 108.0, 107.0, 109.0,
+    ];
 
-    /// Minimum number of data points required
-    fn min_periods(&self) -> usize;
-}
+    let sma = SMA::new(3);
+    let sma_values = sma.calculate(&prices);
+    println!("SMA(3): {:?}", sma_values);
+    assert!(!sma_values.is_empty(), "SMA should produce values");
 
-/// Simple Moving Average (SMA)
-#[derive(Debug, Clone)]
-pub struct SMA {
-    period: usize,
-}
+    let ema = EMA::new(3);
+    let ema_values = ema.calculate(&prices);
+    println!("EMA(3): {:?}", ema_values);
+    assert!(!ema_values.is_empty(), "EMA should produce values");
 
-impl SMA {
-    pub fn new(period: usize) -> Self {
-        assert!(period > 0, "Period must be greater than 0");
-        SMA { period }
-    }
+    // Test RSI with more data
+    let rsi_prices: Vec<f64> = (0..30).map(|i| 50000.0 + (i as f64 * 100.0)).collect();
+    let rsi = RSI::new(14);
+    let rsi_values = rsi.calculate(&rsi_prices);
+    println!("RSI(14) values count: {}", rsi_values.len());
 
-    pub fn period(&self) -> usize {
-        self.period
+    // Test strategy: a sine wave produces a handful of clean crossovers
+    let mut full = MarketData::new("BTCUSDT");
+    for i in 0..200 {
+        let price = 50000.0 + 2000.0 * (i as f64 * 0.05).sin();
+        full.add_candle(price, 1000.0, i);
     }
-}
 
-impl TradingIndicator for SMA {
-    fn calculate(&self, prices: &[f64]) -> Vec<f64> {
-        if prices.len() < self.period {
-            return vec![];
+    let mut strategy = CrossoverStrategy::new(5, 20);
+    println!("\nStrategy: {}", strategy.name());
+    println!("Parameters: {:?}", strategy.parameters());
+
+    // Drive the strategy bar by bar, filling target-position intents at the close
+    let mut ctx = StrategyContext::new(Account::new(100_000.0));
+    let mut data = MarketData::new(&full.symbol);
+    let mut emitted = Vec::new();
+    let mut fills = 0;
+
+    strategy.on_start(&ctx);
+    for i in 0..full.prices.len() {
+        data.add_candle(full.prices[i], full.volumes[i], full.timestamps[i]);
+        ctx.bar_index = i;
+
+        let intents = strategy.on_bar(&data, &ctx);
+        let price = full.prices[i];
+        let signal = Signal::from_intents(&intents, &ctx.position, price);
+        if signal != Signal::Hold {
+            emitted.push((i, signal));
         }
 
-        prices
-            .windows(self.period)
-            .map(|window| window.iter().sum::<f64>() / self.period as f64)
-            .collect()
+        for intent in intents {
+            if let OrderIntent::TargetPosition { quantity } = intent {
+                let delta = quantity - ctx.position.quantity;
+                if delta == 0.0 {
+                    continue;
+                }
+                let fill = Fill {
+                    timestamp: full.timestamps[i],
+                    side: if delta > 0.0 { Side::Buy } else { Side::Sell },
+                    price,
+                    quantity: delta.abs(),
+                    commission: 0.0,
+                };
+                ctx.account.cash += ctx.position.apply_fill(&fill);
+                strategy.on_fill(&fill, &ctx);
+                fills += 1;
+            }
+        }
+        ctx.account.equity = ctx.account.cash + ctx.position.unrealized_pnl(price);
     }
-
-    fn name(&self) -> &str {
-        "SMA"
+    strategy.on_end(&ctx);
+
+    // Count the crosses independently
+    let sma = |end: usize, period: usize| {
+        full.prices[end + 1 - period..=end].iter().sum::<f64>() / period as f64
+    };
+    let trends: Vec<f64> = (19..full.prices.len())
+        .map(|i| (sma(i, 5) - sma(i, 20)).signum())
+        .collect();
+    let crosses = trends.windows(2).filter(|w| w[0] != w[1]).count();
+
+    println!(
+        "Signals emitted: {} on {} bars ({} crosses)",
+        emitted.len(),
+        full.prices.len(),
+        crosses
+    );
+    for (bar, signal) in &emitted {
+        println!("  bar {:>3}: {:?}", bar, signal);
     }
-
-    fn min_periods(&self) -> usize {
-        self.period
+    assert!(!emitted.is_empty(), "Crossover should trade on a sine wave");
+    assert!(
+        emitted.len() <= crosses,
+        "Signals only on crosses, not on every bar"
+    );
+    assert_eq!(fills, emitted.len());
+    for pair in emitted.windows(2) {
+        let alternates = matches!(
+            (&pair[0].1, &pair[1].1),
+            (Signal::Buy { .. }, Signal::Sell { .. }) | (Signal::Sell { .. }, Signal::Buy { .. })
+        );
+        assert!(alternates, "Buys and sells must alternate");
     }
-}
-
-/// Exponential Moving Average (EMA)
-#[derive(Debug, Clone)]
-pub struct EMA {
-    period: usize,
-    multiplier: f64,
-}
-
-impl EMA {
-    pub fn new(period: usize) -> Self {
-        assert!(period > 0, "Period must be greater than 0");
-        let multiplier = 2.0 / (period as f64 + 1.0);
-        EMA { period, multiplier }
+    assert!(
+        matches!(emitted[0].1, Signal::Buy { .. }),
+        "Long-only: first trade is a buy"
+    );
+    println!("Final equity: ${:.2}", ctx.account.equity);
+
+    // Test typed parameters
+    println!("\n=== Parameter Schema ===");
+    let schema = CrossoverStrategy::schema();
+    for spec in &schema.specs {
+        println!(
+            "  {:<12} {:?} (default {})",
+            spec.name, spec.kind, spec.default
+        );
     }
-
-    pub fn period(&self) -> usize {
-        self.period
+    println!("Defaults: {}", schema.defaults());
+
+    let from_config = schema
+        .parse(&[("fast_period", "8"), ("slow_period", "21")])
+        .expect("valid config");
+    let built = CrossoverStrategy::from_params(&from_config).expect("valid params");
+    println!("Built from config: {}", built.parameters());
+    assert_eq!(built.parameters().int("slow_period"), Some(21));
+    assert_eq!(built.parameters().float("quantity"), Some(1.0));
+
+    let bad_configs: Vec<(&str, Vec<(&str, &str)>)> = vec![
+        (
+            "fast >= slow",
+            vec![("fast_period", "30"), ("slow_period", "20")],
+        ),
+        ("float period", vec![("fast_period", "5.5")]),
+        ("out of range", vec![("slow_period", "1000")]),
+        ("unknown name", vec![("fast", "5")]),
+    ];
+    for (label, raw) in &bad_configs {
+        let err = schema.parse(raw).expect_err("config must be rejected");
+        println!("  Rejected ({}): {}", label, err);
     }
-}
-
-impl TradingIndicator for EMA {
-    fn calculate(&self, prices: &[f64]) -> Vec<f64> {
-        if prices.len() < self.period {
-            return vec![];
-        }
+    assert!(matches!(
+        CrossoverStrategy::try_new(20, 10),
+        Err(ParamError::ConstraintViolated(_))
+    ));
+
+    let search_space = ParamSchema::new()
+        .param(ParamSpec::int("fast_period", 5, 5, 20, 5))
+        .param(ParamSpec::int("slow_period", 20, 10, 30, 10))
+        .param(ParamSpec::bool("allow_short", false))
+        .constraint(Constraint::less_than("fast_period", "slow_period"));
+    let grid = search_space.grid();
+    println!(
+        "Search space: {} raw points, {} valid",
+        search_space.grid_size(),
+        grid.len()
+    );
+    assert_eq!(search_space.grid_size(), 4 * 3 * 2);
+    // (5,10) (5,20) (10,20) (15,20) (5,30) (10,30) (15,30) (20,30), each with both bools
+    assert_eq!(grid.len(), 16);
+    assert!(
+        grid.iter()
+            .all(|p| p.int("fast_period") < p.int("slow_period"))
+    );
 
-        let mut result = Vec::with_capacity(prices.len() - self.period + 1);
-
-        let first_ema: f64 = prices[..self.period].iter().sum::<f64>()
-            / self.period as f64;
-        result.push(first_ema);
+    // Test strategy manager
+    let mut manager =
+        StrategyManager::new().with_policy(AggregationPolicy::WeightedVote { threshold: 0.5 });
+    manager.add_weighted_strategy(Box::new(CrossoverStrategy::new(5, 20)), 2.0);
+    manager.add_strategy(Box::new(CrossoverStrategy::new(10, 30)));
 
-        let mut prev_ema = first_ema;
-        for price in &prices[self.period..] {
-            let ema = (price - prev_ema) * self.multiplier + prev_ema;
-            result.push(ema);
-            prev_ema = ema;
+    println!("\n=== Combined Decisions from All Strategies ===");
+    let ctx = StrategyContext::new(Account::new(100_000.0));
+    let mut data = MarketData::new("BTCUSDT");
+    manager.start(&ctx);
+    for i in 0..full.prices.len() {
+        data.add_candle(full.prices[i], full.volumes[i], full.timestamps[i]);
+        let decision = manager.decide(&data, &ctx);
+        if decision.signal != Signal::Hold {
+            print!("bar {:>3} ", i);
+            decision.print();
+            assert!(!decision.supporters().is_empty());
         }
-
-        result
     }
-
-    fn name(&self) -> &str {
-        "EMA"
+    manager.finish(&ctx);
+
+    // Test aggregation policies on a fixed set of votes
+    println!("\n=== Aggregation Policies ===");
+    let buy = |q: f64| Signal::Buy {
+        price: 100.0,
+        quantity: q,
+    };
+    let sell = |q: f64| Signal::Sell {
+        price: 100.0,
+        quantity: q,
+    };
+    let votes = vec![
+        ("trend", Signal::Hold, 1.0),
+        ("breakout", buy(2.0), 1.0),
+        ("momentum", buy(1.0), 1.0),
+        ("reversion", sell(1.0), 3.0),
+    ];
+
+    let majority = combine_signals(&votes, AggregationPolicy::MajorityVote, 100.0);
+    majority.print();
+    assert_eq!(majority.signal, Signal::Hold, "2 of 4 is not a majority");
+
+    let weighted = combine_signals(
+        &votes,
+        AggregationPolicy::WeightedVote { threshold: 0.1 },
+        100.0,
+    );
+    weighted.print();
+    assert!(
+        matches!(weighted.signal, Signal::Sell { .. }),
+        "Weight 3 sell outvotes two buys"
+    );
+    assert_eq!(weighted.supporters(), vec!["reversion"]);
+
+    let unanimous = combine_signals(&votes[1..3], AggregationPolicy::Unanimous, 100.0);
+    unanimous.print();
+    assert_eq!(
+        unanimous.signal,
+        buy(1.5),
+        "Unanimous buy uses the average quantity"
+    );
+
+    let priority = combine_signals(&votes, AggregationPolicy::Priority, 100.0);
+    priority.print();
+    assert_eq!(priority.signal, buy(2.0), "First non-hold wins");
+    assert_eq!(priority.attribution[2].contribution, 0.0);
+
+    let net = combine_signals(&votes, AggregationPolicy::NetPosition, 100.0);
+    net.print();
+    assert_eq!(net.signal, Signal::Hold, "2 + 1 - 3 = 0");
+    assert_eq!(net.attribution[3].contribution, -3.0);
+
+    // Test pairs trading on a synthetic cointegrated pair
+    println!("\n=== Pairs Trading ===");
+    let mut seed = 7u64;
+    let mut x_walk = 100.0;
+    let mut z_walk = 80.0;
+    let mut spread = 0.0;
+    let (mut xs, mut ys, mut zs) = (Vec::new(), Vec::new(), Vec::new());
+    for _ in 0..500 {
+        x_walk += 2.0 * gaussian_noise(&mut seed);
+        z_walk += gaussian_noise(&mut seed);
+        spread = 0.8 * spread + gaussian_noise(&mut seed);
+        xs.push(x_walk);
+        ys.push(10.0 + 1.5 * x_walk + spread);
+        zs.push(z_walk);
     }
 
-    fn min_periods(&self) -> usize {
-        self.period
+    let coint = engle_granger(&ys, &xs, 1).expect("enough data");
+    println!(
+        "Y~X: beta={:.3}, alpha={:.2}, ADF={:.2}, cointegrated(5%)={}",
+        coint.beta,
+        coint.alpha,
+        coint.adf_stat,
+        coint.is_cointegrated(0.05)
+    );
+    assert!(
+        (coint.beta - 1.5).abs() < 0.1,
+        "OLS should recover the hedge ratio"
+    );
+    assert!(coint.is_cointegrated(0.05));
+
+    let unrelated = engle_granger(&zs, &xs, 1).expect("enough data");
+    println!(
+        "Z~X: beta={:.3}, ADF={:.2}, cointegrated(5%)={}",
+        unrelated.beta,
+        unrelated.adf_stat,
+        unrelated.is_cointegrated(0.05)
+    );
+    assert!(
+        !unrelated.is_cointegrated(0.05),
+        "Independent walks are not cointegrated"
+    );
+
+    let mut kalman = KalmanHedge::new(1e-4, 1e-3);
+    for (x, y) in xs.iter().zip(&ys) {
+        kalman.update(*x, *y);
     }
-}
+    println!(
+        "Kalman hedge after {} bars: beta={:.3}, alpha={:.2}",
+        xs.len(),
+        kalman.beta(),
+        kalman.alpha()
+    );
+    assert!(
+        (kalman.beta() - 1.5).abs() < 0.2,
+        "Kalman should track the hedge ratio"
+    );
+
+    let to_bars = |closes: &[f64]| -> Vec<OHLCV> {
+        closes
+            .iter()
+            .enumerate()
+            .map(|(i, &close)| {
+                let open = if i > 0 { closes[i - 1] } else { close };
+                OHLCV::new(
+                    i as i64,
+                    open,
+                    open.max(close) + 0.2,
+                    open.min(close) - 0.2,
+                    close,
+                    1000.0,
+                )
+            })
+            .collect()
+    };
+    let mut tester = MultiInstrumentTester::new(10_000.0).with_commission(0.0005);
+    tester.add_instrument(Instrument::new("Y", to_bars(&ys)));
+    tester.add_instrument(Instrument::new("X", to_bars(&xs)));
+    tester.add_instrument(Instrument::new("Z", to_bars(&zs)));
+
+    let mut results = Vec::new();
+    for method in ["ols", "kalman"] {
+        let params = ParamSet::new()
+            .with("hedge_method", ParamValue::Choice(method.to_string()))
+            .with("quantity", ParamValue::Float(10.0));
+        let mut pairs = PairsTradingStrategy::from_params("Y", "X", &params).expect("valid params");
+        let result = tester.run_multi_leg(&mut pairs).expect("both legs exist");
+
+        println!(
+            "{} hedge: final equity ${:.2}, trades {}",
+            method,
+            result.final_equity(),
+            result.total_trades
+        );
+        for leg in &result.legs {
+            println!(
+                "  {:<2} realized {:>9.2}  commission {:>6.2}  fills {}",
+                leg.symbol, leg.realized, leg.commission, leg.fills
+            );
+            assert!(leg.fills > 0, "Both legs must trade");
+        }
+        let accounted = result.initial_capital + result.net_pnl();
+        assert!(
+            (accounted - result.final_equity()).abs() < 1e-6,
+            "Leg P&L must add up to equity"
+        );
+        results.push(result);
+    }
+    tester.print_summary(&results);
+
+    let mut missing =
+        PairsTradingStrategy::from_params("Y", "NOPE", &ParamSet::new()).expect("defaults");
+    assert_eq!(
+        tester.run_multi_leg(&mut missing).err(),
+        Some(TesterError::UnknownSymbol("NOPE".to_string()))
+    );
+    assert!(
+        PairsTradingStrategy::from_params(
+            "Y",
+            "X",
+            &ParamSet::new().with("exit_z", ParamValue::Float(2.5))
+        )
+        .is_err()
+    );
+
+    // Opening and closing both legs is one round trip, not one trade per leg
+    let mut spread = ScriptedLegs {
+        symbols: vec!["Y".to_string(), "X".to_string()],
+        orders: vec![
+            (
+                2,
+                LegIntent {
+                    leg: 0,
+                    intent: OrderIntent::TargetPosition { quantity: 10.0 },
+                },
+            ),
+            (
+                2,
+                LegIntent {
+                    leg: 1,
+                    intent: OrderIntent::TargetPosition { quantity: -10.0 },
+                },
+            ),
+            (
+                5,
+                LegIntent {
+                    leg: 0,
+                    intent: OrderIntent::ClosePosition,
+                },
+            ),
+            (
+                6,
+                LegIntent {
+                    leg: 1,
+                    intent: OrderIntent::ClosePosition,
+                },
+            ),
+        ],
+    };
+    let result = tester
+        .run_multi_leg(&mut spread)
+        .expect("market orders only");
+    assert_eq!(
+        result.legs.iter().map(|leg| leg.fills).collect::<Vec<_>>(),
+        vec![2, 2]
+    );
+    assert_eq!(result.total_trades, 1);
+    assert_eq!(result.winning_trades, usize::from(result.net_pnl() > 0.0));
+
+    // Resting orders and brackets are refused rather than dropped
+    let limit_entry = OrderIntent::Entry {
+        side: Side::Buy,
+        quantity: 1.0,
+        order_type: OrderType::Limit(95.0),
+        bracket: None,
+    };
+    spread.orders = vec![(
+        2,
+        LegIntent {
+            leg: 1,
+            intent: limit_entry.clone(),
+        },
+    )];
+    assert_eq!(
+        tester.run_multi_leg(&mut spread).err(),
+        Some(TesterError::UnsupportedOrder(limit_entry))
+    );
+    spread.orders = vec![(
+        2,
+        LegIntent {
+            leg: 2,
+            intent: OrderIntent::ClosePosition,
+        },
+    )];
+    assert_eq!(
+        tester.run_multi_leg(&mut spread).err(),
+        Some(TesterError::UnknownLeg(2))
+    );
+
+    test_reference_strategies();
+    test_event_engine();
+    test_margin_and_shorts();
+    test_perpetuals();
+    test_portfolio();
+    test_walk_forward();
+    test_cross_validation();
+    test_overfitting();
+    test_grid_search();
+    test_optimizers();
+    test_monte_carlo();
+    test_stress();
+    test_metrics();
+    test_extended_metrics();
+    test_comparison();
+    test_trade_analysis();
+    test_benchmark();
+    test_paper_trading();
 
-/// RSI indicator
-#[derive(Debug, Clone)]
-pub struct RSI {
-    period: usize,
+    println!("\n=== All tests passed! ===");
 }
 
-impl RSI {
-    pub fn new(period: usize) -> Self {
-        assert!(period > 0, "Period must be greater than 0");
-        RSI { period }
+/// Deterministic, roughly normal noise (sum of uniforms from an LCG)
+fn gaussian_noise(seed: &mut u64) -> f64 {
+    let mut sum = 0.0;
+    for _ in 0..6 {
+        *seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        sum += (*seed >> 11) as f64 / (1u64 << 53) as f64;
     }
+    (sum - 3.0) * 2.0_f64.sqrt()
 }
 
-impl TradingIndicator for RSI {
-    fn calculate(&self, prices: &[f64]) -> Vec<f64> {
-        if prices.len() < self.period + 1 {
-            return vec![];
+/// Drives a strategy over closes, filling every intent at the same close
+///
+/// Returns the net position change of every bar that traded.
+fn trades_on_closes(strategy: &mut dyn Strategy, closes: &[f64]) -> Vec<(usize, f64)> {
+    let mut ctx = StrategyContext::new(Account::new(10_000.0));
+    let mut data = MarketData::new("TEST");
+    let mut trades = Vec::new();
+
+    strategy.on_start(&ctx);
+    for (i, &close) in closes.iter().enumerate() {
+        data.add_candle(close, 1000.0, i as i64);
+        ctx.bar_index = i;
+
+        let before = ctx.position.quantity;
+        for intent in strategy.on_bar(&data, &ctx) {
+            let delta = intent.position_delta(&ctx.position);
+            if delta != 0.0 {
+                let fill = Fill {
+                    timestamp: i as i64,
+                    side: if delta > 0.0 { Side::Buy } else { Side::Sell },
+                    price: close,
+                    quantity: delta.abs(),
+                    commission: 0.0,
+                };
+                ctx.account.cash += ctx.position.apply_fill(&fill);
+                strategy.on_fill(&fill, &ctx);
+            }
         }
+        if ctx.position.quantity != before {
+            trades.push((i, ctx.position.quantity - before));
+        }
+    }
+    strategy.on_end(&ctx);
+    trades
+}
 
-        let changes: Vec<f64> = prices
-            .windows(2)
-            .map(|w| w[1] - w[0])
-            .collect();
-
-        let mut gains: Vec<f64> = Vec::new();
-        let mut losses: Vec<f64> = Vec::new();
+fn test_reference_strategies() {
+    println!("\n=== Reference Strategies ===");
+
+    // Bollinger: quiet range, a drop below the lower band, then recovery to the middle
+    let mut closes: Vec<f64> = (0..20)
+        .map(|i| if i % 2 == 0 { 100.0 } else { 101.0 })
+        .collect();
+    closes.extend([95.0, 97.0, 100.0]);
+    let mut bollinger =
+        BollingerReversion::from_params(&ParamSet::new().with("period", ParamValue::Int(10)))
+            .expect("valid params");
+    let trades = trades_on_closes(&mut bollinger, &closes);
+    println!("{}: {:?}", bollinger.name(), trades);
+    assert_eq!(trades, vec![(20, 1.0), (22, -1.0)]);
+
+    // Same entry, but the next close hits a 3% stop
+    closes[21] = 92.0;
+    let stop_params = ParamSet::new()
+        .with("period", ParamValue::Int(10))
+        .with("stop_loss_pct", ParamValue::Float(0.03));
+    let mut stopped = BollingerReversion::from_params(&stop_params).expect("valid params");
+    let trades = trades_on_closes(&mut stopped, &closes);
+    println!("{} with 3% stop: {:?}", stopped.name(), trades);
+    assert_eq!(trades, vec![(20, 1.0), (21, -1.0)]);
+
+    let mut data = MarketData::new("TEST");
+    for (i, close) in closes[..21].iter().enumerate() {
+        data.add_candle(*close, 1000.0, i as i64);
+    }
+    let intents = stopped.on_bar(&data, &StrategyContext::new(Account::new(10_000.0)));
+    let Some(OrderIntent::Entry {
+        bracket: Some(bracket),
+        ..
+    }) = intents.first()
+    else {
+        panic!("Entry should carry a bracket, got {:?}", intents);
+    };
+    assert!((bracket.stop_loss.unwrap() - 95.0 * 0.97).abs() < 1e-9);
+    assert_eq!(bracket.take_profit, None);
+
+    // Donchian: breakout above the 5-bar high, exit below the 3-bar low, then a short breakdown
+    let closes = [
+        100.0, 101.0, 100.0, 101.0, 100.0, 101.0, 102.0, 103.0, 104.0, 103.5, 101.0, 99.0,
+    ];
+    let donchian_params = ParamSet::new()
+        .with("entry_period", ParamValue::Int(5))
+        .with("exit_period", ParamValue::Int(3))
+        .with("allow_short", ParamValue::Bool(true));
+    let mut donchian = DonchianBreakout::from_params(&donchian_params).expect("valid params");
+    let trades = trades_on_closes(&mut donchian, &closes);
+    println!("{}: {:?}", donchian.name(), trades);
+    assert_eq!(trades, vec![(6, 1.0), (10, -1.0), (11, -1.0)]);
+
+    // Momentum: 3-bar return above +2% goes long, fades to flat, then below -2% goes short
+    let closes = [
+        100.0, 100.0, 100.0, 100.0, 103.0, 104.0, 103.0, 101.0, 98.0, 97.0,
+    ];
+    let momentum_params = ParamSet::new()
+        .with("lookback", ParamValue::Int(3))
+        .with("threshold", ParamValue::Float(0.02))
+        .with("allow_short", ParamValue::Bool(true));
+    let mut momentum = TimeSeriesMomentum::from_params(&momentum_params).expect("valid params");
+    let trades = trades_on_closes(&mut momentum, &closes);
+    println!("{}: {:?}", momentum.name(), trades);
+    assert_eq!(trades, vec![(4, 1.0), (7, -1.0), (8, -1.0)]);
+
+    assert!(
+        DonchianBreakout::from_params(&ParamSet::new().with("exit_period", ParamValue::Int(50)))
+            .is_err()
+    );
+
+    // All three on a common instrument set
+    let mut tester = MultiInstrumentTester::new(10_000.0);
+    let mut seed = 11u64;
+    let mut price = 100.0;
+    let bars: Vec<OHLCV> = (0..300)
+        .map(|i| {
+            let open = price;
+            price *= 1.0 + 0.01 * gaussian_noise(&mut seed) + 0.002 * (i as f64 * 0.03).sin();
+            OHLCV::new(
+                i,
+                open,
+                open.max(price) * 1.002,
+                open.min(price) * 0.998,
+                price,
+                1000.0,
+            )
+        })
+        .collect();
+    tester.add_instrument(Instrument::new("SYN", bars));
+
+    let mut results = Vec::new();
+    results.extend(
+        tester
+            .run_tests(|| {
+                Box::new(BollingerReversion::from_params(&ParamSet::new()).expect("defaults"))
+            })
+            .expect("no resting orders"),
+    );
+    results.extend(
+        tester
+            .run_tests(|| {
+                Box::new(DonchianBreakout::from_params(&ParamSet::new()).expect("defaults"))
+            })
+            .expect("no resting orders"),
+    );
+    results.extend(
+        tester
+            .run_tests(|| {
+                Box::new(TimeSeriesMomentum::from_params(&ParamSet::new()).expect("defaults"))
+            })
+            .expect("no resting orders"),
+    );
+    tester.print_summary(&results);
+    assert_eq!(results.len(), 3);
+}
 
-        for change in &changes {
-            if *change > 0.0 {
-                gains.push(*change);
-                losses.push(0.0);
-            } else {
-                gains.push(0.0);
-                losses.push(-change);
-            }
-        }
+/// Emits fixed intents on fixed bars
+#[derive(Clone)]
+struct Scripted {
+    orders: Vec<(usize, OrderIntent)>,
+}
 
-        let mut result = Vec::new();
+impl Strategy for Scripted {
+    fn name(&self) -> &str {
+        "Scripted"
+    }
 
-        let mut avg_gain: f64 = gains[..self.period].iter().sum::<f64>()
-            / self.period as f64;
-        let mut avg_loss: f64 = losses[..self.period].iter().sum::<f64>()
-            / self.period as f64;
+    fn parameters(&self) -> ParamSet {
+        ParamSet::new()
+    }
 
-        for i in self.period..gains.len() {
-            avg_gain = (avg_gain * (self.period - 1) as f64 + gains[i])
-                / self.period as f64;
-            avg_loss = (avg_loss * (self.period - 1) as f64 + losses[i])
-                / self.period as f64;
+    fn on_bar(&mut self, _data: &MarketData, ctx: &StrategyContext) -> Vec<OrderIntent> {
+        self.orders
+            .iter()
+            .filter(|(bar, _)| *bar == ctx.bar_index)
+            .map(|(_, intent)| intent.clone())
+            .collect()
+    }
+}
 
-            let rs = if avg_loss != 0.0 {
-                avg_gain / avg_loss
-            } else {
-                100.0
-            };
+/// Emits fixed leg intents on fixed bars
+struct ScriptedLegs {
+    symbols: Vec<String>,
+    orders: Vec<(usize, LegIntent)>,
+}
 
-            result.push(100.0 - (100.0 / (1.0 + rs)));
-        }
+impl MultiLegStrategy for ScriptedLegs {
+    fn name(&self) -> &str {
+        "ScriptedLegs"
+    }
 
-        result
+    fn symbols(&self) -> Vec<String> {
+        self.symbols.clone()
     }
 
-    fn name(&self) -> &str {
-        "RSI"
+    fn parameters(&self) -> ParamSet {
+        ParamSet::new()
     }
 
-    fn min_periods(&self) -> usize {
-        self.period + 1
+    fn on_bar(&mut self, _data: &[MarketData], ctx: &MultiLegContext) -> Vec<LegIntent> {
+        self.orders
+            .iter()
+            .filter(|(bar, _)| *bar == ctx.bar_index)
+            .map(|(_, intent)| intent.clone())
+            .collect()
     }
 }
 
-// ============================================
-// Part 2: Trading Strategy
-// ============================================
+fn test_event_engine() {
+    println!("\n=== Event-Driven Engine ===");
+
+    let bar = |t: i64, o: f64, h: f64, l: f64, c: f64| OHLCV::new(t, o, h, l, c, 1000.0);
+    let engine = Backtester::new(BacktestConfig::new(10_000.0));
+
+    // A decision on bar 0's close fills at bar 1's open, never at bar 0's close
+    let instrument = Instrument::new(
+        "TEST",
+        vec![
+            bar(0, 100.0, 101.0, 99.0, 100.0),
+            bar(1, 102.0, 103.0, 101.0, 102.5),
+            bar(2, 103.0, 104.0, 102.0, 103.5),
+        ],
+    );
+    let mut strategy = Scripted {
+        orders: vec![(0, OrderIntent::TargetPosition { quantity: 1.0 })],
+    };
+    let results = engine.run(&mut strategy, &instrument);
+    assert_eq!(results.fills[0].timestamp, 1);
+    assert_eq!(results.fills[0].price, 102.0);
+    assert_eq!(results.trades.len(), 1);
+    assert_eq!(results.trades[0].exit_reason, ExitReason::EndOfData);
+    assert!((results.final_balance - 10_001.5).abs() < 1e-9);
+
+    // A buy limit rests until a bar trades through it; a gap fills at the open
+    let limit_entry = OrderIntent::Entry {
+        side: Side::Buy,
+        quantity: 1.0,
+        order_type: OrderType::Limit(95.0),
+        bracket: None,
+    };
+    let instrument = Instrument::new(
+        "TEST",
+        vec![
+            bar(0, 100.0, 101.0, 99.0, 100.0),
+            bar(1, 99.0, 100.0, 97.0, 98.0),
+            bar(2, 98.0, 98.5, 94.0, 96.0),
+            bar(3, 93.0, 94.0, 92.0, 93.0),
+        ],
+    );
+    let mut strategy = Scripted {
+        orders: vec![(0, limit_entry.clone()), (2, limit_entry)],
+    };
+    let results = engine.run(&mut strategy, &instrument);
+    let entries: Vec<(i64, f64)> = results
+        .fills
+        .iter()
+        .map(|f| (f.timestamp, f.price))
+        .take(2)
+        .collect();
+    println!("Limit fills: {:?}", entries);
+    assert_eq!(entries, vec![(2, 95.0), (3, 93.0)]);
+
+    // CancelPending removes a resting order before it can fill
+    let mut strategy = Scripted {
+        orders: vec![
+            (
+                0,
+                OrderIntent::Entry {
+                    side: Side::Buy,
+                    quantity: 1.0,
+                    order_type: OrderType::Limit(95.0),
+                    bracket: None,
+                },
+            ),
+            (1, OrderIntent::CancelPending),
+        ],
+    };
+    assert!(engine.run(&mut strategy, &instrument).fills.is_empty());
+
+    // A bar touching both the stop-loss and the take-profit: the path decides
+    let bracketed = |side: Side, stop: f64, target: f64| OrderIntent::Entry {
+        side,
+        quantity: 1.0,
+        order_type: OrderType::Market,
+        bracket: Some(Bracket {
+            stop_loss: Some(stop),
+            take_profit: Some(target),
+        }),
+    };
+    let instrument = Instrument::new(
+        "TEST",
+        vec![
+            bar(0, 100.0, 100.5, 99.5, 100.0),
+            bar(1, 100.0, 100.5, 99.5, 100.0),
+            bar(2, 100.0, 106.0, 94.0, 100.0),
+        ],
+    );
+    let exit_of = |path: IntrabarPath, side: Side, stop: f64, target: f64| {
+        let engine = Backtester::new(BacktestConfig::new(10_000.0).with_intrabar_path(path));
+        let mut strategy = Scripted {
+            orders: vec![(0, bracketed(side, stop, target))],
+        };
+        let trade = engine.run(&mut strategy, &instrument).trades[0].clone();
+        (trade.exit_reason, trade.exit_price)
+    };
+    for path in [
+        IntrabarPath::Ohlc,
+        IntrabarPath::Olhc,
+        IntrabarPath::WorstCase,
+    ] {
+        println!(
+            "{:>10}: long {:?}, short {:?}",
+            path.to_string(),
+            exit_of(path, Side::Buy, 95.0, 105.0),
+            exit_of(path, Side::Sell, 105.0, 95.0)
+        );
+    }
+    assert_eq!(
+        exit_of(IntrabarPath::Ohlc, Side::Buy, 95.0, 105.0),
+        (ExitReason::TakeProfit, 105.0)
+    );
+    assert_eq!(
+        exit_of(IntrabarPath::Olhc, Side::Buy, 95.0, 105.0),
+        (ExitReason::StopLoss, 95.0)
+    );
+    assert_eq!(
+        exit_of(IntrabarPath::WorstCase, Side::Buy, 95.0, 105.0),
+        (ExitReason::StopLoss, 95.0)
+    );
+    assert_eq!(
+        exit_of(IntrabarPath::Olhc, Side::Sell, 105.0, 95.0),
+        (ExitReason::TakeProfit, 95.0)
+    );
+    assert_eq!(
+        exit_of(IntrabarPath::WorstCase, Side::Sell, 105.0, 95.0),
+        (ExitReason::StopLoss, 105.0)
+    );
+
+    // A stop that the open gaps through fills at the open
+    let gapped = Instrument::new(
+        "TEST",
+        vec![
+            bar(0, 100.0, 100.5, 99.5, 100.0),
+            bar(1, 100.0, 100.5, 99.5, 100.0),
+            bar(2, 90.0, 91.0, 89.0, 90.5),
+        ],
+    );
+    let mut strategy = Scripted {
+        orders: vec![(0, bracketed(Side::Buy, 95.0, 105.0))],
+    };
+    let trade = engine.run(&mut strategy, &gapped).trades[0].clone();
+    assert_eq!(
+        (trade.exit_reason, trade.exit_price),
+        (ExitReason::StopLoss, 90.0)
+    );
+
+    // Crossover on a noisy trend: equity reconciles with trades and costs
+    let mut seed = 5u64;
+    let mut price = 100.0;
+    let bars: Vec<OHLCV> = (0..400)
+        .map(|i| {
+            let open = price;
+            price *= 1.0 + 0.01 * gaussian_noise(&mut seed) + 0.003 * (i as f64 * 0.02).sin();
+            OHLCV::new(
+                i,
+                open,
+                open.max(price) * 1.003,
+                open.min(price) * 0.997,
+                price,
+                1000.0,
+            )
+        })
+        .collect();
+    let engine = Backtester::new(
+        BacktestConfig::new(10_000.0)
+            .with_commission(0.001)
+            .with_slippage(0.0005)
+            .with_intrabar_path(IntrabarPath::WorstCase),
+    );
+    let mut crossover = CrossoverStrategy::new(5, 20);
+    let results = engine.run(&mut crossover, &Instrument::new("SYN", bars));
+    println!(
+        "{} on SYN: {} trades, win rate {:.1}%, return {:.2}%, max DD {:.2}%, commission {:.2}",
+        results.strategy,
+        results.trades.len(),
+        results.win_rate(),
+        results.total_return(),
+        results.max_drawdown(),
+        results.total_commission()
+    );
+    let trade_pnl: f64 = results.trades.iter().map(|t| t.profit).sum();
+    assert!(!results.trades.is_empty());
+    assert!((results.initial_balance + trade_pnl - results.final_balance).abs() < 1e-6);
+    assert_eq!(results.equity_curve.len(), 400);
+}
 
-/// Trading signal
-#[derive(Debug, Clone, PartialEq)]
-pub enum Signal {
-    Buy { price: f64, quantity: f64 },
-    Sell { price: f64, quantity: f64 },
-    Hold,
+fn test_margin_and_shorts() {
+    println!("\n=== Shorts, Leverage and Margin ===");
+
+    let bar = |t: i64, o: f64, h: f64, l: f64, c: f64| OHLCV::new(t, o, h, l, c, 1000.0);
+    let falling = Instrument::new(
+        "TEST",
+        (0..6)
+            .map(|i| {
+                let c = 100.0 - 2.0 * i as f64;
+                bar(i, c + 1.0, c + 1.5, c - 0.5, c)
+            })
+            .collect(),
+    );
+
+    // Short 10 units with a 10% annual borrow fee; shorts are marked to market every bar
+    let engine = Backtester::new(
+        BacktestConfig::new(10_000.0)
+            .with_margin(MarginConfig::cash().with_borrow_rate(0.10))
+            .with_bars_per_year(252.0),
+    );
+    let mut strategy = Scripted {
+        orders: vec![(0, OrderIntent::TargetPosition { quantity: -10.0 })],
+    };
+    let results = engine.run(&mut strategy, &falling);
+    let entry = results.fills[0].price;
+    assert_eq!(entry, 99.0);
+    let at_bar_3 = results.equity_curve[3].balance;
+    let fees_so_far: f64 = (1..=3)
+        .map(|i| 10.0 * (100.0 - 2.0 * i as f64) * 0.10 / 252.0)
+        .sum();
+    assert!((at_bar_3 - (10_000.0 + 10.0 * (entry - 94.0) - fees_so_far)).abs() < 1e-9);
+
+    let trade = &results.trades[0];
+    println!(
+        "Short: entry {:.2}, exit {:.2}, P&L {:.2} after {:.4} borrow fees",
+        trade.entry_price, trade.exit_price, trade.profit, trade.financing
+    );
+    assert_eq!(trade.side, Side::Sell);
+    assert!(trade.profit > 0.0 && trade.financing > 0.0);
+    assert!((results.initial_balance + trade.profit - results.final_balance).abs() < 1e-9);
+
+    // A cash account cannot open twice its equity; 5x leverage can
+    let double = Scripted {
+        orders: vec![(0, OrderIntent::TargetPosition { quantity: 200.0 })],
+    };
+    let results = Backtester::new(BacktestConfig::new(10_000.0)).run(&mut double.clone(), &falling);
+    assert!(results.fills.is_empty());
+    assert_eq!(results.rejected_orders, 1);
+    let leveraged = BacktestConfig::new(10_000.0).with_margin(MarginConfig::leveraged(5.0, 0.05));
+    let results = Backtester::new(leveraged).run(&mut double.clone(), &falling);
+    assert_eq!(results.fills.len(), 2);
+
+    // 5x long: equity reaches 5% maintenance margin near 84.21 and the position is liquidated there
+    let crash = Instrument::new(
+        "TEST",
+        vec![
+            bar(0, 100.0, 100.5, 99.5, 100.0),
+            bar(1, 100.0, 101.0, 99.0, 100.0),
+            bar(2, 99.0, 99.5, 80.0, 82.0),
+            bar(3, 82.0, 83.0, 81.0, 82.5),
+        ],
+    );
+    let mut strategy = Scripted {
+        orders: vec![(0, OrderIntent::TargetPosition { quantity: 500.0 })],
+    };
+    let results = Backtester::new(leveraged).run(&mut strategy, &crash);
+    let trade = &results.trades[0];
+    let expected = (500.0 * 100.0 - 10_000.0) / (500.0 * 0.95);
+    println!(
+        "5x long liquidated at {:.2} ({}), equity left {:.2}",
+        trade.exit_price, trade.exit_reason, results.final_balance
+    );
+    assert_eq!(results.liquidations(), 1);
+    assert!((trade.exit_price - expected).abs() < 1e-9);
+    assert!((results.final_balance - 0.05 * 500.0 * expected).abs() < 1e-6);
+    assert_eq!(
+        leveraged
+            .margin
+            .liquidation_price(&leveraged.contract, &Default::default(), 10_000.0),
+        None
+    );
 }
 
-/// Trait for trading strategies
-pub trait Strategy: Send + Sync {
-    fn name(&self) -> &str;
-    fn generate_signal(&self, data: &MarketData) -> Signal;
-    fn parameters(&self) -> HashMap<String, f64>;
+fn test_perpetuals() {
+    println!("\n=== Perpetual Futures ===");
+
+    let flat = |t: i64, p: f64| OHLCV::new(t, p, p, p, p, 1000.0);
+    let buy = |quantity: f64| OrderIntent::TargetPosition { quantity };
+
+    // Linear contract: 0.01 BTC per contract, fills rounded to a 0.5 tick
+    let instrument = Instrument::new(
+        "BTC-PERP",
+        vec![flat(0, 30_000.0), flat(1, 30_000.0), flat(2, 31_000.0)],
+    );
+    let config = BacktestConfig::new(10_000.0)
+        .with_contract(ContractSpec::linear(0.01, 0.5))
+        .with_slippage(0.0001);
+    let mut strategy = Scripted {
+        orders: vec![(0, buy(10.0))],
+    };
+    let results = Backtester::new(config).run(&mut strategy, &instrument);
+    let entry = results.fills[0].price;
+    assert_eq!(entry, 30_003.0);
+    assert!((results.trades[0].profit - 10.0 * 0.01 * (31_000.0 - entry)).abs() < 1e-9);
+
+    // Inverse contract: $100 per contract, P&L in BTC; two entries average harmonically
+    let instrument = Instrument::new(
+        "BTCUSD",
+        vec![
+            flat(0, 20_000.0),
+            flat(1, 20_000.0),
+            flat(2, 25_000.0),
+            flat(3, 25_000.0),
+        ],
+    );
+    let config = BacktestConfig::new(1.0)
+        .with_contract(ContractSpec::inverse(100.0, 0.5))
+        .with_margin(MarginConfig::leveraged(10.0, 0.005));
+    let mut strategy = Scripted {
+        orders: vec![(0, buy(50.0)), (1, buy(100.0))],
+    };
+    let results = Backtester::new(config).run(&mut strategy, &instrument);
+    let trade = &results.trades[0];
+    println!(
+        "Inverse: entry {:.2}, P&L {:.4} BTC, return {:.2}%",
+        trade.entry_price,
+        trade.profit,
+        trade.return_pct()
+    );
+    assert!((trade.entry_price - 2.0 / (1.0 / 20_000.0 + 1.0 / 25_000.0)).abs() < 1e-6);
+    assert!((trade.profit - 50.0 * 100.0 * (1.0 / 20_000.0 - 1.0 / 25_000.0)).abs() < 1e-12);
+
+    // Hourly bars, funding every 8 hours: pay 0.01% once, then receive 0.02%
+    let hour = 3_600;
+    let instrument = Instrument::new("ETH-PERP", (0..24).map(|i| flat(i * hour, 100.0)).collect());
+    let funding = FundingSchedule::new(8 * hour, vec![(0, 0.0001), (10 * hour, -0.0002)]);
+    assert_eq!(
+        funding.payments_between(0, 23 * hour),
+        vec![8 * hour, 16 * hour]
+    );
+    let perpetual = PerpetualData::new(Vec::new(), funding);
+    let mut strategy = Scripted {
+        orders: vec![(0, buy(10.0))],
+    };
+    let results = Backtester::new(BacktestConfig::new(10_000.0)).run_perpetual(
+        &mut strategy,
+        &instrument,
+        &perpetual,
+    );
+    println!(
+        "Funding: trading {:.4}, funding {:.4}, final {:.4}",
+        results.trading_pnl(),
+        results.funding_pnl(),
+        results.final_balance
+    );
+    assert!(results.trading_pnl().abs() < 1e-12);
+    assert!((results.funding_pnl() - (-1_000.0 * 0.0001 + 1_000.0 * 0.0002)).abs() < 1e-12);
+    assert!((results.final_balance - 10_000.0 - results.funding_pnl()).abs() < 1e-9);
+
+    // 10x long, liquidation near 94.74: a wick in the last price alone does not liquidate,
+    // a drop in the mark price does; unrealized P&L follows the mark
+    let bar = |t: i64, o: f64, h: f64, l: f64, c: f64| OHLCV::new(t, o, h, l, c, 1000.0);
+    let instrument = Instrument::new(
+        "SOL-PERP",
+        vec![
+            flat(0, 100.0),
+            flat(1, 100.0),
+            bar(2, 100.0, 100.0, 90.0, 99.0),
+            bar(3, 98.0, 98.0, 96.0, 97.0),
+        ],
+    );
+    let mark = vec![
+        flat(0, 100.0),
+        flat(1, 100.0),
+        bar(2, 100.0, 100.0, 97.0, 98.0),
+        bar(3, 98.0, 98.0, 94.0, 95.0),
+    ];
+    let perpetual = PerpetualData {
+        mark,
+        funding: None,
+    };
+    let config = BacktestConfig::new(10_000.0).with_margin(MarginConfig::leveraged(10.0, 0.05));
+    let mut strategy = Scripted {
+        orders: vec![(0, buy(1_000.0))],
+    };
+    let results = Backtester::new(config).run_perpetual(&mut strategy, &instrument, &perpetual);
+    assert_eq!(
+        results.equity_curve[2].balance,
+        10_000.0 + 1_000.0 * (98.0 - 100.0)
+    );
+    let trade = &results.trades[0];
+    assert_eq!(
+        (trade.exit_reason, trade.exit_bar),
+        (ExitReason::Liquidation, 3)
+    );
+    assert!((trade.exit_price - 90_000.0 / 950.0).abs() < 1e-9);
 }
 
-/// Market data
-#[derive(Debug, Clone)]
-pub struct MarketData {
-    pub symbol: String,
-    pub prices: Vec<f64>,
-    pub volumes: Vec<f64>,
-    pub timestamps: Vec<i64>,
+fn test_portfolio() {
+    println!("\n=== Portfolio Backtest ===");
+
+    let series = |seed: u64, drift: f64, vol: f64, range: std::ops::Range<i64>| {
+        let mut seed = seed;
+        let mut price = 100.0;
+        range
+            .map(|t| {
+                let open = price;
+                price *= 1.0 + drift + vol * gaussian_noise(&mut seed);
+                OHLCV::new(t, open, open.max(price), open.min(price), price, 1000.0)
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // A trades every day; B misses every 10th bar; C lists at 50 and delists at 150
+    let a = series(1, 0.002, 0.01, 0..200);
+    let b: Vec<OHLCV> = series(2, -0.002, 0.01, 0..200)
+        .into_iter()
+        .filter(|bar| bar.timestamp % 10 != 5)
+        .collect();
+    let c = series(3, 0.001, 0.015, 50..150);
+
+    let make_portfolio = |missing: MissingBars| {
+        let mut portfolio =
+            PortfolioBacktester::new(BacktestConfig::new(30_000.0).with_commission(0.001))
+                .with_missing_bars(missing);
+        portfolio.add_instrument(Instrument::new("A", a.clone()));
+        portfolio.add_instrument(Instrument::new("B", b.clone()));
+        portfolio.add_instrument(Instrument::new("C", c.clone()));
+        portfolio
+    };
+    let momentum = || -> Box<dyn Strategy> {
+        let params = ParamSet::new()
+            .with("lookback", ParamValue::Int(10))
+            .with("allow_short", ParamValue::Bool(true));
+        Box::new(TimeSeriesMomentum::from_params(&params).expect("valid params"))
+    };
+
+    let portfolio = make_portfolio(MissingBars::Skip);
+    let results = portfolio.run(momentum);
+    results.print_summary();
+    let attributed: f64 = results.instruments.iter().map(|i| i.pnl.net()).sum();
+    assert_eq!(results.equity_curve.len(), 200);
+    assert!((results.initial_balance + attributed - results.final_balance).abs() < 1e-6);
+    assert_eq!(results.instrument("B").unwrap().missing_bars, 20);
+    assert_eq!(results.instrument("C").unwrap().missing_bars, 0);
+    assert_eq!(results.instrument("C").unwrap().bars, 100);
+
+    // Dropping incomplete timestamps shortens the timeline to the common calendar
+    let intersect = make_portfolio(MissingBars::Intersect);
+    assert_eq!(intersect.timeline().len(), 90);
+    let results = intersect.run(momentum);
+    assert!(
+        (results.initial_balance + results.instruments.iter().map(|i| i.pnl.net()).sum::<f64>()
+            - results.final_balance)
+            .abs()
+            < 1e-6
+    );
+
+    // Forward-filled bars reach the strategy but nothing fills on them
+    let results = make_portfolio(MissingBars::ForwardFill).run(momentum);
+    assert_eq!(results.instrument("B").unwrap().bars, 180);
+
+    // At most one position at a time
+    let results = make_portfolio(MissingBars::Skip)
+        .with_max_positions(1)
+        .run(momentum);
+    println!(
+        "Max one position: {} entries, {} signals skipped",
+        results.instruments.iter().map(|i| i.entries).sum::<usize>(),
+        results.skipped_entries
+    );
+    assert!(results.skipped_entries > 0);
+
+    // Volatility targeting gives the quiet instrument the larger allocation
+    let mut portfolio = PortfolioBacktester::new(
+        BacktestConfig::new(10_000.0).with_margin(MarginConfig::leveraged(10.0, 0.05)),
+    )
+    .with_allocation(Allocation::VolatilityTarget {
+        target_vol: 0.10,
+        lookback: 20,
+    });
+    portfolio.add_instrument(Instrument::new("QUIET", series(4, 0.0, 0.002, 0..60)));
+    portfolio.add_instrument(Instrument::new("WILD", series(5, 0.0, 0.02, 0..60)));
+    let results = portfolio.run(|| -> Box<dyn Strategy> {
+        Box::new(Scripted {
+            orders: vec![(30, OrderIntent::TargetPosition { quantity: 1.0 })],
+        })
+    });
+    let quiet = results.instrument("QUIET").unwrap().entry_notional;
+    let wild = results.instrument("WILD").unwrap().entry_notional;
+    println!("Vol target notional: QUIET {:.0}, WILD {:.0}", quiet, wild);
+    assert!(quiet > 5.0 * wild && wild > 0.0);
 }
 
-impl MarketData {
-    pub fn new(symbol: &str) -> Self {
-        MarketData {
-            symbol: symbol.to_string(),
-            prices: Vec::new(),
-            volumes: Vec::new(),
-            timestamps: Vec::new(),
+fn test_walk_forward() {
+    println!("\n=== Walk-Forward Optimization ===");
+
+    // Trend regimes of changing length, so the best MA periods drift over time
+    let mut seed = 17u64;
+    let mut price = 100.0;
+    let bars: Vec<OHLCV> = (0..600)
+        .map(|i| {
+            let open = price;
+            let cycle = if i < 300 { 40.0 } else { 90.0 };
+            price *= 1.0 + 0.004 * (i as f64 / cycle).sin() + 0.006 * gaussian_noise(&mut seed);
+            OHLCV::new(i, open, open.max(price), open.min(price), price, 1000.0)
+        })
+        .collect();
+    let instrument = Instrument::new("WF", bars);
+
+    let mut grid = Vec::new();
+    for fast in [5, 10, 20] {
+        for slow in [30, 50] {
+            grid.push(
+                ParamSet::new()
+                    .with("fast_period", ParamValue::Int(fast))
+                    .with("slow_period", ParamValue::Int(slow))
+                    .with("quantity", ParamValue::Float(80.0)),
+            );
         }
     }
-
-    pub fn add_candle(&mut self, price: f64, volume: f64, timestamp: i64) {
-        self.prices.push(price);
-        self.volumes.push(volume);
-        self.timestamps.push(timestamp);
-    }
-
-    pub fn last_price(&self) -> Option<f64> {
-        self.prices.last().copied()
+    let engine = Backtester::new(BacktestConfig::new(10_000.0).with_commission(0.0005));
+    let objective = |r: &ch352_test::BacktestResults| r.total_return();
+
+    let rolling = WalkForward::new(engine.clone(), 200, 100);
+    let windows = rolling.windows(600);
+    assert_eq!(windows.len(), 4);
+    assert_eq!(windows[1], (100..300, 300..400));
+    let anchored = WalkForward::new(engine.clone(), 200, 100).with_mode(WindowMode::Anchored);
+    assert_eq!(anchored.windows(600)[3], (0..500, 500..600));
+
+    for wf in [&rolling, &anchored] {
+        let report = wf
+            .run::<CrossoverStrategy, _>(&instrument, &grid, objective)
+            .expect("walk-forward runs");
+        report.print();
+
+        assert_eq!(report.equity_curve.len(), 400);
+        assert_eq!(report.equity_curve[0].timestamp, 200);
+        let chained = report.windows.iter().fold(report.initial_balance, |b, w| {
+            b * (1.0 + w.oos_return / 100.0)
+        });
+        assert!((chained - report.final_balance()).abs() < 1e-6);
+        assert!(report.efficiency.is_finite());
+        assert_eq!(report.stability.len(), 3);
     }
-}
 
-/// MA Crossover Strategy
-#[derive(Debug, Clone)]
-pub struct CrossoverStrategy {
-    fast_period: usize,
-    slow_period: usize,
+    // OOS runs see the in-sample bars as warm-up without trading on them
+    let mut crossover = CrossoverStrategy::new(5, 30);
+    let warm = engine.run_with_warmup(&mut crossover, &instrument, 200);
+    assert_eq!(warm.equity_curve.len(), 400);
+    assert!(warm.trades.iter().all(|t| t.entry_bar > 200));
+
+    assert!(matches!(
+        rolling.run::<CrossoverStrategy, _>(
+            &Instrument::new("SHORT", Vec::new()),
+            &grid,
+            objective
+        ),
+        Err(WalkForwardError::NotEnoughData {
+            needed: 201,
+            available: 0
+        })
+    ));
+    assert_eq!(
+        rolling
+            .run::<CrossoverStrategy, _>(&instrument, &[], objective)
+            .unwrap_err(),
+        WalkForwardError::EmptyGrid
+    );
+    let bad = [ParamSet::new().with("fast_period", ParamValue::Int(60))];
+    assert!(matches!(
+        rolling.run::<CrossoverStrategy, _>(&instrument, &bad, objective),
+        Err(WalkForwardError::InvalidParams(
+            ParamError::ConstraintViolated(_)
+        ))
+    ));
 }
 
-impl CrossoverStrategy {
-    pub fn new(fast_period: usize, slow_period: usize) -> Self {
-        assert!(fast_period < slow_period, "Fast period must be less than slow period");
-        CrossoverStrategy { fast_period, slow_period }
+fn test_cross_validation() {
+    println!("\n=== Purged Cross-Validation ===");
+
+    let engine = Backtester::new(BacktestConfig::new(10_000.0).with_commission(0.0005));
+
+    // Purging drops the label horizon before each test group, the embargo the bars after it
+    let kfold = PurgedCv::new(engine.clone(), CvScheme::KFold { folds: 5 })
+        .with_purge(5)
+        .with_embargo(10);
+    let splits = kfold.splits(500).expect("valid scheme");
+    assert_eq!(splits.len(), 5);
+    assert_eq!(splits[0].test, vec![0..100]);
+    assert_eq!(splits[0].train, vec![110..500]);
+    assert_eq!(splits[2].train, vec![0..195, 310..500]);
+    assert_eq!(splits[4].train, vec![0..395]);
+    for split in &splits {
+        for train in &split.train {
+            for test in &split.test {
+                assert!(train.end + 5 <= test.start || train.start >= test.end + 10);
+            }
+        }
     }
 
-    fn calculate_sma(&self, prices: &[f64], period: usize) -> Option<f64> {
-        if prices.len() < period {
-            return None;
+    let cpcv = PurgedCv::new(
+        engine.clone(),
+        CvScheme::Combinatorial {
+            groups: 6,
+            test_groups: 2,
+        },
+    )
+    .with_purge(3)
+    .with_embargo(6)
+    .with_warmup(50);
+    let splits = cpcv.splits(600).expect("valid scheme");
+    assert_eq!(splits.len(), 15);
+    assert_eq!(
+        CvScheme::Combinatorial {
+            groups: 6,
+            test_groups: 2
         }
-
-        let sum: f64 = prices[prices.len() - period..].iter().sum();
-        Some(sum / period as f64)
+        .paths(),
+        5
+    );
+    assert_eq!(splits[0].test_groups, vec![0, 1]);
+    assert_eq!(splits[0].train, vec![206..600]);
+    assert_eq!(splits[14].train, vec![0..397]);
+    for group in 0..6 {
+        assert_eq!(
+            splits
+                .iter()
+                .filter(|s| s.test_groups.contains(&group))
+                .count(),
+            5
+        );
     }
-}
 
-impl Strategy for CrossoverStrategy {
-    fn name(&self) -> &str {
-        "MA Crossover"
+    let mut seed = 23u64;
+    let mut price = 100.0;
+    let bars: Vec<OHLCV> = (0..600)
+        .map(|i| {
+            let open = price;
+            price *= 1.0 + 0.003 * (i as f64 / 35.0).sin() + 0.006 * gaussian_noise(&mut seed);
+            OHLCV::new(i, open, open.max(price), open.min(price), price, 1000.0)
+        })
+        .collect();
+    let instrument = Instrument::new("CV", bars);
+    let grid: Vec<ParamSet> = [(5, 20), (10, 30), (20, 50)]
+        .into_iter()
+        .map(|(fast, slow)| {
+            ParamSet::new()
+                .with("fast_period", ParamValue::Int(fast))
+                .with("slow_period", ParamValue::Int(slow))
+                .with("quantity", ParamValue::Float(80.0))
+        })
+        .collect();
+    let objective = |r: &ch352_test::BacktestResults| r.total_return();
+
+    let report = cpcv
+        .run::<CrossoverStrategy, _>(&instrument, &grid, objective)
+        .expect("cross-validation runs");
+    report.print();
+    assert_eq!(report.folds.len(), 15);
+    assert_eq!(report.test_returns().count, 15);
+    assert_eq!(report.path_returns.len(), 5);
+    for fold in &report.folds {
+        let chained = fold
+            .group_returns
+            .iter()
+            .fold(1.0, |acc, r| acc * (1.0 + r / 100.0));
+        assert!(((chained - 1.0) * 100.0 - fold.test_return).abs() < 1e-9);
+        assert!(
+            grid.iter()
+                .any(|g| fold.params.get("fast_period") == g.get("fast_period"))
+        );
     }
+    let summary = report.path_summary();
+    assert!(summary.min <= summary.median && summary.median <= summary.max);
+
+    // Plain k-fold recombines into a single path through all the data
+    let report = kfold
+        .run::<CrossoverStrategy, _>(&instrument, &grid, objective)
+        .expect("cross-validation runs");
+    assert_eq!(report.path_returns.len(), 1);
+    let chained = report
+        .folds
+        .iter()
+        .fold(1.0, |acc, f| acc * (1.0 + f.test_return / 100.0));
+    assert!(((chained - 1.0) * 100.0 - report.path_returns[0]).abs() < 1e-9);
+
+    assert!(matches!(
+        PurgedCv::new(
+            engine.clone(),
+            CvScheme::Combinatorial {
+                groups: 4,
+                test_groups: 4
+            }
+        )
+        .splits(100),
+        Err(CvError::InvalidScheme(_))
+    ));
+    assert_eq!(
+        kfold
+            .run::<CrossoverStrategy, _>(&Instrument::new("SHORT", Vec::new()), &grid, objective)
+            .unwrap_err(),
+        CvError::NotEnoughData {
+            needed: 10,
+            available: 0
+        }
+    );
+    assert_eq!(
+        kfold
+            .run::<CrossoverStrategy, _>(&instrument, &[], objective)
+            .unwrap_err(),
+        CvError::EmptyGrid
+    );
+    let bad = [ParamSet::new().with("fast_period", ParamValue::Int(60))];
+    assert!(matches!(
+        kfold.run::<CrossoverStrategy, _>(&instrument, &bad, objective),
+        Err(CvError::InvalidParams(ParamError::ConstraintViolated(_)))
+    ));
+}
 
-    fn generate_signal(&self, data: &MarketData) -> Signal {
-        let fast_ma = match self.calculate_sma(&data.prices, self.fast_period) {
-            Some(ma) => ma,
-            None => return Signal::Hold,
-        };
-
-        let slow_ma = match self.calculate_sma(&data.prices, self.slow_period) {
-            Some(ma) => ma,
-            None => return Signal::Hold,
-        };
+fn test_overfitting() {
+    use ch352_test::overfitting::{
+        DeflatedSharpe, expected_max_sharpe, min_backtest_length, probabilistic_sharpe_ratio,
+        probability_of_backtest_overfitting, sharpe_ratio,
+    };
+    use ch352_test::stats::{kurtosis, normal_cdf, normal_quantile, skewness};
 
-        let current_price = match data.last_price() {
-            Some(p) => p,
-            None => return Signal::Hold,
-        };
+    println!("\n=== Overfitting Statistics ===");
 
-        if fast_ma > slow_ma {
-            Signal::Buy {
-                price: current_price,
-                quantity: 1.0,
-            }
-        } else if fast_ma < slow_ma {
-            Signal::Sell {
-                price: current_price,
-                quantity: 1.0,
-            }
-        } else {
-            Signal::Hold
-        }
+    assert!((normal_cdf(0.0) - 0.5).abs() < 1e-7);
+    assert!((normal_cdf(1.959964) - 0.975).abs() < 1e-6);
+    assert!((normal_quantile(0.975) - 1.959964).abs() < 1e-5);
+    for p in [0.001, 0.2, 0.5, 0.9, 0.999] {
+        assert!((normal_cdf(normal_quantile(p)) - p).abs() < 1e-6);
     }
-
-    fn parameters(&self) -> HashMap<String, f64> {
-        let mut params = HashMap::new();
-        params.insert("fast_period".to_string(), self.fast_period as f64);
-        params.insert("slow_period".to_string(), self.slow_period as f64);
-        params
+    assert!(skewness(&[1.0, 2.0, 3.0]).abs() < 1e-12);
+    assert!((kurtosis(&[-1.0, 1.0]) - 1.0).abs() < 1e-12);
+
+    // The more trials, the higher the best Sharpe ratio luck alone produces
+    assert_eq!(expected_max_sharpe(1, 0.01), 0.0);
+    assert!(expected_max_sharpe(100, 0.01) > expected_max_sharpe(10, 0.01));
+    assert!(min_backtest_length(100, 0.1) > min_backtest_length(10, 0.1));
+    assert!(min_backtest_length(10, -0.1).is_infinite());
+
+    let mut seed = 31u64;
+    let noise: Vec<Vec<f64>> = (0..20)
+        .map(|_| (0..800).map(|_| 0.01 * gaussian_noise(&mut seed)).collect())
+        .collect();
+
+    // A single trial is not deflated: DSR equals PSR against zero
+    let single = DeflatedSharpe::of(&noise[0], &[sharpe_ratio(&noise[0])]);
+    assert_eq!(single.expected_max, 0.0);
+    let psr = probabilistic_sharpe_ratio(
+        single.sharpe,
+        0.0,
+        800,
+        skewness(&noise[0]),
+        kurtosis(&noise[0]),
+    );
+    assert!((single.probability - psr).abs() < 1e-12);
+
+    // Picking the best of 20 noise series does not survive deflation
+    let sharpes: Vec<f64> = noise.iter().map(|r| sharpe_ratio(r)).collect();
+    let best = (0..20)
+        .max_by(|&a, &b| sharpes[a].total_cmp(&sharpes[b]))
+        .unwrap();
+    let deflated = DeflatedSharpe::of(&noise[best], &sharpes);
+    let undeflated = DeflatedSharpe::of(&noise[best], &sharpes[best..=best]);
+    assert!(deflated.probability < undeflated.probability);
+    assert!(deflated.probability < 0.95);
+
+    // Without skill the in-sample winner is as likely as not to land in the bottom half
+    let pbo = probability_of_backtest_overfitting(&noise, 10).expect("enough data");
+    assert_eq!(pbo.logits.len(), 252);
+    let average: f64 = (0..10)
+        .map(|_| {
+            let draws: Vec<Vec<f64>> = (0..20)
+                .map(|_| (0..400).map(|_| 0.01 * gaussian_noise(&mut seed)).collect())
+                .collect();
+            probability_of_backtest_overfitting(&draws, 10)
+                .unwrap()
+                .probability
+        })
+        .sum::<f64>()
+        / 10.0;
+    println!(
+        "PBO of pure noise: {:.3}, averaged over draws: {:.3}",
+        pbo.probability, average
+    );
+    assert!(average > 0.3 && average < 0.7);
+
+    // One trial with a real edge wins in-sample and out of sample
+    let mut skilled = noise.clone();
+    for r in skilled[7].iter_mut() {
+        *r += 0.004;
     }
+    let pbo = probability_of_backtest_overfitting(&skilled, 10).expect("enough data");
+    assert!(pbo.probability < 0.05);
+    assert!(probability_of_backtest_overfitting(&noise, 7).is_none());
+    assert!(probability_of_backtest_overfitting(&noise[..1], 10).is_none());
+
+    // Every sweep reports the statistics for its own trials
+    let mut price = 100.0;
+    let bars: Vec<OHLCV> = (0..640)
+        .map(|i| {
+            let open = price;
+            price *= 1.0 + 0.006 * gaussian_noise(&mut seed);
+            OHLCV::new(i, open, open.max(price), open.min(price), price, 1000.0)
+        })
+        .collect();
+    let instrument = Instrument::new("NOISE", bars);
+    let mut grid = Vec::new();
+    for fast in [3, 5, 8, 12, 20] {
+        for slow in [25, 40, 60, 90] {
+            grid.push(
+                ParamSet::new()
+                    .with("fast_period", ParamValue::Int(fast))
+                    .with("slow_period", ParamValue::Int(slow))
+                    .with("quantity", ParamValue::Float(80.0)),
+            );
+        }
+    }
+    let sweep = ParamSweep::new(Backtester::new(BacktestConfig::new(10_000.0)));
+    let report = sweep
+        .run::<CrossoverStrategy, _>(&instrument, &grid, |r: &ch352_test::BacktestResults| {
+            r.total_return()
+        })
+        .expect("sweep runs");
+    report.print(5);
+
+    assert_eq!(report.trials.len(), grid.len());
+    assert_eq!(report.overfitting.deflated_sharpe.trials, grid.len());
+    assert_eq!(report.overfitting.deflated_sharpe.observations, 640);
+    assert_eq!(report.top(1)[0].score, report.best_trial().score);
+    assert!(
+        report
+            .trials
+            .iter()
+            .all(|t| t.score <= report.best_trial().score)
+    );
+    let best = report.best_trial();
+    let final_balance = best.returns.iter().fold(10_000.0, |b, r| b * (1.0 + r));
+    assert!(((final_balance / 10_000.0 - 1.0) * 100.0 - best.total_return).abs() < 1e-6);
+    let pbo = report
+        .overfitting
+        .pbo
+        .as_ref()
+        .expect("16 groups of 40 bars");
+    assert_eq!(pbo.logits.len(), 12_870);
+    assert!(report.overfitting.is_overfitted(0.95));
+
+    assert_eq!(
+        sweep
+            .run::<CrossoverStrategy, _>(&instrument, &[], |r: &ch352_test::BacktestResults| r
+                .total_return())
+            .unwrap_err(),
+        SweepError::EmptyGrid
+    );
 }
 
-/// Strategy manager
-pub struct StrategyManager {
-    strategies: Vec<Box<dyn Strategy>>,
-}
-
-impl StrategyManager {
-    pub fn new() -> Self {
-        StrategyManager {
-            strategies: Vec::new(),
-        }
+fn test_grid_search() {
+    println!("\n=== Parallel Grid Search ===");
+
+    // Fingerprints ignore insertion order but not value types
+    let a = ParamSet::new()
+        .with("fast_period", ParamValue::Int(5))
+        .with("quantity", ParamValue::Float(1.0));
+    let b = ParamSet::new()
+        .with("quantity", ParamValue::Float(1.0))
+        .with("fast_period", ParamValue::Int(5));
+    assert_eq!(a.fingerprint(), b.fingerprint());
+    assert_eq!(a.fingerprint().len(), 16);
+    let c = ParamSet::new()
+        .with("fast_period", ParamValue::Int(5))
+        .with("quantity", ParamValue::Int(1));
+    assert_ne!(a.fingerprint(), c.fingerprint());
+
+    let mut seed = 41u64;
+    let mut price = 100.0;
+    let bars: Vec<OHLCV> = (0..500)
+        .map(|i| {
+            let open = price;
+            price *= 1.0 + 0.003 * (i as f64 / 30.0).sin() + 0.005 * gaussian_noise(&mut seed);
+            OHLCV::new(i, open, open.max(price), open.min(price), price, 1000.0)
+        })
+        .collect();
+    let instrument = Instrument::new("GRID", bars);
+    let space = ParamSchema::new()
+        .param(ParamSpec::int("fast_period", 5, 4, 16, 4))
+        .param(ParamSpec::int("slow_period", 30, 20, 60, 10))
+        .param(ParamSpec::float("quantity", 40.0, 40.0, 80.0, Some(40.0)))
+        .constraint(Constraint::less_than("fast_period", "slow_period"));
+    let grid = space.grid();
+    assert_eq!(grid.len(), 4 * 5 * 2);
+    let objective = |r: &ch352_test::BacktestResults| r.total_return();
+    let engine = Backtester::new(BacktestConfig::new(10_000.0).with_commission(0.0005));
+
+    // Parallel and single-threaded sweeps agree, trial for trial and in grid order
+    let parallel = ParamSweep::new(engine.clone())
+        .run::<CrossoverStrategy, _>(&instrument, &grid, objective)
+        .expect("sweep runs");
+    let single = ParamSweep::new(engine.clone())
+        .with_threads(1)
+        .run::<CrossoverStrategy, _>(&instrument, &grid, objective)
+        .expect("sweep runs");
+    assert_eq!(parallel.trials, single.trials);
+    assert_eq!(parallel.trials[7].params, grid[7]);
+
+    // An interrupted sweep: half the grid is stored, then a torn line
+    let path = std::env::temp_dir().join(format!("ch352_sweep_{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let stored = ParamSweep::new(engine.clone())
+        .with_store(&path)
+        .run::<CrossoverStrategy, _>(&instrument, &grid[..20], objective)
+        .expect("sweep runs");
+    assert_eq!(stored.resumed, 0);
+    {
+        use std::io::Write;
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        write!(file, "{{\"params\":{{\"fast_per").unwrap();
     }
+    let key = SweepKey::new::<CrossoverStrategy>(&instrument, engine.config());
+    assert_eq!(
+        TrialStore::open(&path, &key).expect("store opens").len(),
+        20
+    );
+
+    let resumed = ParamSweep::new(engine.clone())
+        .with_store(&path)
+        .run::<CrossoverStrategy, _>(&instrument, &grid, objective)
+        .expect("sweep resumes");
+    assert_eq!(resumed.resumed, 20);
+    assert_eq!(resumed.trials, parallel.trials);
+    assert_eq!(
+        TrialStore::open(&path, &key).expect("store opens").len(),
+        grid.len()
+    );
+    let again = ParamSweep::new(engine.clone())
+        .with_store(&path)
+        .run::<CrossoverStrategy, _>(&instrument, &grid, objective)
+        .expect("sweep resumes");
+    assert_eq!(again.resumed, grid.len());
+
+    // Stored backtests are scored with the objective of the sweep reading them
+    let drawdown = |r: &ch352_test::BacktestResults| -r.max_drawdown();
+    let rescored = ParamSweep::new(engine.clone())
+        .with_store(&path)
+        .run::<CrossoverStrategy, _>(&instrument, &grid, drawdown)
+        .expect("sweep resumes");
+    assert_eq!(rescored.resumed, grid.len());
+    assert!(rescored.trials.iter().all(|t| t.score == -t.max_drawdown));
+
+    // A store of another engine configuration or another series is refused
+    let costly = Backtester::new(BacktestConfig::new(10_000.0).with_commission(0.001));
+    let refused = ParamSweep::new(costly)
+        .with_store(&path)
+        .run::<CrossoverStrategy, _>(&instrument, &grid, objective)
+        .unwrap_err();
+    println!("Other engine: {}", refused);
+    assert!(matches!(&refused, SweepError::Store(e) if e.contains("engine settings")));
+    let mut shifted = instrument.clone();
+    shifted.data[250].close *= 1.01;
+    let refused = ParamSweep::new(engine.clone())
+        .with_store(&path)
+        .run::<CrossoverStrategy, _>(&shifted, &grid, objective)
+        .unwrap_err();
+    assert!(matches!(&refused, SweepError::Store(e) if e.contains("data")));
+    std::fs::remove_file(&path).unwrap();
+    resumed.print(5);
+
+    // Top-N by any metric, best first
+    let calmest = resumed.top_by(SweepMetric::MaxDrawdown, 3);
+    assert!(
+        calmest
+            .windows(2)
+            .all(|w| w[0].max_drawdown <= w[1].max_drawdown)
+    );
+    assert!(
+        resumed
+            .trials
+            .iter()
+            .all(|t| t.max_drawdown >= calmest[0].max_drawdown)
+    );
+    assert_eq!(resumed.top(1)[0], resumed.best_trial());
+
+    // A 2D slice with quantity held fixed, and one taking the best quantity per cell
+    let fixed = ParamSet::new().with("quantity", ParamValue::Float(80.0));
+    let heatmap = resumed.heatmap("fast_period", "slow_period", SweepMetric::Sharpe, &fixed);
+    heatmap.print();
+    assert_eq!(
+        heatmap.x_values,
+        vec![
+            ParamValue::Int(4),
+            ParamValue::Int(8),
+            ParamValue::Int(12),
+            ParamValue::Int(16)
+        ]
+    );
+    assert_eq!(heatmap.y_values.len(), 5);
+    let trial = resumed
+        .trials
+        .iter()
+        .find(|t| {
+            t.params.int("fast_period") == Some(8)
+                && t.params.int("slow_period") == Some(40)
+                && t.params.float("quantity") == Some(80.0)
+        })
+        .unwrap();
+    assert_eq!(
+        heatmap.get(&ParamValue::Int(8), &ParamValue::Int(40)),
+        Some(trial.sharpe())
+    );
+    let best_of = resumed.heatmap(
+        "fast_period",
+        "slow_period",
+        SweepMetric::TotalReturn,
+        &ParamSet::new(),
+    );
+    let both: Vec<f64> = resumed
+        .trials
+        .iter()
+        .filter(|t| {
+            t.params.int("fast_period") == Some(8) && t.params.int("slow_period") == Some(40)
+        })
+        .map(|t| t.total_return)
+        .collect();
+    assert_eq!(both.len(), 2);
+    assert_eq!(
+        best_of.get(&ParamValue::Int(8), &ParamValue::Int(40)),
+        Some(both[0].max(both[1]))
+    );
+}
 
-    pub fn add_strategy(&mut self, strategy: Box<dyn Strategy>) {
-        self.strategies.push(strategy);
+fn test_optimizers() {
+    println!("\n=== Random, TPE and Genetic Optimizers ===");
+
+    let mut seed = 53u64;
+    let mut price = 100.0;
+    let bars: Vec<OHLCV> = (0..500)
+        .map(|i| {
+            let open = price;
+            price *= 1.0 + 0.003 * (i as f64 / 25.0).sin() + 0.005 * gaussian_noise(&mut seed);
+            OHLCV::new(i, open, open.max(price), open.min(price), price, 1000.0)
+        })
+        .collect();
+    let instrument = Instrument::new("OPT", bars);
+    let space = ParamSchema::new()
+        .param(ParamSpec::int("fast_period", 5, 2, 30, 1))
+        .param(ParamSpec::int("slow_period", 30, 10, 120, 2))
+        .param(ParamSpec::float("quantity", 50.0, 20.0, 80.0, None))
+        .constraint(Constraint::less_than("fast_period", "slow_period"));
+    let objective = |r: &ch352_test::BacktestResults| r.total_return();
+    let sweep = ParamSweep::new(Backtester::new(
+        BacktestConfig::new(10_000.0).with_commission(0.0005),
+    ));
+    let in_space = |p: &ParamSet| {
+        let (fast, slow, qty) = (
+            p.int("fast_period").unwrap(),
+            p.int("slow_period").unwrap(),
+            p.float("quantity").unwrap(),
+        );
+        (2..=30).contains(&fast)
+            && (10..=120).contains(&slow)
+            && slow % 2 == 0
+            && fast < slow
+            && (20.0..=80.0).contains(&qty)
+    };
+
+    let mut reports = Vec::new();
+    let optimizers: Vec<Box<dyn ch352_test::Optimizer>> = vec![
+        Box::new(RandomSearch::new(7)),
+        Box::new(TreeParzen::new(7)),
+        Box::new(Genetic::new(7).with_population(10)),
+    ];
+    for mut optimizer in optimizers {
+        let report = sweep
+            .optimize::<CrossoverStrategy, _>(
+                &instrument,
+                &space,
+                optimizer.as_mut(),
+                40,
+                objective,
+            )
+            .expect("optimization runs");
+        report.print(3);
+        assert_eq!(report.trials.len(), 40);
+        assert!(report.trials.iter().all(|t| in_space(&t.params)));
+        let mut keys: Vec<String> = report
+            .trials
+            .iter()
+            .map(|t| t.params.fingerprint())
+            .collect();
+        keys.sort();
+        keys.dedup();
+        assert_eq!(keys.len(), 40, "{} proposes each set once", report.method);
+        reports.push(report);
     }
 
-    pub fn generate_signals(&self, data: &MarketData) -> Vec<(&str, Signal)> {
-        self.strategies
+    // The same seed repeats the search exactly; another seed does not
+    let rerun = |seed: u64| {
+        sweep
+            .optimize::<CrossoverStrategy, _>(
+                &instrument,
+                &space,
+                &mut TreeParzen::new(seed),
+                24,
+                objective,
+            )
+            .expect("optimization runs")
+            .trials
+            .into_iter()
+            .map(|t| t.params)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(rerun(11), rerun(11));
+    assert_ne!(rerun(11), rerun(12));
+    let genetic = |seed: u64| {
+        sweep
+            .optimize::<CrossoverStrategy, _>(
+                &instrument,
+                &space,
+                &mut Genetic::new(seed).with_population(8),
+                24,
+                objective,
+            )
+            .expect("optimization runs")
+            .trials
+    };
+    assert_eq!(genetic(3), genetic(3));
+
+    // Elitism: each generation's best survives, so later generations never do worse
+    let trials = genetic(5);
+    let best_of = |range: std::ops::Range<usize>| {
+        trials[range]
             .iter()
-            .map(|s| (s.name(), s.generate_signal(data)))
-            .collect()
+            .map(|t| t.score)
+            .fold(f64::NEG_INFINITY, f64::max)
+    };
+    assert!(best_of(0..16) >= best_of(0..8));
+
+    // A grid through the optimizer interface is the plain grid sweep
+    let small = ParamSchema::new()
+        .param(ParamSpec::int("fast_period", 5, 5, 15, 5))
+        .param(ParamSpec::int("slow_period", 30, 30, 60, 30))
+        .param(ParamSpec::float("quantity", 50.0, 50.0, 50.0, None));
+    let via_optimizer = sweep
+        .optimize::<CrossoverStrategy, _>(
+            &instrument,
+            &small,
+            &mut GridSearch::new(),
+            100,
+            objective,
+        )
+        .expect("grid runs");
+    let direct = sweep
+        .run::<CrossoverStrategy, _>(&instrument, &small.grid(), objective)
+        .expect("grid runs");
+    assert_eq!(via_optimizer.trials, direct.trials);
+    assert_eq!(via_optimizer.trials.len(), 6);
+    assert_eq!(via_optimizer.rejected, 0);
+
+    // Proposals outside the strategy's own schema are skipped, not fatal
+    let wide = ParamSchema::new()
+        .param(ParamSpec::int("fast_period", 150, 150, 250, 50))
+        .param(ParamSpec::int("slow_period", 300, 300, 300, 1))
+        .param(ParamSpec::float("quantity", 50.0, 50.0, 50.0, None));
+    let partial = sweep
+        .optimize::<CrossoverStrategy, _>(
+            &instrument,
+            &wide,
+            &mut GridSearch::new(),
+            100,
+            objective,
+        )
+        .expect("the valid sets still run");
+    assert_eq!(partial.rejected, 1);
+    assert_eq!(partial.trials.len(), 2);
+    assert!(
+        partial
+            .trials
+            .iter()
+            .all(|t| t.params.int("fast_period") != Some(250))
+    );
+
+    // Two objectives: the search returns the trade-off between Sharpe and drawdown
+    let pareto = ParamSweep::new(Backtester::new(
+        BacktestConfig::new(10_000.0).with_commission(0.0005),
+    ))
+    .with_objectives(&[SweepMetric::Sharpe, SweepMetric::MaxDrawdown])
+    .optimize::<CrossoverStrategy, _>(
+        &instrument,
+        &space,
+        &mut Genetic::new(9).with_population(12),
+        48,
+        objective,
+    )
+    .expect("optimization runs");
+    pareto.print(3);
+    let front = pareto.pareto_front();
+    assert!(!front.is_empty());
+    for pair in front.windows(2) {
+        assert!(pair[0].sharpe() >= pair[1].sharpe());
+        assert!(pair[0].max_drawdown >= pair[1].max_drawdown);
+    }
+    for member in &front {
+        assert!(!pareto.trials.iter().any(|t| {
+            t.sharpe() >= member.sharpe()
+                && t.max_drawdown <= member.max_drawdown
+                && (t.sharpe() > member.sharpe() || t.max_drawdown < member.max_drawdown)
+        }));
     }
 }
 
-impl Default for StrategyManager {
-    fn default() -> Self {
-        Self::new()
+fn test_monte_carlo() {
+    println!("\n=== Seeded Monte Carlo ===");
+
+    // Returns with volatility clusters and positive autocorrelation
+    let mut seed = 97u64;
+    let mut previous = 0.0;
+    let returns: Vec<f64> = (0..400)
+        .map(|i| {
+            let vol = if (i / 50) % 2 == 0 { 0.005 } else { 0.02 };
+            previous = 0.5 * previous + vol * gaussian_noise(&mut seed) + 0.0004;
+            previous
+        })
+        .collect();
+    let lag1 = |r: &[f64]| {
+        let m = ch352_test::stats::mean(r);
+        let num: f64 = r.windows(2).map(|w| (w[0] - m) * (w[1] - m)).sum();
+        let den: f64 = r.iter().map(|x| (x - m) * (x - m)).sum();
+        num / den
+    };
+    let historical_total: f64 = returns.iter().map(|r| 1.0 + r).product::<f64>() - 1.0;
+
+    // Shuffling only reorders the path: every path ends at the historical return
+    let shuffled = MonteCarlo::new(returns.clone(), 1)
+        .with_method(Resampling::Shuffle)
+        .with_paths(200)
+        .run()
+        .expect("simulation runs");
+    assert!(
+        shuffled
+            .final_returns
+            .iter()
+            .all(|r| (r - historical_total).abs() < 1e-9)
+    );
+    let dd = shuffled.drawdown_summary();
+    assert!(dd.max > dd.min);
+
+    // Same seed, same report, whatever the thread count; another seed differs
+    let bootstrap = MonteCarlo::new(returns.clone(), 42)
+        .with_paths(500)
+        .run()
+        .expect("simulation runs");
+    bootstrap.print(4);
+    let single = MonteCarlo::new(returns.clone(), 42)
+        .with_paths(500)
+        .with_threads(1)
+        .run()
+        .expect("simulation runs");
+    assert_eq!(bootstrap, single);
+    let other = MonteCarlo::new(returns.clone(), 43)
+        .with_paths(500)
+        .run()
+        .expect("simulation runs");
+    assert_ne!(bootstrap.final_returns, other.final_returns);
+    assert!(bootstrap.return_summary().std_dev > 0.0);
+    assert_eq!(bootstrap.bands.len(), returns.len() + 1);
+    assert_eq!(bootstrap.bands[0].p5, 1.0);
+    assert_eq!(bootstrap.bands[0].p95, 1.0);
+    for b in &bootstrap.bands {
+        assert!(b.p5 <= b.p25 && b.p25 <= b.median && b.median <= b.p75 && b.p75 <= b.p95);
     }
+    assert!(
+        bootstrap.bands[returns.len()].p95 - bootstrap.bands[returns.len()].p5
+            > bootstrap.bands[50].p95 - bootstrap.bands[50].p5
+    );
+    assert!(bootstrap.drawdown_at_risk(0.95) >= bootstrap.drawdown_summary().median);
+
+    // Block bootstraps keep the autocorrelation that independent draws destroy
+    let mean_lag1 = |method: Resampling| {
+        let mc = MonteCarlo::new(returns.clone(), 7).with_method(method);
+        (0..50)
+            .map(|path| lag1(&mc.sample(path).expect("valid setup")))
+            .sum::<f64>()
+            / 50.0
+    };
+    let original = lag1(&returns);
+    let iid = mean_lag1(Resampling::Bootstrap);
+    let block = mean_lag1(Resampling::BlockBootstrap { block: 20 });
+    let stationary = mean_lag1(Resampling::StationaryBootstrap { mean_block: 20.0 });
+    println!(
+        "Lag-1 autocorrelation: historical {:.3}, bootstrap {:.3}, block {:.3}, stationary {:.3}",
+        original, iid, block, stationary
+    );
+    assert!(iid.abs() < 0.1);
+    assert!((block - original).abs() < 0.1);
+    assert!((stationary - original).abs() < 0.1);
+    let stationary_report = MonteCarlo::new(returns.clone(), 7)
+        .with_method(Resampling::StationaryBootstrap { mean_block: 20.0 })
+        .with_paths(300)
+        .run()
+        .expect("simulation runs");
+    stationary_report.print(4);
+
+    // Parametric models match the first two moments; student-t has fatter tails
+    let draws = |method: Resampling| {
+        let mc = MonteCarlo::new(returns.clone(), 5)
+            .with_method(method)
+            .with_horizon(2000);
+        (0..10)
+            .flat_map(|path| mc.sample(path).expect("valid setup"))
+            .collect::<Vec<f64>>()
+    };
+    let normal = draws(Resampling::Normal);
+    let student = draws(Resampling::StudentT { dof: 4.0 });
+    let sd = ch352_test::stats::std_dev(&returns);
+    assert!((ch352_test::stats::std_dev(&normal) / sd - 1.0).abs() < 0.05);
+    assert!((ch352_test::stats::std_dev(&student) / sd - 1.0).abs() < 0.15);
+    assert!(
+        (ch352_test::stats::mean(&normal) - ch352_test::stats::mean(&returns)).abs() < 0.1 * sd
+    );
+    assert!(ch352_test::stats::kurtosis(&student) > ch352_test::stats::kurtosis(&normal) + 1.0);
+
+    // Risk of ruin rises with the ruin level and with leverage
+    let ruin = |level: f64, leverage: f64| {
+        MonteCarlo::new(returns.iter().map(|r| r * leverage).collect(), 11)
+            .with_method(Resampling::StudentT { dof: 4.0 })
+            .with_paths(400)
+            .with_ruin_level(level)
+            .run()
+            .expect("simulation runs")
+            .risk_of_ruin()
+    };
+    assert!(ruin(0.9, 1.0) >= ruin(0.5, 1.0));
+    assert!(ruin(0.5, 8.0) > ruin(0.5, 1.0));
+    println!(
+        "Risk of ruin at 50%: {:.1}% unlevered, {:.1}% at 8x",
+        ruin(0.5, 1.0) * 100.0,
+        ruin(0.5, 8.0) * 100.0
+    );
+
+    // Trade P&Ls become returns on the equity before each trade
+    let trades = MonteCarlo::from_trade_pnls(
+        &[
+            150.0, -80.0, 200.0, -120.0, 300.0, 100.0, -90.0, 250.0, -150.0, 180.0,
+        ],
+        10_000.0,
+        3,
+    )
+    .with_horizon(50)
+    .run()
+    .expect("simulation runs");
+    assert_eq!(trades.horizon(), 50);
+    assert!(trades.probability_of_loss() < 0.5);
+
+    assert_eq!(
+        MonteCarlo::new(vec![0.01], 1).run(),
+        Err(MonteCarloError::NotEnoughData {
+            needed: 2,
+            available: 1
+        })
+    );
+    assert!(matches!(
+        MonteCarlo::new(returns.clone(), 1)
+            .with_method(Resampling::Shuffle)
+            .with_horizon(10)
+            .run(),
+        Err(MonteCarloError::InvalidConfig(_))
+    ));
+    assert!(matches!(
+        MonteCarlo::new(returns, 1)
+            .with_method(Resampling::StudentT { dof: 2.0 })
+            .run(),
+        Err(MonteCarloError::InvalidConfig(_))
+    ));
 }
 
-// ============================================
-// Main function to test everything
-// ============================================
+fn test_stress() {
+    println!("\n=== Stress Scenarios ===");
+
+    let mut seed = 71u64;
+    let mut price = 100.0;
+    let bars: Vec<OHLCV> = (0..300)
+        .map(|i| {
+            let open = price;
+            price *= 1.0 + 0.0015 + 0.01 * gaussian_noise(&mut seed);
+            let wick = 0.004 * price;
+            OHLCV::new(
+                i,
+                open,
+                open.max(price) + wick,
+                open.min(price) - wick,
+                price,
+                1000.0,
+            )
+        })
+        .collect();
+    let instrument = Instrument::new("STRESS", bars.clone());
+    let closes = |i: &Instrument| i.data.iter().map(|b| b.close).collect::<Vec<f64>>();
+    let well_formed = |i: &Instrument| {
+        i.data
+            .iter()
+            .all(|b| b.low <= b.open.min(b.close) && b.high >= b.open.max(b.close))
+    };
+
+    // Flash crash: a 30% gap, fully regained over ten bars or never
+    let crash = |recovery: Recovery| {
+        Scenario::new("crash")
+            .with_shock(Shock::FlashCrash {
+                at: 100,
+                depth: 0.3,
+                recovery,
+            })
+            .apply(&instrument)
+            .instrument
+    };
+    let recovered = closes(&crash(Recovery::Linear {
+        bars: 10,
+        share: 1.0,
+    }));
+    let permanent = closes(&crash(Recovery::None));
+    assert_eq!(recovered[99], bars[99].close);
+    assert!((recovered[100] / bars[100].close - 0.7).abs() < 1e-12);
+    assert!((recovered[105] / bars[105].close - 0.85).abs() < 1e-12);
+    assert!((recovered[150] - bars[150].close).abs() < 1e-9);
+    assert!((permanent[299] / bars[299].close - 0.7).abs() < 1e-12);
+    let half = closes(&crash(Recovery::Exponential {
+        half_life: 5.0,
+        share: 1.0,
+    }));
+    assert!((half[105] / bars[105].close - 0.85).abs() < 1e-12);
+
+    // Volatility regime: log moves double inside the window, later returns are unchanged
+    let volatile = Scenario::new("vol x2")
+        .with_shock(Shock::Volatility {
+            bars: 50..100,
+            multiplier: 2.0,
+        })
+        .apply(&instrument)
+        .instrument;
+    let stressed = closes(&volatile);
+    assert!(well_formed(&volatile));
+    assert_eq!(stressed[..50], closes(&instrument)[..50]);
+    for i in 50..100 {
+        let original = (bars[i].close / bars[i - 1].close).ln();
+        assert!(((stressed[i] / stressed[i - 1]).ln() - 2.0 * original).abs() < 1e-9);
+    }
+    for i in 101..300 {
+        assert!((stressed[i] / stressed[i - 1] - bars[i].close / bars[i - 1].close).abs() < 1e-9);
+    }
 
-fn main() {
-    println!("=== Testing Chapter 352 Code Examples ===\n");
+    // Liquidity drought: thinner volume, and slippage scaled on those bars only
+    let drought = Scenario::new("drought")
+        .with_shock(Shock::Liquidity {
+            bars: 120..140,
+            volume: 0.1,
+            slippage: 10.0,
+        })
+        .apply(&instrument);
+    assert_eq!(drought.instrument.data[130].volume, 100.0);
+    assert_eq!(drought.instrument.data[140].volume, 1000.0);
+    assert_eq!(drought.slippage_scale.len(), 20);
+    let engine = Backtester::new(BacktestConfig::new(10_000.0).with_slippage(0.001));
+    let churn = || Scripted {
+        orders: (0..300)
+            .map(|i| {
+                (
+                    i,
+                    OrderIntent::TargetPosition {
+                        quantity: if i % 2 == 0 { 20.0 } else { 0.0 },
+                    },
+                )
+            })
+            .collect(),
+    };
+    let everywhere = instrument
+        .data
+        .iter()
+        .map(|b| (b.timestamp, 10.0))
+        .collect();
+    let scaled = engine.run_with_slippage_scale(&mut churn(), &instrument, &everywhere);
+    let wide = Backtester::new(BacktestConfig::new(10_000.0).with_slippage(0.01))
+        .run(&mut churn(), &instrument);
+    assert!((scaled.final_balance - wide.final_balance).abs() < 1e-6);
+    let thin =
+        engine.run_with_slippage_scale(&mut churn(), &drought.instrument, &drought.slippage_scale);
+    let normal = engine.run(&mut churn(), &instrument);
+    assert!(thin.final_balance < normal.final_balance);
+    assert!(thin.final_balance > wide.final_balance);
+
+    // Outage: the bars are gone, and orders wait for the exchange to come back
+    let outage = Scenario::new("outage")
+        .with_shock(Shock::Outage { bars: 200..210 })
+        .apply(&instrument)
+        .instrument;
+    assert_eq!(outage.data.len(), 290);
+    assert!(
+        outage
+            .data
+            .iter()
+            .all(|b| !(200..210).contains(&b.timestamp))
+    );
+    let held = engine.run(
+        &mut Scripted {
+            orders: vec![
+                (195, OrderIntent::TargetPosition { quantity: 10.0 }),
+                (199, OrderIntent::ClosePosition),
+            ],
+        },
+        &outage,
+    );
+    assert_eq!(held.trades.len(), 1);
+    assert_eq!(held.trades[0].exit_time, 210);
+
+    // Historical replay: the episode's returns, then the original returns from the new level
+    let replayed = closes(
+        &Scenario::episode(Episode::BlackMonday1987, 150)
+            .apply(&instrument)
+            .instrument,
+    );
+    assert!((replayed[153] / replayed[152] - (1.0 - 0.2047)).abs() < 1e-12);
+    let days = Episode::BlackMonday1987.days();
+    assert!(
+        (replayed[149 + days] / replayed[149] - (1.0 + Episode::BlackMonday1987.total_return()))
+            .abs()
+            < 1e-12
+    );
+    assert!((replayed[250] / replayed[249] - bars[250].close / bars[249].close).abs() < 1e-12);
+    let flash = Scenario::episode(Episode::FlashCrash2010, 150)
+        .apply(&instrument)
+        .instrument;
+    assert!(well_formed(&flash));
+    assert!((flash.data[150].low / flash.data[149].close - (1.0 - 0.092)).abs() < 1e-12);
+
+    // Every strategy under every scenario, against its own baseline
+    let report = StressTest::new(Backtester::new(
+        BacktestConfig::new(10_000.0).with_slippage(0.0005),
+    ))
+    .with_scenario(
+        Scenario::new("Flash crash -30%").with_shock(Shock::FlashCrash {
+            at: 150,
+            depth: 0.3,
+            recovery: Recovery::Exponential {
+                half_life: 10.0,
+                share: 0.5,
+            },
+        }),
+    )
+    .with_scenario(
+        Scenario::new("Vol x3 + drought")
+            .with_shock(Shock::Volatility {
+                bars: 100..200,
+                multiplier: 3.0,
+            })
+            .with_shock(Shock::Liquidity {
+                bars: 100..200,
+                volume: 0.2,
+                slippage: 20.0,
+            }),
+    )
+    .with_scenario(Scenario::new("Exchange outage").with_shock(Shock::Outage { bars: 140..160 }))
+    .with_scenario(Scenario::episode(Episode::Covid2020, 150))
+    .run(&instrument, || {
+        vec![
+            Box::new(Scripted {
+                orders: vec![(0, OrderIntent::TargetPosition { quantity: 50.0 })],
+            }) as Box<dyn Strategy>,
+            Box::new(CrossoverStrategy::new(5, 20).with_quantity(50.0)),
+            Box::new(DonchianBreakout::from_params(&ParamSet::new()).expect("defaults")),
+        ]
+    });
+    report.print();
+    assert_eq!(report.rows.len(), 12);
+    let hold: Vec<_> = report
+        .rows
+        .iter()
+        .filter(|r| r.strategy == "Scripted")
+        .collect();
+    assert!(hold.iter().all(|r| r.baseline == hold[0].baseline));
+    assert!(hold[0].return_impact() < -10.0);
+    assert!(hold[0].drawdown_impact() > 10.0);
+    let worst = report.worst("Scripted").expect("rows for buy and hold");
+    println!(
+        "Worst for buy and hold: {} ({:+.2} pts)",
+        worst.scenario,
+        worst.return_impact()
+    );
+    assert!(worst.return_impact() <= hold[0].return_impact());
+    assert!(report.worst("missing").is_none());
+}
 
-    // Test indicators
-    let prices = vec![100.0, 102.0, 101.0, 103.0, 105.0, 104.0, 106.0, 108.0, 107.0, 109.0];
+fn test_metrics() {
+    println!("\n=== Unified Metrics ===");
+
+    let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+
+    // +10%, -10%, +10% from 100: hand-checked
+    let curve = [(0, 110.0), (1, 99.0), (2, 108.9)];
+    let m = Metrics::compute(
+        100.0,
+        &curve,
+        &[100.0, -50.0, 30.0, -20.0],
+        &MetricsConfig::per_bar(1.0),
+    );
+    let sd = (0.04f64 / 3.0).sqrt();
+    assert_eq!(m.periods, 3);
+    assert!(close(m.total_return, 0.089));
+    assert!(close(m.max_drawdown, 0.1));
+    assert!(close(m.volatility, sd));
+    assert!(close(m.sharpe, (0.1 / 3.0) / sd));
+    assert!(close(m.sortino, (0.1 / 3.0) / (0.01f64 / 3.0).sqrt()));
+    assert!(close(m.annual_return, 1.089f64.powf(1.0 / 3.0) - 1.0));
+    assert!(close(m.calmar, m.annual_return / 0.1));
+    assert!(close(m.profit_factor, 130.0 / 70.0));
+    assert!(close(m.win_rate, 0.5));
+    assert!(close(m.avg_trade, 15.0));
+    assert!(close(m.avg_win, 65.0));
+    assert!(close(m.avg_loss, -35.0));
+
+    // Annualization scales Sharpe by the square root of the periods per year
+    let quarterly = Metrics::compute(100.0, &curve, &[], &MetricsConfig::per_bar(4.0));
+    assert!(close(quarterly.sharpe, 2.0 * m.sharpe));
+    assert!(close(
+        quarterly.annual_return,
+        1.089f64.powf(4.0 / 3.0) - 1.0
+    ));
+    assert_eq!(quarterly.trades, 0);
+    assert_eq!(quarterly.profit_factor, 0.0);
+
+    // The annual risk-free rate is compounded down to one period
+    let config = MetricsConfig::per_bar(4.0).with_risk_free_rate(0.1);
+    assert!(close(
+        config.risk_free_per_period(),
+        1.1f64.powf(0.25) - 1.0
+    ));
+    let excess = Metrics::compute(100.0, &curve, &[], &config);
+    assert!(close(
+        excess.sharpe,
+        (0.1 / 3.0 - config.risk_free_per_period()) / sd * 2.0
+    ));
+
+    // Resampling keeps the last point of each period
+    let hourly = [
+        (0, 101.0),
+        (1, 103.0),
+        (2, 102.0),
+        (3, 99.0),
+        (4, 104.0),
+        (5, 110.0),
+    ];
+    let daily = Metrics::compute(100.0, &hourly, &[], &MetricsConfig::resampled(3, 252.0));
+    assert_eq!(daily.periods, 2);
+    assert!(close(daily.total_return, 0.1));
+    assert_eq!(
+        ch352_test::metrics::period_returns(100.0, &hourly, ch352_test::ReturnFrequency::Every(3)),
+        vec![102.0 / 100.0 - 1.0, 110.0 / 102.0 - 1.0]
+    );
+    assert!(close(daily.max_drawdown, 1.0 - 99.0 / 103.0));
+    assert_eq!(
+        Metrics::compute(100.0, &curve, &[5.0], &MetricsConfig::default()).profit_factor,
+        f64::INFINITY
+    );
+
+    // Every backtester reports through the same module
+    let mut seed = 19u64;
+    let mut price = 100.0;
+    let bars: Vec<OHLCV> = (0..300)
+        .map(|i| {
+            let open = price;
+            price *= 1.0 + 0.001 + 0.01 * gaussian_noise(&mut seed);
+            OHLCV::new(i, open, open.max(price), open.min(price), price, 1000.0)
+        })
+        .collect();
+    let instrument = Instrument::new("MET", bars);
+    let results = Backtester::new(
+        BacktestConfig::new(10_000.0)
+            .with_commission(0.001)
+            .with_bars_per_year(365.0),
+    )
+    .run(
+        &mut CrossoverStrategy::new(5, 20).with_quantity(40.0),
+        &instrument,
+    );
+    let metrics = results.metrics();
+    println!("{}", metrics);
+    assert!(close(metrics.total_return * 100.0, results.total_return()));
+    assert!((metrics.max_drawdown * 100.0 - results.max_drawdown()).abs() < 1e-6);
+    assert_eq!(metrics.trades, results.trades.len());
+    assert!(close(
+        metrics.sharpe,
+        ch352_test::overfitting::sharpe_ratio(&results.returns()) * 365f64.sqrt()
+    ));
+    let daily = results.metrics_with(&MetricsConfig::per_bar(252.0));
+    assert!(close(
+        daily.sharpe / metrics.sharpe,
+        (252.0f64 / 365.0).sqrt()
+    ));
+
+    let mut tester = MultiInstrumentTester::new(10_000.0);
+    tester.add_instrument(instrument);
+    let multi = tester
+        .run_tests(|| Box::new(CrossoverStrategy::new(5, 20).with_quantity(40.0)))
+        .expect("market orders only");
+    let m = multi[0].metrics(&MetricsConfig::default());
+    assert!(close(multi[0].sharpe_ratio, m.sharpe));
+    assert!(close(multi[0].total_return, m.total_return * 100.0));
+}
 
-    let sma = SMA::new(3);
-    let sma_values = sma.calculate(&prices);
-    println!("SMA(3): {:?}", sma_values);
-    assert!(!sma_values.is_empty(), "SMA should produce values");
+fn test_extended_metrics() {
+    println!("\n=== Extended Metrics ===");
+
+    use ch352_test::metrics::{self, Drawdowns};
+
+    let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+    // 100 -> 120 -> 90 -> 108 -> 120 -> 96 -> 100.8: hand-checked
+    let equity = [120.0, 90.0, 108.0, 120.0, 96.0, 100.8];
+    let curve: Vec<(i64, f64)> = equity
+        .iter()
+        .enumerate()
+        .map(|(t, &e)| (t as i64, e))
+        .collect();
+    let returns = [0.2, -0.25, 0.2, 1.0 / 9.0, -0.2, 0.05];
+    let config = MetricsConfig::per_bar(252.0).with_confidence(0.8);
+    let m = Metrics::compute(100.0, &curve, &[], &config);
+    println!("{}", m);
+
+    // Under water from bar 1 to 4, and again from 4 to the end; the 25% drop took 2 bars to recover
+    assert_eq!(m.max_drawdown_duration, 3);
+    assert_eq!(m.time_to_recovery, Some(2));
+    let squares = 0.25f64.powi(2) + 0.1f64.powi(2) + 0.2f64.powi(2) + 0.16f64.powi(2);
+    assert!(close(m.ulcer_index, (squares / 6.0).sqrt()));
+    assert!(close(m.ulcer_performance, m.annual_return / m.ulcer_index));
+    assert!(close(
+        m.omega,
+        (0.2 + 0.2 + 1.0 / 9.0 + 0.05) / (0.25 + 0.2)
+    ));
+    assert!(close(m.tail_ratio, 0.2 / (0.25 - 0.05 * 0.25)));
+    assert!(close(m.var, 0.2));
+    assert!(close(m.cvar, 0.225));
+    // Mean return 1/54; the deviations from it are 196, -290, 196, 100, -236 and 34 over 1080,
+    // whose 2nd, 3rd and 4th powers average 37964, -3572480 and 2204628144 (over 1080^p)
+    assert!(close(m.skewness, -3_572_480.0 / 37_964f64.powf(1.5)));
+    assert!((m.skewness + 0.482_961).abs() < 1e-6);
+    assert!(close(m.kurtosis, 2_204_628_144.0 / 37_964f64.powi(2)));
+    assert!((m.kurtosis - 1.529_648).abs() < 1e-6);
+    let (mean, sd) = (
+        ch352_test::stats::mean(&returns),
+        ch352_test::stats::std_dev(&returns),
+    );
+    let z = ch352_test::stats::normal_quantile(0.2);
+    assert!((z + 0.841_621).abs() < 1e-6);
+    assert!(close(m.parametric_var, -(mean + z * sd)));
+    assert!(close(
+        m.parametric_cvar,
+        -(mean - sd * ch352_test::stats::normal_pdf(z) / 0.2)
+    ));
+    assert!(m.parametric_cvar > m.parametric_var);
+    assert!(close(metrics::parametric_var(&returns, 0.5), -mean));
+
+    // Still under water at the end: no recovery, and the drawdown lasts to the last bar
+    let sinking = Drawdowns::of(&[100.0, 90.0, 95.0]);
+    assert_eq!(sinking.max_duration, 2);
+    assert_eq!(sinking.time_to_recovery, None);
+    let rising = Drawdowns::of(&[100.0, 101.0, 102.0]);
+    assert_eq!(
+        (
+            rising.max_duration,
+            rising.time_to_recovery,
+            rising.ulcer_index
+        ),
+        (0, Some(0), 0.0)
+    );
+
+    // The Omega threshold is the risk-free return
+    assert!(close(
+        metrics::omega_ratio(&returns, 0.05),
+        (0.15 + 0.15 + 1.0 / 9.0 - 0.05) / (0.3 + 0.25)
+    ));
+    assert_eq!(metrics::omega_ratio(&[0.01, 0.02], 0.0), f64::INFINITY);
+
+    // Rolling windows of any metric, each starting from the equity before it
+    let rolling = metrics::rolling(100.0, &curve, 2, &config, |m| m.total_return);
+    assert_eq!(
+        rolling.iter().map(|r| r.0).collect::<Vec<_>>(),
+        vec![1, 2, 3, 4, 5]
+    );
+    assert!(close(rolling[0].1, 90.0 / 100.0 - 1.0));
+    assert!(close(rolling[1].1, 108.0 / 120.0 - 1.0));
+    assert!(close(rolling[2].1, 120.0 / 90.0 - 1.0));
+    assert!(close(rolling[3].1, 96.0 / 108.0 - 1.0));
+    assert!(close(rolling[4].1, 100.8 / 120.0 - 1.0));
+    let rolling_dd = metrics::rolling(100.0, &curve, 3, &config, |m| m.max_drawdown);
+    assert!(close(rolling_dd[0].1, 0.25));
+    assert!(metrics::rolling(100.0, &curve, 7, &config, |m| m.sharpe).is_empty());
+}
 
-    let ema = EMA::new(3);
-    let ema_values = ema.calculate(&prices);
-    println!("EMA(3): {:?}", ema_values);
-    assert!(!ema_values.is_empty(), "EMA should produce values");
+fn test_comparison() {
+    println!("\n=== Strategy Comparison ===");
 
-    // Test RSI with more data
-    let rsi_prices: Vec<f64> = (0..30).map(|i| 50000.0 + (i as f64 * 100.0)).collect();
-    let rsi = RSI::new(14);
-    let rsi_values = rsi.calculate(&rsi_prices);
-    println!("RSI(14) values count: {}", rsi_values.len());
+    use ch352_test::comparison::jobson_korkie;
 
-    // Test strategy
-    let mut data = MarketData::new("BTCUSDT");
-    for i in 0..50 {
-        let price = 50000.0 + (i as f64 * 50.0);
-        data.add_candle(price, 1000.0, i);
+    // Three noise strategies, one with a real daily edge, one a copy of the edge plus noise
+    let mut seed = 2024u64;
+    let mut series = |drift: f64| -> Vec<f64> {
+        (0..750)
+            .map(|_| drift + 0.01 * gaussian_noise(&mut seed))
+            .collect()
+    };
+    let (noise_a, noise_b, noise_c, edge) = (series(0.0), series(0.0), series(0.0), series(0.002));
+    let mut noise_seed = 11u64;
+    let shadow: Vec<f64> = edge
+        .iter()
+        .map(|r| r + 0.005 * gaussian_noise(&mut noise_seed))
+        .collect();
+
+    let config = MetricsConfig::per_bar(252.0);
+    let noise_only = || {
+        StrategyComparison::new(config, 42)
+            .with_strategy("noise-a", noise_a.clone())
+            .with_strategy("noise-b", noise_b.clone())
+            .with_strategy("noise-c", noise_c.clone())
+            .with_resamples(500)
+    };
+    let report = noise_only().run().unwrap();
+    report.print();
+    // Picking the best of several coin flips is not evidence of skill
+    assert!(report.reality_check.p_value > 0.05);
+    assert!(!report.best_is_significant(0.05));
+    assert_eq!(report, noise_only().run().unwrap());
+
+    let report = noise_only()
+        .with_strategy("edge", edge.clone())
+        .with_strategy("shadow", shadow.clone())
+        .run()
+        .unwrap();
+    report.print();
+    assert_eq!(report.rows[0].name, "edge");
+    assert!(report.rows.windows(2).all(|w| w[0].sharpe >= w[1].sharpe));
+    assert!(report.best_is_significant(0.05));
+    assert!(report.reality_check.p_value < 0.05);
+    assert!(report.row("edge").unwrap().p_value < 0.01);
+    let leader = report.row("edge").unwrap();
+    assert!(leader.sharpe_low < leader.sharpe && leader.sharpe < leader.sharpe_high);
+    assert!(leader.versus_best.is_none());
+
+    // The copy trails the edge, but a gap that small cannot be told from chance
+    let gap = report.row("shadow").unwrap().versus_best.clone().unwrap();
+    assert!(gap.low < gap.difference && gap.difference < gap.high);
+    assert!(gap.p_value > 0.05);
+    let noise_gap = report.row("noise-a").unwrap().versus_best.clone().unwrap();
+    assert!(noise_gap.low > 0.0 && noise_gap.p_value < 0.05);
+
+    assert!((report.correlation("edge", "edge").unwrap() - 1.0).abs() < 1e-9);
+    assert!(report.correlation("edge", "shadow").unwrap() > 0.8);
+    assert!(report.correlation("noise-a", "edge").unwrap().abs() < 0.2);
+
+    // Jobson-Korkie-Memmel is symmetric, and zero for identical strategies
+    let (z, p) = jobson_korkie(&edge, &noise_a, 0.0);
+    let (z_back, p_back) = jobson_korkie(&noise_a, &edge, 0.0);
+    assert!((z + z_back).abs() < 1e-12 && (p - p_back).abs() < 1e-12);
+    assert_eq!(jobson_korkie(&edge, &edge, 0.0), (0.0, 1.0));
+
+    // Against a benchmark that is the edge itself, nothing beats it
+    let versus_edge = noise_only().with_benchmark(edge.clone()).run().unwrap();
+    assert!(
+        versus_edge
+            .rows
+            .iter()
+            .all(|r| r.excess_return < 0.0 && r.p_value > 0.5)
+    );
+
+    assert_eq!(
+        StrategyComparison::new(config, 1)
+            .with_strategy("solo", edge.clone())
+            .run(),
+        Err(ComparisonError::NotEnoughStrategies)
+    );
+    assert!(matches!(
+        noise_only().with_strategy("short", vec![0.01; 10]).run(),
+        Err(ComparisonError::LengthMismatch {
+            expected: 750,
+            found: 10,
+            ..
+        })
+    ));
+    assert_eq!(
+        StrategyComparison::new(config, 1)
+            .with_strategy("a", vec![0.01; 5])
+            .with_strategy("b", vec![0.02; 5])
+            .run(),
+        Err(ComparisonError::NotEnoughData {
+            needed: 10,
+            available: 5
+        })
+    );
+
+    // Without resamples every p-value would be 0 and noise would look significant
+    assert!(matches!(
+        noise_only().with_resamples(0).run(),
+        Err(ComparisonError::InvalidConfig(_))
+    ));
+    for confidence in [0.0, 1.0, f64::NAN] {
+        assert!(matches!(
+            noise_only().with_confidence(confidence).run(),
+            Err(ComparisonError::InvalidConfig(_))
+        ));
     }
+}
 
-    let strategy = CrossoverStrategy::new(5, 20);
-    println!("\nStrategy: {}", strategy.name());
-    println!("Parameters: {:?}", strategy.parameters());
-
-    let signal = strategy.generate_signal(&data);
-    println!("Signal: {:?}", signal);
+fn test_trade_analysis() {
+    println!("\n=== Trade Analysis ===");
+
+    use ch352_test::Streaks;
+
+    let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+    // Hourly bars from Monday 1970-01-05 00:00 UTC
+    let monday = 4 * 86_400;
+    let bars = [
+        (100.0, 100.0, 100.0, 100.0),
+        (100.0, 103.0, 98.0, 102.0),
+        (102.0, 106.0, 101.0, 105.0),
+        (104.0, 110.0, 90.0, 95.0),
+        (95.0, 100.0, 94.0, 100.0),
+        (100.0, 104.0, 99.0, 103.0),
+        (103.0, 109.0, 102.0, 108.0),
+        (108.0, 108.0, 107.0, 107.0),
+        (107.0, 108.0, 105.0, 106.0),
+        (106.0, 107.0, 104.0, 105.0),
+    ];
+    let instrument = Instrument::new(
+        "TEST",
+        bars.iter()
+            .enumerate()
+            .map(|(i, &(o, h, l, c))| OHLCV::new(monday + i as i64 * 3_600, o, h, l, c, 1000.0))
+            .collect(),
+    );
+    let mut strategy = Scripted {
+        orders: vec![
+            // Long 1 -> 3 exiting on a signal, short 5 -> 6 stopped out, long 8 -> end
+            (0, OrderIntent::TargetPosition { quantity: 1.0 }),
+            (2, OrderIntent::TargetPosition { quantity: 0.0 }),
+            (
+                4,
+                OrderIntent::Entry {
+                    side: Side::Sell,
+                    quantity: 1.0,
+                    order_type: OrderType::Market,
+                    bracket: Some(Bracket {
+                        stop_loss: Some(108.0),
+                        take_profit: Some(90.0),
+                    }),
+                },
+            ),
+            (7, OrderIntent::TargetPosition { quantity: 1.0 }),
+        ],
+    };
+    let results = Backtester::new(BacktestConfig::new(10_000.0)).run(&mut strategy, &instrument);
+    let reasons: Vec<ExitReason> = results.trades.iter().map(|t| t.exit_reason).collect();
+    assert_eq!(
+        reasons,
+        vec![
+            ExitReason::Signal,
+            ExitReason::StopLoss,
+            ExitReason::EndOfData
+        ]
+    );
+
+    let report = TradeAnalyzer::new().analyze(&results, &instrument);
+    report.print();
+
+    // The 90-110 swing of bar 3 came after the exit at its open and does not count
+    let e = &report.excursions;
+    assert!(close(e[0].mae, 0.02) && close(e[0].mfe, 0.06));
+    assert!(close(e[0].result, 0.04) && close(e[0].efficiency(), 4.0 / 6.0));
+    assert!(close(e[1].mae, 0.08) && close(e[1].mfe, 0.01));
+    assert!(close(e[2].mae, 2.0 / 107.0) && close(e[2].mfe, 1.0 / 107.0));
+    assert!(close(report.winner_mae_quantile(0.95), 0.02));
+    assert!(close(report.loser_mfe_quantile(1.0), 0.01));
+
+    let labels = |rows: &[ch352_test::Breakdown]| -> Vec<(String, usize)> {
+        rows.iter().map(|r| (r.label.clone(), r.trades())).collect()
+    };
+    assert_eq!(
+        labels(&report.by_holding_time),
+        vec![("1".to_string(), 2), ("2-5".to_string(), 1)]
+    );
+    assert_eq!(
+        labels(&report.by_hour),
+        vec![
+            ("01:00".to_string(), 1),
+            ("05:00".to_string(), 1),
+            ("08:00".to_string(), 1)
+        ]
+    );
+    assert_eq!(labels(&report.by_weekday), vec![("Mon".to_string(), 3)]);
+    let by_exit: Vec<(String, f64)> = report
+        .by_exit_reason
+        .iter()
+        .map(|r| (r.label.clone(), r.total_pnl))
+        .collect();
+    assert_eq!(
+        by_exit,
+        vec![
+            ("signal".to_string(), 4.0),
+            ("stop-loss".to_string(), -8.0),
+            ("end of data".to_string(), -2.0)
+        ]
+    );
+    assert_eq!(report.by_exit_reason[0].win_rate, 100.0);
+
+    // Two hours behind UTC the first entry falls on Sunday night
+    let shifted = TradeAnalyzer::new()
+        .with_utc_offset(-2 * 3_600)
+        .analyze(&results, &instrument);
+    assert_eq!(
+        labels(&shifted.by_weekday),
+        vec![("Mon".to_string(), 2), ("Sun".to_string(), 1)]
+    );
+    assert_eq!(shifted.by_hour[0].label, "03:00");
+    assert_eq!(shifted.by_hour[2].label, "23:00");
+    let coarse = TradeAnalyzer::new()
+        .with_holding_buckets(vec![1])
+        .analyze(&results, &instrument);
+    assert_eq!(
+        labels(&coarse.by_holding_time),
+        vec![("0-1".to_string(), 2), (">1".to_string(), 1)]
+    );
+
+    assert_eq!(report.streaks.current, -2);
+    let streaks = Streaks::of(&[1.0, 2.0, -1.0, 0.0, 3.0, -1.0, -2.0, -3.0]);
+    println!("{:?}", streaks);
+    assert_eq!(
+        (streaks.longest_win, streaks.longest_loss, streaks.current),
+        (2, 3, -3)
+    );
+    assert!(close(streaks.average_win, 1.5) && close(streaks.average_loss, 2.0));
+    assert!(close(streaks.win_after_win, 100.0 / 3.0) && close(streaks.win_after_loss, 0.0));
+}
 
-    // Test strategy manager
-    let mut manager = StrategyManager::new();
-    manager.add_strategy(Box::new(CrossoverStrategy::new(5, 20)));
-    manager.add_strategy(Box::new(CrossoverStrategy::new(10, 30)));
+fn test_benchmark() {
+    println!("\n=== Benchmark-Relative Performance ===");
+
+    let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+    let config = MetricsConfig::per_bar(252.0);
+
+    // A daily random walk, and a strategy that holds 90 units from the second bar
+    let mut seed = 99u64;
+    let mut price = 100.0;
+    let bars: Vec<OHLCV> = (0..500)
+        .map(|i| {
+            let open = price;
+            price *= 1.0 + 0.0005 + 0.01 * gaussian_noise(&mut seed);
+            OHLCV::new(
+                i * 86_400,
+                open,
+                open.max(price) * 1.002,
+                open.min(price) * 0.998,
+                price,
+                1000.0,
+            )
+        })
+        .collect();
+    let instrument = Instrument::new("IDX", bars);
+    let mut strategy = Scripted {
+        orders: vec![(0, OrderIntent::TargetPosition { quantity: 90.0 })],
+    };
+    let results = Backtester::new(BacktestConfig::new(10_000.0)).run(&mut strategy, &instrument);
+
+    let benchmark = Benchmark::buy_and_hold(&instrument);
+    let report = benchmark.compare(&results, &config).unwrap();
+    report.print();
+    let first_open = instrument.data[0].open;
+    assert!(close(
+        report.benchmark_metrics.total_return,
+        price / first_open - 1.0
+    ));
+    assert_eq!(report.relative.periods, 500);
+    // Mostly invested, so it tracks the index at a little less than full beta
+    let relative = report.relative;
+    assert!(
+        relative.beta > 0.7 && relative.beta < 1.0,
+        "beta {}",
+        relative.beta
+    );
+    assert!(relative.correlation > 0.95);
+    assert!(relative.up_capture < 1.0 && relative.down_capture < 1.0);
+    assert!(relative.tracking_error > 0.0);
+
+    // The benchmark is valued at or before each timestamp
+    assert_eq!(benchmark.value_at(-1), first_open);
+    assert_eq!(benchmark.value_at(86_400 + 5), instrument.data[1].close);
+    let weekly = MetricsConfig::resampled(7 * 86_400, 52.0);
+    let timestamps: Vec<i64> = results.equity_curve.iter().map(|p| p.timestamp).collect();
+    assert_eq!(benchmark.returns_along(&timestamps, &weekly).len(), 72);
+
+    let mut seed = 7u64;
+    let mut series = |drift: f64, scale: f64| -> Vec<f64> {
+        (0..1000)
+            .map(|_| drift + scale * gaussian_noise(&mut seed))
+            .collect()
+    };
+    let market = series(0.0004, 0.01);
+    let momentum = series(0.0, 0.006);
+    let volatility = series(0.0, 0.004);
+    let noise = series(0.0, 0.002);
+
+    // Twice the market: beta 2, no alpha, capture twice the market both ways
+    let levered: Vec<f64> = market.iter().map(|r| 2.0 * r).collect();
+    let relative = RelativeMetrics::compute(&levered, &market, &config).unwrap();
+    println!("\n2x market:\n{}", relative);
+    assert!(close(relative.beta, 2.0) && close(relative.alpha, 0.0));
+    assert!(close(relative.correlation, 1.0) && close(relative.r_squared, 1.0));
+    assert!(close(relative.up_capture, 2.0) && close(relative.down_capture, 2.0));
+    let market_sharpe =
+        ch352_test::stats::mean(&market) / ch352_test::stats::std_dev(&market) * 252f64.sqrt();
+    assert!(close(relative.information_ratio, market_sharpe));
+
+    let with_rate = config.with_risk_free_rate(0.03);
+    let same = RelativeMetrics::compute(&market, &market, &with_rate).unwrap();
+    assert!(close(same.beta, 1.0) && close(same.alpha, 0.0));
+    assert!(close(same.tracking_error, 0.0) && close(same.information_ratio, 0.0));
+
+    // A known mix of factors plus a daily edge is recovered by the regression
+    let strategy: Vec<f64> = (0..1000)
+        .map(|t| 0.0003 + 0.6 * market[t] + 0.9 * momentum[t] + noise[t])
+        .collect();
+    let model = FactorModel::new()
+        .with_factor("market", market.clone())
+        .with_factor("momentum", momentum.clone())
+        .with_factor("volatility", volatility.clone());
+    let attribution = model.fit(&strategy, &config).unwrap();
+    attribution.print();
+    let beta = |name: &str| attribution.exposure(name).unwrap().beta;
+    assert!((beta("market") - 0.6).abs() < 0.02);
+    assert!((beta("momentum") - 0.9).abs() < 0.03);
+    assert!(attribution.exposure("volatility").unwrap().t_stat.abs() < 3.0);
+    assert!(
+        attribution.alpha > 0.03 && attribution.alpha < 0.12,
+        "alpha {}",
+        attribution.alpha
+    );
+    assert!(attribution.alpha_t > 2.0);
+    assert!(attribution.r_squared > 0.9);
+    let explained: f64 = attribution.exposures.iter().map(|e| e.contribution).sum();
+    assert!(close(
+        explained + attribution.alpha,
+        attribution.excess_return
+    ));
+
+    assert_eq!(
+        FactorModel::new().fit(&strategy, &config),
+        Err(BenchmarkError::NoFactors)
+    );
+    assert!(matches!(
+        FactorModel::new()
+            .with_factor("market", market.clone())
+            .with_factor("short", vec![0.01; 10])
+            .fit(&strategy, &config),
+        Err(BenchmarkError::LengthMismatch { name, expected: 1000, found: 10 }) if name == "short"
+    ));
+    assert_eq!(
+        RelativeMetrics::compute(&strategy, &vec![0.001; 1000], &config),
+        Err(BenchmarkError::Singular)
+    );
+    assert_eq!(
+        RelativeMetrics::compute(&[0.01, 0.02], &[0.01, 0.02], &config),
+        Err(BenchmarkError::NotEnoughData {
+            needed: 3,
+            available: 2
+        })
+    );
+}
 
-    println!("\n=== Signals from All Strategies ===");
-    for (name, signal) in manager.generate_signals(&data) {
-        println!("{}: {:?}", name, signal);
+fn test_paper_trading() {
+    println!("\n=== Paper Trading ===");
+
+    use ch352_test::PriceFeed;
+    use std::time::Duration;
+
+    let mut seed = 301u64;
+    let mut price = 100.0;
+    let bars: Vec<OHLCV> = (0..300)
+        .map(|i| {
+            let open = price;
+            price *= 1.0 + 0.01 * gaussian_noise(&mut seed);
+            OHLCV::new(
+                1_700_000_000 + i * 3_600,
+                open,
+                open.max(price) * 1.001,
+                open.min(price) * 0.999,
+                price,
+                50.0,
+            )
+        })
+        .collect();
+    let instrument = Instrument::new("BTCUSDT", bars.clone());
+    let dir = std::env::temp_dir().join(format!("ch352_paper_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let replay = dir.join("btcusdt_1h.csv");
+    let mut csv = String::from("timestamp,open,high,low,close,volume\n");
+    for b in &bars {
+        csv.push_str(&format!(
+            "{},{:?},{:?},{:?},{:?},{:?}\n",
+            b.timestamp, b.open, b.high, b.low, b.close, b.volume
+        ));
     }
-
-    println!("\n=== All tests passed! ===");
+    std::fs::write(&replay, csv).unwrap();
+
+    let config = BacktestConfig::new(10_000.0)
+        .with_commission(0.001)
+        .with_slippage(0.0005);
+    let backtest = Backtester::new(config).run(&mut CrossoverStrategy::new(5, 20), &instrument);
+
+    // Replaying the recorded bars makes the backtest's fills, minus its closing one
+    let mut strategy = CrossoverStrategy::new(5, 20);
+    let mut trader =
+        PaperTrader::open(dir.join("replay.jsonl"), config, "BTCUSDT", &mut strategy).unwrap();
+    let traded = trader
+        .run(&mut strategy, &mut LineFeed::replay(&replay).unwrap())
+        .unwrap();
+    assert_eq!(traded, 300);
+    let paper = trader.results(&strategy);
+    assert!(!paper.fills.is_empty());
+    assert_eq!(paper.fills[..], backtest.fills[..paper.fills.len()]);
+    assert!(backtest.fills.len() - paper.fills.len() <= 1);
+    assert_eq!(paper.equity_curve[..299], backtest.equity_curve[..299]);
+    let drift = ExecutionDrift::between(&backtest, &paper);
+    drift.print();
+    assert_eq!(
+        (drift.matched_fills, drift.paper_only, drift.shared_bars),
+        (paper.fills.len(), 0, 300)
+    );
+    assert_eq!(drift.price_drift_bps, 0.0);
+    println!("Paper run: {}", paper.metrics());
+
+    // Stop halfway, restart from the journal and replay the whole feed again
+    let state = dir.join("restart.jsonl");
+    {
+        let mut strategy = CrossoverStrategy::new(5, 20);
+        let mut trader = PaperTrader::open(&state, config, "BTCUSDT", &mut strategy).unwrap();
+        let mut feed = LineFeed::replay(&replay).unwrap();
+        for _ in 0..150 {
+            let bar = feed.next_bar().unwrap().unwrap();
+            trader.on_bar(&mut strategy, &bar).unwrap();
+        }
+    }
+    // The header, then one line per bar
+    assert_eq!(
+        std::fs::read_to_string(&state).unwrap().lines().count(),
+        151
+    );
+    let mut strategy = CrossoverStrategy::new(5, 20);
+    let mut resumed = PaperTrader::open(&state, config, "BTCUSDT", &mut strategy).unwrap();
+    assert_eq!(resumed.bars(), 150);
+    let traded = resumed
+        .run(&mut strategy, &mut LineFeed::replay(&replay).unwrap())
+        .unwrap();
+    assert_eq!(traded, 150);
+    let restarted = resumed.results(&strategy);
+    assert_eq!(restarted.fills, paper.fills);
+    assert_eq!(restarted.trades, paper.trades);
+    assert_eq!(restarted.equity_curve, paper.equity_curve);
+    assert_eq!(resumed.context(), trader.context());
+
+    // A journal only resumes the run it was written by
+    assert!(matches!(
+        PaperTrader::open(
+            &state,
+            config,
+            "BTCUSDT",
+            &mut CrossoverStrategy::new(10, 30)
+        ),
+        Err(PaperError::State(_))
+    ));
+    assert!(matches!(
+        PaperTrader::open(
+            &state,
+            config,
+            "ETHUSDT",
+            &mut CrossoverStrategy::new(5, 20)
+        ),
+        Err(PaperError::State(_))
+    ));
+
+    // The same bars streamed over a local websocket
+    let server = StubServer::start(bars[..120].to_vec(), Duration::from_millis(1)).unwrap();
+    let mut strategy = CrossoverStrategy::new(5, 20);
+    let mut streamed =
+        PaperTrader::open(dir.join("stream.jsonl"), config, "BTCUSDT", &mut strategy).unwrap();
+    let mut feed = WebSocketFeed::connect(&server.url()).unwrap();
+    assert_eq!(streamed.run(&mut strategy, &mut feed).unwrap(), 120);
+    server.join().unwrap();
+    let streamed = streamed.results(&strategy);
+    assert_eq!(streamed.equity_curve[..], paper.equity_curve[..120]);
+
+    // Live fills that cost more than the backtest assumed show up as drift
+    let live_config = config.with_slippage(0.002);
+    let mut strategy = CrossoverStrategy::new(5, 20);
+    let mut live = PaperTrader::open(
+        dir.join("live.jsonl"),
+        live_config,
+        "BTCUSDT",
+        &mut strategy,
+    )
+    .unwrap();
+    live.run(&mut strategy, &mut LineFeed::replay(&replay).unwrap())
+        .unwrap();
+    let live = live.results(&strategy);
+    let drift = ExecutionDrift::between(&backtest, &live);
+    drift.print();
+    assert_eq!(drift.matched_fills, live.fills.len());
+    assert!(
+        (drift.price_drift_bps - 15.0).abs() < 0.1,
+        "drift {}",
+        drift.price_drift_bps
+    );
+    assert!(drift.return_gap < 0.0 && drift.tracking_error > 0.0);
+
+    let mut bad = LineFeed::new("timestamp,open,high,low,close,volume\n\n1,2,3\n".as_bytes());
+    assert!(matches!(
+        bad.next_bar(),
+        Err(PaperError::Parse { line: 3, .. })
+    ));
+    let line = serde_json::to_string(&bars[0]).unwrap();
+    let mut json = LineFeed::new(line.as_bytes());
+    assert_eq!(json.next_bar().unwrap(), Some(bars[0].clone()));
+    assert_eq!(json.next_bar().unwrap(), None);
+
+    std::fs::remove_dir_all(&dir).unwrap();
 }
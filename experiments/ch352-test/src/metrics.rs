@@ -0,0 +1,485 @@
+//! Performance metrics shared by every backtester
+//!
+//! Everything is computed from a timestamped equity curve and the net P&L
+//! of closed trades, under the conventions of a [`MetricsConfig`]:
+//!
+//! - returns are simple returns between consecutive equity points, the first
+//!   one measured from the starting equity; with a resampling period, only
+//!   the last point of each period counts;
+//! - volatility is the sample standard deviation of those returns, annualized
+//!   by the square root of `periods_per_year`;
+//! - the risk-free rate is annual and compounded down to one period; Sharpe
+//!   and Sortino ratios use returns in excess of it;
+//! - downside deviation is the root mean square of the excess returns below
+//!   zero, taken over all periods;
+//! - drawdowns are measured from the running peak, starting equity included;
+//! - profit factor is gross profit over gross loss;
+//! - drawdown durations and the Ulcer index use the equity at the end of
+//!   each return period, so durations count return periods;
+//! - Omega uses the risk-free return as its threshold;
+//! - value at risk and expected shortfall are losses over one period at the
+//!   configured confidence, positive when the return is negative.
+//!
+//! Returns, rates and drawdowns are fractions, not percent.
+
+use std::fmt;
+
+use crate::stats;
+
+/// Which equity points returns are measured between
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReturnFrequency {
+    /// Every point of the curve
+    Bar,
+    /// The last point of each period of this many timestamp units
+    Every(i64),
+}
+
+/// Conventions for turning an equity curve into metrics
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MetricsConfig {
+    pub frequency: ReturnFrequency,
+    /// Return periods in a year, for annualization
+    pub periods_per_year: f64,
+    /// Annual risk-free rate
+    pub risk_free_rate: f64,
+    /// Confidence level of value at risk and expected shortfall
+    pub confidence: f64,
+}
+
+impl MetricsConfig {
+    /// Returns from bar to bar
+    pub fn per_bar(bars_per_year: f64) -> Self {
+        MetricsConfig {
+            frequency: ReturnFrequency::Bar,
+            periods_per_year: bars_per_year,
+            risk_free_rate: 0.0,
+            confidence: 0.95,
+        }
+    }
+
+    /// Returns over periods of `period` timestamp units, e.g. daily returns
+    /// from intraday bars with second timestamps: `resampled(86_400, 365.0)`
+    pub fn resampled(period: i64, periods_per_year: f64) -> Self {
+        MetricsConfig {
+            frequency: ReturnFrequency::Every(period),
+            periods_per_year,
+            risk_free_rate: 0.0,
+            confidence: 0.95,
+        }
+    }
+
+    pub fn with_risk_free_rate(mut self, rate: f64) -> Self {
+        self.risk_free_rate = rate;
+        self
+    }
+
+    pub fn with_confidence(mut self, confidence: f64) -> Self {
+        self.confidence = confidence;
+        self
+    }
+
+    /// Risk-free return over one period
+    pub fn risk_free_per_period(&self) -> f64 {
+        (1.0 + self.risk_free_rate).powf(1.0 / self.periods_per_year) - 1.0
+    }
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        MetricsConfig::per_bar(252.0)
+    }
+}
+
+/// Equity at the end of each period of `curve`, after `initial`
+pub fn period_equity(initial: f64, curve: &[(i64, f64)], frequency: ReturnFrequency) -> Vec<f64> {
+    let mut closes = vec![initial];
+    match frequency {
+        ReturnFrequency::Bar => closes.extend(curve.iter().map(|&(_, equity)| equity)),
+        ReturnFrequency::Every(period) => {
+            let period = period.max(1);
+            let mut bucket = None;
+            for &(timestamp, equity) in curve {
+                let current = timestamp.div_euclid(period);
+                if bucket == Some(current) {
+                    *closes.last_mut().expect("starts with the initial equity") = equity;
+                } else {
+                    closes.push(equity);
+                    bucket = Some(current);
+                }
+            }
+        }
+    }
+    closes
+}
+
+/// Simple returns of `curve` at `frequency`, starting from `initial` equity
+pub fn period_returns(initial: f64, curve: &[(i64, f64)], frequency: ReturnFrequency) -> Vec<f64> {
+    period_equity(initial, curve, frequency)
+        .windows(2)
+        .map(|w| if w[0] != 0.0 { w[1] / w[0] - 1.0 } else { 0.0 })
+        .collect()
+}
+
+/// Largest fall from a running peak, as a fraction of the peak
+pub fn max_drawdown(initial: f64, curve: &[(i64, f64)]) -> f64 {
+    let mut peak = initial;
+    let mut worst: f64 = 0.0;
+    for &(_, equity) in curve {
+        peak = peak.max(equity);
+        if peak > 0.0 {
+            worst = worst.max(1.0 - equity / peak);
+        }
+    }
+    worst
+}
+
+/// Probability-weighted gains over losses relative to `threshold`
+pub fn omega_ratio(returns: &[f64], threshold: f64) -> f64 {
+    let gains: f64 = returns.iter().map(|r| (r - threshold).max(0.0)).sum();
+    let losses: f64 = returns.iter().map(|r| (threshold - r).max(0.0)).sum();
+    if losses > 0.0 {
+        gains / losses
+    } else if gains > 0.0 {
+        f64::INFINITY
+    } else {
+        0.0
+    }
+}
+
+/// 95th percentile return over the size of the 5th percentile return
+pub fn tail_ratio(returns: &[f64]) -> f64 {
+    let lower = stats::quantile(returns, 0.05).abs();
+    if lower > 0.0 {
+        stats::quantile(returns, 0.95).abs() / lower
+    } else {
+        0.0
+    }
+}
+
+/// Loss not exceeded with probability `confidence`, from the sample quantile
+pub fn historical_var(returns: &[f64], confidence: f64) -> f64 {
+    -stats::quantile(returns, 1.0 - confidence)
+}
+
+/// Average loss beyond the historical value at risk
+pub fn historical_cvar(returns: &[f64], confidence: f64) -> f64 {
+    let cutoff = stats::quantile(returns, 1.0 - confidence);
+    let tail: Vec<f64> = returns.iter().copied().filter(|r| *r <= cutoff).collect();
+    if tail.is_empty() {
+        0.0
+    } else {
+        -stats::mean(&tail)
+    }
+}
+
+/// Value at risk of normal returns with the sample mean and deviation
+pub fn parametric_var(returns: &[f64], confidence: f64) -> f64 {
+    -(stats::mean(returns) + stats::std_dev(returns) * stats::normal_quantile(1.0 - confidence))
+}
+
+/// Expected shortfall of normal returns with the sample mean and deviation
+pub fn parametric_cvar(returns: &[f64], confidence: f64) -> f64 {
+    let z = stats::normal_quantile(1.0 - confidence);
+    -(stats::mean(returns) - stats::std_dev(returns) * stats::normal_pdf(z) / (1.0 - confidence))
+}
+
+/// `metric` over every window of `window` consecutive curve points, keyed by
+/// the timestamp of the window's last point
+///
+/// Each window starts from the equity just before it, so its first return is
+/// included. Trade statistics are empty.
+pub fn rolling<F>(
+    initial: f64,
+    curve: &[(i64, f64)],
+    window: usize,
+    config: &MetricsConfig,
+    metric: F,
+) -> Vec<(i64, f64)>
+where
+    F: Fn(&Metrics) -> f64,
+{
+    if window == 0 || window > curve.len() {
+        return Vec::new();
+    }
+    (window - 1..curve.len())
+        .map(|end| {
+            let start = end + 1 - window;
+            let before = if start == 0 {
+                initial
+            } else {
+                curve[start - 1].1
+            };
+            let m = Metrics::compute(before, &curve[start..=end], &[], config);
+            (curve[end].0, metric(&m))
+        })
+        .collect()
+}
+
+/// Longest and deepest stretches below a previous peak of `equity`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Drawdowns {
+    /// Longest run from a peak to its recovery, or to the end if there was none
+    pub max_duration: usize,
+    /// From the trough of the deepest drawdown back to its peak; `None` if
+    /// still under water
+    pub time_to_recovery: Option<usize>,
+    /// Root mean square drawdown
+    pub ulcer_index: f64,
+}
+
+impl Drawdowns {
+    /// `equity` is a series of points, one per period, the first being the start
+    pub fn of(equity: &[f64]) -> Self {
+        let mut peak = equity.first().copied().unwrap_or(0.0);
+        let mut peak_at = 0;
+        let mut underwater = false;
+        let mut max_duration = 0;
+        let mut deepest = 0.0;
+        let mut deepest_peak = 0.0;
+        let mut deepest_trough = 0;
+        let mut squares = 0.0;
+        for (t, &e) in equity.iter().enumerate().skip(1) {
+            if e >= peak {
+                if underwater {
+                    max_duration = max_duration.max(t - peak_at);
+                    underwater = false;
+                }
+                peak = e;
+                peak_at = t;
+                continue;
+            }
+            underwater = true;
+            let dd = if peak > 0.0 { 1.0 - e / peak } else { 0.0 };
+            squares += dd * dd;
+            if dd > deepest {
+                deepest = dd;
+                deepest_peak = peak;
+                deepest_trough = t;
+            }
+        }
+        if underwater {
+            max_duration = max_duration.max(equity.len() - 1 - peak_at);
+        }
+        let time_to_recovery = if deepest > 0.0 {
+            equity[deepest_trough..]
+                .iter()
+                .position(|&e| e >= deepest_peak)
+        } else {
+            Some(0)
+        };
+        Drawdowns {
+            max_duration,
+            time_to_recovery,
+            ulcer_index: if equity.len() > 1 {
+                (squares / (equity.len() - 1) as f64).sqrt()
+            } else {
+                0.0
+            },
+        }
+    }
+}
+
+/// Headline performance of one run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Metrics {
+    /// Number of return periods
+    pub periods: usize,
+    pub total_return: f64,
+    /// Compound annual growth rate
+    pub annual_return: f64,
+    /// Annualized
+    pub volatility: f64,
+    pub sharpe: f64,
+    pub sortino: f64,
+    pub max_drawdown: f64,
+    /// Annual return over maximum drawdown
+    pub calmar: f64,
+    pub trades: usize,
+    pub win_rate: f64,
+    /// Infinite when there are profits and no losses
+    pub profit_factor: f64,
+    pub avg_trade: f64,
+    pub avg_win: f64,
+    /// Negative, or 0 without losing trades
+    pub avg_loss: f64,
+    /// In return periods
+    pub max_drawdown_duration: usize,
+    /// In return periods; `None` if the deepest drawdown has not recovered
+    pub time_to_recovery: Option<usize>,
+    pub ulcer_index: f64,
+    /// Annual excess return over the Ulcer index
+    pub ulcer_performance: f64,
+    pub omega: f64,
+    pub tail_ratio: f64,
+    pub skewness: f64,
+    /// Plain, not excess: 3 for normal returns
+    pub kurtosis: f64,
+    pub var: f64,
+    pub cvar: f64,
+    pub parametric_var: f64,
+    pub parametric_cvar: f64,
+}
+
+impl Metrics {
+    /// Metrics of an equity curve of `(timestamp, equity)` points that starts
+    /// from `initial`, and of the net P&L of each closed trade
+    pub fn compute(
+        initial: f64,
+        curve: &[(i64, f64)],
+        trade_pnls: &[f64],
+        config: &MetricsConfig,
+    ) -> Self {
+        let returns = period_returns(initial, curve, config.frequency);
+        let periods = returns.len();
+        let final_equity = curve.last().map_or(initial, |&(_, equity)| equity);
+        let total_return = if initial != 0.0 {
+            final_equity / initial - 1.0
+        } else {
+            0.0
+        };
+        let annual_return = if periods == 0 {
+            0.0
+        } else if total_return <= -1.0 {
+            -1.0
+        } else {
+            (1.0 + total_return).powf(config.periods_per_year / periods as f64) - 1.0
+        };
+
+        let annualize = config.periods_per_year.sqrt();
+        let rf = config.risk_free_per_period();
+        let excess: Vec<f64> = returns.iter().map(|r| r - rf).collect();
+        let sd = stats::std_dev(&returns);
+        let sharpe = if sd > 0.0 {
+            stats::mean(&excess) / sd * annualize
+        } else {
+            0.0
+        };
+        let downside = if periods > 0 {
+            (excess.iter().map(|r| r.min(0.0).powi(2)).sum::<f64>() / periods as f64).sqrt()
+        } else {
+            0.0
+        };
+        let sortino = if downside > 0.0 {
+            stats::mean(&excess) / downside * annualize
+        } else {
+            0.0
+        };
+        let max_drawdown = max_drawdown(initial, curve);
+        let drawdowns = Drawdowns::of(&period_equity(initial, curve, config.frequency));
+
+        let wins: Vec<f64> = trade_pnls.iter().copied().filter(|p| *p > 0.0).collect();
+        let losses: Vec<f64> = trade_pnls.iter().copied().filter(|p| *p < 0.0).collect();
+        let gross_profit: f64 = wins.iter().sum();
+        let gross_loss: f64 = -losses.iter().sum::<f64>();
+        let profit_factor = if gross_loss > 0.0 {
+            gross_profit / gross_loss
+        } else if gross_profit > 0.0 {
+            f64::INFINITY
+        } else {
+            0.0
+        };
+
+        Metrics {
+            periods,
+            total_return,
+            annual_return,
+            volatility: sd * annualize,
+            sharpe,
+            sortino,
+            max_drawdown,
+            calmar: if max_drawdown > 0.0 {
+                annual_return / max_drawdown
+            } else {
+                0.0
+            },
+            trades: trade_pnls.len(),
+            win_rate: if trade_pnls.is_empty() {
+                0.0
+            } else {
+                wins.len() as f64 / trade_pnls.len() as f64
+            },
+            profit_factor,
+            avg_trade: if trade_pnls.is_empty() {
+                0.0
+            } else {
+                stats::mean(trade_pnls)
+            },
+            avg_win: if wins.is_empty() {
+                0.0
+            } else {
+                stats::mean(&wins)
+            },
+            avg_loss: if losses.is_empty() {
+                0.0
+            } else {
+                stats::mean(&losses)
+            },
+            max_drawdown_duration: drawdowns.max_duration,
+            time_to_recovery: drawdowns.time_to_recovery,
+            ulcer_index: drawdowns.ulcer_index,
+            ulcer_performance: if drawdowns.ulcer_index > 0.0 {
+                (annual_return - config.risk_free_rate) / drawdowns.ulcer_index
+            } else {
+                0.0
+            },
+            omega: omega_ratio(&returns, rf),
+            tail_ratio: tail_ratio(&returns),
+            skewness: stats::skewness(&returns),
+            kurtosis: stats::kurtosis(&returns),
+            var: historical_var(&returns, config.confidence),
+            cvar: historical_cvar(&returns, config.confidence),
+            parametric_var: parametric_var(&returns, config.confidence),
+            parametric_cvar: parametric_cvar(&returns, config.confidence),
+        }
+    }
+}
+
+impl fmt::Display for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Return {:.2}% (annual {:.2}%), volatility {:.2}%, max drawdown {:.2}%",
+            self.total_return * 100.0,
+            self.annual_return * 100.0,
+            self.volatility * 100.0,
+            self.max_drawdown * 100.0
+        )?;
+        writeln!(
+            f,
+            "Sharpe {:.2}, Sortino {:.2}, Calmar {:.2} over {} periods",
+            self.sharpe, self.sortino, self.calmar, self.periods
+        )?;
+        writeln!(
+            f,
+            "Omega {:.2}, tail ratio {:.2}, Ulcer {:.2}% (UPI {:.2}), skew {:.2}, kurtosis {:.2}",
+            self.omega,
+            self.tail_ratio,
+            self.ulcer_index * 100.0,
+            self.ulcer_performance,
+            self.skewness,
+            self.kurtosis
+        )?;
+        writeln!(
+            f,
+            "VaR {:.2}% / CVaR {:.2}% (normal {:.2}% / {:.2}%), longest drawdown {} periods, recovery {}",
+            self.var * 100.0,
+            self.cvar * 100.0,
+            self.parametric_var * 100.0,
+            self.parametric_cvar * 100.0,
+            self.max_drawdown_duration,
+            self.time_to_recovery
+                .map_or("not yet".to_string(), |t| format!("{} periods", t))
+        )?;
+        write!(
+            f,
+            "{} trades, win rate {:.1}%, profit factor {:.2}, avg trade {:.2} (win {:.2}, loss {:.2})",
+            self.trades,
+            self.win_rate * 100.0,
+            self.profit_factor,
+            self.avg_trade,
+            self.avg_win,
+            self.avg_loss
+        )
+    }
+}
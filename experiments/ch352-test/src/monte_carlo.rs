@@ -0,0 +1,413 @@
+//! Monte Carlo simulation of equity paths
+//!
+//! Each path is a new sequence of returns drawn from the historical ones,
+//! compounded from an equity of 1. Shuffling the order only changes the path
+//! and never the total return, so the default draws with replacement; the
+//! block bootstraps keep runs of neighbouring returns together to preserve
+//! autocorrelation and volatility clustering, and the parametric models draw
+//! from a distribution fitted to the returns.
+//!
+//! Path `i` uses stream `i` of a ChaCha generator seeded with the given seed,
+//! so a simulation repeats exactly whatever the number of threads.
+
+use std::fmt;
+
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rand_distr::{Distribution, Normal, StudentT};
+use rayon::prelude::*;
+
+use crate::stats::{self, Summary};
+
+/// How a path's returns are drawn from the historical ones
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resampling {
+    /// Permutation of the returns: same total return, different path
+    Shuffle,
+    /// Independent draws with replacement
+    Bootstrap,
+    /// Circular blocks of `block` consecutive returns
+    BlockBootstrap { block: usize },
+    /// Politis-Romano bootstrap: circular blocks of geometric length with
+    /// mean `mean_block`
+    StationaryBootstrap { mean_block: f64 },
+    /// Normal returns with the historical mean and standard deviation
+    Normal,
+    /// Student-t returns with `dof` degrees of freedom, scaled to the
+    /// historical mean and standard deviation
+    StudentT { dof: f64 },
+}
+
+impl fmt::Display for Resampling {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Shuffle => write!(f, "shuffle"),
+            Self::Bootstrap => write!(f, "bootstrap"),
+            Self::BlockBootstrap { block } => write!(f, "block bootstrap ({} bars)", block),
+            Self::StationaryBootstrap { mean_block } => {
+                write!(
+                    f,
+                    "stationary bootstrap (mean block {:.1} bars)",
+                    mean_block
+                )
+            }
+            Self::Normal => write!(f, "normal"),
+            Self::StudentT { dof } => write!(f, "student-t ({} dof)", dof),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MonteCarloError {
+    NotEnoughData { needed: usize, available: usize },
+    InvalidConfig(String),
+}
+
+impl fmt::Display for MonteCarloError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotEnoughData { needed, available } => write!(
+                f,
+                "Monte Carlo needs at least {} returns, got {}",
+                needed, available
+            ),
+            Self::InvalidConfig(reason) => write!(f, "Invalid Monte Carlo setup: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for MonteCarloError {}
+
+/// Percentiles of equity across paths at one step
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EquityBand {
+    pub p5: f64,
+    pub p25: f64,
+    pub median: f64,
+    pub p75: f64,
+    pub p95: f64,
+}
+
+impl EquityBand {
+    fn of(values: &[f64]) -> Self {
+        EquityBand {
+            p5: stats::quantile(values, 0.05),
+            p25: stats::quantile(values, 0.25),
+            median: stats::quantile(values, 0.5),
+            p75: stats::quantile(values, 0.75),
+            p95: stats::quantile(values, 0.95),
+        }
+    }
+}
+
+/// Outcome of a simulation; equity is relative to a start of 1
+#[derive(Debug, Clone, PartialEq)]
+pub struct MonteCarloReport {
+    pub method: Resampling,
+    pub seed: u64,
+    /// One band per step, the first being the start
+    pub bands: Vec<EquityBand>,
+    /// Total return of each path
+    pub final_returns: Vec<f64>,
+    /// Maximum drawdown of each path, as a fraction of the peak
+    pub max_drawdowns: Vec<f64>,
+    /// Equity at or below which a path counts as ruined
+    pub ruin_level: f64,
+    /// Paths that reached `ruin_level`
+    pub ruined: usize,
+}
+
+impl MonteCarloReport {
+    pub fn paths(&self) -> usize {
+        self.final_returns.len()
+    }
+
+    pub fn horizon(&self) -> usize {
+        self.bands.len() - 1
+    }
+
+    pub fn risk_of_ruin(&self) -> f64 {
+        self.ruined as f64 / self.paths() as f64
+    }
+
+    pub fn probability_of_loss(&self) -> f64 {
+        self.final_returns.iter().filter(|&&r| r < 0.0).count() as f64 / self.paths() as f64
+    }
+
+    pub fn return_summary(&self) -> Summary {
+        Summary::of(&self.final_returns)
+    }
+
+    pub fn drawdown_summary(&self) -> Summary {
+        Summary::of(&self.max_drawdowns)
+    }
+
+    /// Maximum drawdown not exceeded by a share `confidence` of the paths
+    pub fn drawdown_at_risk(&self, confidence: f64) -> f64 {
+        stats::quantile(&self.max_drawdowns, confidence)
+    }
+
+    /// Prints the summary and the equity bands at `rows` evenly spaced steps
+    pub fn print(&self, rows: usize) {
+        println!(
+            "\n=== Monte Carlo ({}, {} paths x {} bars, seed {}) ===",
+            self.method,
+            self.paths(),
+            self.horizon(),
+            self.seed
+        );
+        let returns = self.return_summary();
+        println!(
+            "Return:   median {:>7.2}%  mean {:>7.2}%  5% {:>7.2}%  95% {:>7.2}%",
+            returns.median * 100.0,
+            returns.mean * 100.0,
+            stats::quantile(&self.final_returns, 0.05) * 100.0,
+            stats::quantile(&self.final_returns, 0.95) * 100.0
+        );
+        let drawdowns = self.drawdown_summary();
+        println!(
+            "Drawdown: median {:>7.2}%  mean {:>7.2}%  95% {:>7.2}%  worst {:>7.2}%",
+            drawdowns.median * 100.0,
+            drawdowns.mean * 100.0,
+            self.drawdown_at_risk(0.95) * 100.0,
+            drawdowns.max * 100.0
+        );
+        println!(
+            "P(loss) {:.1}%, risk of ruin (equity <= {:.0}%) {:.1}%",
+            self.probability_of_loss() * 100.0,
+            self.ruin_level * 100.0,
+            self.risk_of_ruin() * 100.0
+        );
+
+        println!(
+            "\n{:>6}  {:>8}  {:>8}  {:>8}  {:>8}  {:>8}",
+            "Bar", "5%", "25%", "Median", "75%", "95%"
+        );
+        let rows = rows.clamp(1, self.horizon().max(1));
+        for i in 0..=rows {
+            let step = i * self.horizon() / rows;
+            let b = &self.bands[step];
+            println!(
+                "{:>6}  {:>8.4}  {:>8.4}  {:>8.4}  {:>8.4}  {:>8.4}",
+                step, b.p5, b.p25, b.median, b.p75, b.p95
+            );
+        }
+    }
+}
+
+/// Monte Carlo simulation over a series of per-bar or per-trade returns
+pub struct MonteCarlo {
+    returns: Vec<f64>,
+    seed: u64,
+    method: Resampling,
+    paths: usize,
+    horizon: Option<usize>,
+    ruin_level: f64,
+    threads: Option<usize>,
+}
+
+impl MonteCarlo {
+    /// `returns` are simple returns, e.g. `BacktestResults::returns()`
+    pub fn new(returns: Vec<f64>, seed: u64) -> Self {
+        MonteCarlo {
+            returns,
+            seed,
+            method: Resampling::Bootstrap,
+            paths: 1000,
+            horizon: None,
+            ruin_level: 0.5,
+            threads: None,
+        }
+    }
+
+    /// Simulation over closed trades: each P&L becomes a return on the
+    /// equity before it, starting from `initial_capital`
+    pub fn from_trade_pnls(pnls: &[f64], initial_capital: f64, seed: u64) -> Self {
+        let mut equity = initial_capital;
+        let returns = pnls
+            .iter()
+            .map(|pnl| {
+                let r = if equity > 0.0 { pnl / equity } else { 0.0 };
+                equity += pnl;
+                r
+            })
+            .collect();
+        Self::new(returns, seed)
+    }
+
+    pub fn with_method(mut self, method: Resampling) -> Self {
+        self.method = method;
+        self
+    }
+
+    pub fn with_paths(mut self, paths: usize) -> Self {
+        self.paths = paths;
+        self
+    }
+
+    /// Steps per path; defaults to the number of returns
+    pub fn with_horizon(mut self, horizon: usize) -> Self {
+        self.horizon = Some(horizon);
+        self
+    }
+
+    /// Equity, relative to the start, at which a path is ruined
+    pub fn with_ruin_level(mut self, level: f64) -> Self {
+        self.ruin_level = level;
+        self
+    }
+
+    /// Runs on a dedicated pool of `threads` threads instead of the global one
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads);
+        self
+    }
+
+    fn horizon(&self) -> usize {
+        self.horizon.unwrap_or(self.returns.len())
+    }
+
+    fn validate(&self) -> Result<(), MonteCarloError> {
+        if self.returns.len() < 2 {
+            return Err(MonteCarloError::NotEnoughData {
+                needed: 2,
+                available: self.returns.len(),
+            });
+        }
+        let invalid = |reason: &str| Err(MonteCarloError::InvalidConfig(reason.to_string()));
+        if self.paths == 0 || self.horizon() == 0 {
+            return invalid("paths and horizon must be positive");
+        }
+        if !(self.ruin_level > 0.0 && self.ruin_level < 1.0) {
+            return invalid("ruin level must be between 0 and 1");
+        }
+        match self.method {
+            Resampling::Shuffle if self.horizon() != self.returns.len() => {
+                invalid("a shuffle keeps the number of returns")
+            }
+            Resampling::BlockBootstrap { block } if block == 0 || block > self.returns.len() => {
+                invalid("block length must be between 1 and the number of returns")
+            }
+            Resampling::StationaryBootstrap { mean_block }
+                if mean_block.is_nan() || mean_block < 1.0 =>
+            {
+                invalid("mean block length must be at least 1")
+            }
+            Resampling::StudentT { dof } if dof.is_nan() || dof <= 2.0 => {
+                invalid("student-t needs more than 2 degrees of freedom for a finite variance")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns drawn for path `path`; the same ones `run` compounds
+    pub fn sample(&self, path: usize) -> Result<Vec<f64>, MonteCarloError> {
+        self.validate()?;
+        Ok(self.draw(path))
+    }
+
+    fn draw(&self, path: usize) -> Vec<f64> {
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        rng.set_stream(path as u64);
+        let n = self.returns.len();
+        let horizon = self.horizon();
+        match self.method {
+            Resampling::Shuffle => {
+                let mut returns = self.returns.clone();
+                returns.shuffle(&mut rng);
+                returns
+            }
+            Resampling::Bootstrap => (0..horizon)
+                .map(|_| self.returns[rng.gen_range(0..n)])
+                .collect(),
+            Resampling::BlockBootstrap { block } => {
+                let mut out = Vec::with_capacity(horizon);
+                while out.len() < horizon {
+                    let start = rng.gen_range(0..n);
+                    let take = block.min(horizon - out.len());
+                    out.extend((start..start + take).map(|i| self.returns[i % n]));
+                }
+                out
+            }
+            Resampling::StationaryBootstrap { mean_block } => {
+                let restart = 1.0 / mean_block;
+                let mut i = rng.gen_range(0..n);
+                (0..horizon)
+                    .map(|step| {
+                        if step > 0 {
+                            i = if rng.r#gen::<f64>() < restart {
+                                rng.gen_range(0..n)
+                            } else {
+                                (i + 1) % n
+                            };
+                        }
+                        self.returns[i]
+                    })
+                    .collect()
+            }
+            Resampling::Normal => {
+                let normal = Normal::new(stats::mean(&self.returns), stats::std_dev(&self.returns))
+                    .expect("finite standard deviation");
+                (0..horizon).map(|_| normal.sample(&mut rng)).collect()
+            }
+            Resampling::StudentT { dof } => {
+                let t = StudentT::new(dof).expect("positive degrees of freedom");
+                let mean = stats::mean(&self.returns);
+                let scale = stats::std_dev(&self.returns) * ((dof - 2.0) / dof).sqrt();
+                (0..horizon)
+                    .map(|_| mean + scale * t.sample(&mut rng))
+                    .collect()
+            }
+        }
+    }
+
+    pub fn run(&self) -> Result<MonteCarloReport, MonteCarloError> {
+        self.validate()?;
+        let pool = self
+            .threads
+            .map(|threads| rayon::ThreadPoolBuilder::new().num_threads(threads).build())
+            .transpose()
+            .map_err(|e| MonteCarloError::InvalidConfig(e.to_string()))?;
+        let simulate = |path: usize| {
+            // A return below -100% would leave negative equity; it wipes the path out
+            let mut equity = 1.0f64;
+            let mut peak = 1.0f64;
+            let mut max_drawdown = 0.0f64;
+            let mut ruined = false;
+            let mut curve = Vec::with_capacity(self.horizon() + 1);
+            curve.push(equity);
+            for r in self.draw(path) {
+                equity = (equity * (1.0 + r)).max(0.0);
+                peak = peak.max(equity);
+                max_drawdown = max_drawdown.max(1.0 - equity / peak);
+                ruined |= equity <= self.ruin_level;
+                curve.push(equity);
+            }
+            (curve, max_drawdown, ruined)
+        };
+        let simulated: Vec<(Vec<f64>, f64, bool)> = match &pool {
+            Some(pool) => pool.install(|| (0..self.paths).into_par_iter().map(simulate).collect()),
+            None => (0..self.paths).into_par_iter().map(simulate).collect(),
+        };
+
+        let bands = (0..=self.horizon())
+            .map(|step| {
+                let column: Vec<f64> = simulated.iter().map(|(curve, _, _)| curve[step]).collect();
+                EquityBand::of(&column)
+            })
+            .collect();
+        Ok(MonteCarloReport {
+            method: self.method,
+            seed: self.seed,
+            bands,
+            final_returns: simulated
+                .iter()
+                .map(|(curve, _, _)| curve[curve.len() - 1] - 1.0)
+                .collect(),
+            max_drawdowns: simulated.iter().map(|(_, dd, _)| *dd).collect(),
+            ruin_level: self.ruin_level,
+            ruined: simulated.iter().filter(|(_, _, ruined)| *ruined).count(),
+        })
+    }
+}
@@ -0,0 +1,506 @@
+//! Multi-instrument testing (from Chapter 299), extended to strategies that
+//! trade several instruments at once
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::data::{Instrument, OHLCV};
+use crate::metrics::{Metrics, MetricsConfig};
+use crate::params::ParamSet;
+use crate::strategy::{
+    Account, Fill, MarketData, OrderIntent, OrderType, Position, Side, Strategy, StrategyContext,
+};
+
+/// An order intent for one leg of a multi-leg strategy
+#[derive(Debug, Clone, PartialEq)]
+pub struct LegIntent {
+    /// Index into `MultiLegStrategy::symbols()`
+    pub leg: usize,
+    pub intent: OrderIntent,
+}
+
+/// What a multi-leg strategy sees besides market data
+#[derive(Debug, Clone, PartialEq)]
+pub struct MultiLegContext {
+    pub bar_index: usize,
+    /// One position per leg, in `symbols()` order
+    pub positions: Vec<Position>,
+    pub account: Account,
+}
+
+/// A strategy whose decisions involve several instruments together
+///
+/// Same lifecycle as `Strategy`, but `on_bar` receives one `MarketData` per
+/// leg (aligned on common timestamps) and returns intents tagged with a leg.
+pub trait MultiLegStrategy: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Symbols of the legs, in the order used by `LegIntent::leg`
+    fn symbols(&self) -> Vec<String>;
+
+    fn parameters(&self) -> ParamSet;
+
+    fn on_start(&mut self, _ctx: &MultiLegContext) {}
+
+    fn on_bar(&mut self, data: &[MarketData], ctx: &MultiLegContext) -> Vec<LegIntent>;
+
+    fn on_fill(&mut self, _leg: usize, _fill: &Fill, _ctx: &MultiLegContext) {}
+
+    fn on_end(&mut self, _ctx: &MultiLegContext) {}
+}
+
+/// Runs a single-instrument `Strategy` as a one-leg strategy
+pub struct SingleLeg {
+    symbol: String,
+    strategy: Box<dyn Strategy>,
+}
+
+impl SingleLeg {
+    pub fn new(symbol: &str, strategy: Box<dyn Strategy>) -> Self {
+        SingleLeg {
+            symbol: symbol.to_string(),
+            strategy,
+        }
+    }
+
+    fn context(ctx: &MultiLegContext) -> StrategyContext {
+        StrategyContext {
+            bar_index: ctx.bar_index,
+            position: ctx.positions[0],
+            account: ctx.account,
+        }
+    }
+}
+
+impl MultiLegStrategy for SingleLeg {
+    fn name(&self) -> &str {
+        self.strategy.name()
+    }
+
+    fn symbols(&self) -> Vec<String> {
+        vec![self.symbol.clone()]
+    }
+
+    fn parameters(&self) -> ParamSet {
+        self.strategy.parameters()
+    }
+
+    fn on_start(&mut self, ctx: &MultiLegContext) {
+        self.strategy.on_start(&Self::context(ctx));
+    }
+
+    fn on_bar(&mut self, data: &[MarketData], ctx: &MultiLegContext) -> Vec<LegIntent> {
+        self.strategy
+            .on_bar(&data[0], &Self::context(ctx))
+            .into_iter()
+            .map(|intent| LegIntent { leg: 0, intent })
+            .collect()
+    }
+
+    fn on_fill(&mut self, _leg: usize, fill: &Fill, ctx: &MultiLegContext) {
+        self.strategy.on_fill(fill, &Self::context(ctx));
+    }
+
+    fn on_end(&mut self, ctx: &MultiLegContext) {
+        self.strategy.on_end(&Self::context(ctx));
+    }
+}
+
+/// Profit and loss of one leg
+#[derive(Debug, Clone, PartialEq)]
+pub struct LegPnl {
+    pub symbol: String,
+    pub realized: f64,
+    pub commission: f64,
+    pub fills: usize,
+}
+
+impl LegPnl {
+    pub fn net(&self) -> f64 {
+        self.realized - self.commission
+    }
+}
+
+#[derive(Debug)]
+pub struct BacktestResult {
+    pub instrument: String,
+    /// Round trips of the whole leg set: from flat, until every leg that was
+    /// open has been closed or reversed
+    pub total_trades: usize,
+    /// Round trips whose net profit over all legs was positive
+    pub winning_trades: usize,
+    pub total_return: f64,
+    pub max_drawdown: f64,
+    pub sharpe_ratio: f64,
+    pub win_rate: f64,
+    pub initial_capital: f64,
+    /// Mark-to-market equity at every close: (timestamp, equity)
+    pub equity_curve: Vec<(i64, f64)>,
+    pub legs: Vec<LegPnl>,
+}
+
+impl BacktestResult {
+    fn new(instrument: String, initial_capital: f64) -> Self {
+        BacktestResult {
+            instrument,
+            total_trades: 0,
+            winning_trades: 0,
+            total_return: 0.0,
+            max_drawdown: 0.0,
+            sharpe_ratio: 0.0,
+            win_rate: 0.0,
+            initial_capital,
+            equity_curve: Vec::new(),
+            legs: Vec::new(),
+        }
+    }
+
+    fn calculate_metrics(&mut self) {
+        self.win_rate = if self.total_trades > 0 {
+            (self.winning_trades as f64 / self.total_trades as f64) * 100.0
+        } else {
+            0.0
+        };
+
+        let metrics = self.metrics(&MetricsConfig::default());
+        self.total_return = metrics.total_return * 100.0;
+        self.max_drawdown = metrics.max_drawdown * 100.0;
+        self.sharpe_ratio = metrics.sharpe;
+    }
+
+    /// Metrics of the equity curve; only whole-run trade counts are kept,
+    /// so the trade statistics are empty
+    pub fn metrics(&self, config: &MetricsConfig) -> Metrics {
+        Metrics::compute(self.initial_capital, &self.equity_curve, &[], config)
+    }
+
+    pub fn final_equity(&self) -> f64 {
+        self.equity_curve
+            .last()
+            .map(|&(_, e)| e)
+            .unwrap_or(self.initial_capital)
+    }
+
+    /// Net profit summed over all legs
+    pub fn net_pnl(&self) -> f64 {
+        self.legs.iter().map(LegPnl::net).sum()
+    }
+}
+
+/// Why a multi-instrument run could not start
+#[derive(Debug, Clone, PartialEq)]
+pub enum TesterError {
+    UnknownSymbol(String),
+    NoCommonBars,
+    /// An intent tagged with a leg the strategy does not have
+    UnknownLeg(usize),
+    /// Resting orders and brackets, which this tester does not execute
+    UnsupportedOrder(OrderIntent),
+}
+
+impl fmt::Display for TesterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownSymbol(symbol) => write!(f, "Unknown instrument: {}", symbol),
+            Self::NoCommonBars => write!(f, "Legs have no timestamps in common"),
+            Self::UnknownLeg(leg) => write!(f, "Intent for unknown leg {}", leg),
+            Self::UnsupportedOrder(intent) => {
+                write!(
+                    f,
+                    "Order not supported by the multi-instrument tester: {:?}",
+                    intent
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for TesterError {}
+
+/// Backtests strategies over a set of instruments
+///
+/// Orders decided on a bar's close are executed at the next bar's open.
+/// Only market orders are executed: `TargetPosition`, `ClosePosition` and
+/// market `Entry` without a bracket. Limit and stop entries, brackets and
+/// intents for unknown legs fail the run with a `TesterError`. Positions left
+/// open at the end are closed at the last close.
+pub struct MultiInstrumentTester {
+    instruments: Vec<Instrument>,
+    initial_capital: f64,
+    /// Commission as a fraction of traded notional
+    commission_rate: f64,
+}
+
+impl MultiInstrumentTester {
+    pub fn new(initial_capital: f64) -> Self {
+        MultiInstrumentTester {
+            instruments: Vec::new(),
+            initial_capital,
+            commission_rate: 0.0,
+        }
+    }
+
+    pub fn with_commission(mut self, commission_rate: f64) -> Self {
+        self.commission_rate = commission_rate;
+        self
+    }
+
+    pub fn add_instrument(&mut self, instrument: Instrument) {
+        self.instruments.push(instrument);
+    }
+
+    pub fn instrument(&self, symbol: &str) -> Option<&Instrument> {
+        self.instruments.iter().find(|i| i.symbol == symbol)
+    }
+
+    /// Runs a fresh single-instrument strategy on every instrument in isolation
+    pub fn run_tests<F>(&self, make_strategy: F) -> Result<Vec<BacktestResult>, TesterError>
+    where
+        F: Fn() -> Box<dyn Strategy>,
+    {
+        self.instruments
+            .iter()
+            .map(|instrument| {
+                let mut strategy = SingleLeg::new(&instrument.symbol, make_strategy());
+                self.run_multi_leg(&mut strategy)
+            })
+            .collect()
+    }
+
+    /// Steps all legs of `strategy` together on their common timestamps
+    pub fn run_multi_leg(
+        &self,
+        strategy: &mut dyn MultiLegStrategy,
+    ) -> Result<BacktestResult, TesterError> {
+        let symbols = strategy.symbols();
+        let legs = symbols
+            .iter()
+            .map(|s| {
+                self.instrument(s)
+                    .ok_or_else(|| TesterError::UnknownSymbol(s.clone()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let bars = align_bars(&legs);
+        if bars.is_empty() {
+            return Err(TesterError::NoCommonBars);
+        }
+
+        let mut result = BacktestResult::new(symbols.join("/"), self.initial_capital);
+        result.legs = symbols
+            .iter()
+            .map(|s| LegPnl {
+                symbol: s.clone(),
+                realized: 0.0,
+                commission: 0.0,
+                fills: 0,
+            })
+            .collect();
+
+        let mut ctx = MultiLegContext {
+            bar_index: 0,
+            positions: vec![Position::default(); legs.len()],
+            account: Account::new(self.initial_capital),
+        };
+        let mut data: Vec<MarketData> = symbols.iter().map(|s| MarketData::new(s)).collect();
+        let mut pending = vec![0.0; legs.len()];
+        let mut round_trip = RoundTrip::default();
+
+        strategy.on_start(&ctx);
+        for (t, row) in bars.iter().enumerate() {
+            ctx.bar_index = t;
+
+            // Orders from the previous close fill at this open
+            round_trip.begin(&ctx.positions);
+            for leg in 0..legs.len() {
+                let delta = std::mem::take(&mut pending[leg]);
+                if delta != 0.0 {
+                    let fill = self.execute(
+                        &mut ctx,
+                        &mut result,
+                        &mut round_trip,
+                        leg,
+                        delta,
+                        row[leg].open,
+                        row[leg].timestamp,
+                    );
+                    strategy.on_fill(leg, &fill, &ctx);
+                }
+            }
+            round_trip.settle(&ctx.positions, &mut result);
+
+            for (leg, bar) in row.iter().enumerate() {
+                data[leg].add_bar(bar);
+            }
+            mark_to_market(&mut ctx, row);
+            result
+                .equity_curve
+                .push((row[0].timestamp, ctx.account.equity));
+
+            let mut projected = ctx.positions.clone();
+            for LegIntent { leg, intent } in strategy.on_bar(&data, &ctx) {
+                let unsupported = match intent {
+                    OrderIntent::Entry {
+                        order_type,
+                        bracket,
+                        ..
+                    } => order_type != OrderType::Market || bracket.is_some(),
+                    OrderIntent::SetBracket(_) => true,
+                    _ => false,
+                };
+                if unsupported {
+                    return Err(TesterError::UnsupportedOrder(intent));
+                }
+                let position = projected.get_mut(leg).ok_or(TesterError::UnknownLeg(leg))?;
+                let delta = intent.position_delta(position);
+                position.quantity += delta;
+                pending[leg] += delta;
+            }
+        }
+
+        // Close whatever is still open at the last close
+        let last = bars.last().expect("checked non-empty");
+        round_trip.begin(&ctx.positions);
+        for (leg, bar) in last.iter().enumerate() {
+            let open_qty = ctx.positions[leg].quantity;
+            if open_qty != 0.0 {
+                let fill = self.execute(
+                    &mut ctx,
+                    &mut result,
+                    &mut round_trip,
+                    leg,
+                    -open_qty,
+                    bar.close,
+                    bar.timestamp,
+                );
+                strategy.on_fill(leg, &fill, &ctx);
+            }
+        }
+        round_trip.settle(&ctx.positions, &mut result);
+        mark_to_market(&mut ctx, last);
+        if let Some(point) = result.equity_curve.last_mut() {
+            point.1 = ctx.account.equity;
+        }
+        strategy.on_end(&ctx);
+
+        result.calculate_metrics();
+        Ok(result)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn execute(
+        &self,
+        ctx: &mut MultiLegContext,
+        result: &mut BacktestResult,
+        round_trip: &mut RoundTrip,
+        leg: usize,
+        delta: f64,
+        price: f64,
+        timestamp: i64,
+    ) -> Fill {
+        let fill = Fill {
+            timestamp,
+            side: if delta > 0.0 { Side::Buy } else { Side::Sell },
+            price,
+            quantity: delta.abs(),
+            commission: delta.abs() * price * self.commission_rate,
+        };
+
+        let realized = ctx.positions[leg].apply_fill(&fill);
+        ctx.account.cash += realized - fill.commission;
+        round_trip.pnl += realized - fill.commission;
+
+        let pnl = &mut result.legs[leg];
+        pnl.realized += realized;
+        pnl.commission += fill.commission;
+        pnl.fills += 1;
+
+        fill
+    }
+
+    pub fn print_summary(&self, results: &[BacktestResult]) {
+        println!("\n=== Summary Across All Instruments ===\n");
+        println!(
+            "{:<16} {:<8} {:<10} {:<12} {:<12} {:<8}",
+            "Instrument", "Trades", "Win Rate", "Return", "Drawdown", "Sharpe"
+        );
+        println!("{}", "-".repeat(70));
+
+        for result in results {
+            println!(
+                "{:<16} {:<8} {:<9.2}% {:<11.2}% {:<11.2}% {:<8.3}",
+                result.instrument,
+                result.total_trades,
+                result.win_rate,
+                result.total_return,
+                result.max_drawdown,
+                result.sharpe_ratio
+            );
+        }
+    }
+}
+
+/// Net profit of the leg set since it was last flat
+///
+/// A bar's fills close the round trip when every leg that was open before
+/// them is flat or reversed afterwards; a pairs position therefore counts as
+/// one trade, not one per leg.
+#[derive(Debug, Default)]
+struct RoundTrip {
+    pnl: f64,
+    before: Vec<f64>,
+}
+
+impl RoundTrip {
+    fn begin(&mut self, positions: &[Position]) {
+        self.before = positions.iter().map(|p| p.quantity).collect();
+    }
+
+    fn settle(&mut self, positions: &[Position], result: &mut BacktestResult) {
+        let was_open = self.before.iter().any(|&q| q != 0.0);
+        let closed = self
+            .before
+            .iter()
+            .zip(positions)
+            .all(|(&q, p)| q * p.quantity <= 0.0);
+        if was_open && closed {
+            result.total_trades += 1;
+            if self.pnl > 0.0 {
+                result.winning_trades += 1;
+            }
+            self.pnl = 0.0;
+        }
+    }
+}
+
+/// Keeps only timestamps present in every leg, returning one row of bars per timestamp
+fn align_bars<'a>(legs: &[&'a Instrument]) -> Vec<Vec<&'a OHLCV>> {
+    let Some(first) = legs.first() else {
+        return Vec::new();
+    };
+
+    let by_timestamp: Vec<HashMap<i64, &OHLCV>> = legs
+        .iter()
+        .map(|leg| leg.data.iter().map(|b| (b.timestamp, b)).collect())
+        .collect();
+
+    first
+        .data
+        .iter()
+        .filter_map(|bar| {
+            by_timestamp
+                .iter()
+                .map(|bars| bars.get(&bar.timestamp).copied())
+                .collect::<Option<Vec<_>>>()
+        })
+        .collect()
+}
+
+fn mark_to_market(ctx: &mut MultiLegContext, row: &[&OHLCV]) {
+    let unrealized: f64 = ctx
+        .positions
+        .iter()
+        .zip(row)
+        .map(|(p, bar)| p.unrealized_pnl(bar.close))
+        .sum();
+    ctx.account.equity = ctx.account.cash + unrealized;
+}
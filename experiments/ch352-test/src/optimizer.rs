@@ -0,0 +1,457 @@
+//! Search strategies for parameter optimization
+//!
+//! Exhaustive grids grow exponentially with the number of parameters. An
+//! [`Optimizer`] instead proposes parameter sets in batches and is shown the
+//! fitness of everything evaluated so far; `ParamSweep::optimize` drives it
+//! and backtests each batch in parallel. The optimizers take a seed, so a
+//! search can be repeated exactly, and only propose sets that pass the
+//! schema, constraints included.
+
+use std::collections::{HashMap, HashSet};
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::params::{ParamKind, ParamSchema, ParamSet, ParamSpec, ParamValue};
+
+/// Draws of a random set before giving up on satisfying the constraints
+const MAX_DRAWS: usize = 100;
+
+/// A parameter set and how well it did; higher fitness is better
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evaluated {
+    pub params: ParamSet,
+    pub fitness: f64,
+}
+
+/// Proposes parameter sets to evaluate
+pub trait Optimizer {
+    fn name(&self) -> String;
+
+    /// Next sets to evaluate; an empty batch ends the search
+    ///
+    /// `history` holds every set evaluated so far, in evaluation order.
+    /// Sets already in it are skipped, so proposing them again is harmless.
+    fn ask(&mut self, schema: &ParamSchema, history: &[Evaluated]) -> Vec<ParamSet>;
+}
+
+/// Every point of the schema's grid, in one batch
+#[derive(Debug, Clone, Default)]
+pub struct GridSearch {
+    done: bool,
+}
+
+impl GridSearch {
+    pub fn new() -> Self {
+        GridSearch::default()
+    }
+}
+
+impl Optimizer for GridSearch {
+    fn name(&self) -> String {
+        "grid".to_string()
+    }
+
+    fn ask(&mut self, schema: &ParamSchema, _history: &[Evaluated]) -> Vec<ParamSet> {
+        if std::mem::replace(&mut self.done, true) {
+            Vec::new()
+        } else {
+            schema.grid()
+        }
+    }
+}
+
+/// Sets drawn uniformly from the parameter ranges
+pub struct RandomSearch {
+    rng: ChaCha8Rng,
+    batch: usize,
+}
+
+impl RandomSearch {
+    pub fn new(seed: u64) -> Self {
+        RandomSearch {
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            batch: 8,
+        }
+    }
+
+    pub fn with_batch(mut self, batch: usize) -> Self {
+        self.batch = batch.max(1);
+        self
+    }
+}
+
+impl Optimizer for RandomSearch {
+    fn name(&self) -> String {
+        "random".to_string()
+    }
+
+    fn ask(&mut self, schema: &ParamSchema, _history: &[Evaluated]) -> Vec<ParamSet> {
+        (0..self.batch)
+            .filter_map(|_| sample_set(schema, &mut self.rng))
+            .collect()
+    }
+}
+
+/// Tree-structured Parzen estimator (Bergstra et al., 2011)
+///
+/// After a random start, the evaluated sets are split into the best
+/// `gamma` share and the rest, and a kernel density is fitted to each
+/// parameter of both groups. Candidates are drawn around the good sets and
+/// the ones most likely under the good density relative to the other are
+/// evaluated next.
+pub struct TreeParzen {
+    rng: ChaCha8Rng,
+    startup: usize,
+    candidates: usize,
+    gamma: f64,
+    batch: usize,
+}
+
+impl TreeParzen {
+    pub fn new(seed: u64) -> Self {
+        TreeParzen {
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            startup: 10,
+            candidates: 24,
+            gamma: 0.25,
+            batch: 4,
+        }
+    }
+
+    /// Random sets evaluated before the model is used
+    pub fn with_startup(mut self, startup: usize) -> Self {
+        self.startup = startup;
+        self
+    }
+
+    /// Candidates drawn per proposed set
+    pub fn with_candidates(mut self, candidates: usize) -> Self {
+        self.candidates = candidates.max(1);
+        self
+    }
+
+    /// Share of the history counted as good
+    pub fn with_gamma(mut self, gamma: f64) -> Self {
+        self.gamma = gamma.clamp(0.01, 0.99);
+        self
+    }
+
+    pub fn with_batch(mut self, batch: usize) -> Self {
+        self.batch = batch.max(1);
+        self
+    }
+
+    fn bandwidth(observations: usize) -> f64 {
+        0.2 * (observations.max(1) as f64).powf(-0.2)
+    }
+
+    /// Density of `value` under kernels around `observed`, plus a uniform prior
+    fn density(spec: &ParamSpec, observed: &[&ParamValue], value: &ParamValue) -> f64 {
+        let n = observed.len() as f64;
+        match numeric_range(spec) {
+            Some((min, max)) => {
+                let width = (max - min).max(f64::EPSILON);
+                let h = Self::bandwidth(observed.len());
+                let x = (value.as_f64().unwrap_or(min) - min) / width;
+                let kernels: f64 = observed
+                    .iter()
+                    .map(|o| {
+                        let z = (x - (o.as_f64().unwrap_or(min) - min) / width) / h;
+                        (-0.5 * z * z).exp() / (h * (2.0 * std::f64::consts::PI).sqrt())
+                    })
+                    .sum();
+                (kernels + 1.0) / (n + 1.0)
+            }
+            None => {
+                let options = spec.values().len().max(1) as f64;
+                let count = observed.iter().filter(|o| **o == value).count() as f64;
+                (count + 1.0) / (n + options)
+            }
+        }
+    }
+
+    /// A set near a randomly chosen good one
+    fn draw_near(&mut self, schema: &ParamSchema, good: &[&Evaluated]) -> Option<ParamSet> {
+        let h = Self::bandwidth(good.len());
+        let mut set = ParamSet::new();
+        for spec in &schema.specs {
+            let around = good[self.rng.gen_range(0..good.len())]
+                .params
+                .get(&spec.name)
+                .unwrap_or(&spec.default)
+                .clone();
+            let value = match numeric_range(spec) {
+                Some((min, max)) => {
+                    let x =
+                        around.as_f64().unwrap_or(min) + gaussian(&mut self.rng) * h * (max - min);
+                    snap(spec, x)
+                }
+                None if self.rng.gen_bool(0.8) => around,
+                None => sample_value(spec, &mut self.rng),
+            };
+            set.set(&spec.name, value);
+        }
+        schema.validate(&set).ok()
+    }
+}
+
+impl Optimizer for TreeParzen {
+    fn name(&self) -> String {
+        "TPE".to_string()
+    }
+
+    fn ask(&mut self, schema: &ParamSchema, history: &[Evaluated]) -> Vec<ParamSet> {
+        if history.len() < self.startup.max(2) {
+            return (0..self.batch)
+                .filter_map(|_| sample_set(schema, &mut self.rng))
+                .collect();
+        }
+
+        let mut ranked: Vec<&Evaluated> = history.iter().collect();
+        ranked.sort_by(|a, b| b.fitness.total_cmp(&a.fitness));
+        let split = ((self.gamma * ranked.len() as f64).ceil() as usize).clamp(1, ranked.len() - 1);
+        let (good, bad) = ranked.split_at(split);
+
+        let columns: Vec<(Vec<&ParamValue>, Vec<&ParamValue>)> = schema
+            .specs
+            .iter()
+            .map(|spec| (column(good, spec), column(bad, spec)))
+            .collect();
+        let seen: HashSet<String> = history.iter().map(|e| e.params.fingerprint()).collect();
+        let mut scored: Vec<(f64, ParamSet)> = Vec::new();
+        for _ in 0..self.batch * self.candidates {
+            let Some(candidate) = self.draw_near(schema, good) else {
+                continue;
+            };
+            if seen.contains(&candidate.fingerprint()) {
+                continue;
+            }
+            let score: f64 = schema
+                .specs
+                .iter()
+                .zip(&columns)
+                .map(|(spec, (good, bad))| {
+                    let value = candidate.get(&spec.name).unwrap_or(&spec.default);
+                    (Self::density(spec, good, value) / Self::density(spec, bad, value)).ln()
+                })
+                .sum();
+            scored.push((score, candidate));
+        }
+
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let mut batch: Vec<ParamSet> = Vec::new();
+        for (_, candidate) in scored {
+            if batch.len() == self.batch {
+                break;
+            }
+            if !batch.contains(&candidate) {
+                batch.push(candidate);
+            }
+        }
+        if batch.is_empty() {
+            // Every candidate was already evaluated; explore instead
+            batch.extend(sample_set(schema, &mut self.rng));
+        }
+        batch
+    }
+}
+
+/// Genetic algorithm with elitism
+///
+/// Each generation keeps its `elite` best sets unchanged and fills the rest
+/// with children of tournament-selected parents: uniform crossover, then
+/// each parameter mutates with probability `mutation_rate`.
+pub struct Genetic {
+    rng: ChaCha8Rng,
+    population: usize,
+    elite: usize,
+    mutation_rate: f64,
+    tournament: usize,
+    current: Vec<ParamSet>,
+}
+
+impl Genetic {
+    pub fn new(seed: u64) -> Self {
+        Genetic {
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            population: 20,
+            elite: 2,
+            mutation_rate: 0.2,
+            tournament: 3,
+            current: Vec::new(),
+        }
+    }
+
+    pub fn with_population(mut self, population: usize) -> Self {
+        self.population = population.max(2);
+        self
+    }
+
+    /// Best sets carried over unchanged to the next generation
+    pub fn with_elite(mut self, elite: usize) -> Self {
+        self.elite = elite;
+        self
+    }
+
+    pub fn with_mutation_rate(mut self, rate: f64) -> Self {
+        self.mutation_rate = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_tournament(mut self, size: usize) -> Self {
+        self.tournament = size.max(1);
+        self
+    }
+
+    fn select<'a>(&mut self, ranked: &'a [(f64, ParamSet)]) -> &'a ParamSet {
+        // Ranked best first, so the smallest index drawn wins
+        let winner = (0..self.tournament)
+            .map(|_| self.rng.gen_range(0..ranked.len()))
+            .min()
+            .expect("tournament size is at least 1");
+        &ranked[winner].1
+    }
+
+    fn breed(&mut self, schema: &ParamSchema, a: &ParamSet, b: &ParamSet) -> Option<ParamSet> {
+        let mut child = ParamSet::new();
+        for spec in &schema.specs {
+            let parent = if self.rng.gen_bool(0.5) { a } else { b };
+            let mut value = parent.get(&spec.name).unwrap_or(&spec.default).clone();
+            if self.rng.gen_bool(self.mutation_rate) {
+                value = match numeric_range(spec) {
+                    Some((min, max)) => snap(
+                        spec,
+                        value.as_f64().unwrap_or(min) + gaussian(&mut self.rng) * 0.1 * (max - min),
+                    ),
+                    None => sample_value(spec, &mut self.rng),
+                };
+            }
+            child.set(&spec.name, value);
+        }
+        schema.validate(&child).ok()
+    }
+}
+
+impl Optimizer for Genetic {
+    fn name(&self) -> String {
+        "genetic".to_string()
+    }
+
+    fn ask(&mut self, schema: &ParamSchema, history: &[Evaluated]) -> Vec<ParamSet> {
+        if self.current.is_empty() {
+            self.current = (0..self.population)
+                .filter_map(|_| sample_set(schema, &mut self.rng))
+                .collect();
+            return self.current.clone();
+        }
+
+        let fitness: HashMap<String, f64> = history
+            .iter()
+            .map(|e| (e.params.fingerprint(), e.fitness))
+            .collect();
+        let mut ranked: Vec<(f64, ParamSet)> = self
+            .current
+            .drain(..)
+            .map(|p| {
+                (
+                    *fitness.get(&p.fingerprint()).unwrap_or(&f64::NEG_INFINITY),
+                    p,
+                )
+            })
+            .collect();
+        ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let mut next: Vec<ParamSet> = ranked
+            .iter()
+            .take(self.elite.min(self.population))
+            .map(|(_, p)| p.clone())
+            .collect();
+        let mut attempts = 0;
+        while next.len() < self.population && attempts < self.population * MAX_DRAWS {
+            attempts += 1;
+            let a = self.select(&ranked).clone();
+            let b = self.select(&ranked).clone();
+            if let Some(child) = self.breed(schema, &a, &b)
+                && !next.contains(&child)
+            {
+                next.push(child);
+            }
+        }
+        self.current = next;
+        self.current.clone()
+    }
+}
+
+/// Values of one parameter across a group of evaluated sets
+fn column<'a>(group: &[&'a Evaluated], spec: &'a ParamSpec) -> Vec<&'a ParamValue> {
+    group
+        .iter()
+        .map(|e| e.params.get(&spec.name).unwrap_or(&spec.default))
+        .collect()
+}
+
+/// `(min, max)` of numeric parameters; `None` for bools and choices
+fn numeric_range(spec: &ParamSpec) -> Option<(f64, f64)> {
+    match spec.kind {
+        ParamKind::Int { min, max, .. } => Some((min as f64, max as f64)),
+        ParamKind::Float { min, max, .. } => Some((min, max)),
+        ParamKind::Bool | ParamKind::Choice(_) => None,
+    }
+}
+
+/// Nearest valid value to `x`, clamped into range and onto the step grid
+fn snap(spec: &ParamSpec, x: f64) -> ParamValue {
+    match spec.kind {
+        ParamKind::Int { min, max, step } => {
+            let steps = ((x.clamp(min as f64, max as f64) - min as f64) / step as f64).round();
+            ParamValue::Int((min + steps as i64 * step).min(max - (max - min) % step))
+        }
+        ParamKind::Float { min, max, step } => {
+            let x = x.clamp(min, max);
+            match step {
+                Some(step) if step > 0.0 => {
+                    let snapped = min + ((x - min) / step).round() * step;
+                    ParamValue::Float(if snapped > max {
+                        snapped - step
+                    } else {
+                        snapped
+                    })
+                }
+                _ => ParamValue::Float(x),
+            }
+        }
+        _ => spec.default.clone(),
+    }
+}
+
+fn sample_value(spec: &ParamSpec, rng: &mut ChaCha8Rng) -> ParamValue {
+    match &spec.kind {
+        ParamKind::Int { min, max, step } => {
+            ParamValue::Int(min + rng.gen_range(0..=(max - min) / step) * step)
+        }
+        ParamKind::Float { min, max, .. } => snap(spec, rng.gen_range(*min..=*max)),
+        ParamKind::Bool => ParamValue::Bool(rng.gen_bool(0.5)),
+        ParamKind::Choice(options) => {
+            ParamValue::Choice(options[rng.gen_range(0..options.len())].clone())
+        }
+    }
+}
+
+/// A uniformly drawn set that satisfies the schema, if one is found
+fn sample_set(schema: &ParamSchema, rng: &mut ChaCha8Rng) -> Option<ParamSet> {
+    (0..MAX_DRAWS).find_map(|_| {
+        let mut set = ParamSet::new();
+        for spec in &schema.specs {
+            set.set(&spec.name, sample_value(spec, rng));
+        }
+        schema.validate(&set).ok()
+    })
+}
+
+/// Standard normal draw (Box-Muller)
+fn gaussian(rng: &mut ChaCha8Rng) -> f64 {
+    let u1: f64 = rng.gen_range(f64::EPSILON..1.0);
+    let u2: f64 = rng.r#gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
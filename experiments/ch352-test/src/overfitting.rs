@@ -0,0 +1,292 @@
+//! Overfitting statistics for parameter searches
+//!
+//! Picking the best of many backtests inflates its Sharpe ratio. The
+//! statistics here use every trial of a search to measure how much:
+//!
+//! - the deflated Sharpe ratio (Bailey and López de Prado, 2014) is the
+//!   probability that the winner's true Sharpe ratio is above what the best
+//!   of that many unskilled trials would be expected to show, correcting for
+//!   skewed and fat-tailed returns;
+//! - the probability of backtest overfitting comes from combinatorially
+//!   symmetric cross-validation (CSCV): the bars are split into groups, and
+//!   for every half of the groups the trial that was best in-sample is ranked
+//!   on the other half;
+//! - the minimum backtest length is the number of bars below which the best
+//!   of that many unskilled trials would be expected to reach the winner's
+//!   Sharpe ratio by luck alone.
+//!
+//! Sharpe ratios here are per bar and not annualized.
+
+use crate::cross_validation::combinations;
+use crate::stats;
+
+const EULER_GAMMA: f64 = 0.577_215_664_901_532_9;
+
+/// Mean over sample standard deviation of per-bar returns
+pub fn sharpe_ratio(returns: &[f64]) -> f64 {
+    let sd = stats::std_dev(returns);
+    if sd > 0.0 {
+        stats::mean(returns) / sd
+    } else {
+        0.0
+    }
+}
+
+/// Probability that the true Sharpe ratio exceeds `benchmark`
+///
+/// `kurtosis` is the plain (not excess) kurtosis of the returns.
+pub fn probabilistic_sharpe_ratio(
+    sharpe: f64,
+    benchmark: f64,
+    observations: usize,
+    skewness: f64,
+    kurtosis: f64,
+) -> f64 {
+    if observations < 2 {
+        return 0.0;
+    }
+    let variance = 1.0 - skewness * sharpe + (kurtosis - 1.0) / 4.0 * sharpe * sharpe;
+    if variance <= 0.0 {
+        return if sharpe > benchmark { 1.0 } else { 0.0 };
+    }
+    stats::normal_cdf((sharpe - benchmark) * ((observations - 1) as f64).sqrt() / variance.sqrt())
+}
+
+/// Expected maximum of `trials` independent standard normal draws
+fn expected_max_standard_normal(trials: usize) -> f64 {
+    if trials < 2 {
+        return 0.0;
+    }
+    let n = trials as f64;
+    (1.0 - EULER_GAMMA) * stats::normal_quantile(1.0 - 1.0 / n)
+        + EULER_GAMMA * stats::normal_quantile(1.0 - 1.0 / (n * std::f64::consts::E))
+}
+
+/// Expected best Sharpe ratio of `trials` unskilled trials whose Sharpe
+/// ratios have variance `variance`
+pub fn expected_max_sharpe(trials: usize, variance: f64) -> f64 {
+    variance.max(0.0).sqrt() * expected_max_standard_normal(trials)
+}
+
+/// Bars needed before the best of `trials` unskilled trials would not be
+/// expected to reach `sharpe`; infinite for a non-positive `sharpe`
+pub fn min_backtest_length(trials: usize, sharpe: f64) -> f64 {
+    if sharpe <= 0.0 {
+        return f64::INFINITY;
+    }
+    (expected_max_standard_normal(trials) / sharpe).powi(2)
+}
+
+/// Sharpe ratio of a search winner, deflated by the number of trials
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeflatedSharpe {
+    pub sharpe: f64,
+    /// Expected best Sharpe ratio of the trials under no skill
+    pub expected_max: f64,
+    /// Probability that the true Sharpe ratio beats `expected_max`
+    pub probability: f64,
+    pub trials: usize,
+    pub observations: usize,
+    pub skewness: f64,
+    pub kurtosis: f64,
+}
+
+impl DeflatedSharpe {
+    /// `returns` are the winner's per-bar returns, `trial_sharpes` the
+    /// Sharpe ratios of all trials including the winner
+    pub fn of(returns: &[f64], trial_sharpes: &[f64]) -> Self {
+        let sharpe = sharpe_ratio(returns);
+        let skewness = stats::skewness(returns);
+        let kurtosis = stats::kurtosis(returns);
+        let expected_max = expected_max_sharpe(trial_sharpes.len(), stats::variance(trial_sharpes));
+        DeflatedSharpe {
+            sharpe,
+            expected_max,
+            probability: probabilistic_sharpe_ratio(
+                sharpe,
+                expected_max,
+                returns.len(),
+                skewness,
+                kurtosis,
+            ),
+            trials: trial_sharpes.len(),
+            observations: returns.len(),
+            skewness,
+            kurtosis,
+        }
+    }
+}
+
+/// Result of combinatorially symmetric cross-validation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pbo {
+    /// Share of splits where the in-sample winner ranked in the bottom half
+    /// out of sample
+    pub probability: f64,
+    /// Logit of the winner's relative out-of-sample rank, one per split
+    pub logits: Vec<f64>,
+    /// Slope of the winner's out-of-sample on in-sample Sharpe ratio
+    pub degradation: f64,
+    /// Share of splits where the winner lost money out of sample
+    pub probability_of_loss: f64,
+}
+
+/// Runs CSCV over `groups` contiguous groups of bars
+///
+/// `trial_returns` holds the per-bar returns of each trial over the same
+/// bars. Returns `None` with fewer than two trials, an odd or too small
+/// `groups`, or fewer than two bars per group.
+pub fn probability_of_backtest_overfitting(
+    trial_returns: &[Vec<f64>],
+    groups: usize,
+) -> Option<Pbo> {
+    let trials = trial_returns.len();
+    let bars = trial_returns.first()?.len();
+    if trials < 2 || groups < 2 || !groups.is_multiple_of(2) || bars < 2 * groups {
+        return None;
+    }
+    if trial_returns.iter().any(|r| r.len() != bars) {
+        return None;
+    }
+
+    // Per trial and group: count, sum and sum of squares, so each split is a few additions
+    let size = bars / groups;
+    let sums: Vec<Vec<(f64, f64, f64)>> = trial_returns
+        .iter()
+        .map(|returns| {
+            (0..groups)
+                .map(|g| {
+                    let end = if g + 1 == groups {
+                        bars
+                    } else {
+                        (g + 1) * size
+                    };
+                    returns[g * size..end]
+                        .iter()
+                        .fold((0.0, 0.0, 0.0), |(n, s, q), r| (n + 1.0, s + r, q + r * r))
+                })
+                .collect()
+        })
+        .collect();
+    let sharpe_over = |trial: usize, chosen: &[bool], inside: bool| {
+        let (n, s, q) = sums[trial]
+            .iter()
+            .zip(chosen)
+            .filter(|(_, c)| **c == inside)
+            .fold((0.0, 0.0, 0.0), |(n, s, q), (g, _)| {
+                (n + g.0, s + g.1, q + g.2)
+            });
+        let mean = s / n;
+        let variance = (q - n * mean * mean) / (n - 1.0);
+        if variance > 1e-18 {
+            mean / variance.sqrt()
+        } else {
+            0.0
+        }
+    };
+
+    let mut logits = Vec::new();
+    let mut is_sharpes = Vec::new();
+    let mut oos_sharpes = Vec::new();
+    for in_sample in combinations(groups, groups / 2) {
+        let mut chosen = vec![false; groups];
+        for g in in_sample {
+            chosen[g] = true;
+        }
+        let is: Vec<f64> = (0..trials).map(|t| sharpe_over(t, &chosen, true)).collect();
+        let oos: Vec<f64> = (0..trials)
+            .map(|t| sharpe_over(t, &chosen, false))
+            .collect();
+        let best = (0..trials)
+            .max_by(|&a, &b| is[a].total_cmp(&is[b]))
+            .expect("at least two trials");
+
+        // Rank 1 is the worst; ties share the average rank
+        let below = oos.iter().filter(|&&s| s < oos[best]).count();
+        let ties = oos.iter().filter(|&&s| s == oos[best]).count() - 1;
+        let rank = 1.0 + below as f64 + ties as f64 / 2.0;
+        let omega = rank / (trials + 1) as f64;
+        logits.push((omega / (1.0 - omega)).ln());
+        is_sharpes.push(is[best]);
+        oos_sharpes.push(oos[best]);
+    }
+
+    let splits = logits.len() as f64;
+    Some(Pbo {
+        probability: logits.iter().filter(|&&l| l <= 0.0).count() as f64 / splits,
+        degradation: stats::linear_fit(&is_sharpes, &oos_sharpes).map_or(0.0, |(_, beta)| beta),
+        probability_of_loss: oos_sharpes.iter().filter(|&&s| s < 0.0).count() as f64 / splits,
+        logits,
+    })
+}
+
+/// Overfitting statistics of one parameter search
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverfittingReport {
+    pub deflated_sharpe: DeflatedSharpe,
+    /// `None` when there is too little data or only one trial
+    pub pbo: Option<Pbo>,
+    /// In bars; compare with `deflated_sharpe.observations`
+    pub min_backtest_length: f64,
+}
+
+impl OverfittingReport {
+    /// Statistics for the search over `trial_returns` that picked trial `best`
+    pub fn from_trials(trial_returns: &[Vec<f64>], best: usize, cscv_groups: usize) -> Self {
+        let sharpes: Vec<f64> = trial_returns.iter().map(|r| sharpe_ratio(r)).collect();
+        let deflated_sharpe = DeflatedSharpe::of(&trial_returns[best], &sharpes);
+        OverfittingReport {
+            min_backtest_length: min_backtest_length(trial_returns.len(), deflated_sharpe.sharpe),
+            pbo: probability_of_backtest_overfitting(trial_returns, cscv_groups),
+            deflated_sharpe,
+        }
+    }
+
+    /// Whether the backtest is shorter than the minimum for this many trials
+    pub fn too_short(&self) -> bool {
+        (self.deflated_sharpe.observations as f64) < self.min_backtest_length
+    }
+
+    /// The winner fails the deflated Sharpe test at `confidence`, or CSCV
+    /// finds it more likely than not to be overfit
+    pub fn is_overfitted(&self, confidence: f64) -> bool {
+        self.deflated_sharpe.probability < confidence
+            || self.pbo.as_ref().is_some_and(|p| p.probability > 0.5)
+    }
+
+    pub fn print(&self, bars_per_year: f64) {
+        let d = &self.deflated_sharpe;
+        let annualize = bars_per_year.sqrt();
+        println!(
+            "\n--- Overfitting ({} trials, {} bars) ---",
+            d.trials, d.observations
+        );
+        println!(
+            "Sharpe {:.2} vs expected best under no skill {:.2} (annualized), skew {:.2}, kurtosis {:.2}",
+            d.sharpe * annualize,
+            d.expected_max * annualize,
+            d.skewness,
+            d.kurtosis
+        );
+        println!("Deflated Sharpe ratio: {:.3}", d.probability);
+        match &self.pbo {
+            Some(pbo) => println!(
+                "PBO: {:.3} over {} splits, degradation slope {:.2}, P(OOS loss) {:.3}",
+                pbo.probability,
+                pbo.logits.len(),
+                pbo.degradation,
+                pbo.probability_of_loss
+            ),
+            None => println!("PBO: not enough data"),
+        }
+        println!(
+            "Minimum backtest length: {:.0} bars{}",
+            self.min_backtest_length,
+            if self.too_short() {
+                " (backtest is shorter)"
+            } else {
+                ""
+            }
+        );
+    }
+}
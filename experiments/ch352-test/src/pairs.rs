@@ -0,0 +1,344 @@
+//! Pairs trading / statistical arbitrage
+//!
+//! Hedge ratio estimation (rolling OLS or Kalman filter), the Engle-Granger
+//! cointegration test and a z-score spread strategy that trades both legs.
+
+use crate::multi_instrument::{LegIntent, MultiLegContext, MultiLegStrategy};
+use crate::params::{Constraint, ParamError, ParamSchema, ParamSet, ParamSpec, ParamValue};
+use crate::stats;
+use crate::strategy::{MarketData, OrderIntent};
+
+/// Engle-Granger critical values for two variables with a constant
+/// (MacKinnon 2010): (significance level, critical value)
+pub const ENGLE_GRANGER_CRITICAL_VALUES: [(f64, f64); 3] =
+    [(0.01, -3.90), (0.05, -3.34), (0.10, -3.04)];
+
+/// Result of the Engle-Granger two-step test of `y = alpha + beta * x + e`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CointegrationResult {
+    pub alpha: f64,
+    pub beta: f64,
+    /// ADF t-statistic of the residuals
+    pub adf_stat: f64,
+    pub lags: usize,
+}
+
+impl CointegrationResult {
+    /// True when the unit-root null is rejected at `level` (0.01, 0.05 or 0.10)
+    pub fn is_cointegrated(&self, level: f64) -> bool {
+        ENGLE_GRANGER_CRITICAL_VALUES
+            .iter()
+            .find(|(l, _)| (*l - level).abs() < 1e-9)
+            .map(|(_, cv)| self.adf_stat < *cv)
+            .unwrap_or(false)
+    }
+}
+
+/// Augmented Dickey-Fuller t-statistic without constant or trend
+///
+/// Regresses `d(e_t)` on `e_(t-1)` and `lags` lagged differences and returns
+/// the t-statistic of the `e_(t-1)` coefficient. Returns `None` if there is
+/// not enough data.
+pub fn adf_statistic(series: &[f64], lags: usize) -> Option<f64> {
+    let diffs: Vec<f64> = series.windows(2).map(|w| w[1] - w[0]).collect();
+    if diffs.len() <= lags + 2 {
+        return None;
+    }
+
+    // Row t uses d(e_t) with t running over diffs[lags..]
+    let rows = lags..diffs.len();
+    let y: Vec<f64> = rows.clone().map(|t| diffs[t]).collect();
+    let mut columns = vec![rows.clone().map(|t| series[t]).collect::<Vec<f64>>()];
+    for lag in 1..=lags {
+        columns.push(rows.clone().map(|t| diffs[t - lag]).collect());
+    }
+
+    stats::ols(&columns, &y).map(|fit| fit.t_stat(0))
+}
+
+/// Engle-Granger cointegration test: OLS of `y` on `x`, then ADF on the residuals
+pub fn engle_granger(y: &[f64], x: &[f64], lags: usize) -> Option<CointegrationResult> {
+    if y.len() != x.len() {
+        return None;
+    }
+    let fit = stats::ols(&[vec![1.0; x.len()], x.to_vec()], y)?;
+    let adf_stat = adf_statistic(&fit.residuals, lags)?;
+
+    Some(CointegrationResult {
+        alpha: fit.coefficients[0],
+        beta: fit.coefficients[1],
+        adf_stat,
+        lags,
+    })
+}
+
+/// Hedge ratio from an OLS fit over the last `window` bars
+pub fn rolling_hedge_ratio(y: &[f64], x: &[f64], window: usize) -> Option<(f64, f64)> {
+    if y.len() < window || x.len() < window {
+        return None;
+    }
+    stats::linear_fit(&x[x.len() - window..], &y[y.len() - window..])
+}
+
+/// Online hedge ratio estimate with a Kalman filter
+///
+/// The state `[beta, alpha]` follows a random walk whose speed is set by
+/// `delta`; `observation_var` is the noise of `y` around `alpha + beta * x`.
+#[derive(Debug, Clone)]
+pub struct KalmanHedge {
+    delta: f64,
+    observation_var: f64,
+    state: [f64; 2],
+    covariance: [[f64; 2]; 2],
+}
+
+impl KalmanHedge {
+    pub fn new(delta: f64, observation_var: f64) -> Self {
+        KalmanHedge {
+            delta,
+            observation_var,
+            state: [0.0, 0.0],
+            covariance: [[0.0; 2]; 2],
+        }
+    }
+
+    pub fn beta(&self) -> f64 {
+        self.state[0]
+    }
+
+    pub fn alpha(&self) -> f64 {
+        self.state[1]
+    }
+
+    /// Feeds one observation and returns the updated `(alpha, beta)`
+    pub fn update(&mut self, x: f64, y: f64) -> (f64, f64) {
+        let f = [x, 1.0];
+        let vw = self.delta / (1.0 - self.delta);
+
+        // Predict: R = P + Vw
+        let mut r = self.covariance;
+        r[0][0] += vw;
+        r[1][1] += vw;
+
+        // Innovation
+        let y_hat = f[0] * self.state[0] + f[1] * self.state[1];
+        let rf = [
+            r[0][0] * f[0] + r[0][1] * f[1],
+            r[1][0] * f[0] + r[1][1] * f[1],
+        ];
+        let q = f[0] * rf[0] + f[1] * rf[1] + self.observation_var;
+        let error = y - y_hat;
+
+        // Update
+        let gain = [rf[0] / q, rf[1] / q];
+        self.state[0] += gain[0] * error;
+        self.state[1] += gain[1] * error;
+        let fr = [
+            f[0] * r[0][0] + f[1] * r[1][0],
+            f[0] * r[0][1] + f[1] * r[1][1],
+        ];
+        for (i, row) in self.covariance.iter_mut().enumerate() {
+            for (j, cell) in row.iter_mut().enumerate() {
+                *cell = r[i][j] - gain[i] * fr[j];
+            }
+        }
+
+        (self.alpha(), self.beta())
+    }
+}
+
+/// How the pairs strategy estimates the hedge ratio
+#[derive(Debug, Clone)]
+pub enum HedgeMethod {
+    RollingOls { window: usize },
+    Kalman(KalmanHedge),
+}
+
+/// Z-score mean reversion on the spread `y - alpha - beta * x`
+///
+/// Flat: sells the spread (short `y`, long `beta * x`) above `entry_z` and
+/// buys it below `-entry_z`. In a position: exits when `|z|` falls to
+/// `exit_z` or blows out past `stop_z`.
+#[derive(Debug, Clone)]
+pub struct PairsTradingStrategy {
+    leg_y: String,
+    leg_x: String,
+    lookback: usize,
+    entry_z: f64,
+    exit_z: f64,
+    stop_z: f64,
+    quantity: f64,
+    method: HedgeMethod,
+    spreads: Vec<f64>,
+    hedge: Option<(f64, f64)>,
+    last_z: Option<f64>,
+}
+
+impl PairsTradingStrategy {
+    pub fn schema() -> ParamSchema {
+        ParamSchema::new()
+            .param(ParamSpec::int("lookback", 30, 5, 500, 1))
+            .param(ParamSpec::float("entry_z", 2.0, 0.5, 5.0, Some(0.25)))
+            .param(ParamSpec::float("exit_z", 0.5, 0.0, 3.0, Some(0.25)))
+            .param(ParamSpec::float("stop_z", 4.0, 1.0, 10.0, Some(0.5)))
+            .param(ParamSpec::choice("hedge_method", "ols", &["ols", "kalman"]))
+            .param(ParamSpec::int("ols_window", 60, 10, 1000, 1))
+            .param(ParamSpec::float("kalman_delta", 1e-4, 1e-7, 0.1, None))
+            .param(ParamSpec::float("kalman_obs_var", 1e-3, 1e-9, 1e6, None))
+            .param(ParamSpec::float("quantity", 1.0, 0.0, 1_000_000.0, None))
+            .constraint(Constraint::less_than("exit_z", "entry_z"))
+            .constraint(Constraint::less_than("entry_z", "stop_z"))
+    }
+
+    /// Builds the strategy for `leg_y` against `leg_x` from a parameter set
+    pub fn from_params(leg_y: &str, leg_x: &str, params: &ParamSet) -> Result<Self, ParamError> {
+        let p = Self::schema().validate(params)?;
+        let method = match p.choice("hedge_method") {
+            Some("kalman") => HedgeMethod::Kalman(KalmanHedge::new(
+                p.float("kalman_delta").unwrap_or(1e-4),
+                p.float("kalman_obs_var").unwrap_or(1e-3),
+            )),
+            _ => HedgeMethod::RollingOls {
+                window: p.int("ols_window").unwrap_or(60) as usize,
+            },
+        };
+
+        Ok(PairsTradingStrategy {
+            leg_y: leg_y.to_string(),
+            leg_x: leg_x.to_string(),
+            lookback: p.int("lookback").unwrap_or(30) as usize,
+            entry_z: p.float("entry_z").unwrap_or(2.0),
+            exit_z: p.float("exit_z").unwrap_or(0.5),
+            stop_z: p.float("stop_z").unwrap_or(4.0),
+            quantity: p.float("quantity").unwrap_or(1.0),
+            method,
+            spreads: Vec::new(),
+            hedge: None,
+            last_z: None,
+        })
+    }
+
+    /// Latest `(alpha, beta)` estimate
+    pub fn hedge_ratio(&self) -> Option<(f64, f64)> {
+        self.hedge
+    }
+
+    pub fn last_z_score(&self) -> Option<f64> {
+        self.last_z
+    }
+
+    fn estimate_hedge(&mut self, y: &[f64], x: &[f64]) -> Option<(f64, f64)> {
+        match &mut self.method {
+            HedgeMethod::RollingOls { window } => rolling_hedge_ratio(y, x, *window),
+            HedgeMethod::Kalman(filter) => {
+                let fitted = filter.update(*x.last()?, *y.last()?);
+                // The first bars only move the filter away from its zero state
+                (y.len() > self.lookback).then_some(fitted)
+            }
+        }
+    }
+
+    fn spread_targets(&self, direction: f64, beta: f64) -> Vec<LegIntent> {
+        vec![
+            LegIntent {
+                leg: 0,
+                intent: OrderIntent::TargetPosition {
+                    quantity: direction * self.quantity,
+                },
+            },
+            LegIntent {
+                leg: 1,
+                intent: OrderIntent::TargetPosition {
+                    quantity: -direction * beta * self.quantity,
+                },
+            },
+        ]
+    }
+}
+
+impl MultiLegStrategy for PairsTradingStrategy {
+    fn name(&self) -> &str {
+        "Pairs Trading"
+    }
+
+    fn symbols(&self) -> Vec<String> {
+        vec![self.leg_y.clone(), self.leg_x.clone()]
+    }
+
+    fn parameters(&self) -> ParamSet {
+        let mut params = ParamSet::new()
+            .with("lookback", ParamValue::Int(self.lookback as i64))
+            .with("entry_z", ParamValue::Float(self.entry_z))
+            .with("exit_z", ParamValue::Float(self.exit_z))
+            .with("stop_z", ParamValue::Float(self.stop_z))
+            .with("quantity", ParamValue::Float(self.quantity));
+        match &self.method {
+            HedgeMethod::RollingOls { window } => {
+                params.set("hedge_method", ParamValue::Choice("ols".to_string()));
+                params.set("ols_window", ParamValue::Int(*window as i64));
+            }
+            HedgeMethod::Kalman(filter) => {
+                params.set("hedge_method", ParamValue::Choice("kalman".to_string()));
+                params.set("kalman_delta", ParamValue::Float(filter.delta));
+                params.set("kalman_obs_var", ParamValue::Float(filter.observation_var));
+            }
+        }
+        params
+    }
+
+    fn on_start(&mut self, _ctx: &MultiLegContext) {
+        self.spreads.clear();
+        self.hedge = None;
+        self.last_z = None;
+        if let HedgeMethod::Kalman(filter) = &mut self.method {
+            *filter = KalmanHedge::new(filter.delta, filter.observation_var);
+        }
+    }
+
+    fn on_bar(&mut self, data: &[MarketData], ctx: &MultiLegContext) -> Vec<LegIntent> {
+        let (y, x) = (&data[0].prices, &data[1].prices);
+        let Some((alpha, beta)) = self.estimate_hedge(y, x) else {
+            return Vec::new();
+        };
+        self.hedge = Some((alpha, beta));
+
+        let (Some(&y_now), Some(&x_now)) = (y.last(), x.last()) else {
+            return Vec::new();
+        };
+        self.spreads.push(y_now - alpha - beta * x_now);
+        if self.spreads.len() < self.lookback {
+            return Vec::new();
+        }
+
+        let window = &self.spreads[self.spreads.len() - self.lookback..];
+        let std_dev = stats::std_dev(window);
+        if std_dev == 0.0 {
+            return Vec::new();
+        }
+        let z = (window[window.len() - 1] - stats::mean(window)) / std_dev;
+        self.last_z = Some(z);
+
+        let in_position = !ctx.positions[0].is_flat() || !ctx.positions[1].is_flat();
+        if !in_position {
+            if z > self.entry_z {
+                return self.spread_targets(-1.0, beta);
+            }
+            if z < -self.entry_z {
+                return self.spread_targets(1.0, beta);
+            }
+        } else if z.abs() <= self.exit_z || z.abs() >= self.stop_z {
+            return vec![
+                LegIntent {
+                    leg: 0,
+                    intent: OrderIntent::ClosePosition,
+                },
+                LegIntent {
+                    leg: 1,
+                    intent: OrderIntent::ClosePosition,
+                },
+            ];
+        }
+
+        Vec::new()
+    }
+}
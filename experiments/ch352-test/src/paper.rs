@@ -0,0 +1,594 @@
+//! Paper trading (from Chapter 301)
+//!
+//! [`PaperTrader`] runs the same [`Strategy`] implementations as the
+//! backtester on bars as they arrive from a [`PriceFeed`], and fills their
+//! orders with the backtester's engine: market orders at the next bar's open,
+//! resting orders and brackets along the bar's intrabar path, with the
+//! configured slippage, commission and margin. Given the same bars, a paper
+//! run makes the same fills as a backtest, so the two can be compared with
+//! [`ExecutionDrift`] and every report that takes [`BacktestResults`].
+//!
+//! The trader keeps a journal: a JSONL file that starts with the strategy,
+//! symbol and engine settings, and gets one line per bar traded, plus one
+//! whenever the settings change. Saving a bar appends one line, however long
+//! the session. Reopening the journal resumes the run by stepping a fresh
+//! strategy through the journaled bars with the engine, as a backtest would,
+//! so it sees each bar with the position and account it had at that bar and
+//! its orders are filled again; the position, orders, trades and any state
+//! the strategy keeps end up as they were. Bars the feed repeats are skipped.
+//!
+//! A bar is either a CSV line (`timestamp,open,high,low,close,volume`) or a
+//! JSON object with those fields. [`LineFeed`] reads one per line from a
+//! recorded file, and [`WebSocketFeed`] one per text message from a websocket
+//! stream. [`StubServer`] stands in for an exchange's stream: a websocket
+//! server on localhost that sends bars as JSON messages.
+
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+
+use crate::backtest::{BacktestConfig, BacktestResults, Run};
+use crate::data::OHLCV;
+use crate::params::ParamSet;
+use crate::stats;
+use crate::strategy::{MarketData, Strategy, StrategyContext};
+
+#[derive(Debug)]
+pub enum PaperError {
+    Io(io::Error),
+    /// A feed line that is not a bar
+    Parse {
+        line: usize,
+        message: String,
+    },
+    /// The journal is unreadable or belongs to another run
+    State(String),
+}
+
+impl fmt::Display for PaperError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "I/O error: {}", e),
+            Self::Parse { line, message } => write!(f, "Feed line {}: {}", line, message),
+            Self::State(message) => write!(f, "Paper state: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for PaperError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PaperError {
+    fn from(e: io::Error) -> Self {
+        PaperError::Io(e)
+    }
+}
+
+impl From<tungstenite::Error> for PaperError {
+    fn from(e: tungstenite::Error) -> Self {
+        match e {
+            tungstenite::Error::Io(e) => PaperError::Io(e),
+            e => PaperError::Io(io::Error::other(e)),
+        }
+    }
+}
+
+/// A stream of bars in time order
+pub trait PriceFeed {
+    /// The next bar, or `None` once the feed has ended
+    fn next_bar(&mut self) -> Result<Option<OHLCV>, PaperError>;
+}
+
+/// Bars read one per line; blank lines, `#` comments and a CSV header are skipped
+pub struct LineFeed<R> {
+    reader: R,
+    line: usize,
+    delay: Duration,
+}
+
+impl LineFeed<BufReader<File>> {
+    /// Replays a recorded file
+    pub fn replay(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(LineFeed::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead> LineFeed<R> {
+    pub fn new(reader: R) -> Self {
+        LineFeed {
+            reader,
+            line: 0,
+            delay: Duration::ZERO,
+        }
+    }
+
+    /// Waits this long before handing out each bar, to replay at a live pace
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+impl<R: BufRead> PriceFeed for LineFeed<R> {
+    fn next_bar(&mut self) -> Result<Option<OHLCV>, PaperError> {
+        let mut text = String::new();
+        loop {
+            text.clear();
+            if self.reader.read_line(&mut text)? == 0 {
+                return Ok(None);
+            }
+            self.line += 1;
+            if let Some(bar) = parse_bar(text.trim(), self.line)? {
+                if !self.delay.is_zero() {
+                    thread::sleep(self.delay);
+                }
+                return Ok(Some(bar));
+            }
+        }
+    }
+}
+
+/// The bar on one feed line, `None` for lines that carry none
+fn parse_bar(text: &str, line: usize) -> Result<Option<OHLCV>, PaperError> {
+    if text.is_empty() || text.starts_with('#') || text.starts_with("timestamp") {
+        return Ok(None);
+    }
+    let error = |message: String| PaperError::Parse { line, message };
+    if text.starts_with('{') {
+        return serde_json::from_str(text)
+            .map(Some)
+            .map_err(|e| error(e.to_string()));
+    }
+    let fields: Vec<&str> = text.split(',').map(str::trim).collect();
+    if fields.len() != 6 {
+        return Err(error(format!("expected 6 fields, found {}", fields.len())));
+    }
+    let timestamp: i64 = fields[0]
+        .parse()
+        .map_err(|e| error(format!("timestamp '{}': {}", fields[0], e)))?;
+    let mut values = [0.0; 5];
+    for (value, field) in values.iter_mut().zip(&fields[1..]) {
+        *value = field
+            .parse()
+            .map_err(|e| error(format!("'{}': {}", field, e)))?;
+    }
+    let [open, high, low, close, volume] = values;
+    Ok(Some(OHLCV::new(timestamp, open, high, low, close, volume)))
+}
+
+/// Bars received one per text message from a websocket stream
+pub struct WebSocketFeed {
+    socket: WebSocket<MaybeTlsStream<TcpStream>>,
+    /// Messages received, reported as the line of a parse error
+    messages: usize,
+}
+
+impl WebSocketFeed {
+    /// Connects to a `ws://` stream such as [`StubServer`]'s
+    pub fn connect(url: &str) -> Result<Self, PaperError> {
+        let (socket, _) = tungstenite::connect(url)?;
+        Ok(WebSocketFeed {
+            socket,
+            messages: 0,
+        })
+    }
+}
+
+impl PriceFeed for WebSocketFeed {
+    fn next_bar(&mut self) -> Result<Option<OHLCV>, PaperError> {
+        loop {
+            let text = match self.socket.read() {
+                Ok(Message::Text(text)) => text,
+                Ok(Message::Binary(bytes)) => String::from_utf8_lossy(&bytes).into_owned(),
+                Ok(Message::Close(_)) => {
+                    // Sends the close reply the socket queued
+                    let _ = self.socket.flush();
+                    return Ok(None);
+                }
+                // Pings are answered by the socket itself
+                Ok(_) => continue,
+                Err(tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed) => {
+                    return Ok(None);
+                }
+                Err(e) => return Err(e.into()),
+            };
+            self.messages += 1;
+            if let Some(bar) = parse_bar(text.trim(), self.messages)? {
+                return Ok(Some(bar));
+            }
+        }
+    }
+}
+
+/// Local stand-in for an exchange's websocket stream
+pub struct StubServer {
+    address: SocketAddr,
+    handle: JoinHandle<io::Result<()>>,
+}
+
+impl StubServer {
+    /// Listens on a free localhost port, accepts the websocket handshake of
+    /// the first client and sends it `bars` as JSON text messages, `interval`
+    /// apart, then closes the connection
+    pub fn start(bars: Vec<OHLCV>, interval: Duration) -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", 0))?;
+        let address = listener.local_addr()?;
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept()?;
+            let mut socket =
+                tungstenite::accept(stream).map_err(|e| io::Error::other(e.to_string()))?;
+            for bar in &bars {
+                let text = serde_json::to_string(bar).map_err(io::Error::other)?;
+                socket.send(Message::Text(text)).map_err(io::Error::other)?;
+                thread::sleep(interval);
+            }
+            socket.close(None).map_err(io::Error::other)?;
+            // Wait for the client to answer the close
+            loop {
+                match socket.read() {
+                    Ok(_) => {}
+                    Err(tungstenite::Error::ConnectionClosed) => return Ok(()),
+                    Err(e) => return Err(io::Error::other(e)),
+                }
+            }
+        });
+        Ok(StubServer { address, handle })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// `ws://` URL of the stream
+    pub fn url(&self) -> String {
+        format!("ws://{}", self.address)
+    }
+
+    /// Waits until every bar has been sent
+    pub fn join(self) -> io::Result<()> {
+        self.handle
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("stub server panicked")))
+    }
+}
+
+/// One line of a paper run's journal
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Entry {
+    /// First line: what is traded, and the engine settings it starts with
+    Run {
+        strategy: String,
+        parameters: ParamSet,
+        symbol: String,
+        config: BacktestConfig,
+    },
+    /// Engine settings for the bars that follow
+    Config(BacktestConfig),
+    Bar(OHLCV),
+}
+
+/// A strategy trading a live feed against simulated fills
+pub struct PaperTrader {
+    path: PathBuf,
+    journal: File,
+    symbol: String,
+    run: Run<'static>,
+    data: MarketData,
+    bars: usize,
+    last_timestamp: Option<i64>,
+}
+
+impl PaperTrader {
+    /// Resumes the run journaled at `path`, or starts a new one if there is none
+    ///
+    /// Calls the strategy's `on_start`, then on resume steps it through the
+    /// journaled bars with the engine settings each was traded with. A
+    /// journal of another strategy, parameter set or symbol is an error. The
+    /// configuration given here applies from now on.
+    pub fn open(
+        path: impl AsRef<Path>,
+        config: BacktestConfig,
+        symbol: &str,
+        strategy: &mut dyn Strategy,
+    ) -> Result<Self, PaperError> {
+        let path = path.as_ref().to_path_buf();
+        let (entries, torn) = read_journal(&path)?;
+        let mut entries = entries.into_iter();
+        let header = entries.next();
+
+        let mut run = match &header {
+            Some(Entry::Run {
+                strategy: name,
+                parameters,
+                symbol: traded,
+                config: initial,
+            }) => {
+                if name != strategy.name()
+                    || *parameters != strategy.parameters()
+                    || traded != symbol
+                {
+                    return Err(PaperError::State(format!(
+                        "{} holds {} ({}) on {}, not {} ({}) on {}",
+                        path.display(),
+                        name,
+                        parameters,
+                        traded,
+                        strategy.name(),
+                        strategy.parameters(),
+                        symbol
+                    )));
+                }
+                Run::new(*initial)
+            }
+            Some(_) => {
+                return Err(PaperError::State(format!(
+                    "{} does not start with its run",
+                    path.display()
+                )));
+            }
+            None => Run::new(config),
+        };
+
+        let mut data = MarketData::new(symbol);
+        let mut bars = 0;
+        let mut last_timestamp = None;
+        strategy.on_start(&run.ctx);
+        for entry in entries {
+            match entry {
+                Entry::Config(changed) => run.config = changed,
+                Entry::Bar(bar) => {
+                    run.step(strategy, &mut data, &bar, bars);
+                    bars += 1;
+                    last_timestamp = Some(bar.timestamp);
+                }
+                Entry::Run { .. } => {
+                    return Err(PaperError::State(format!(
+                        "{} holds more than one run",
+                        path.display()
+                    )));
+                }
+            }
+        }
+
+        let mut journal = OpenOptions::new().create(true).append(true).open(&path)?;
+        if torn {
+            // Start the next entry on a fresh line
+            writeln!(journal)?;
+        }
+        if header.is_none() {
+            let header = Entry::Run {
+                strategy: strategy.name().to_string(),
+                parameters: strategy.parameters(),
+                symbol: symbol.to_string(),
+                config,
+            };
+            append(&mut journal, &header)?;
+        } else if run.config != config {
+            append(&mut journal, &Entry::Config(config))?;
+            run.config = config;
+        }
+
+        Ok(PaperTrader {
+            path,
+            journal,
+            symbol: symbol.to_string(),
+            run,
+            data,
+            bars,
+            last_timestamp,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Position and account after the last bar
+    pub fn context(&self) -> &StrategyContext {
+        &self.run.ctx
+    }
+
+    /// Number of bars traded, across restarts
+    pub fn bars(&self) -> usize {
+        self.bars
+    }
+
+    /// Trades one bar and appends it to the journal
+    ///
+    /// A bar at or before the last one traded is skipped and `false` returned.
+    pub fn on_bar(&mut self, strategy: &mut dyn Strategy, bar: &OHLCV) -> Result<bool, PaperError> {
+        if self
+            .last_timestamp
+            .is_some_and(|last| bar.timestamp <= last)
+        {
+            return Ok(false);
+        }
+        self.run.step(strategy, &mut self.data, bar, self.bars);
+        append(&mut self.journal, &Entry::Bar(bar.clone()))?;
+        self.bars += 1;
+        self.last_timestamp = Some(bar.timestamp);
+        Ok(true)
+    }
+
+    /// Trades every new bar of `feed` until it ends, returning how many
+    pub fn run(
+        &mut self,
+        strategy: &mut dyn Strategy,
+        feed: &mut dyn PriceFeed,
+    ) -> Result<usize, PaperError> {
+        let mut traded = 0;
+        while let Some(bar) = feed.next_bar()? {
+            if self.on_bar(strategy, &bar)? {
+                traded += 1;
+            }
+        }
+        Ok(traded)
+    }
+
+    /// The run so far, with any open position marked to the last close
+    pub fn results(&self, strategy: &dyn Strategy) -> BacktestResults {
+        self.run.clone().into_results(strategy, &self.symbol)
+    }
+}
+
+/// Entries of the journal at `path`, and whether its last line was cut short
+fn read_journal(path: &Path) -> Result<(Vec<Entry>, bool), PaperError> {
+    if !path.exists() {
+        return Ok((Vec::new(), false));
+    }
+    let lines = BufReader::new(File::open(path)?)
+        .lines()
+        .collect::<Result<Vec<String>, _>>()?;
+    let mut entries = Vec::with_capacity(lines.len());
+    let mut torn = false;
+    for (i, line) in lines.iter().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(line) {
+            Ok(entry) => entries.push(entry),
+            // Only the line being written when the process stopped can be torn
+            Err(_) if i + 1 == lines.len() => torn = true,
+            Err(e) => {
+                return Err(PaperError::State(format!(
+                    "{} line {}: {}",
+                    path.display(),
+                    i + 1,
+                    e
+                )));
+            }
+        }
+    }
+    Ok((entries, torn))
+}
+
+/// Writes `entry` as one line and flushes it
+fn append(journal: &mut File, entry: &Entry) -> Result<(), PaperError> {
+    let line = serde_json::to_string(entry).map_err(io::Error::other)?;
+    writeln!(journal, "{}", line)?;
+    journal.flush()?;
+    Ok(())
+}
+
+/// How a paper run's executions and equity differ from a backtest's
+///
+/// Fills pair up by timestamp and side. Equity is compared on the bars both
+/// runs recorded; a backtest's last point includes closing the position at
+/// the end of the data, which a paper run does not do.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExecutionDrift {
+    pub matched_fills: usize,
+    /// Backtest fills the paper run did not make
+    pub backtest_only: usize,
+    /// Paper fills the backtest did not make
+    pub paper_only: usize,
+    /// Mean extra cost of matched paper fills, in basis points of the
+    /// backtest price; positive when paper trading paid more
+    pub price_drift_bps: f64,
+    /// Mean absolute difference in filled quantity of matched fills
+    pub quantity_drift: f64,
+    pub shared_bars: usize,
+    /// Paper minus backtest return up to the last shared bar
+    pub return_gap: f64,
+    /// Standard deviation of per-bar return differences
+    pub tracking_error: f64,
+}
+
+impl ExecutionDrift {
+    pub fn between(backtest: &BacktestResults, paper: &BacktestResults) -> Self {
+        let mut used = vec![false; backtest.fills.len()];
+        let mut costs = Vec::new();
+        let mut quantities = Vec::new();
+        for fill in &paper.fills {
+            let twin = backtest.fills.iter().enumerate().position(|(i, b)| {
+                !used[i] && b.timestamp == fill.timestamp && b.side == fill.side
+            });
+            if let Some(i) = twin {
+                used[i] = true;
+                let expected = &backtest.fills[i];
+                if expected.price != 0.0 {
+                    costs.push(fill.side.sign() * (fill.price / expected.price - 1.0) * 10_000.0);
+                }
+                quantities.push((fill.quantity - expected.quantity).abs());
+            }
+        }
+        let matched_fills = quantities.len();
+
+        let mut gaps = Vec::new();
+        let (mut backtest_before, mut paper_before) =
+            (backtest.initial_balance, paper.initial_balance);
+        let mut j = 0;
+        for point in &paper.equity_curve {
+            while j < backtest.equity_curve.len()
+                && backtest.equity_curve[j].timestamp < point.timestamp
+            {
+                j += 1;
+            }
+            let Some(expected) = backtest
+                .equity_curve
+                .get(j)
+                .filter(|p| p.timestamp == point.timestamp)
+            else {
+                continue;
+            };
+            gaps.push(
+                (point.balance / paper_before - 1.0) - (expected.balance / backtest_before - 1.0),
+            );
+            backtest_before = expected.balance;
+            paper_before = point.balance;
+        }
+
+        ExecutionDrift {
+            matched_fills,
+            backtest_only: backtest.fills.len() - matched_fills,
+            paper_only: paper.fills.len() - matched_fills,
+            price_drift_bps: if costs.is_empty() {
+                0.0
+            } else {
+                stats::mean(&costs)
+            },
+            quantity_drift: if quantities.is_empty() {
+                0.0
+            } else {
+                stats::mean(&quantities)
+            },
+            shared_bars: gaps.len(),
+            return_gap: paper_before / paper.initial_balance
+                - backtest_before / backtest.initial_balance,
+            tracking_error: stats::std_dev(&gaps),
+        }
+    }
+
+    pub fn print(&self) {
+        println!("\n=== Paper vs Backtest ===\n");
+        println!(
+            "Fills: {} matched, {} backtest only, {} paper only",
+            self.matched_fills, self.backtest_only, self.paper_only
+        );
+        println!(
+            "Price drift {:.2} bps, quantity drift {:.4}",
+            self.price_drift_bps, self.quantity_drift
+        );
+        println!(
+            "Over {} shared bars: return gap {:.3}%, tracking error {:.4}% per bar",
+            self.shared_bars,
+            self.return_gap * 100.0,
+            self.tracking_error * 100.0
+        );
+    }
+}
@@ -0,0 +1,578 @@
+//! Typed strategy parameters: schemas, validation and search spaces
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// A single parameter value
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ParamValue {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Choice(String),
+}
+
+impl ParamValue {
+    /// Numeric view of the value (bools are 0/1, choices have none)
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            ParamValue::Int(v) => Some(*v as f64),
+            ParamValue::Float(v) => Some(*v),
+            ParamValue::Bool(v) => Some(if *v { 1.0 } else { 0.0 }),
+            ParamValue::Choice(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for ParamValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParamValue::Int(v) => write!(f, "{}", v),
+            ParamValue::Float(v) => write!(f, "{}", v),
+            ParamValue::Bool(v) => write!(f, "{}", v),
+            ParamValue::Choice(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+/// Type and allowed range of a parameter
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamKind {
+    /// Integer in `min..=max`, on the grid `min + k * step`
+    Int {
+        min: i64,
+        max: i64,
+        step: i64,
+    },
+    /// Float in `min..=max`; `step` is only used to enumerate a grid
+    Float {
+        min: f64,
+        max: f64,
+        step: Option<f64>,
+    },
+    Bool,
+    /// One of a fixed set of options
+    Choice(Vec<String>),
+}
+
+/// Description of one strategy parameter
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamSpec {
+    pub name: String,
+    pub kind: ParamKind,
+    pub default: ParamValue,
+}
+
+impl ParamSpec {
+    pub fn int(name: &str, default: i64, min: i64, max: i64, step: i64) -> Self {
+        assert!(step > 0, "Step must be greater than 0");
+        ParamSpec {
+            name: name.to_string(),
+            kind: ParamKind::Int { min, max, step },
+            default: ParamValue::Int(default),
+        }
+    }
+
+    pub fn float(name: &str, default: f64, min: f64, max: f64, step: Option<f64>) -> Self {
+        ParamSpec {
+            name: name.to_string(),
+            kind: ParamKind::Float { min, max, step },
+            default: ParamValue::Float(default),
+        }
+    }
+
+    pub fn bool(name: &str, default: bool) -> Self {
+        ParamSpec {
+            name: name.to_string(),
+            kind: ParamKind::Bool,
+            default: ParamValue::Bool(default),
+        }
+    }
+
+    pub fn choice(name: &str, default: &str, options: &[&str]) -> Self {
+        ParamSpec {
+            name: name.to_string(),
+            kind: ParamKind::Choice(options.iter().map(|o| o.to_string()).collect()),
+            default: ParamValue::Choice(default.to_string()),
+        }
+    }
+
+    /// Checks type and range of `value`, converting ints to floats where needed
+    pub fn check(&self, value: &ParamValue) -> Result<ParamValue, ParamError> {
+        let wrong_type = || ParamError::WrongType {
+            name: self.name.clone(),
+            expected: self.type_name(),
+            got: value.clone(),
+        };
+        let out_of_range = || ParamError::OutOfRange {
+            name: self.name.clone(),
+            value: value.clone(),
+            min: self.bounds().0,
+            max: self.bounds().1,
+        };
+
+        match (&self.kind, value) {
+            (ParamKind::Int { min, max, step }, ParamValue::Int(v)) => {
+                if v < min || v > max {
+                    Err(out_of_range())
+                } else if (v - min) % step != 0 {
+                    Err(ParamError::OffGrid {
+                        name: self.name.clone(),
+                        value: value.clone(),
+                    })
+                } else {
+                    Ok(value.clone())
+                }
+            }
+            (ParamKind::Float { min, max, .. }, ParamValue::Float(_) | ParamValue::Int(_)) => {
+                let v = value.as_f64().unwrap_or(f64::NAN);
+                if v.is_nan() || v < *min || v > *max {
+                    Err(out_of_range())
+                } else {
+                    Ok(ParamValue::Float(v))
+                }
+            }
+            (ParamKind::Bool, ParamValue::Bool(_)) => Ok(value.clone()),
+            (ParamKind::Choice(options), ParamValue::Choice(v)) => {
+                if options.contains(v) {
+                    Ok(value.clone())
+                } else {
+                    Err(ParamError::UnknownChoice {
+                        name: self.name.clone(),
+                        value: v.clone(),
+                        options: options.clone(),
+                    })
+                }
+            }
+            _ => Err(wrong_type()),
+        }
+    }
+
+    /// Parses a raw config string into a value of this parameter's type
+    pub fn parse(&self, raw: &str) -> Result<ParamValue, ParamError> {
+        let raw = raw.trim();
+        let parsed = match &self.kind {
+            ParamKind::Int { .. } => raw.parse().ok().map(ParamValue::Int),
+            ParamKind::Float { .. } => raw.parse().ok().map(ParamValue::Float),
+            ParamKind::Bool => raw.parse().ok().map(ParamValue::Bool),
+            ParamKind::Choice(_) => Some(ParamValue::Choice(raw.to_string())),
+        };
+
+        parsed.ok_or_else(|| ParamError::Parse {
+            name: self.name.clone(),
+            raw: raw.to_string(),
+            expected: self.type_name(),
+        })
+    }
+
+    /// All grid values of this parameter, in ascending order
+    ///
+    /// Floats without a step only contribute their default.
+    pub fn values(&self) -> Vec<ParamValue> {
+        match &self.kind {
+            ParamKind::Int { min, max, step } => (*min..=*max)
+                .step_by(*step as usize)
+                .map(ParamValue::Int)
+                .collect(),
+            ParamKind::Float {
+                min,
+                max,
+                step: Some(step),
+            } if *step > 0.0 => {
+                let count = ((max - min) / step + 1e-9).floor() as usize + 1;
+                (0..count)
+                    .map(|i| ParamValue::Float(min + i as f64 * step))
+                    .collect()
+            }
+            ParamKind::Float { .. } => vec![self.default.clone()],
+            ParamKind::Bool => vec![ParamValue::Bool(false), ParamValue::Bool(true)],
+            ParamKind::Choice(options) => options
+                .iter()
+                .map(|o| ParamValue::Choice(o.clone()))
+                .collect(),
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self.kind {
+            ParamKind::Int { .. } => "int",
+            ParamKind::Float { .. } => "float",
+            ParamKind::Bool => "bool",
+            ParamKind::Choice(_) => "choice",
+        }
+    }
+
+    fn bounds(&self) -> (f64, f64) {
+        match self.kind {
+            ParamKind::Int { min, max, .. } => (min as f64, max as f64),
+            ParamKind::Float { min, max, .. } => (min, max),
+            _ => (f64::NAN, f64::NAN),
+        }
+    }
+}
+
+/// Rule that involves more than one parameter
+#[derive(Debug, Clone)]
+pub enum Constraint {
+    /// `left < right`
+    LessThan { left: String, right: String },
+    /// `left <= right`
+    LessOrEqual { left: String, right: String },
+    /// Any other rule, with a description used in error messages
+    Custom {
+        description: String,
+        check: fn(&ParamSet) -> bool,
+    },
+}
+
+impl Constraint {
+    pub fn less_than(left: &str, right: &str) -> Self {
+        Constraint::LessThan {
+            left: left.to_string(),
+            right: right.to_string(),
+        }
+    }
+
+    pub fn holds(&self, params: &ParamSet) -> bool {
+        match self {
+            Constraint::LessThan { left, right } => {
+                matches!((params.number(left), params.number(right)), (Some(l), Some(r)) if l < r)
+            }
+            Constraint::LessOrEqual { left, right } => {
+                matches!((params.number(left), params.number(right)), (Some(l), Some(r)) if l <= r)
+            }
+            Constraint::Custom { check, .. } => check(params),
+        }
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Constraint::LessThan { left, right } => write!(f, "{} < {}", left, right),
+            Constraint::LessOrEqual { left, right } => write!(f, "{} <= {}", left, right),
+            Constraint::Custom { description, .. } => write!(f, "{}", description),
+        }
+    }
+}
+
+/// Why a parameter set was rejected
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamError {
+    Unknown(String),
+    WrongType {
+        name: String,
+        expected: &'static str,
+        got: ParamValue,
+    },
+    OutOfRange {
+        name: String,
+        value: ParamValue,
+        min: f64,
+        max: f64,
+    },
+    OffGrid {
+        name: String,
+        value: ParamValue,
+    },
+    UnknownChoice {
+        name: String,
+        value: String,
+        options: Vec<String>,
+    },
+    Parse {
+        name: String,
+        raw: String,
+        expected: &'static str,
+    },
+    ConstraintViolated(String),
+}
+
+impl fmt::Display for ParamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unknown(name) => write!(f, "Unknown parameter '{}'", name),
+            Self::WrongType {
+                name,
+                expected,
+                got,
+            } => {
+                write!(
+                    f,
+                    "Parameter '{}' expects {}, got {:?}",
+                    name, expected, got
+                )
+            }
+            Self::OutOfRange {
+                name,
+                value,
+                min,
+                max,
+            } => {
+                write!(
+                    f,
+                    "Parameter '{}' = {} is outside [{}, {}]",
+                    name, value, min, max
+                )
+            }
+            Self::OffGrid { name, value } => {
+                write!(
+                    f,
+                    "Parameter '{}' = {} is not on the step grid",
+                    name, value
+                )
+            }
+            Self::UnknownChoice {
+                name,
+                value,
+                options,
+            } => write!(
+                f,
+                "Parameter '{}' = '{}' is not one of {:?}",
+                name, value, options
+            ),
+            Self::Parse {
+                name,
+                raw,
+                expected,
+            } => {
+                write!(
+                    f,
+                    "Cannot parse '{}' as {} for parameter '{}'",
+                    raw, expected, name
+                )
+            }
+            Self::ConstraintViolated(rule) => write!(f, "Constraint violated: {}", rule),
+        }
+    }
+}
+
+impl std::error::Error for ParamError {}
+
+/// A complete set of parameter values
+///
+/// Sets returned by `ParamSchema::validate` have every parameter present,
+/// with the right type and within range.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ParamSet {
+    values: BTreeMap<String, ParamValue>,
+}
+
+impl ParamSet {
+    pub fn new() -> Self {
+        ParamSet::default()
+    }
+
+    /// Adds a value, builder style
+    pub fn with(mut self, name: &str, value: ParamValue) -> Self {
+        self.set(name, value);
+        self
+    }
+
+    pub fn set(&mut self, name: &str, value: ParamValue) {
+        self.values.insert(name.to_string(), value);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ParamValue> {
+        self.values.get(name)
+    }
+
+    pub fn int(&self, name: &str) -> Option<i64> {
+        match self.values.get(name) {
+            Some(ParamValue::Int(v)) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn float(&self, name: &str) -> Option<f64> {
+        match self.values.get(name) {
+            Some(ParamValue::Float(v)) => Some(*v),
+            Some(ParamValue::Int(v)) => Some(*v as f64),
+            _ => None,
+        }
+    }
+
+    pub fn bool(&self, name: &str) -> Option<bool> {
+        match self.values.get(name) {
+            Some(ParamValue::Bool(v)) => Some(*v),
+            _ => None,
+        }
+    }
+
+    pub fn choice(&self, name: &str) -> Option<&str> {
+        match self.values.get(name) {
+            Some(ParamValue::Choice(v)) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Numeric view of a parameter, for constraints and optimizers
+    pub fn number(&self, name: &str) -> Option<f64> {
+        self.values.get(name).and_then(ParamValue::as_f64)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &ParamValue)> {
+        self.values.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Stable hash of names, types and values, for keying stored results
+    ///
+    /// 64-bit FNV-1a, so the key is the same across runs and platforms.
+    pub fn fingerprint(&self) -> String {
+        let mut hash = FNV_OFFSET;
+        for (name, value) in &self.values {
+            let tagged = match value {
+                ParamValue::Int(v) => format!("{}=i:{};", name, v),
+                ParamValue::Float(v) => format!("{}=f:{:?};", name, v),
+                ParamValue::Bool(v) => format!("{}=b:{};", name, v),
+                ParamValue::Choice(v) => format!("{}=c:{};", name, v),
+            };
+            hash = fnv1a(hash, tagged.as_bytes());
+        }
+        format!("{:016x}", hash)
+    }
+}
+
+/// Initial state of a 64-bit FNV-1a hash
+pub(crate) const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// Folds `bytes` into the FNV-1a `hash`
+pub(crate) fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for &byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+impl fmt::Display for ParamSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = self
+            .values
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect();
+        write!(f, "{{{}}}", parts.join(", "))
+    }
+}
+
+/// All parameters of a strategy plus the rules between them
+#[derive(Debug, Clone, Default)]
+pub struct ParamSchema {
+    pub specs: Vec<ParamSpec>,
+    pub constraints: Vec<Constraint>,
+}
+
+impl ParamSchema {
+    pub fn new() -> Self {
+        ParamSchema::default()
+    }
+
+    pub fn param(mut self, spec: ParamSpec) -> Self {
+        self.specs.push(spec);
+        self
+    }
+
+    pub fn constraint(mut self, constraint: Constraint) -> Self {
+        self.constraints.push(constraint);
+        self
+    }
+
+    pub fn spec(&self, name: &str) -> Option<&ParamSpec> {
+        self.specs.iter().find(|s| s.name == name)
+    }
+
+    /// The default value of every parameter
+    pub fn defaults(&self) -> ParamSet {
+        let mut set = ParamSet::new();
+        for spec in &self.specs {
+            set.set(&spec.name, spec.default.clone());
+        }
+        set
+    }
+
+    /// Validates `values`, filling missing parameters with their defaults
+    pub fn validate(&self, values: &ParamSet) -> Result<ParamSet, ParamError> {
+        if let Some((name, _)) = values.iter().find(|(name, _)| self.spec(name).is_none()) {
+            return Err(ParamError::Unknown(name.to_string()));
+        }
+
+        let mut set = ParamSet::new();
+        for spec in &self.specs {
+            let value = values.get(&spec.name).unwrap_or(&spec.default);
+            set.set(&spec.name, spec.check(value)?);
+        }
+
+        self.check_constraints(&set)?;
+        Ok(set)
+    }
+
+    /// Parses raw `name -> string` pairs (e.g. from a config file) and validates them
+    pub fn parse(&self, raw: &[(&str, &str)]) -> Result<ParamSet, ParamError> {
+        let mut values = ParamSet::new();
+        for (name, text) in raw {
+            let spec = self
+                .spec(name)
+                .ok_or_else(|| ParamError::Unknown(name.to_string()))?;
+            values.set(name, spec.parse(text)?);
+        }
+        self.validate(&values)
+    }
+
+    pub fn check_constraints(&self, set: &ParamSet) -> Result<(), ParamError> {
+        match self.constraints.iter().find(|c| !c.holds(set)) {
+            Some(c) => Err(ParamError::ConstraintViolated(c.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Number of grid points before constraints are applied
+    pub fn grid_size(&self) -> usize {
+        self.specs.iter().map(|s| s.values().len()).product()
+    }
+
+    /// Every combination of grid values that satisfies the constraints
+    pub fn grid(&self) -> Vec<ParamSet> {
+        let mut sets = vec![ParamSet::new()];
+        for spec in &self.specs {
+            let values = spec.values();
+            sets = sets
+                .into_iter()
+                .flat_map(|set| {
+                    values
+                        .iter()
+                        .map(move |v| set.clone().with(&spec.name, v.clone()))
+                })
+                .collect();
+        }
+
+        sets.into_iter()
+            .filter(|set| self.check_constraints(set).is_ok())
+            .collect()
+    }
+}
+
+/// Strategies that can be built from a validated parameter set
+pub trait FromParams: Sized {
+    fn schema() -> ParamSchema;
+
+    /// Builds the strategy from a set already validated against `schema()`
+    fn from_validated(params: &ParamSet) -> Self;
+
+    /// Validates `params` and builds the strategy
+    fn from_params(params: &ParamSet) -> Result<Self, ParamError> {
+        let validated = Self::schema().validate(params)?;
+        Ok(Self::from_validated(&validated))
+    }
+}
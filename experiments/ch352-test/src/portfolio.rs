@@ -0,0 +1,541 @@
+//! Portfolio backtesting: many instruments stepped together on one cash balance
+//!
+//! Each instrument gets its own strategy instance. Strategies decide the
+//! direction; the portfolio decides the size from shared equity using an
+//! [`Allocation`] rule, sizing positions when they are entered and holding
+//! that quantity until the strategy exits or flips. As in the single-asset
+//! engine, orders decided on a bar fill at the instrument's next real bar open.
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::backtest::{BacktestConfig, EquityPoint};
+use crate::data::{Instrument, OHLCV};
+use crate::metrics::{Metrics, MetricsConfig};
+use crate::multi_instrument::LegPnl;
+use crate::stats;
+use crate::strategy::{
+    Account, Fill, MarketData, OrderIntent, OrderType, Position, Side, Strategy, StrategyContext,
+};
+
+/// How new positions are sized from portfolio equity
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Allocation {
+    /// Every position gets the same share of equity
+    EqualWeight,
+    /// Scales each share of equity by `target_vol` over the instrument's
+    /// annualized volatility of its last `lookback` returns
+    VolatilityTarget { target_vol: f64, lookback: usize },
+}
+
+/// Treatment of timestamps at which an instrument has no bar
+///
+/// Only gaps between an instrument's first and last bar count as missing;
+/// before its first bar and after its last one it is simply not trading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MissingBars {
+    /// The instrument sits the timestamp out and is valued at its last close
+    #[default]
+    Skip,
+    /// Timestamps missing from any instrument are dropped from the timeline
+    Intersect,
+    /// A flat bar at the last close is inserted; the strategy sees it, but
+    /// orders wait for the next real bar
+    ForwardFill,
+}
+
+/// What one instrument contributed to the portfolio
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstrumentAttribution {
+    pub pnl: LegPnl,
+    /// Real bars processed
+    pub bars: usize,
+    pub missing_bars: usize,
+    pub entries: usize,
+    /// Notional allocated to entries, summed over all of them
+    pub entry_notional: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct PortfolioResults {
+    pub strategy: String,
+    /// Equity at every timestamp of the merged timeline
+    pub equity_curve: Vec<EquityPoint>,
+    pub instruments: Vec<InstrumentAttribution>,
+    pub initial_balance: f64,
+    pub final_balance: f64,
+    /// New signals not acted on because `max_positions` were already open
+    pub skipped_entries: usize,
+    /// Entries dropped because the margin limit left no room
+    pub rejected_orders: usize,
+}
+
+impl PortfolioResults {
+    pub fn total_return(&self) -> f64 {
+        ((self.final_balance - self.initial_balance) / self.initial_balance) * 100.0
+    }
+
+    pub fn max_drawdown(&self) -> f64 {
+        self.equity_curve
+            .iter()
+            .map(|ep| ep.drawdown)
+            .fold(0.0, f64::max)
+    }
+
+    /// Metrics of the portfolio equity; the trade statistics are empty, as
+    /// only per-instrument P&L is kept
+    pub fn metrics(&self, config: &MetricsConfig) -> Metrics {
+        let curve: Vec<(i64, f64)> = self
+            .equity_curve
+            .iter()
+            .map(|p| (p.timestamp, p.balance))
+            .collect();
+        Metrics::compute(self.initial_balance, &curve, &[], config)
+    }
+
+    pub fn instrument(&self, symbol: &str) -> Option<&InstrumentAttribution> {
+        self.instruments.iter().find(|i| i.pnl.symbol == symbol)
+    }
+
+    pub fn print_summary(&self) {
+        println!("\n=== Portfolio: {} ===\n", self.strategy);
+        println!(
+            "{:<12} {:>8} {:>8} {:>10} {:>12} {:>12}",
+            "Instrument", "Bars", "Missing", "Entries", "Net P&L", "Commission"
+        );
+        println!("{}", "-".repeat(67));
+        for item in &self.instruments {
+            println!(
+                "{:<12} {:>8} {:>8} {:>10} {:>12.2} {:>12.2}",
+                item.pnl.symbol,
+                item.bars,
+                item.missing_bars,
+                item.entries,
+                item.pnl.net(),
+                item.pnl.commission
+            );
+        }
+        println!("{}", "-".repeat(67));
+        println!(
+            "Return {:.2}%, max drawdown {:.2}%, skipped entries {}",
+            self.total_return(),
+            self.max_drawdown(),
+            self.skipped_entries
+        );
+    }
+}
+
+/// Backtests one strategy per instrument against a shared account
+pub struct PortfolioBacktester {
+    config: BacktestConfig,
+    instruments: Vec<Instrument>,
+    allocation: Allocation,
+    max_positions: Option<usize>,
+    missing_bars: MissingBars,
+}
+
+impl PortfolioBacktester {
+    pub fn new(config: BacktestConfig) -> Self {
+        PortfolioBacktester {
+            config,
+            instruments: Vec::new(),
+            allocation: Allocation::EqualWeight,
+            max_positions: None,
+            missing_bars: MissingBars::default(),
+        }
+    }
+
+    pub fn with_allocation(mut self, allocation: Allocation) -> Self {
+        self.allocation = allocation;
+        self
+    }
+
+    /// Caps simultaneous positions; equity is split into this many shares
+    pub fn with_max_positions(mut self, max_positions: usize) -> Self {
+        self.max_positions = Some(max_positions);
+        self
+    }
+
+    pub fn with_missing_bars(mut self, missing_bars: MissingBars) -> Self {
+        self.missing_bars = missing_bars;
+        self
+    }
+
+    pub fn add_instrument(&mut self, instrument: Instrument) {
+        self.instruments.push(instrument);
+    }
+
+    /// Sorted union of all timestamps, or their intersection under `MissingBars::Intersect`
+    pub fn timeline(&self) -> Vec<i64> {
+        let all: BTreeSet<i64> = self
+            .instruments
+            .iter()
+            .flat_map(|i| i.data.iter().map(|b| b.timestamp))
+            .collect();
+        match self.missing_bars {
+            MissingBars::Intersect => {
+                let sets: Vec<BTreeSet<i64>> = self
+                    .instruments
+                    .iter()
+                    .map(|i| i.data.iter().map(|b| b.timestamp).collect())
+                    .collect();
+                all.into_iter()
+                    .filter(|t| sets.iter().all(|s| s.contains(t)))
+                    .collect()
+            }
+            _ => all.into_iter().collect(),
+        }
+    }
+
+    pub fn run<F>(&self, make_strategy: F) -> PortfolioResults
+    where
+        F: Fn() -> Box<dyn Strategy>,
+    {
+        let mut account = Account::new(self.config.initial_capital);
+        let mut slots: Vec<Slot> = self
+            .instruments
+            .iter()
+            .map(|i| Slot::new(i, make_strategy()))
+            .collect();
+        let strategy = slots
+            .first()
+            .map(|s| s.strategy.name().to_string())
+            .unwrap_or_default();
+
+        let mut equity_curve = Vec::new();
+        let mut peak = account.equity;
+        let mut skipped_entries = 0;
+        let mut rejected_orders = 0;
+
+        for slot in slots.iter_mut() {
+            slot.strategy.on_start(&slot.context(account));
+        }
+
+        for timestamp in self.timeline() {
+            for slot in slots.iter_mut() {
+                let Some((bar, synthetic)) = self.bar_at(slot, timestamp) else {
+                    continue;
+                };
+                if !synthetic {
+                    self.execute_pending(slot, &mut account, &bar);
+                    slot.bars_seen += 1;
+                }
+                slot.on_bar(&bar, account);
+
+                // Delisted: close out at its final close
+                if !synthetic && Some(timestamp) == slot.last_timestamp {
+                    let open = slot.position.quantity;
+                    if open != 0.0 {
+                        self.fill(slot, &mut account, -open, bar.close, timestamp);
+                    }
+                    slot.finished = true;
+                }
+            }
+
+            account.equity = account.cash
+                + slots
+                    .iter()
+                    .map(|s| {
+                        s.last_close.map_or(0.0, |close| {
+                            self.config.contract.unrealized_pnl(&s.position, close)
+                        })
+                    })
+                    .sum::<f64>();
+            peak = peak.max(account.equity);
+            equity_curve.push(EquityPoint {
+                timestamp,
+                balance: account.equity,
+                drawdown: (peak - account.equity) / peak * 100.0,
+            });
+
+            let (skipped, rejected) = self.allocate(&mut slots, account.equity);
+            skipped_entries += skipped;
+            rejected_orders += rejected;
+        }
+
+        // Anything still open (its last bar may have been dropped from the
+        // timeline) is closed at its last close
+        for slot in slots.iter_mut() {
+            let open = slot.position.quantity;
+            if let (true, Some(close)) = (open != 0.0, slot.last_close) {
+                let timestamp = slot.data.timestamps.last().copied().unwrap_or_default();
+                self.fill(slot, &mut account, -open, close, timestamp);
+            }
+        }
+        account.equity = account.cash;
+        if let Some(point) = equity_curve.last_mut() {
+            point.balance = account.equity;
+            point.drawdown = (peak - account.equity).max(0.0) / peak * 100.0;
+        }
+
+        for slot in slots.iter_mut() {
+            slot.strategy.on_end(&slot.context(account));
+        }
+
+        PortfolioResults {
+            strategy,
+            equity_curve,
+            instruments: slots
+                .into_iter()
+                .map(|s| InstrumentAttribution {
+                    pnl: s.pnl,
+                    bars: s.bars_seen,
+                    missing_bars: s.missing_bars,
+                    entries: s.entries,
+                    entry_notional: s.entry_notional,
+                })
+                .collect(),
+            initial_balance: self.config.initial_capital,
+            final_balance: account.equity,
+            skipped_entries,
+            rejected_orders,
+        }
+    }
+
+    /// The instrument's bar at `timestamp`, flagged `true` when synthetic
+    fn bar_at(&self, slot: &mut Slot, timestamp: i64) -> Option<(OHLCV, bool)> {
+        if slot.finished {
+            return None;
+        }
+        if let Some(bar) = slot.bars.get(&timestamp) {
+            return Some(((*bar).clone(), false));
+        }
+        if slot.first_timestamp.is_none_or(|first| timestamp < first) {
+            return None;
+        }
+        slot.missing_bars += 1;
+        match (self.missing_bars, slot.last_close) {
+            (MissingBars::ForwardFill, Some(close)) => {
+                Some((OHLCV::new(timestamp, close, close, close, close, 0.0), true))
+            }
+            _ => None,
+        }
+    }
+
+    fn execute_pending(&self, slot: &mut Slot, account: &mut Account, bar: &OHLCV) {
+        let delta = std::mem::take(&mut slot.pending);
+        if delta != 0.0 {
+            let side = if delta > 0.0 { Side::Buy } else { Side::Sell };
+            let price = bar.open * (1.0 + side.sign() * self.config.slippage);
+            self.fill(slot, account, delta, price, bar.timestamp);
+        }
+
+        // The strategy's own view of its position moves at the same time
+        let intended = std::mem::take(&mut slot.intended);
+        if intended != 0.0 {
+            let fill = Fill {
+                timestamp: bar.timestamp,
+                side: if intended > 0.0 {
+                    Side::Buy
+                } else {
+                    Side::Sell
+                },
+                price: bar.open,
+                quantity: intended.abs(),
+                commission: 0.0,
+            };
+            slot.virtual_position.apply_fill(&fill);
+            slot.strategy.on_fill(&fill, &slot.context(*account));
+        }
+    }
+
+    fn fill(&self, slot: &mut Slot, account: &mut Account, delta: f64, price: f64, timestamp: i64) {
+        let contract = self.config.contract;
+        let price = contract.round_price(price);
+        let fill = Fill {
+            timestamp,
+            side: if delta > 0.0 { Side::Buy } else { Side::Sell },
+            price,
+            quantity: delta.abs(),
+            commission: contract.notional(delta, price) * self.config.commission_rate,
+        };
+        let realized = contract.apply_fill(&mut slot.position, &fill);
+        account.cash += realized - fill.commission;
+        slot.pnl.realized += realized;
+        slot.pnl.commission += fill.commission;
+        slot.pnl.fills += 1;
+    }
+
+    /// Turns desired directions into orders; returns (skipped, rejected) entries
+    fn allocate(&self, slots: &mut [Slot], equity: f64) -> (usize, usize) {
+        let shares = self.max_positions.unwrap_or(slots.len()).max(1);
+        let max_gross = equity / self.config.margin.initial_margin;
+        let (mut skipped, mut rejected) = (0, 0);
+
+        for i in 0..slots.len() {
+            let slot = &slots[i];
+            let Some(price) = slot.last_close else {
+                continue;
+            };
+            let held = slot.held();
+            let want = slot.desired;
+
+            if slot.finished || held * want > 0.0 || (held == 0.0 && want == 0.0) {
+                continue;
+            }
+            if want == 0.0 {
+                let slot = &mut slots[i];
+                slot.pending = -slot.position.quantity;
+                continue;
+            }
+
+            // Entry or flip: needs a free position and room under the margin limit
+            let open_elsewhere = slots
+                .iter()
+                .enumerate()
+                .filter(|&(j, s)| j != i && s.held() != 0.0)
+                .count();
+            if self.max_positions.is_some_and(|max| open_elsewhere >= max) {
+                if slot.new_signal {
+                    skipped += 1;
+                }
+                continue;
+            }
+
+            let Some(mut notional) = self.target_notional(slot, equity / shares as f64) else {
+                continue;
+            };
+            let gross_elsewhere: f64 = slots
+                .iter()
+                .enumerate()
+                .filter(|&(j, _)| j != i)
+                .map(|(_, s)| {
+                    s.last_close
+                        .map_or(0.0, |p| self.config.contract.notional(s.held(), p))
+                })
+                .sum();
+            notional = notional.min(max_gross - gross_elsewhere);
+            if notional <= 0.0 {
+                rejected += 1;
+                continue;
+            }
+
+            let quantity = notional / self.config.contract.notional(1.0, price);
+            let slot = &mut slots[i];
+            slot.pending = want * quantity - slot.position.quantity;
+            slot.entries += 1;
+            slot.entry_notional += notional;
+        }
+
+        for slot in slots.iter_mut() {
+            slot.new_signal = false;
+        }
+        (skipped, rejected)
+    }
+
+    fn target_notional(&self, slot: &Slot, share: f64) -> Option<f64> {
+        match self.allocation {
+            Allocation::EqualWeight => Some(share),
+            Allocation::VolatilityTarget {
+                target_vol,
+                lookback,
+            } => {
+                let closes = &slot.data.prices;
+                if lookback < 2 || closes.len() <= lookback {
+                    return None;
+                }
+                let returns: Vec<f64> = closes[closes.len() - lookback - 1..]
+                    .windows(2)
+                    .map(|w| (w[1] / w[0]).ln())
+                    .collect();
+                let vol = stats::std_dev(&returns) * self.config.bars_per_year.sqrt();
+                (vol > 0.0).then(|| share * target_vol / vol)
+            }
+        }
+    }
+}
+
+/// Per-instrument state of a portfolio run
+struct Slot<'a> {
+    strategy: Box<dyn Strategy>,
+    bars: HashMap<i64, &'a OHLCV>,
+    first_timestamp: Option<i64>,
+    last_timestamp: Option<i64>,
+    data: MarketData,
+    /// Actual holding in the shared account
+    position: Position,
+    /// Order waiting for the next real bar
+    pending: f64,
+    /// What the strategy believes it holds, in its own units
+    virtual_position: Position,
+    intended: f64,
+    /// Direction the strategy wants: -1, 0 or 1
+    desired: f64,
+    new_signal: bool,
+    last_close: Option<f64>,
+    finished: bool,
+    pnl: LegPnl,
+    bars_seen: usize,
+    missing_bars: usize,
+    entries: usize,
+    entry_notional: f64,
+}
+
+impl<'a> Slot<'a> {
+    fn new(instrument: &'a Instrument, strategy: Box<dyn Strategy>) -> Self {
+        Slot {
+            strategy,
+            bars: instrument.data.iter().map(|b| (b.timestamp, b)).collect(),
+            first_timestamp: instrument.data.iter().map(|b| b.timestamp).min(),
+            last_timestamp: instrument.data.iter().map(|b| b.timestamp).max(),
+            data: MarketData::new(&instrument.symbol),
+            position: Position::default(),
+            pending: 0.0,
+            virtual_position: Position::default(),
+            intended: 0.0,
+            desired: 0.0,
+            new_signal: false,
+            last_close: None,
+            finished: false,
+            pnl: LegPnl {
+                symbol: instrument.symbol.clone(),
+                realized: 0.0,
+                commission: 0.0,
+                fills: 0,
+            },
+            bars_seen: 0,
+            missing_bars: 0,
+            entries: 0,
+            entry_notional: 0.0,
+        }
+    }
+
+    /// Position once the pending order has filled
+    fn held(&self) -> f64 {
+        self.position.quantity + self.pending
+    }
+
+    fn context(&self, account: Account) -> StrategyContext {
+        StrategyContext {
+            bar_index: self.data.prices.len().saturating_sub(1),
+            position: self.virtual_position,
+            account,
+        }
+    }
+
+    /// Feeds the bar to the strategy and records the direction it asks for
+    fn on_bar(&mut self, bar: &OHLCV, account: Account) {
+        self.data.add_bar(bar);
+        self.last_close = Some(bar.close);
+
+        let mut projected = self.virtual_position;
+        projected.quantity += self.intended;
+        for intent in self.strategy.on_bar(&self.data, &self.context(account)) {
+            let executable = match intent {
+                OrderIntent::Entry { order_type, .. } => order_type == OrderType::Market,
+                _ => true,
+            };
+            if executable {
+                projected.quantity += intent.position_delta(&projected);
+            }
+        }
+        self.intended = projected.quantity - self.virtual_position.quantity;
+
+        let desired = if projected.is_flat() {
+            0.0
+        } else {
+            projected.quantity.signum()
+        };
+        self.new_signal |= desired != self.desired;
+        self.desired = desired;
+    }
+}
@@ -0,0 +1,317 @@
+//! Small statistics toolkit: moments, quantiles, the normal distribution and
+//! least-squares regression
+
+use std::fmt;
+
+pub fn mean(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Sample variance (n - 1 in the denominator)
+pub fn variance(values: &[f64]) -> f64 {
+    if values.len() < 2 {
+        return 0.0;
+    }
+    let m = mean(values);
+    values.iter().map(|v| (v - m).powi(2)).sum::<f64>() / (values.len() - 1) as f64
+}
+
+/// Sample standard deviation
+pub fn std_dev(values: &[f64]) -> f64 {
+    variance(values).sqrt()
+}
+
+/// Pearson correlation of two equally long samples; 0 if either is constant
+pub fn correlation(x: &[f64], y: &[f64]) -> f64 {
+    let n = x.len().min(y.len());
+    let (mx, my) = (mean(&x[..n]), mean(&y[..n]));
+    let (mut sxy, mut sxx, mut syy) = (0.0, 0.0, 0.0);
+    for (a, b) in x[..n].iter().zip(&y[..n]) {
+        sxy += (a - mx) * (b - my);
+        sxx += (a - mx).powi(2);
+        syy += (b - my).powi(2);
+    }
+    if sxx > 0.0 && syy > 0.0 {
+        sxy / (sxx * syy).sqrt()
+    } else {
+        0.0
+    }
+}
+
+/// Sample skewness (population moments, no bias correction)
+pub fn skewness(values: &[f64]) -> f64 {
+    let (m2, m3, _) = central_moments(values);
+    if m2 > 0.0 { m3 / m2.powf(1.5) } else { 0.0 }
+}
+
+/// Sample kurtosis, not excess: 3 for a normal distribution
+pub fn kurtosis(values: &[f64]) -> f64 {
+    let (m2, _, m4) = central_moments(values);
+    if m2 > 0.0 { m4 / (m2 * m2) } else { 3.0 }
+}
+
+fn central_moments(values: &[f64]) -> (f64, f64, f64) {
+    if values.is_empty() {
+        return (0.0, 0.0, 0.0);
+    }
+    let m = mean(values);
+    let n = values.len() as f64;
+    values.iter().fold((0.0, 0.0, 0.0), |(m2, m3, m4), v| {
+        let d = v - m;
+        (m2 + d * d / n, m3 + d.powi(3) / n, m4 + d.powi(4) / n)
+    })
+}
+
+/// Standard normal density
+pub fn normal_pdf(x: f64) -> f64 {
+    (-0.5 * x * x).exp() / (2.0 * std::f64::consts::PI).sqrt()
+}
+
+/// Standard normal cumulative distribution function
+pub fn normal_cdf(x: f64) -> f64 {
+    0.5 * erfc(-x / std::f64::consts::SQRT_2)
+}
+
+/// Complementary error function, accurate to about 1e-7 (Numerical Recipes)
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let poly = -z * z - 1.26551223
+        + t * (1.00002368
+            + t * (0.37409196
+                + t * (0.09678418
+                    + t * (-0.18628806
+                        + t * (0.27886807
+                            + t * (-1.13520398
+                                + t * (1.48851587 + t * (-0.82215223 + t * 0.17087277))))))));
+    let r = t * poly.exp();
+    if x >= 0.0 { r } else { 2.0 - r }
+}
+
+/// Inverse of the standard normal CDF (Acklam's rational approximation)
+pub fn normal_quantile(p: f64) -> f64 {
+    if p <= 0.0 {
+        return f64::NEG_INFINITY;
+    }
+    if p >= 1.0 {
+        return f64::INFINITY;
+    }
+    const A: [f64; 6] = [
+        -3.969683028665376e1,
+        2.209460984245205e2,
+        -2.759285104469687e2,
+        1.38357751867269e2,
+        -3.066479806614716e1,
+        2.506628277459239,
+    ];
+    const B: [f64; 5] = [
+        -5.447609879822406e1,
+        1.615858368580409e2,
+        -1.556989798598866e2,
+        6.680131188771972e1,
+        -1.328068155288572e1,
+    ];
+    const C: [f64; 6] = [
+        -7.784894002430293e-3,
+        -3.223964580411365e-1,
+        -2.400758277161838,
+        -2.549732539343734,
+        4.374664141464968,
+        2.938163982698783,
+    ];
+    const D: [f64; 4] = [
+        7.784695709041462e-3,
+        3.224671290700398e-1,
+        2.445134137142996,
+        3.754408661907416,
+    ];
+    let tail = |q: f64| {
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    };
+    const P_LOW: f64 = 0.02425;
+    if p < P_LOW {
+        tail((-2.0 * p.ln()).sqrt())
+    } else if p > 1.0 - P_LOW {
+        -tail((-2.0 * (1.0 - p).ln()).sqrt())
+    } else {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    }
+}
+
+/// Result of an ordinary least squares fit
+#[derive(Debug, Clone)]
+pub struct Regression {
+    /// Coefficients in the order of the regressor columns
+    pub coefficients: Vec<f64>,
+    /// Standard errors of the coefficients
+    pub std_errors: Vec<f64>,
+    pub residuals: Vec<f64>,
+    pub r_squared: f64,
+}
+
+impl Regression {
+    /// t-statistic of coefficient `i`
+    pub fn t_stat(&self, i: usize) -> f64 {
+        if self.std_errors[i] > 0.0 {
+            self.coefficients[i] / self.std_errors[i]
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Fits `y = X b` by least squares
+///
+/// `columns` holds one vector per regressor; add a column of ones for an
+/// intercept. Returns `None` when the system is singular or underdetermined.
+pub fn ols(columns: &[Vec<f64>], y: &[f64]) -> Option<Regression> {
+    let k = columns.len();
+    let n = y.len();
+    if k == 0 || n <= k || columns.iter().any(|c| c.len() != n) {
+        return None;
+    }
+
+    // Normal equations: (X'X) b = X'y
+    let mut xtx = vec![vec![0.0; k]; k];
+    let mut xty = vec![0.0; k];
+    for i in 0..k {
+        for j in i..k {
+            let dot: f64 = columns[i].iter().zip(&columns[j]).map(|(a, b)| a * b).sum();
+            xtx[i][j] = dot;
+            xtx[j][i] = dot;
+        }
+        xty[i] = columns[i].iter().zip(y).map(|(a, b)| a * b).sum();
+    }
+
+    let inverse = invert(&xtx)?;
+    let coefficients: Vec<f64> = (0..k)
+        .map(|i| (0..k).map(|j| inverse[i][j] * xty[j]).sum())
+        .collect();
+
+    let residuals: Vec<f64> = (0..n)
+        .map(|t| y[t] - (0..k).map(|i| coefficients[i] * columns[i][t]).sum::<f64>())
+        .collect();
+    let sse: f64 = residuals.iter().map(|r| r * r).sum();
+    let sigma2 = sse / (n - k) as f64;
+    let std_errors = (0..k)
+        .map(|i| (sigma2 * inverse[i][i]).max(0.0).sqrt())
+        .collect();
+
+    let y_mean = mean(y);
+    let sst: f64 = y.iter().map(|v| (v - y_mean).powi(2)).sum();
+    let r_squared = if sst > 0.0 { 1.0 - sse / sst } else { 0.0 };
+
+    Some(Regression {
+        coefficients,
+        std_errors,
+        residuals,
+        r_squared,
+    })
+}
+
+/// Simple regression `y = alpha + beta * x`, returns `(alpha, beta)`
+pub fn linear_fit(x: &[f64], y: &[f64]) -> Option<(f64, f64)> {
+    let fit = ols(&[vec![1.0; x.len()], x.to_vec()], y)?;
+    Some((fit.coefficients[0], fit.coefficients[1]))
+}
+
+/// Gauss-Jordan inversion with partial pivoting
+fn invert(matrix: &[Vec<f64>]) -> Option<Vec<Vec<f64>>> {
+    let n = matrix.len();
+    let mut a: Vec<Vec<f64>> = matrix.to_vec();
+    let mut inv: Vec<Vec<f64>> = (0..n)
+        .map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect())
+        .collect();
+
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        inv.swap(col, pivot);
+
+        let p = a[col][col];
+        for j in 0..n {
+            a[col][j] /= p;
+            inv[col][j] /= p;
+        }
+
+        for row in 0..n {
+            if row != col {
+                let factor = a[row][col];
+                for j in 0..n {
+                    a[row][j] -= factor * a[col][j];
+                    inv[row][j] -= factor * inv[col][j];
+                }
+            }
+        }
+    }
+
+    Some(inv)
+}
+
+/// Quantile `q` in [0, 1] with linear interpolation between order statistics
+pub fn quantile(values: &[f64], q: f64) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let pos = q.clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let lower = pos.floor() as usize;
+    let upper = pos.ceil() as usize;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (pos - lower as f64)
+}
+
+/// Distribution of a sample
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    pub count: usize,
+    pub mean: f64,
+    pub std_dev: f64,
+    pub min: f64,
+    pub q25: f64,
+    pub median: f64,
+    pub q75: f64,
+    pub max: f64,
+}
+
+impl Summary {
+    pub fn of(values: &[f64]) -> Self {
+        Summary {
+            count: values.len(),
+            mean: mean(values),
+            std_dev: std_dev(values),
+            min: values.iter().copied().fold(f64::INFINITY, f64::min),
+            q25: quantile(values, 0.25),
+            median: quantile(values, 0.5),
+            q75: quantile(values, 0.75),
+            max: values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        }
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "n={} mean={:.3} std={:.3} min={:.3} q25={:.3} median={:.3} q75={:.3} max={:.3}",
+            self.count,
+            self.mean,
+            self.std_dev,
+            self.min,
+            self.q25,
+            self.median,
+            self.q75,
+            self.max
+        )
+    }
+}
@@ -0,0 +1,360 @@
+//! Reference strategies: Bollinger mean reversion, Donchian breakout and
+//! time-series momentum
+//!
+//! Every entry carries a stop-loss / take-profit bracket for engines that
+//! fill intrabar. The strategies also check the levels on each close and
+//! exit themselves, so they behave the same on close-only executors.
+
+use crate::params::{Constraint, FromParams, ParamSchema, ParamSet, ParamSpec, ParamValue};
+use crate::stats;
+use crate::strategy::{
+    Bracket, MarketData, OrderIntent, OrderType, Position, Side, Strategy, StrategyContext,
+};
+
+/// Percentage stop-loss and take-profit around the entry price (0 disables)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RiskExits {
+    pub stop_loss_pct: f64,
+    pub take_profit_pct: f64,
+}
+
+impl RiskExits {
+    fn schema_params(schema: ParamSchema) -> ParamSchema {
+        schema
+            .param(ParamSpec::float("stop_loss_pct", 0.0, 0.0, 0.5, Some(0.01)))
+            .param(ParamSpec::float(
+                "take_profit_pct",
+                0.0,
+                0.0,
+                2.0,
+                Some(0.01),
+            ))
+    }
+
+    fn from_params(params: &ParamSet) -> Self {
+        RiskExits {
+            stop_loss_pct: params.float("stop_loss_pct").unwrap_or(0.0),
+            take_profit_pct: params.float("take_profit_pct").unwrap_or(0.0),
+        }
+    }
+
+    fn write_params(&self, params: ParamSet) -> ParamSet {
+        params
+            .with("stop_loss_pct", ParamValue::Float(self.stop_loss_pct))
+            .with("take_profit_pct", ParamValue::Float(self.take_profit_pct))
+    }
+
+    /// Bracket levels for a position opened at `entry_price` on `side`
+    pub fn bracket(&self, entry_price: f64, side: Side) -> Option<Bracket> {
+        if self.stop_loss_pct <= 0.0 && self.take_profit_pct <= 0.0 {
+            return None;
+        }
+        let s = side.sign();
+        Some(Bracket {
+            stop_loss: (self.stop_loss_pct > 0.0)
+                .then_some(entry_price * (1.0 - s * self.stop_loss_pct)),
+            take_profit: (self.take_profit_pct > 0.0)
+                .then_some(entry_price * (1.0 + s * self.take_profit_pct)),
+        })
+    }
+
+    /// True when `price` has reached the stop or the target of `position`
+    pub fn triggered(&self, position: &Position, price: f64) -> bool {
+        if position.is_flat() || position.avg_price <= 0.0 {
+            return false;
+        }
+        let ret = (price / position.avg_price - 1.0) * position.quantity.signum();
+        (self.stop_loss_pct > 0.0 && ret <= -self.stop_loss_pct)
+            || (self.take_profit_pct > 0.0 && ret >= self.take_profit_pct)
+    }
+}
+
+/// Market entry on `side` with the bracket derived from `exits`
+fn entry(side: Side, quantity: f64, price: f64, exits: &RiskExits) -> OrderIntent {
+    OrderIntent::Entry {
+        side,
+        quantity,
+        order_type: OrderType::Market,
+        bracket: exits.bracket(price, side),
+    }
+}
+
+/// Bollinger Bands mean reversion
+///
+/// Buys when the close drops below the lower band, sells short above the
+/// upper band (if allowed) and exits when the close returns to the middle band.
+#[derive(Debug, Clone)]
+pub struct BollingerReversion {
+    period: usize,
+    num_std: f64,
+    allow_short: bool,
+    quantity: f64,
+    exits: RiskExits,
+}
+
+impl FromParams for BollingerReversion {
+    fn schema() -> ParamSchema {
+        RiskExits::schema_params(
+            ParamSchema::new()
+                .param(ParamSpec::int("period", 20, 2, 500, 1))
+                .param(ParamSpec::float("num_std", 2.0, 0.5, 5.0, Some(0.25)))
+                .param(ParamSpec::bool("allow_short", false))
+                .param(ParamSpec::float("quantity", 1.0, 0.0, 1_000_000.0, None)),
+        )
+    }
+
+    fn from_validated(params: &ParamSet) -> Self {
+        BollingerReversion {
+            period: params.int("period").unwrap_or(20) as usize,
+            num_std: params.float("num_std").unwrap_or(2.0),
+            allow_short: params.bool("allow_short").unwrap_or(false),
+            quantity: params.float("quantity").unwrap_or(1.0),
+            exits: RiskExits::from_params(params),
+        }
+    }
+}
+
+impl Strategy for BollingerReversion {
+    fn name(&self) -> &str {
+        "Bollinger Reversion"
+    }
+
+    fn parameters(&self) -> ParamSet {
+        self.exits.write_params(
+            ParamSet::new()
+                .with("period", ParamValue::Int(self.period as i64))
+                .with("num_std", ParamValue::Float(self.num_std))
+                .with("allow_short", ParamValue::Bool(self.allow_short))
+                .with("quantity", ParamValue::Float(self.quantity)),
+        )
+    }
+
+    fn on_bar(&mut self, data: &MarketData, ctx: &StrategyContext) -> Vec<OrderIntent> {
+        let prices = &data.prices;
+        if prices.len() < self.period {
+            return Vec::new();
+        }
+        let window = &prices[prices.len() - self.period..];
+        let middle = stats::mean(window);
+        let band = self.num_std * stats::std_dev(window);
+        let close = prices[prices.len() - 1];
+        let position = &ctx.position;
+
+        if self.exits.triggered(position, close) {
+            return vec![OrderIntent::ClosePosition];
+        }
+        if (position.is_long() && close >= middle) || (position.is_short() && close <= middle) {
+            return vec![OrderIntent::ClosePosition];
+        }
+        if position.is_flat() && band > 0.0 {
+            if close < middle - band {
+                return vec![entry(Side::Buy, self.quantity, close, &self.exits)];
+            }
+            if self.allow_short && close > middle + band {
+                return vec![entry(Side::Sell, self.quantity, close, &self.exits)];
+            }
+        }
+
+        Vec::new()
+    }
+}
+
+/// Donchian channel breakout
+///
+/// Goes long when the close breaks above the highest close of the previous
+/// `entry_period` bars and exits below the lowest close of the previous
+/// `exit_period` bars. Shorts mirror this when allowed.
+#[derive(Debug, Clone)]
+pub struct DonchianBreakout {
+    entry_period: usize,
+    exit_period: usize,
+    allow_short: bool,
+    quantity: f64,
+    exits: RiskExits,
+}
+
+impl FromParams for DonchianBreakout {
+    fn schema() -> ParamSchema {
+        RiskExits::schema_params(
+            ParamSchema::new()
+                .param(ParamSpec::int("entry_period", 20, 2, 500, 1))
+                .param(ParamSpec::int("exit_period", 10, 1, 500, 1))
+                .param(ParamSpec::bool("allow_short", false))
+                .param(ParamSpec::float("quantity", 1.0, 0.0, 1_000_000.0, None))
+                .constraint(Constraint::LessOrEqual {
+                    left: "exit_period".to_string(),
+                    right: "entry_period".to_string(),
+                }),
+        )
+    }
+
+    fn from_validated(params: &ParamSet) -> Self {
+        DonchianBreakout {
+            entry_period: params.int("entry_period").unwrap_or(20) as usize,
+            exit_period: params.int("exit_period").unwrap_or(10) as usize,
+            allow_short: params.bool("allow_short").unwrap_or(false),
+            quantity: params.float("quantity").unwrap_or(1.0),
+            exits: RiskExits::from_params(params),
+        }
+    }
+}
+
+/// Highest and lowest value of the `period` bars before the last one
+fn prior_channel(prices: &[f64], period: usize) -> (f64, f64) {
+    let prior = &prices[prices.len() - 1 - period..prices.len() - 1];
+    let high = prior.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let low = prior.iter().cloned().fold(f64::INFINITY, f64::min);
+    (high, low)
+}
+
+impl Strategy for DonchianBreakout {
+    fn name(&self) -> &str {
+        "Donchian Breakout"
+    }
+
+    fn parameters(&self) -> ParamSet {
+        self.exits.write_params(
+            ParamSet::new()
+                .with("entry_period", ParamValue::Int(self.entry_period as i64))
+                .with("exit_period", ParamValue::Int(self.exit_period as i64))
+                .with("allow_short", ParamValue::Bool(self.allow_short))
+                .with("quantity", ParamValue::Float(self.quantity)),
+        )
+    }
+
+    fn on_bar(&mut self, data: &MarketData, ctx: &StrategyContext) -> Vec<OrderIntent> {
+        let prices = &data.prices;
+        if prices.len() <= self.entry_period {
+            return Vec::new();
+        }
+        let close = prices[prices.len() - 1];
+        let position = &ctx.position;
+
+        if self.exits.triggered(position, close) {
+            return vec![OrderIntent::ClosePosition];
+        }
+
+        let (exit_high, exit_low) = prior_channel(prices, self.exit_period);
+        if (position.is_long() && close < exit_low) || (position.is_short() && close > exit_high) {
+            return vec![OrderIntent::ClosePosition];
+        }
+
+        if position.is_flat() {
+            let (high, low) = prior_channel(prices, self.entry_period);
+            if close > high {
+                return vec![entry(Side::Buy, self.quantity, close, &self.exits)];
+            }
+            if self.allow_short && close < low {
+                return vec![entry(Side::Sell, self.quantity, close, &self.exits)];
+            }
+        }
+
+        Vec::new()
+    }
+}
+
+/// Time-series momentum
+///
+/// Holds a long position while the return over `lookback` bars exceeds
+/// `threshold`, a short one (if allowed) while it is below `-threshold`,
+/// and is flat otherwise. After a stop or target exit it waits for the
+/// signal to change before re-entering.
+#[derive(Debug, Clone)]
+pub struct TimeSeriesMomentum {
+    lookback: usize,
+    threshold: f64,
+    allow_short: bool,
+    quantity: f64,
+    exits: RiskExits,
+    /// Direction of the last entry, cleared when the signal changes
+    last_entry: Option<f64>,
+}
+
+impl FromParams for TimeSeriesMomentum {
+    fn schema() -> ParamSchema {
+        RiskExits::schema_params(
+            ParamSchema::new()
+                .param(ParamSpec::int("lookback", 20, 1, 500, 1))
+                .param(ParamSpec::float("threshold", 0.0, 0.0, 1.0, Some(0.01)))
+                .param(ParamSpec::bool("allow_short", false))
+                .param(ParamSpec::float("quantity", 1.0, 0.0, 1_000_000.0, None)),
+        )
+    }
+
+    fn from_validated(params: &ParamSet) -> Self {
+        TimeSeriesMomentum {
+            lookback: params.int("lookback").unwrap_or(20) as usize,
+            threshold: params.float("threshold").unwrap_or(0.0),
+            allow_short: params.bool("allow_short").unwrap_or(false),
+            quantity: params.float("quantity").unwrap_or(1.0),
+            exits: RiskExits::from_params(params),
+            last_entry: None,
+        }
+    }
+}
+
+impl Strategy for TimeSeriesMomentum {
+    fn name(&self) -> &str {
+        "Time-Series Momentum"
+    }
+
+    fn parameters(&self) -> ParamSet {
+        self.exits.write_params(
+            ParamSet::new()
+                .with("lookback", ParamValue::Int(self.lookback as i64))
+                .with("threshold", ParamValue::Float(self.threshold))
+                .with("allow_short", ParamValue::Bool(self.allow_short))
+                .with("quantity", ParamValue::Float(self.quantity)),
+        )
+    }
+
+    fn on_start(&mut self, _ctx: &StrategyContext) {
+        self.last_entry = None;
+    }
+
+    fn on_bar(&mut self, data: &MarketData, ctx: &StrategyContext) -> Vec<OrderIntent> {
+        let prices = &data.prices;
+        if prices.len() <= self.lookback {
+            return Vec::new();
+        }
+        let close = prices[prices.len() - 1];
+        let past = prices[prices.len() - 1 - self.lookback];
+        let momentum = close / past - 1.0;
+        let position = &ctx.position;
+
+        if self.exits.triggered(position, close) {
+            return vec![OrderIntent::ClosePosition];
+        }
+
+        let desired = if momentum > self.threshold {
+            1.0
+        } else if self.allow_short && momentum < -self.threshold {
+            -1.0
+        } else {
+            0.0
+        };
+        if self.last_entry.is_some_and(|d| d != desired) {
+            self.last_entry = None;
+        }
+
+        let current = if position.is_flat() {
+            0.0
+        } else {
+            position.quantity.signum()
+        };
+        if desired == current || (desired != 0.0 && self.last_entry == Some(desired)) {
+            return Vec::new();
+        }
+
+        let mut intents = Vec::new();
+        if !position.is_flat() {
+            intents.push(OrderIntent::ClosePosition);
+        }
+        if desired != 0.0 {
+            let side = if desired > 0.0 { Side::Buy } else { Side::Sell };
+            intents.push(entry(side, self.quantity, close, &self.exits));
+            self.last_entry = Some(desired);
+        }
+        intents
+    }
+}
@@ -0,0 +1,485 @@
+//! Strategy trait, order intents and the strategy manager
+
+use serde::{Deserialize, Serialize};
+
+use crate::data::OHLCV;
+use crate::ensemble::{AggregationPolicy, CombinedDecision, combine_signals};
+use crate::params::{
+    Constraint, FromParams, ParamError, ParamSchema, ParamSet, ParamSpec, ParamValue,
+};
+
+/// Trading signal
+#[derive(Debug, Clone, PartialEq)]
+pub enum Signal {
+    Buy { price: f64, quantity: f64 },
+    Sell { price: f64, quantity: f64 },
+    Hold,
+}
+
+impl Signal {
+    /// Summarizes a batch of order intents as a single directional signal
+    ///
+    /// The intents are converted to the signed change they would make to
+    /// `position` and netted: a positive change is a buy, a negative one a sell.
+    pub fn from_intents(intents: &[OrderIntent], position: &Position, price: f64) -> Signal {
+        let delta: f64 = intents.iter().map(|i| i.position_delta(position)).sum();
+
+        if delta > 0.0 {
+            Signal::Buy {
+                price,
+                quantity: delta,
+            }
+        } else if delta < 0.0 {
+            Signal::Sell {
+                price,
+                quantity: -delta,
+            }
+        } else {
+            Signal::Hold
+        }
+    }
+}
+
+/// Order side
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+impl Side {
+    /// +1.0 for buys, -1.0 for sells
+    pub fn sign(&self) -> f64 {
+        match self {
+            Side::Buy => 1.0,
+            Side::Sell => -1.0,
+        }
+    }
+}
+
+/// How an entry order should be executed
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum OrderType {
+    /// Fill at the next available price
+    Market,
+    /// Fill at the given price or better
+    Limit(f64),
+    /// Becomes a market order once the given price trades
+    Stop(f64),
+}
+
+/// Protective exits attached to a position
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Bracket {
+    pub stop_loss: Option<f64>,
+    pub take_profit: Option<f64>,
+}
+
+/// What a strategy wants the execution layer to do
+#[derive(Debug, Clone, PartialEq)]
+pub enum OrderIntent {
+    /// Trade whatever is needed to hold exactly `quantity` (negative = short)
+    TargetPosition { quantity: f64 },
+    /// Open or add to a position with an explicit order
+    Entry {
+        side: Side,
+        quantity: f64,
+        order_type: OrderType,
+        bracket: Option<Bracket>,
+    },
+    /// Set or replace the stop-loss / take-profit of the current position
+    SetBracket(Bracket),
+    /// Close the current position at market
+    ClosePosition,
+    /// Cancel all resting orders of this strategy
+    CancelPending,
+}
+
+impl OrderIntent {
+    /// Signed change this intent would make to `position` if fully executed
+    ///
+    /// Brackets and cancellations do not trade on their own, so they return 0.
+    pub fn position_delta(&self, position: &Position) -> f64 {
+        match self {
+            OrderIntent::TargetPosition { quantity } => quantity - position.quantity,
+            OrderIntent::Entry { side, quantity, .. } => side.sign() * quantity,
+            OrderIntent::ClosePosition => -position.quantity,
+            OrderIntent::SetBracket(_) | OrderIntent::CancelPending => 0.0,
+        }
+    }
+}
+
+/// Net position in a single instrument
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Position {
+    /// Signed quantity: positive = long, negative = short
+    pub quantity: f64,
+    /// Volume-weighted average entry price of the open quantity
+    pub avg_price: f64,
+}
+
+impl Position {
+    pub fn is_flat(&self) -> bool {
+        self.quantity == 0.0
+    }
+
+    pub fn is_long(&self) -> bool {
+        self.quantity > 0.0
+    }
+
+    pub fn is_short(&self) -> bool {
+        self.quantity < 0.0
+    }
+
+    /// Unrealized profit of the open quantity at `price`
+    pub fn unrealized_pnl(&self, price: f64) -> f64 {
+        (price - self.avg_price) * self.quantity
+    }
+
+    /// Applies a fill and returns the realized profit it produced
+    pub fn apply_fill(&mut self, fill: &Fill) -> f64 {
+        let signed_qty = fill.side.sign() * fill.quantity;
+        let mut realized = 0.0;
+
+        if self.quantity == 0.0 || self.quantity.signum() == signed_qty.signum() {
+            // Opening or adding: blend the average price
+            let new_qty = self.quantity + signed_qty;
+            self.avg_price =
+                (self.avg_price * self.quantity.abs() + fill.price * fill.quantity) / new_qty.abs();
+            self.quantity = new_qty;
+        } else {
+            // Reducing, closing or flipping
+            let closed = signed_qty.abs().min(self.quantity.abs());
+            realized = (fill.price - self.avg_price) * closed * self.quantity.signum();
+            let new_qty = self.quantity + signed_qty;
+
+            if new_qty == 0.0 {
+                self.avg_price = 0.0;
+            } else if new_qty.signum() != self.quantity.signum() {
+                self.avg_price = fill.price;
+            }
+            self.quantity = new_qty;
+        }
+
+        realized
+    }
+}
+
+/// Account state visible to a strategy
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Account {
+    pub initial_capital: f64,
+    /// Initial capital plus realized P&L net of costs
+    pub cash: f64,
+    /// Cash plus unrealized P&L of open positions
+    pub equity: f64,
+}
+
+impl Account {
+    pub fn new(initial_capital: f64) -> Self {
+        Account {
+            initial_capital,
+            cash: initial_capital,
+            equity: initial_capital,
+        }
+    }
+}
+
+/// An executed order reported back to the strategy
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Fill {
+    pub timestamp: i64,
+    pub side: Side,
+    pub price: f64,
+    pub quantity: f64,
+    pub commission: f64,
+}
+
+/// Everything a strategy may look at besides market data
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct StrategyContext {
+    /// Index of the current bar in the market data
+    pub bar_index: usize,
+    pub position: Position,
+    pub account: Account,
+}
+
+impl StrategyContext {
+    pub fn new(account: Account) -> Self {
+        StrategyContext {
+            bar_index: 0,
+            position: Position::default(),
+            account,
+        }
+    }
+}
+
+/// Trait for trading strategies
+///
+/// The driver (backtester, paper trader) calls `on_start` once, `on_bar`
+/// for every new bar, `on_fill` for every execution and `on_end` after the
+/// last bar. Strategies keep whatever state they need between calls.
+pub trait Strategy: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Current parameter values
+    fn parameters(&self) -> ParamSet;
+
+    /// Called once before the first bar
+    fn on_start(&mut self, _ctx: &StrategyContext) {}
+
+    /// Called on every bar with all data up to and including that bar
+    fn on_bar(&mut self, data: &MarketData, ctx: &StrategyContext) -> Vec<OrderIntent>;
+
+    /// Called after one of the strategy's orders is executed
+    fn on_fill(&mut self, _fill: &Fill, _ctx: &StrategyContext) {}
+
+    /// Called once after the last bar
+    fn on_end(&mut self, _ctx: &StrategyContext) {}
+}
+
+/// Market data
+#[derive(Debug, Clone)]
+pub struct MarketData {
+    pub symbol: String,
+    /// Closing prices
+    pub prices: Vec<f64>,
+    pub opens: Vec<f64>,
+    pub highs: Vec<f64>,
+    pub lows: Vec<f64>,
+    pub volumes: Vec<f64>,
+    pub timestamps: Vec<i64>,
+}
+
+impl MarketData {
+    pub fn new(symbol: &str) -> Self {
+        MarketData {
+            symbol: symbol.to_string(),
+            prices: Vec::new(),
+            opens: Vec::new(),
+            highs: Vec::new(),
+            lows: Vec::new(),
+            volumes: Vec::new(),
+            timestamps: Vec::new(),
+        }
+    }
+
+    /// Adds a close-only candle; open, high and low are set to `price`
+    pub fn add_candle(&mut self, price: f64, volume: f64, timestamp: i64) {
+        self.add_bar(&OHLCV::new(timestamp, price, price, price, price, volume));
+    }
+
+    pub fn add_bar(&mut self, bar: &OHLCV) {
+        self.prices.push(bar.close);
+        self.opens.push(bar.open);
+        self.highs.push(bar.high);
+        self.lows.push(bar.low);
+        self.volumes.push(bar.volume);
+        self.timestamps.push(bar.timestamp);
+    }
+
+    pub fn last_price(&self) -> Option<f64> {
+        self.prices.last().copied()
+    }
+}
+
+/// MA Crossover Strategy
+///
+/// Goes long `quantity` when the fast MA crosses above the slow MA and
+/// flattens when it crosses back below. Nothing is emitted between crosses.
+#[derive(Debug, Clone)]
+pub struct CrossoverStrategy {
+    fast_period: usize,
+    slow_period: usize,
+    quantity: f64,
+    /// Sign of (fast - slow) on the previous bar
+    prev_trend: Option<f64>,
+}
+
+impl CrossoverStrategy {
+    /// Creates the strategy, panicking on invalid periods
+    ///
+    /// Use `try_new` or `FromParams::from_params` to get an error instead.
+    pub fn new(fast_period: usize, slow_period: usize) -> Self {
+        Self::try_new(fast_period, slow_period).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn try_new(fast_period: usize, slow_period: usize) -> Result<Self, ParamError> {
+        let params = ParamSet::new()
+            .with("fast_period", ParamValue::Int(fast_period as i64))
+            .with("slow_period", ParamValue::Int(slow_period as i64));
+        Self::from_params(&params)
+    }
+
+    /// Sets the position size taken on a bullish cross
+    pub fn with_quantity(mut self, quantity: f64) -> Self {
+        self.quantity = quantity;
+        self
+    }
+
+    fn calculate_sma(&self, prices: &[f64], period: usize) -> Option<f64> {
+        if prices.len() < period {
+            return None;
+        }
+
+        let sum: f64 = prices[prices.len() - period..].iter().sum();
+        Some(sum / period as f64)
+    }
+}
+
+impl FromParams for CrossoverStrategy {
+    fn schema() -> ParamSchema {
+        ParamSchema::new()
+            .param(ParamSpec::int("fast_period", 10, 1, 200, 1))
+            .param(ParamSpec::int("slow_period", 30, 2, 500, 1))
+            .param(ParamSpec::float("quantity", 1.0, 0.0, 1_000_000.0, None))
+            .constraint(Constraint::less_than("fast_period", "slow_period"))
+    }
+
+    fn from_validated(params: &ParamSet) -> Self {
+        CrossoverStrategy {
+            fast_period: params.int("fast_period").unwrap_or(10) as usize,
+            slow_period: params.int("slow_period").unwrap_or(30) as usize,
+            quantity: params.float("quantity").unwrap_or(1.0),
+            prev_trend: None,
+        }
+    }
+}
+
+impl Strategy for CrossoverStrategy {
+    fn name(&self) -> &str {
+        "MA Crossover"
+    }
+
+    fn parameters(&self) -> ParamSet {
+        ParamSet::new()
+            .with("fast_period", ParamValue::Int(self.fast_period as i64))
+            .with("slow_period", ParamValue::Int(self.slow_period as i64))
+            .with("quantity", ParamValue::Float(self.quantity))
+    }
+
+    fn on_start(&mut self, _ctx: &StrategyContext) {
+        self.prev_trend = None;
+    }
+
+    fn on_bar(&mut self, data: &MarketData, ctx: &StrategyContext) -> Vec<OrderIntent> {
+        let (fast_ma, slow_ma) = match (
+            self.calculate_sma(&data.prices, self.fast_period),
+            self.calculate_sma(&data.prices, self.slow_period),
+        ) {
+            (Some(fast), Some(slow)) => (fast, slow),
+            _ => return Vec::new(),
+        };
+
+        let diff = fast_ma - slow_ma;
+        if diff == 0.0 {
+            // Touching MAs are not a cross; keep the previous trend
+            return Vec::new();
+        }
+
+        let trend = diff.signum();
+        let prev_trend = self.prev_trend.replace(trend);
+
+        match prev_trend {
+            Some(prev) if prev < 0.0 && trend > 0.0 && !ctx.position.is_long() => {
+                vec![OrderIntent::TargetPosition {
+                    quantity: self.quantity,
+                }]
+            }
+            Some(prev) if prev > 0.0 && trend < 0.0 && ctx.position.is_long() => {
+                vec![OrderIntent::TargetPosition { quantity: 0.0 }]
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Strategy manager
+pub struct StrategyManager {
+    strategies: Vec<Box<dyn Strategy>>,
+    weights: Vec<f64>,
+    policy: AggregationPolicy,
+}
+
+impl StrategyManager {
+    pub fn new() -> Self {
+        StrategyManager {
+            strategies: Vec::new(),
+            weights: Vec::new(),
+            policy: AggregationPolicy::MajorityVote,
+        }
+    }
+
+    /// Sets the policy used by `decide`
+    pub fn with_policy(mut self, policy: AggregationPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn add_strategy(&mut self, strategy: Box<dyn Strategy>) {
+        self.add_weighted_strategy(strategy, 1.0);
+    }
+
+    /// Adds a strategy whose vote (or quantity) is scaled by `weight`
+    pub fn add_weighted_strategy(&mut self, strategy: Box<dyn Strategy>, weight: f64) {
+        self.strategies.push(strategy);
+        self.weights.push(weight);
+    }
+
+    /// Calls `on_start` on every strategy
+    pub fn start(&mut self, ctx: &StrategyContext) {
+        for strategy in &mut self.strategies {
+            strategy.on_start(ctx);
+        }
+    }
+
+    /// Feeds the bar to every strategy and summarizes each one's intents as a signal
+    pub fn generate_signals(
+        &mut self,
+        data: &MarketData,
+        ctx: &StrategyContext,
+    ) -> Vec<(&str, Signal)> {
+        let price = data.last_price().unwrap_or(0.0);
+
+        self.strategies
+            .iter_mut()
+            .map(|s| {
+                let intents = s.on_bar(data, ctx);
+                let s = &**s;
+                (
+                    s.name(),
+                    Signal::from_intents(&intents, &ctx.position, price),
+                )
+            })
+            .collect()
+    }
+
+    /// Feeds the bar to every strategy and combines their signals with the manager's policy
+    pub fn decide(&mut self, data: &MarketData, ctx: &StrategyContext) -> CombinedDecision {
+        let price = data.last_price().unwrap_or(0.0);
+        let weights = self.weights.clone();
+        let policy = self.policy;
+
+        let weighted: Vec<(&str, Signal, f64)> = self
+            .generate_signals(data, ctx)
+            .into_iter()
+            .zip(weights)
+            .map(|((name, signal), weight)| (name, signal, weight))
+            .collect();
+
+        combine_signals(&weighted, policy, price)
+    }
+
+    /// Calls `on_end` on every strategy
+    pub fn finish(&mut self, ctx: &StrategyContext) {
+        for strategy in &mut self.strategies {
+            strategy.on_end(ctx);
+        }
+    }
+}
+
+impl Default for StrategyManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
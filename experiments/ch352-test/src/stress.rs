@@ -0,0 +1,466 @@
+//! Stress scenarios (from Chapter 300): shocks applied to historical bars
+//! before a backtest
+//!
+//! A [`Scenario`] is a named list of [`Shock`]s. Bar positions refer to the
+//! original series; shocks apply in order and outages remove their bars
+//! last, so positions never shift under a later shock. [`StressTest`] runs
+//! every strategy on the original and on each stressed series and reports
+//! the difference.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+
+use crate::backtest::{BacktestResults, Backtester};
+use crate::data::{Instrument, OHLCV};
+use crate::strategy::Strategy;
+
+/// How prices come back after a flash crash
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Recovery {
+    /// The drop is never regained
+    None,
+    /// `share` of the drop is regained evenly over `bars`
+    Linear { bars: usize, share: f64 },
+    /// `share` of the drop is regained, half of it every `half_life` bars
+    Exponential { half_life: f64, share: f64 },
+}
+
+impl Recovery {
+    /// Share of the drop regained `k` bars after the crash bar
+    fn regained(&self, k: usize) -> f64 {
+        match *self {
+            Recovery::None => 0.0,
+            Recovery::Linear { bars, share } => share * (k as f64 / bars.max(1) as f64).min(1.0),
+            Recovery::Exponential { half_life, share } => {
+                share * (1.0 - 0.5f64.powf(k as f64 / half_life))
+            }
+        }
+    }
+}
+
+/// A market crisis replayed from approximate daily index returns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Episode {
+    /// S&P 500, 14-23 October 1987
+    BlackMonday1987,
+    /// S&P 500, 6 May 2010: a 9% intraday drop mostly regained by the close
+    FlashCrash2010,
+    /// S&P 500, 1-8 February 2018, when short-volatility products collapsed
+    Volmageddon2018,
+    /// S&P 500, 24 February - 24 March 2020
+    Covid2020,
+}
+
+impl Episode {
+    /// Close-to-close returns and, where it matters, the intraday low
+    /// relative to the previous close
+    fn bars(&self) -> &'static [(f64, Option<f64>)] {
+        match self {
+            Episode::BlackMonday1987 => &[
+                (-0.0295, None),
+                (-0.0234, None),
+                (-0.0516, None),
+                (-0.2047, None),
+                (0.0533, Some(-0.05)),
+                (0.0910, None),
+                (-0.0392, None),
+                (0.0001, None),
+            ],
+            Episode::FlashCrash2010 => &[(-0.0324, Some(-0.092))],
+            Episode::Volmageddon2018 => &[
+                (-0.0212, None),
+                (-0.0410, None),
+                (0.0174, Some(-0.025)),
+                (-0.0050, None),
+                (-0.0375, None),
+                (0.0150, Some(-0.02)),
+            ],
+            Episode::Covid2020 => &[
+                (-0.0335, None),
+                (-0.0303, None),
+                (-0.0038, None),
+                (-0.0442, None),
+                (-0.0082, None),
+                (0.0460, None),
+                (-0.0281, None),
+                (0.0422, None),
+                (-0.0339, None),
+                (-0.0171, None),
+                (-0.0760, None),
+                (0.0494, None),
+                (-0.0489, None),
+                (-0.0951, None),
+                (0.0929, None),
+                (-0.1198, None),
+                (0.0600, None),
+                (-0.0518, None),
+                (0.0047, None),
+                (-0.0434, None),
+                (-0.0293, None),
+                (0.0938, None),
+            ],
+        }
+    }
+
+    /// Trading days replayed
+    pub fn days(&self) -> usize {
+        self.bars().len()
+    }
+
+    /// Close-to-close return over the episode
+    pub fn total_return(&self) -> f64 {
+        self.bars().iter().map(|(r, _)| 1.0 + r).product::<f64>() - 1.0
+    }
+}
+
+impl fmt::Display for Episode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Episode::BlackMonday1987 => write!(f, "Black Monday 1987"),
+            Episode::FlashCrash2010 => write!(f, "Flash Crash 2010"),
+            Episode::Volmageddon2018 => write!(f, "Volmageddon 2018"),
+            Episode::Covid2020 => write!(f, "COVID-19 crash 2020"),
+        }
+    }
+}
+
+/// One change to a bar series
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shock {
+    /// Prices gap down by `depth` (a fraction) at bar `at`, then recover
+    FlashCrash {
+        at: usize,
+        depth: f64,
+        recovery: Recovery,
+    },
+    /// Bar-to-bar and intrabar log moves scaled by `multiplier`
+    Volatility { bars: Range<usize>, multiplier: f64 },
+    /// Volume scaled by `volume`, slippage by `slippage`
+    Liquidity {
+        bars: Range<usize>,
+        volume: f64,
+        slippage: f64,
+    },
+    /// Bars missing, as when the exchange is down
+    Outage { bars: Range<usize> },
+    /// The bars from `at` replaced by a historical episode
+    Replay { episode: Episode, at: usize },
+}
+
+/// A named set of shocks
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scenario {
+    pub name: String,
+    pub shocks: Vec<Shock>,
+}
+
+/// Bars after a scenario, with the slippage multipliers it implies
+#[derive(Debug, Clone)]
+pub struct StressedMarket {
+    pub instrument: Instrument,
+    /// Slippage multiplier by timestamp, for bars where it is not 1
+    pub slippage_scale: HashMap<i64, f64>,
+}
+
+impl Scenario {
+    pub fn new(name: &str) -> Self {
+        Scenario {
+            name: name.to_string(),
+            shocks: Vec::new(),
+        }
+    }
+
+    pub fn with_shock(mut self, shock: Shock) -> Self {
+        self.shocks.push(shock);
+        self
+    }
+
+    /// Replay of `episode` starting at bar `at`
+    pub fn episode(episode: Episode, at: usize) -> Self {
+        Scenario::new(&episode.to_string()).with_shock(Shock::Replay { episode, at })
+    }
+
+    pub fn apply(&self, instrument: &Instrument) -> StressedMarket {
+        let mut bars = instrument.data.clone();
+        let mut slippage = vec![1.0; bars.len()];
+        let mut missing = vec![false; bars.len()];
+        for shock in &self.shocks {
+            match shock {
+                Shock::FlashCrash {
+                    at,
+                    depth,
+                    recovery,
+                } => {
+                    for (k, bar) in bars.iter_mut().skip(*at).enumerate() {
+                        scale_bar(bar, 1.0 - depth * (1.0 - recovery.regained(k)));
+                    }
+                }
+                Shock::Volatility {
+                    bars: range,
+                    multiplier,
+                } => {
+                    let range = clamp(range, bars.len());
+                    scale_volatility(&mut bars, range, *multiplier);
+                }
+                Shock::Liquidity {
+                    bars: range,
+                    volume,
+                    slippage: scale,
+                } => {
+                    for i in clamp(range, bars.len()) {
+                        bars[i].volume *= volume;
+                        slippage[i] *= scale;
+                    }
+                }
+                Shock::Outage { bars: range } => {
+                    for i in clamp(range, bars.len()) {
+                        missing[i] = true;
+                    }
+                }
+                Shock::Replay { episode, at } => replay(&mut bars, *episode, *at),
+            }
+        }
+
+        let slippage_scale = bars
+            .iter()
+            .zip(&slippage)
+            .filter(|(_, s)| **s != 1.0)
+            .map(|(bar, s)| (bar.timestamp, *s))
+            .collect();
+        let data = bars
+            .into_iter()
+            .zip(missing)
+            .filter(|(_, missing)| !missing)
+            .map(|(bar, _)| bar)
+            .collect();
+        StressedMarket {
+            instrument: Instrument::new(&instrument.symbol, data),
+            slippage_scale,
+        }
+    }
+}
+
+fn clamp(range: &Range<usize>, len: usize) -> Range<usize> {
+    range.start.min(len)..range.end.min(len)
+}
+
+fn scale_bar(bar: &mut OHLCV, factor: f64) {
+    bar.open *= factor;
+    bar.high *= factor;
+    bar.low *= factor;
+    bar.close *= factor;
+}
+
+/// Scales log moves inside `range` and shifts the later bars to the new level
+fn scale_volatility(bars: &mut [OHLCV], range: Range<usize>, multiplier: f64) {
+    if range.is_empty() {
+        return;
+    }
+    let stretch = |x: f64, base: f64, new_base: f64| new_base * (x / base).powf(multiplier);
+    let mut previous = if range.start > 0 {
+        Some((bars[range.start - 1].close, bars[range.start - 1].close))
+    } else {
+        None
+    };
+    for bar in &mut bars[range.clone()] {
+        let close = match previous {
+            Some((old, new)) => stretch(bar.close, old, new),
+            None => bar.close,
+        };
+        previous = Some((bar.close, close));
+        *bar = OHLCV {
+            open: stretch(bar.open, bar.close, close),
+            high: stretch(bar.high, bar.close, close),
+            low: stretch(bar.low, bar.close, close),
+            close,
+            ..*bar
+        };
+    }
+    if let Some((old, new)) = previous {
+        for bar in &mut bars[range.end..] {
+            scale_bar(bar, new / old);
+        }
+    }
+}
+
+/// Replaces the bars from `at` with `episode`, shifting the later bars to the new level
+fn replay(bars: &mut [OHLCV], episode: Episode, at: usize) {
+    if at == 0 || at >= bars.len() {
+        return;
+    }
+    let end = (at + episode.days()).min(bars.len());
+    let old_close = bars[end - 1].close;
+    let mut close = bars[at - 1].close;
+    for (bar, (r, low)) in bars[at..end].iter_mut().zip(episode.bars()) {
+        let open = close;
+        close = open * (1.0 + r);
+        *bar = OHLCV {
+            open,
+            high: open.max(close),
+            low: low.map_or(open.min(close), |l| (open * (1.0 + l)).min(close)),
+            close,
+            ..*bar
+        };
+    }
+    for bar in &mut bars[end..] {
+        scale_bar(bar, close / old_close);
+    }
+}
+
+/// Headline numbers of one backtest
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StressMetrics {
+    /// In percent
+    pub total_return: f64,
+    /// In percent
+    pub max_drawdown: f64,
+    /// Annualized with the engine's `bars_per_year`, as in `BacktestResults::metrics`
+    pub sharpe: f64,
+    pub trades: usize,
+    pub rejected_orders: usize,
+    pub liquidations: usize,
+}
+
+impl StressMetrics {
+    pub fn of(results: &BacktestResults) -> Self {
+        StressMetrics {
+            total_return: results.total_return(),
+            max_drawdown: results.max_drawdown(),
+            sharpe: results.metrics().sharpe,
+            trades: results.trades.len(),
+            rejected_orders: results.rejected_orders,
+            liquidations: results.liquidations(),
+        }
+    }
+}
+
+/// One strategy under one scenario, next to its baseline
+#[derive(Debug, Clone, PartialEq)]
+pub struct StressRow {
+    pub strategy: String,
+    pub scenario: String,
+    pub baseline: StressMetrics,
+    pub stressed: StressMetrics,
+}
+
+impl StressRow {
+    /// Stressed minus baseline return, in percentage points
+    pub fn return_impact(&self) -> f64 {
+        self.stressed.total_return - self.baseline.total_return
+    }
+
+    /// Stressed minus baseline drawdown, in percentage points
+    pub fn drawdown_impact(&self) -> f64 {
+        self.stressed.max_drawdown - self.baseline.max_drawdown
+    }
+}
+
+/// Baseline and stressed metrics of every strategy under every scenario
+#[derive(Debug, Clone, PartialEq)]
+pub struct StressReport {
+    pub symbol: String,
+    pub rows: Vec<StressRow>,
+}
+
+impl StressReport {
+    pub fn rows_for<'a>(&'a self, strategy: &'a str) -> impl Iterator<Item = &'a StressRow> + 'a {
+        self.rows.iter().filter(move |r| r.strategy == strategy)
+    }
+
+    /// The scenario that cost `strategy` the most return
+    pub fn worst(&self, strategy: &str) -> Option<&StressRow> {
+        self.rows
+            .iter()
+            .filter(|r| r.strategy == strategy)
+            .min_by(|a, b| a.return_impact().total_cmp(&b.return_impact()))
+    }
+
+    pub fn print(&self) {
+        println!("\n=== Stress Test: {} ===\n", self.symbol);
+        println!(
+            "{:<18} {:<22} {:>9} {:>9} {:>8} {:>8} {:>8} {:>8} {:>6}",
+            "Strategy",
+            "Scenario",
+            "Return",
+            "Stressed",
+            "MaxDD",
+            "Stressed",
+            "Sharpe",
+            "Stressed",
+            "Liq"
+        );
+        println!("{}", "-".repeat(104));
+        for row in &self.rows {
+            println!(
+                "{:<18} {:<22} {:>8.2}% {:>8.2}% {:>7.2}% {:>7.2}% {:>8.2} {:>8.2} {:>6}",
+                row.strategy,
+                row.scenario,
+                row.baseline.total_return,
+                row.stressed.total_return,
+                row.baseline.max_drawdown,
+                row.stressed.max_drawdown,
+                row.baseline.sharpe,
+                row.stressed.sharpe,
+                row.stressed.liquidations
+            );
+        }
+    }
+}
+
+/// Runs strategies on an instrument before and after each scenario
+pub struct StressTest {
+    engine: Backtester,
+    scenarios: Vec<Scenario>,
+}
+
+impl StressTest {
+    pub fn new(engine: Backtester) -> Self {
+        StressTest {
+            engine,
+            scenarios: Vec::new(),
+        }
+    }
+
+    pub fn with_scenario(mut self, scenario: Scenario) -> Self {
+        self.scenarios.push(scenario);
+        self
+    }
+
+    /// `make_strategies` is called once per run and must return fresh
+    /// strategies in the same order each time
+    pub fn run<F>(&self, instrument: &Instrument, make_strategies: F) -> StressReport
+    where
+        F: Fn() -> Vec<Box<dyn Strategy>>,
+    {
+        let baselines: Vec<(String, StressMetrics)> = make_strategies()
+            .into_iter()
+            .map(|mut strategy| {
+                let results = self.engine.run(strategy.as_mut(), instrument);
+                (results.strategy.clone(), StressMetrics::of(&results))
+            })
+            .collect();
+
+        let mut rows = Vec::new();
+        for scenario in &self.scenarios {
+            let market = scenario.apply(instrument);
+            for (mut strategy, (name, baseline)) in make_strategies().into_iter().zip(&baselines) {
+                let results = self.engine.run_with_slippage_scale(
+                    strategy.as_mut(),
+                    &market.instrument,
+                    &market.slippage_scale,
+                );
+                rows.push(StressRow {
+                    strategy: name.clone(),
+                    scenario: scenario.name.clone(),
+                    baseline: *baseline,
+                    stressed: StressMetrics::of(&results),
+                });
+            }
+        }
+        StressReport {
+            symbol: instrument.symbol.clone(),
+            rows,
+        }
+    }
+}
@@ -0,0 +1,635 @@
+//! Parameter sweep: backtest every point of a grid and keep the best
+//!
+//! Trials run in parallel on the rayon thread pool. With a store attached,
+//! each finished backtest is saved right away and a rerun of the same sweep
+//! only backtests the parameter sets that are not in the store yet, scoring
+//! the stored ones with its own objective.
+//!
+//! Besides a fixed grid, a sweep can be driven by any [`Optimizer`], and
+//! with several objectives it searches for their Pareto front.
+//!
+//! Every sweep also reports the overfitting statistics of its trials, since
+//! the winner of a large grid is expected to look good by chance.
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::backtest::{BacktestResults, Backtester};
+use crate::data::Instrument;
+use crate::optimizer::{Evaluated, Optimizer};
+use crate::overfitting::{self, OverfittingReport};
+use crate::params::{FromParams, ParamError, ParamSchema, ParamSet, ParamValue};
+use crate::strategy::Strategy;
+use crate::trial_store::{SweepKey, TrialStore};
+
+/// One backtest of a sweep
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Trial {
+    pub params: ParamSet,
+    /// Value of the objective
+    pub score: f64,
+    /// Total return, in percent
+    pub total_return: f64,
+    pub max_drawdown: f64,
+    pub trades: usize,
+    /// Per-bar returns, as fractions
+    pub returns: Vec<f64>,
+}
+
+impl Trial {
+    fn from_results(params: ParamSet, score: f64, results: &BacktestResults) -> Self {
+        Trial {
+            params,
+            score,
+            total_return: results.total_return(),
+            max_drawdown: results.max_drawdown(),
+            trades: results.trades.len(),
+            returns: results.returns(),
+        }
+    }
+
+    /// Per-bar Sharpe ratio
+    pub fn sharpe(&self) -> f64 {
+        overfitting::sharpe_ratio(&self.returns)
+    }
+}
+
+/// Trial value to rank or slice by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SweepMetric {
+    Score,
+    TotalReturn,
+    Sharpe,
+    MaxDrawdown,
+    Trades,
+}
+
+impl SweepMetric {
+    pub fn value(&self, trial: &Trial) -> f64 {
+        match self {
+            SweepMetric::Score => trial.score,
+            SweepMetric::TotalReturn => trial.total_return,
+            SweepMetric::Sharpe => trial.sharpe(),
+            SweepMetric::MaxDrawdown => trial.max_drawdown,
+            SweepMetric::Trades => trial.trades as f64,
+        }
+    }
+
+    pub fn higher_is_better(&self) -> bool {
+        !matches!(self, SweepMetric::MaxDrawdown)
+    }
+
+    /// Orders `a` before `b` when it is better
+    fn rank(&self, a: f64, b: f64) -> Ordering {
+        if self.higher_is_better() {
+            b.total_cmp(&a)
+        } else {
+            a.total_cmp(&b)
+        }
+    }
+}
+
+impl fmt::Display for SweepMetric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SweepMetric::Score => "score",
+            SweepMetric::TotalReturn => "total return",
+            SweepMetric::Sharpe => "Sharpe",
+            SweepMetric::MaxDrawdown => "max drawdown",
+            SweepMetric::Trades => "trades",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A metric over two parameters, for heatmaps
+#[derive(Debug, Clone, PartialEq)]
+pub struct Heatmap {
+    pub x: String,
+    pub y: String,
+    pub metric: SweepMetric,
+    /// Sorted axis values
+    pub x_values: Vec<ParamValue>,
+    pub y_values: Vec<ParamValue>,
+    /// `cells[row][col]` for `y_values[row]` and `x_values[col]`; `None`
+    /// where no trial was run
+    pub cells: Vec<Vec<Option<f64>>>,
+}
+
+impl Heatmap {
+    pub fn get(&self, x: &ParamValue, y: &ParamValue) -> Option<f64> {
+        let col = self.x_values.iter().position(|v| v == x)?;
+        let row = self.y_values.iter().position(|v| v == y)?;
+        self.cells[row][col]
+    }
+
+    pub fn print(&self) {
+        println!(
+            "\n{} by {} (rows) and {} (columns)",
+            self.metric, self.y, self.x
+        );
+        print!("{:>10}", "");
+        for x in &self.x_values {
+            print!(" {:>9}", x.to_string());
+        }
+        println!();
+        for (y, row) in self.y_values.iter().zip(&self.cells) {
+            print!("{:>10}", y.to_string());
+            for cell in row {
+                match cell {
+                    Some(v) => print!(" {:>9.3}", v),
+                    None => print!(" {:>9}", "-"),
+                }
+            }
+            println!();
+        }
+    }
+}
+
+/// Distinct values, sorted numerically where possible
+fn axis<'a>(values: impl Iterator<Item = &'a ParamValue>) -> Vec<ParamValue> {
+    let mut distinct: Vec<ParamValue> = Vec::new();
+    for value in values {
+        if !distinct.contains(value) {
+            distinct.push(value.clone());
+        }
+    }
+    distinct.sort_by(compare_values);
+    distinct
+}
+
+fn compare_values(a: &ParamValue, b: &ParamValue) -> Ordering {
+    match (a.as_f64(), b.as_f64()) {
+        (Some(x), Some(y)) => x.total_cmp(&y),
+        _ => a.to_string().cmp(&b.to_string()),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SweepReport {
+    /// "grid" or the name of the optimizer
+    pub method: String,
+    /// In grid order, or in the order the optimizer proposed them
+    pub trials: Vec<Trial>,
+    /// Index of the trial with the highest score
+    pub best: usize,
+    pub overfitting: OverfittingReport,
+    pub bars_per_year: f64,
+    /// Trials loaded from the store instead of backtested
+    pub resumed: usize,
+    /// Optimizer proposals the strategy's schema refused; they were skipped
+    pub rejected: usize,
+    /// Metrics traded off against each other; empty for a single objective
+    pub objectives: Vec<SweepMetric>,
+}
+
+impl SweepReport {
+    pub fn best_trial(&self) -> &Trial {
+        &self.trials[self.best]
+    }
+
+    /// The `n` highest-scoring trials, best first
+    pub fn top(&self, n: usize) -> Vec<&Trial> {
+        self.top_by(SweepMetric::Score, n)
+    }
+
+    /// The `n` best trials by `metric`, best first
+    pub fn top_by(&self, metric: SweepMetric, n: usize) -> Vec<&Trial> {
+        let mut ranked: Vec<&Trial> = self.trials.iter().collect();
+        ranked.sort_by(|a, b| metric.rank(metric.value(a), metric.value(b)));
+        ranked.truncate(n);
+        ranked
+    }
+
+    /// Trials no other trial beats on every objective, ordered by the first
+    ///
+    /// With no objectives set this is the best trial by score.
+    pub fn pareto_front(&self) -> Vec<&Trial> {
+        let objectives = if self.objectives.is_empty() {
+            vec![SweepMetric::Score]
+        } else {
+            self.objectives.clone()
+        };
+        let ranks = pareto_ranks(&self.trials, &objectives);
+        let mut front: Vec<&Trial> = self
+            .trials
+            .iter()
+            .zip(ranks)
+            .filter(|(_, rank)| *rank == 0)
+            .map(|(t, _)| t)
+            .collect();
+        let first = objectives[0];
+        front.sort_by(|a, b| first.rank(first.value(a), first.value(b)));
+        front
+    }
+
+    /// `metric` over parameters `x` and `y`
+    ///
+    /// Only trials matching every value in `fixed` are used. When other
+    /// parameters vary too, each cell holds the best trial over them.
+    pub fn heatmap(&self, x: &str, y: &str, metric: SweepMetric, fixed: &ParamSet) -> Heatmap {
+        let trials: Vec<(&ParamValue, &ParamValue, f64)> = self
+            .trials
+            .iter()
+            .filter(|t| fixed.iter().all(|(name, v)| t.params.get(name) == Some(v)))
+            .filter_map(|t| Some((t.params.get(x)?, t.params.get(y)?, metric.value(t))))
+            .collect();
+        let x_values = axis(trials.iter().map(|t| t.0));
+        let y_values = axis(trials.iter().map(|t| t.1));
+
+        let mut cells = vec![vec![None; x_values.len()]; y_values.len()];
+        for (xv, yv, value) in trials {
+            let col = x_values.iter().position(|v| v == xv).expect("x axis value");
+            let row = y_values.iter().position(|v| v == yv).expect("y axis value");
+            let cell: &mut Option<f64> = &mut cells[row][col];
+            if cell.is_none_or(|best| metric.rank(value, best) == Ordering::Less) {
+                *cell = Some(value);
+            }
+        }
+
+        Heatmap {
+            x: x.to_string(),
+            y: y.to_string(),
+            metric,
+            x_values,
+            y_values,
+            cells,
+        }
+    }
+
+    pub fn print(&self, top: usize) {
+        println!(
+            "\n=== Parameter Sweep ({}, {} trials, {} resumed, {} rejected) ===\n",
+            self.method,
+            self.trials.len(),
+            self.resumed,
+            self.rejected
+        );
+        println!(
+            "{:<4} {:>10} {:>9} {:>8} {:>7}  Params",
+            "#", "Score", "Return", "MaxDD", "Trades"
+        );
+        for (i, t) in self.top(top).iter().enumerate() {
+            println!(
+                "{:<4} {:>10.3} {:>8.2}% {:>7.2}% {:>7}  {}",
+                i + 1,
+                t.score,
+                t.total_return,
+                t.max_drawdown,
+                t.trades,
+                t.params
+            );
+        }
+        if !self.objectives.is_empty() {
+            let names: Vec<String> = self.objectives.iter().map(|m| m.to_string()).collect();
+            println!("\nPareto front ({}):", names.join(" vs "));
+            for t in self.pareto_front() {
+                let values: Vec<String> = self
+                    .objectives
+                    .iter()
+                    .map(|m| format!("{:.3}", m.value(t)))
+                    .collect();
+                println!("  {:<24} {}", values.join("  "), t.params);
+            }
+        }
+        self.overfitting.print(self.bars_per_year);
+    }
+}
+
+/// Whether `a` is at least as good as `b` on every metric and better on one
+fn dominates(a: &Trial, b: &Trial, metrics: &[SweepMetric]) -> bool {
+    let mut better = false;
+    for m in metrics {
+        match m.rank(m.value(a), m.value(b)) {
+            Ordering::Greater => return false,
+            Ordering::Less => better = true,
+            Ordering::Equal => {}
+        }
+    }
+    better
+}
+
+/// Non-dominated sorting: 0 for the Pareto front, 1 for the front once
+/// that is removed, and so on
+fn pareto_ranks(trials: &[Trial], metrics: &[SweepMetric]) -> Vec<usize> {
+    let mut ranks = vec![usize::MAX; trials.len()];
+    let mut rank = 0;
+    while ranks.contains(&usize::MAX) {
+        let front: Vec<usize> = (0..trials.len())
+            .filter(|&i| ranks[i] == usize::MAX)
+            .filter(|&i| {
+                !(0..trials.len()).any(|j| {
+                    (ranks[j] == usize::MAX || ranks[j] == rank)
+                        && j != i
+                        && dominates(&trials[j], &trials[i], metrics)
+                })
+            })
+            .collect();
+        for i in front {
+            ranks[i] = rank;
+        }
+        rank += 1;
+    }
+    ranks
+}
+
+/// Why a sweep could not be carried out
+#[derive(Debug, Clone, PartialEq)]
+pub enum SweepError {
+    EmptyGrid,
+    InvalidParams(ParamError),
+    /// The trial store could not be read or written
+    Store(String),
+}
+
+impl fmt::Display for SweepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EmptyGrid => write!(f, "Parameter grid is empty"),
+            Self::InvalidParams(e) => write!(f, "Invalid parameters: {}", e),
+            Self::Store(e) => write!(f, "Trial store error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SweepError {}
+
+impl From<std::io::Error> for SweepError {
+    fn from(e: std::io::Error) -> Self {
+        SweepError::Store(e.to_string())
+    }
+}
+
+impl From<ParamError> for SweepError {
+    fn from(e: ParamError) -> Self {
+        SweepError::InvalidParams(e)
+    }
+}
+
+/// Exhaustive search over a parameter grid
+pub struct ParamSweep {
+    engine: Backtester,
+    cscv_groups: usize,
+    store: Option<PathBuf>,
+    threads: Option<usize>,
+    objectives: Vec<SweepMetric>,
+}
+
+/// Rounds of only already-evaluated proposals before an optimization stops
+const MAX_STALLED_ROUNDS: usize = 20;
+
+impl ParamSweep {
+    pub fn new(engine: Backtester) -> Self {
+        ParamSweep {
+            engine,
+            cscv_groups: 16,
+            store: None,
+            threads: None,
+            objectives: Vec::new(),
+        }
+    }
+
+    /// Number of bar groups for the PBO estimate; must be even
+    pub fn with_cscv_groups(mut self, groups: usize) -> Self {
+        self.cscv_groups = groups;
+        self
+    }
+
+    /// Saves trials to the JSONL file at `path` and reuses those already there
+    pub fn with_store(mut self, path: impl Into<PathBuf>) -> Self {
+        self.store = Some(path.into());
+        self
+    }
+
+    /// Runs on a dedicated pool of `threads` threads instead of the global one
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads);
+        self
+    }
+
+    /// Optimizes for the Pareto front of `objectives` instead of the score
+    ///
+    /// Optimizers are then guided by each trial's non-dominated rank.
+    pub fn with_objectives(mut self, objectives: &[SweepMetric]) -> Self {
+        self.objectives = objectives.to_vec();
+        self
+    }
+
+    /// Backtests `S` with every set of `grid` and scores it with `objective`
+    ///
+    /// Trials come back in grid order however the work was scheduled.
+    pub fn run<S, F>(
+        &self,
+        instrument: &Instrument,
+        grid: &[ParamSet],
+        objective: F,
+    ) -> Result<SweepReport, SweepError>
+    where
+        S: Strategy + FromParams,
+        F: Fn(&BacktestResults) -> f64 + Sync,
+    {
+        if grid.is_empty() {
+            return Err(SweepError::EmptyGrid);
+        }
+        let grid = grid
+            .iter()
+            .map(|p| S::schema().validate(p))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut evaluator = Evaluator::new::<S>(self, instrument, objective)?;
+        let trials = evaluator.evaluate::<S>(&grid)?;
+        Ok(self.report("grid", trials, evaluator.resumed, 0))
+    }
+
+    /// Evaluates the sets `optimizer` proposes from `space` until `budget`
+    /// distinct sets have been backtested or it stops proposing new ones
+    ///
+    /// `space` narrows the strategy's own schema to the ranges worth
+    /// searching; proposals must pass both. A proposal the schema refuses is
+    /// skipped and counted in `SweepReport::rejected`; a round of nothing but
+    /// refused or repeated proposals counts as stalled.
+    pub fn optimize<S, F>(
+        &self,
+        instrument: &Instrument,
+        space: &ParamSchema,
+        optimizer: &mut dyn Optimizer,
+        budget: usize,
+        objective: F,
+    ) -> Result<SweepReport, SweepError>
+    where
+        S: Strategy + FromParams,
+        F: Fn(&BacktestResults) -> f64 + Sync,
+    {
+        let schema = S::schema();
+        let mut evaluator = Evaluator::new::<S>(self, instrument, objective)?;
+        let mut trials: Vec<Trial> = Vec::new();
+        let mut seen = HashSet::new();
+        let mut stalled = 0;
+        let mut rejected = 0;
+
+        while trials.len() < budget && stalled < MAX_STALLED_ROUNDS {
+            let proposals = optimizer.ask(space, &self.fitness(&trials));
+            if proposals.is_empty() {
+                break;
+            }
+            let mut batch = Vec::new();
+            for params in proposals {
+                let Ok(params) = schema.validate(&params) else {
+                    rejected += 1;
+                    continue;
+                };
+                if trials.len() + batch.len() < budget && seen.insert(params.fingerprint()) {
+                    batch.push(params);
+                }
+            }
+            if batch.is_empty() {
+                stalled += 1;
+                continue;
+            }
+            stalled = 0;
+            trials.extend(evaluator.evaluate::<S>(&batch)?);
+        }
+
+        if trials.is_empty() {
+            return Err(SweepError::EmptyGrid);
+        }
+        Ok(self.report(&optimizer.name(), trials, evaluator.resumed, rejected))
+    }
+
+    /// What optimizers maximize: the score, or minus the Pareto rank
+    fn fitness(&self, trials: &[Trial]) -> Vec<Evaluated> {
+        let fitness: Vec<f64> = if self.objectives.is_empty() {
+            trials.iter().map(|t| t.score).collect()
+        } else {
+            pareto_ranks(trials, &self.objectives)
+                .into_iter()
+                .map(|rank| -(rank as f64))
+                .collect()
+        };
+        trials
+            .iter()
+            .zip(fitness)
+            .map(|(t, fitness)| Evaluated {
+                params: t.params.clone(),
+                fitness,
+            })
+            .collect()
+    }
+
+    fn report(
+        &self,
+        method: &str,
+        trials: Vec<Trial>,
+        resumed: usize,
+        rejected: usize,
+    ) -> SweepReport {
+        // The first of equal scores wins, as in the other optimizers
+        let best = (1..trials.len()).fold(0, |best, i| {
+            if trials[i].score > trials[best].score {
+                i
+            } else {
+                best
+            }
+        });
+        let returns: Vec<Vec<f64>> = trials.iter().map(|t| t.returns.clone()).collect();
+        SweepReport {
+            method: method.to_string(),
+            overfitting: OverfittingReport::from_trials(&returns, best, self.cscv_groups),
+            best,
+            trials,
+            bars_per_year: self.engine.config().bars_per_year,
+            resumed,
+            rejected,
+            objectives: self.objectives.clone(),
+        }
+    }
+}
+
+/// Backtests batches of parameter sets, in parallel and through the store
+struct Evaluator<'a, F> {
+    sweep: &'a ParamSweep,
+    instrument: &'a Instrument,
+    objective: F,
+    store: Mutex<Option<TrialStore>>,
+    pool: Option<rayon::ThreadPool>,
+    resumed: usize,
+}
+
+impl<'a, F> Evaluator<'a, F>
+where
+    F: Fn(&BacktestResults) -> f64 + Sync,
+{
+    fn new<S: Strategy>(
+        sweep: &'a ParamSweep,
+        instrument: &'a Instrument,
+        objective: F,
+    ) -> Result<Self, SweepError> {
+        let key = SweepKey::new::<S>(instrument, sweep.engine.config());
+        let store = sweep
+            .store
+            .as_ref()
+            .map(|path| TrialStore::open(path, &key))
+            .transpose()?;
+        let pool = sweep
+            .threads
+            .map(|threads| rayon::ThreadPoolBuilder::new().num_threads(threads).build())
+            .transpose()
+            .map_err(|e| SweepError::Store(e.to_string()))?;
+        Ok(Evaluator {
+            sweep,
+            instrument,
+            objective,
+            store: Mutex::new(store),
+            pool,
+            resumed: 0,
+        })
+    }
+
+    /// Trials for validated `sets`, in the same order
+    fn evaluate<S: Strategy + FromParams>(
+        &mut self,
+        sets: &[ParamSet],
+    ) -> Result<Vec<Trial>, SweepError> {
+        let mut trials: Vec<Option<Trial>> = {
+            let store = self.store.lock().expect("store lock");
+            sets.iter()
+                .map(|params| {
+                    let results = store.as_ref()?.get(&params.fingerprint())?;
+                    let score = (self.objective)(results);
+                    Some(Trial::from_results(params.clone(), score, results))
+                })
+                .collect()
+        };
+        self.resumed += trials.iter().filter(|t| t.is_some()).count();
+        let pending: Vec<usize> = (0..sets.len()).filter(|&i| trials[i].is_none()).collect();
+
+        let run_trial = |i: usize| -> Result<(usize, Trial), SweepError> {
+            let mut strategy = S::from_validated(&sets[i]);
+            let results = self.sweep.engine.run(&mut strategy, self.instrument);
+            if let Some(store) = self.store.lock().expect("store lock").as_mut() {
+                store.append(&sets[i], &results)?;
+            }
+            let trial = Trial::from_results(sets[i].clone(), (self.objective)(&results), &results);
+            Ok((i, trial))
+        };
+        let finished: Vec<Result<(usize, Trial), SweepError>> = match &self.pool {
+            Some(pool) => pool.install(|| pending.par_iter().map(|&i| run_trial(i)).collect()),
+            None => pending.par_iter().map(|&i| run_trial(i)).collect(),
+        };
+        for result in finished {
+            let (i, trial) = result?;
+            trials[i] = Some(trial);
+        }
+
+        Ok(trials
+            .into_iter()
+            .map(|t| t.expect("every trial ran"))
+            .collect())
+    }
+}
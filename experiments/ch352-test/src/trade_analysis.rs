@@ -0,0 +1,428 @@
+//! Trade-level analytics (from Chapter 285)
+//!
+//! Win rate and average trade hide where a strategy makes and loses money.
+//! [`TradeAnalyzer`] looks at every trade of a backtest against the bars it
+//! was open for:
+//!
+//! - maximum adverse and favorable excursion (MAE/MFE): how far price went
+//!   against and for the position before it was closed. If winners rarely go
+//!   further against the position than some level, a stop beyond it only adds
+//!   loss; if trades give back most of their MFE, targets are too far;
+//! - P&L distributions by holding time, hour of day, weekday and exit reason;
+//! - runs of consecutive wins and losses.
+//!
+//! Timestamps are read as Unix seconds, shifted by an optional UTC offset.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::backtest::{BacktestResults, ExitReason, Trade};
+use crate::data::Instrument;
+use crate::stats::{self, Summary};
+use crate::strategy::Side;
+
+const DAY: i64 = 86_400;
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// How far one trade went against and for the position
+///
+/// Excursions are fractions of the entry price. The entry bar and the bars
+/// up to the exit count with their full range; the exit bar only with its
+/// open and the exit price, since what it did after the fill is not part of
+/// the trade.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Excursion {
+    /// Index into the results' trades
+    pub trade: usize,
+    /// Maximum adverse excursion, positive
+    pub mae: f64,
+    /// Maximum favorable excursion, positive
+    pub mfe: f64,
+    /// Net profit relative to the entry notional
+    pub result: f64,
+}
+
+impl Excursion {
+    fn of(index: usize, trade: &Trade, instrument: &Instrument) -> Self {
+        let entry = trade.entry_price;
+        let mut low = entry.min(trade.exit_price);
+        let mut high = entry.max(trade.exit_price);
+        if trade.exit_bar > trade.entry_bar {
+            for bar in &instrument.data[trade.entry_bar..trade.exit_bar] {
+                low = low.min(bar.low);
+                high = high.max(bar.high);
+            }
+            if let Some(exit) = instrument.data.get(trade.exit_bar) {
+                low = low.min(exit.open);
+                high = high.max(exit.open);
+            }
+        }
+        let (adverse, favorable) = match trade.side {
+            Side::Buy => (entry - low, high - entry),
+            Side::Sell => (high - entry, entry - low),
+        };
+        Excursion {
+            trade: index,
+            mae: adverse / entry,
+            mfe: favorable / entry,
+            result: trade.return_pct() / 100.0,
+        }
+    }
+
+    /// Share of the favorable excursion kept at exit; negative for losers
+    pub fn efficiency(&self) -> f64 {
+        if self.mfe > 0.0 {
+            self.result / self.mfe
+        } else {
+            0.0
+        }
+    }
+}
+
+/// P&L of the trades in one bucket
+#[derive(Debug, Clone, PartialEq)]
+pub struct Breakdown {
+    pub label: String,
+    /// Win rate in percent
+    pub win_rate: f64,
+    pub total_pnl: f64,
+    /// Distribution of the net profit of each trade
+    pub pnl: Summary,
+}
+
+impl Breakdown {
+    fn of(label: String, pnls: &[f64]) -> Self {
+        let wins = pnls.iter().filter(|&&p| p > 0.0).count();
+        Breakdown {
+            label,
+            win_rate: wins as f64 / pnls.len() as f64 * 100.0,
+            total_pnl: pnls.iter().sum(),
+            pnl: Summary::of(pnls),
+        }
+    }
+
+    pub fn trades(&self) -> usize {
+        self.pnl.count
+    }
+}
+
+impl fmt::Display for Breakdown {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} trades, {:.1}% won, P&L {:.2}",
+            self.label,
+            self.trades(),
+            self.win_rate,
+            self.total_pnl
+        )
+    }
+}
+
+/// Runs of consecutive winners and losers; break-even trades end a run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Streaks {
+    pub longest_win: usize,
+    pub longest_loss: usize,
+    pub average_win: f64,
+    pub average_loss: f64,
+    /// Positive for a run of wins at the end, negative for losses
+    pub current: i64,
+    /// Probability of a win right after a win, in percent
+    pub win_after_win: f64,
+    /// Probability of a win right after a loss, in percent
+    pub win_after_loss: f64,
+}
+
+impl Streaks {
+    pub fn of(pnls: &[f64]) -> Self {
+        let mut runs: Vec<i64> = Vec::new();
+        let mut current = 0i64;
+        for &p in pnls {
+            let next = if p > 0.0 {
+                current.max(0) + 1
+            } else if p < 0.0 {
+                current.min(0) - 1
+            } else {
+                0
+            };
+            if current != 0 && next.signum() != current.signum() {
+                runs.push(current);
+            }
+            current = next;
+        }
+        if current != 0 {
+            runs.push(current);
+        }
+
+        let average = |wins: bool| {
+            let lengths: Vec<f64> = runs
+                .iter()
+                .filter(|&&r| (r > 0) == wins)
+                .map(|r| r.unsigned_abs() as f64)
+                .collect();
+            stats::mean(&lengths)
+        };
+        let win_after = |previous_win: bool| {
+            let next: Vec<bool> = pnls
+                .windows(2)
+                .filter(|w| if previous_win { w[0] > 0.0 } else { w[0] < 0.0 })
+                .map(|w| w[1] > 0.0)
+                .collect();
+            if next.is_empty() {
+                0.0
+            } else {
+                next.iter().filter(|&&w| w).count() as f64 / next.len() as f64 * 100.0
+            }
+        };
+
+        Streaks {
+            longest_win: runs.iter().filter(|&&r| r > 0).max().copied().unwrap_or(0) as usize,
+            longest_loss: runs
+                .iter()
+                .filter(|&&r| r < 0)
+                .min()
+                .copied()
+                .unwrap_or(0)
+                .unsigned_abs() as usize,
+            average_win: average(true),
+            average_loss: average(false),
+            current,
+            win_after_win: win_after(true),
+            win_after_loss: win_after(false),
+        }
+    }
+}
+
+/// Everything [`TradeAnalyzer`] found in one backtest
+#[derive(Debug, Clone, PartialEq)]
+pub struct TradeReport {
+    pub strategy: String,
+    pub symbol: String,
+    pub excursions: Vec<Excursion>,
+    pub by_holding_time: Vec<Breakdown>,
+    pub by_hour: Vec<Breakdown>,
+    pub by_weekday: Vec<Breakdown>,
+    pub by_exit_reason: Vec<Breakdown>,
+    pub streaks: Streaks,
+}
+
+impl TradeReport {
+    fn excursions_where(&self, winners: bool) -> impl Iterator<Item = &Excursion> {
+        self.excursions
+            .iter()
+            .filter(move |e| (e.result > 0.0) == winners)
+    }
+
+    /// Adverse excursion not exceeded by a share `q` of the winning trades
+    ///
+    /// A stop placed beyond the 0.95 quantile would have kept nearly every
+    /// winner; one inside it cuts winners short.
+    pub fn winner_mae_quantile(&self, q: f64) -> f64 {
+        let mae: Vec<f64> = self.excursions_where(true).map(|e| e.mae).collect();
+        stats::quantile(&mae, q)
+    }
+
+    /// Favorable excursion reached by a share `1 - q` of the losing trades
+    pub fn loser_mfe_quantile(&self, q: f64) -> f64 {
+        let mfe: Vec<f64> = self.excursions_where(false).map(|e| e.mfe).collect();
+        stats::quantile(&mfe, q)
+    }
+
+    /// Mean share of the favorable excursion kept by winners
+    pub fn winner_efficiency(&self) -> f64 {
+        let kept: Vec<f64> = self
+            .excursions_where(true)
+            .map(Excursion::efficiency)
+            .collect();
+        stats::mean(&kept)
+    }
+
+    pub fn print(&self) {
+        println!(
+            "\n=== Trade Analysis: {} on {} ===",
+            self.strategy, self.symbol
+        );
+
+        let summary = |label: &str, values: Vec<f64>| {
+            let s = Summary::of(&values);
+            println!(
+                "  {:<14} n={:<4} median={:>6.2}% q75={:>6.2}% max={:>6.2}%",
+                label,
+                s.count,
+                s.median * 100.0,
+                s.q75 * 100.0,
+                if s.count > 0 { s.max * 100.0 } else { 0.0 }
+            );
+        };
+        println!("\nExcursions (fraction of entry price):");
+        summary(
+            "MAE winners",
+            self.excursions_where(true).map(|e| e.mae).collect(),
+        );
+        summary(
+            "MAE losers",
+            self.excursions_where(false).map(|e| e.mae).collect(),
+        );
+        summary(
+            "MFE winners",
+            self.excursions_where(true).map(|e| e.mfe).collect(),
+        );
+        summary(
+            "MFE losers",
+            self.excursions_where(false).map(|e| e.mfe).collect(),
+        );
+        println!(
+            "  95% of winners stayed within {:.2}% adverse; winners kept {:.0}% of their MFE",
+            self.winner_mae_quantile(0.95) * 100.0,
+            self.winner_efficiency() * 100.0
+        );
+
+        for (title, rows) in [
+            ("Holding time (bars)", &self.by_holding_time),
+            ("Hour of entry", &self.by_hour),
+            ("Weekday of entry", &self.by_weekday),
+            ("Exit reason", &self.by_exit_reason),
+        ] {
+            println!(
+                "\n{:<20} {:>6} {:>7} {:>11} {:>10} {:>10} {:>10}",
+                title, "Trades", "Win %", "Total P&L", "Mean", "Median", "Worst"
+            );
+            println!("{}", "-".repeat(80));
+            for row in rows {
+                println!(
+                    "{:<20} {:>6} {:>6.1}% {:>11.2} {:>10.2} {:>10.2} {:>10.2}",
+                    row.label,
+                    row.trades(),
+                    row.win_rate,
+                    row.total_pnl,
+                    row.pnl.mean,
+                    row.pnl.median,
+                    row.pnl.min
+                );
+            }
+        }
+
+        let s = &self.streaks;
+        println!(
+            "\nStreaks: longest {} wins / {} losses, average {:.1} / {:.1}, current {:+}",
+            s.longest_win, s.longest_loss, s.average_win, s.average_loss, s.current
+        );
+        println!(
+            "  P(win | previous win) = {:.1}%, P(win | previous loss) = {:.1}%",
+            s.win_after_win, s.win_after_loss
+        );
+    }
+}
+
+/// Breaks down the trades of a backtest
+#[derive(Debug, Clone, PartialEq)]
+pub struct TradeAnalyzer {
+    holding_buckets: Vec<usize>,
+    utc_offset: i64,
+}
+
+impl Default for TradeAnalyzer {
+    fn default() -> Self {
+        TradeAnalyzer::new()
+    }
+}
+
+impl TradeAnalyzer {
+    pub fn new() -> Self {
+        TradeAnalyzer {
+            holding_buckets: vec![0, 1, 5, 20, 100],
+            utc_offset: 0,
+        }
+    }
+
+    /// Inclusive upper bounds, in bars, of the holding-time buckets; longer
+    /// trades fall in a last open-ended bucket
+    pub fn with_holding_buckets(mut self, mut bounds: Vec<usize>) -> Self {
+        bounds.sort_unstable();
+        bounds.dedup();
+        self.holding_buckets = bounds;
+        self
+    }
+
+    /// Seconds added to timestamps before taking hours and weekdays
+    pub fn with_utc_offset(mut self, seconds: i64) -> Self {
+        self.utc_offset = seconds;
+        self
+    }
+
+    fn holding_label(&self, bars: usize) -> (usize, String) {
+        let mut lower = 0;
+        for (i, &upper) in self.holding_buckets.iter().enumerate() {
+            if bars <= upper {
+                let label = if lower == upper {
+                    format!("{}", upper)
+                } else {
+                    format!("{}-{}", lower, upper)
+                };
+                return (i, label);
+            }
+            lower = upper + 1;
+        }
+        (
+            self.holding_buckets.len(),
+            format!(">{}", lower.saturating_sub(1)),
+        )
+    }
+
+    /// `results` must come from a run over `instrument`, whose bars give the
+    /// path of each trade
+    pub fn analyze(&self, results: &BacktestResults, instrument: &Instrument) -> TradeReport {
+        let trades = &results.trades;
+        let local = |t: i64| t + self.utc_offset;
+
+        let group = |key: &dyn Fn(&Trade) -> (usize, String)| {
+            let mut groups: BTreeMap<usize, (String, Vec<f64>)> = BTreeMap::new();
+            for trade in trades {
+                let (order, label) = key(trade);
+                groups
+                    .entry(order)
+                    .or_insert_with(|| (label, Vec::new()))
+                    .1
+                    .push(trade.profit);
+            }
+            groups
+                .into_values()
+                .map(|(label, pnls)| Breakdown::of(label, &pnls))
+                .collect::<Vec<_>>()
+        };
+
+        let pnls: Vec<f64> = trades.iter().map(|t| t.profit).collect();
+        TradeReport {
+            strategy: results.strategy.clone(),
+            symbol: results.symbol.clone(),
+            excursions: trades
+                .iter()
+                .enumerate()
+                .map(|(i, t)| Excursion::of(i, t, instrument))
+                .collect(),
+            by_holding_time: group(&|t| self.holding_label(t.bars_held())),
+            by_hour: group(&|t| {
+                let hour = local(t.entry_time).rem_euclid(DAY) / 3_600;
+                (hour as usize, format!("{:02}:00", hour))
+            }),
+            by_weekday: group(&|t| {
+                // 1970-01-01 was a Thursday
+                let day = (local(t.entry_time).div_euclid(DAY) + 3).rem_euclid(7) as usize;
+                (day, WEEKDAYS[day].to_string())
+            }),
+            by_exit_reason: group(&|t| (exit_order(t.exit_reason), t.exit_reason.to_string())),
+            streaks: Streaks::of(&pnls),
+        }
+    }
+}
+
+fn exit_order(reason: ExitReason) -> usize {
+    match reason {
+        ExitReason::Signal => 0,
+        ExitReason::StopLoss => 1,
+        ExitReason::TakeProfit => 2,
+        ExitReason::Liquidation => 3,
+        ExitReason::EndOfData => 4,
+    }
+}
@@ -0,0 +1,186 @@
+//! Append-only JSONL store of sweep trials, keyed by parameter fingerprint
+//!
+//! The first line identifies the sweep the store belongs to: the strategy
+//! type, the instrument and a hash of its bars, and the engine settings.
+//! Opening the store for another sweep fails rather than handing back
+//! backtests of different data or execution.
+//!
+//! Every following line is the full result of one backtest, written and
+//! flushed as soon as it finishes, so a sweep that is interrupted loses at
+//! most the line being written. Scores are not stored: a resumed sweep
+//! applies its own objective to the stored results. Reopening the file loads
+//! every complete line; a torn last line is skipped.
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::backtest::{BacktestConfig, BacktestResults};
+use crate::data::Instrument;
+use crate::params::{FNV_OFFSET, ParamSet, fnv1a};
+use crate::strategy::Strategy;
+
+/// What the results of a sweep depend on besides the parameters
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SweepKey {
+    /// Type name of the strategy
+    pub strategy: String,
+    pub symbol: String,
+    pub bars: usize,
+    /// FNV-1a hash of every bar
+    pub data_hash: String,
+    pub engine: BacktestConfig,
+}
+
+impl SweepKey {
+    pub fn new<S: Strategy>(instrument: &Instrument, engine: &BacktestConfig) -> Self {
+        let mut hash = FNV_OFFSET;
+        for bar in &instrument.data {
+            hash = fnv1a(hash, &bar.timestamp.to_le_bytes());
+            for value in [bar.open, bar.high, bar.low, bar.close, bar.volume] {
+                hash = fnv1a(hash, &value.to_bits().to_le_bytes());
+            }
+        }
+        SweepKey {
+            strategy: std::any::type_name::<S>().to_string(),
+            symbol: instrument.symbol.clone(),
+            bars: instrument.data.len(),
+            data_hash: format!("{:016x}", hash),
+            engine: *engine,
+        }
+    }
+
+    /// Names of the fields in which `other` differs
+    fn differences(&self, other: &SweepKey) -> Vec<&'static str> {
+        let mut fields = Vec::new();
+        if self.strategy != other.strategy {
+            fields.push("strategy");
+        }
+        if self.symbol != other.symbol {
+            fields.push("symbol");
+        }
+        if self.bars != other.bars || self.data_hash != other.data_hash {
+            fields.push("data");
+        }
+        if self.engine != other.engine {
+            fields.push("engine settings");
+        }
+        fields
+    }
+}
+
+/// One line of the store
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Record<K, P, R> {
+    Sweep(K),
+    Trial { params: P, results: R },
+}
+
+type OwnedRecord = Record<SweepKey, ParamSet, BacktestResults>;
+
+/// Backtests of one sweep, on disk
+pub struct TrialStore {
+    path: PathBuf,
+    file: File,
+    trials: HashMap<String, BacktestResults>,
+}
+
+impl TrialStore {
+    /// Opens the store of the sweep `key` at `path`, creating it if needed,
+    /// and loads the trials already in it
+    ///
+    /// Fails with `InvalidData` if the file belongs to another sweep.
+    pub fn open(path: impl AsRef<Path>, key: &SweepKey) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut sweep = None;
+        let mut complete = 0;
+        let mut trials = HashMap::new();
+        let mut torn = false;
+        if path.exists() {
+            for line in BufReader::new(File::open(&path)?).lines() {
+                let line = line?;
+                match serde_json::from_str::<OwnedRecord>(&line) {
+                    Ok(record) => {
+                        complete += 1;
+                        match record {
+                            Record::Sweep(stored) if complete == 1 => sweep = Some(stored),
+                            Record::Sweep(_) => {}
+                            Record::Trial { params, results } => {
+                                trials.insert(params.fingerprint(), results);
+                            }
+                        }
+                        torn = false;
+                    }
+                    Err(_) => torn = !line.trim().is_empty(),
+                }
+            }
+        }
+
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        match &sweep {
+            Some(stored) if stored != key => {
+                return Err(invalid(format!(
+                    "{} belongs to another sweep (different {})",
+                    path.display(),
+                    stored.differences(key).join(", ")
+                )));
+            }
+            None if complete > 0 => {
+                return Err(invalid(format!("{} has no sweep header", path.display())));
+            }
+            _ => {}
+        }
+
+        let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+        if torn {
+            // Start the next record on a fresh line
+            writeln!(file)?;
+        }
+        if sweep.is_none() {
+            write_record(&mut file, &Record::<_, (), ()>::Sweep(key))?;
+        }
+        Ok(TrialStore { path, file, trials })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Stored results of the parameter set with fingerprint `key`
+    pub fn get(&self, key: &str) -> Option<&BacktestResults> {
+        self.trials.get(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.trials.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.trials.is_empty()
+    }
+
+    /// Writes the backtest of `params` and flushes it to disk
+    pub fn append(&mut self, params: &ParamSet, results: &BacktestResults) -> io::Result<()> {
+        write_record(
+            &mut self.file,
+            &Record::<(), _, _>::Trial { params, results },
+        )?;
+        self.trials.insert(params.fingerprint(), results.clone());
+        Ok(())
+    }
+}
+
+fn write_record<K, P, R>(file: &mut File, record: &Record<K, P, R>) -> io::Result<()>
+where
+    K: Serialize,
+    P: Serialize,
+    R: Serialize,
+{
+    let line = serde_json::to_string(record).map_err(io::Error::other)?;
+    writeln!(file, "{}", line)?;
+    file.flush()
+}
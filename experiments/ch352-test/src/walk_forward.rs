@@ -0,0 +1,344 @@
+//! Walk-forward optimization
+//!
+//! The data is cut into consecutive out-of-sample (OOS) windows. Before each
+//! one, every parameter set of a grid is backtested on the preceding
+//! in-sample (IS) window and the best by the objective is run on the OOS
+//! window. Only the OOS results say anything about future performance; they
+//! are stitched into one equity curve.
+
+use std::fmt;
+use std::ops::Range;
+
+use crate::backtest::{BacktestResults, Backtester, EquityPoint};
+use crate::data::Instrument;
+use crate::params::{FromParams, ParamError, ParamSet};
+use crate::stats;
+use crate::strategy::Strategy;
+
+/// How the in-sample window moves between steps
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WindowMode {
+    /// In-sample always starts at the first bar and grows
+    Anchored,
+    /// In-sample keeps its length and slides forward
+    #[default]
+    Rolling,
+}
+
+/// One in-sample optimization and its out-of-sample test
+#[derive(Debug, Clone)]
+pub struct WalkForwardWindow {
+    /// Bar indices of the in-sample window
+    pub in_sample: Range<usize>,
+    pub out_of_sample: Range<usize>,
+    /// Winning parameters of the in-sample optimization
+    pub params: ParamSet,
+    pub is_score: f64,
+    pub oos_score: f64,
+    /// Total returns, in percent
+    pub is_return: f64,
+    pub oos_return: f64,
+    pub oos_trades: usize,
+}
+
+/// How much a numeric parameter moved between windows
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamStability {
+    pub name: String,
+    pub mean: f64,
+    pub std_dev: f64,
+    /// Standard deviation over the absolute mean; 0 when the choice never changed
+    pub coefficient_of_variation: f64,
+    pub distinct_values: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct WalkForwardReport {
+    pub mode: WindowMode,
+    pub windows: Vec<WalkForwardWindow>,
+    /// OOS equity curves chained together, starting from the initial capital
+    pub equity_curve: Vec<EquityPoint>,
+    pub initial_balance: f64,
+    /// OOS return per bar over IS return per bar of the winners
+    pub efficiency: f64,
+    pub stability: Vec<ParamStability>,
+}
+
+impl WalkForwardReport {
+    pub fn final_balance(&self) -> f64 {
+        self.equity_curve
+            .last()
+            .map_or(self.initial_balance, |p| p.balance)
+    }
+
+    pub fn total_return(&self) -> f64 {
+        (self.final_balance() - self.initial_balance) / self.initial_balance * 100.0
+    }
+
+    /// Share of windows whose winner differs from the previous window's
+    pub fn param_change_rate(&self) -> f64 {
+        if self.windows.len() < 2 {
+            return 0.0;
+        }
+        let changes = self
+            .windows
+            .windows(2)
+            .filter(|w| w[0].params != w[1].params)
+            .count();
+        changes as f64 / (self.windows.len() - 1) as f64
+    }
+
+    pub fn print(&self) {
+        println!("\n=== Walk-Forward ({:?}) ===\n", self.mode);
+        println!(
+            "{:<4} {:<12} {:<12} {:>9} {:>9} {:>7}  Params",
+            "#", "In-sample", "Out-sample", "IS ret", "OOS ret", "Trades"
+        );
+        for (i, w) in self.windows.iter().enumerate() {
+            println!(
+                "{:<4} {:<12} {:<12} {:>8.2}% {:>8.2}% {:>7}  {}",
+                i + 1,
+                format!("{}..{}", w.in_sample.start, w.in_sample.end),
+                format!("{}..{}", w.out_of_sample.start, w.out_of_sample.end),
+                w.is_return,
+                w.oos_return,
+                w.oos_trades,
+                w.params
+            );
+        }
+        println!(
+            "\nStitched OOS return {:.2}%, efficiency {:.2}, params changed in {:.0}% of steps",
+            self.total_return(),
+            self.efficiency,
+            self.param_change_rate() * 100.0
+        );
+        for s in &self.stability {
+            println!(
+                "  {:<16} mean {:>8.3}  std {:>8.3}  cv {:>5.2}  distinct {}",
+                s.name, s.mean, s.std_dev, s.coefficient_of_variation, s.distinct_values
+            );
+        }
+    }
+}
+
+/// Why a walk-forward run could not be carried out
+#[derive(Debug, Clone, PartialEq)]
+pub enum WalkForwardError {
+    NotEnoughData { needed: usize, available: usize },
+    EmptyGrid,
+    InvalidParams(ParamError),
+}
+
+impl fmt::Display for WalkForwardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotEnoughData { needed, available } => write!(
+                f,
+                "Walk-forward needs at least {} bars, got {}",
+                needed, available
+            ),
+            Self::EmptyGrid => write!(f, "Parameter grid is empty"),
+            Self::InvalidParams(e) => write!(f, "Invalid parameters: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for WalkForwardError {}
+
+impl From<ParamError> for WalkForwardError {
+    fn from(e: ParamError) -> Self {
+        WalkForwardError::InvalidParams(e)
+    }
+}
+
+/// Walk-forward optimizer over a fixed parameter grid
+pub struct WalkForward {
+    engine: Backtester,
+    in_sample: usize,
+    out_of_sample: usize,
+    mode: WindowMode,
+}
+
+impl WalkForward {
+    /// Window lengths are in bars
+    pub fn new(engine: Backtester, in_sample: usize, out_of_sample: usize) -> Self {
+        WalkForward {
+            engine,
+            in_sample,
+            out_of_sample,
+            mode: WindowMode::default(),
+        }
+    }
+
+    pub fn with_mode(mut self, mode: WindowMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// `(in-sample, out-of-sample)` bar ranges for `bars` bars of data
+    ///
+    /// The last OOS window is cut short when the data runs out.
+    pub fn windows(&self, bars: usize) -> Vec<(Range<usize>, Range<usize>)> {
+        let mut windows = Vec::new();
+        let mut split = self.in_sample;
+        while self.out_of_sample > 0 && split < bars {
+            let start = match self.mode {
+                WindowMode::Anchored => 0,
+                WindowMode::Rolling => split - self.in_sample,
+            };
+            let end = (split + self.out_of_sample).min(bars);
+            windows.push((start..split, split..end));
+            split = end;
+        }
+        windows
+    }
+
+    /// Optimizes `S` over `grid` window by window, scoring runs with `objective`
+    ///
+    /// Parameter sets may be partial; missing values take their defaults.
+    pub fn run<S, F>(
+        &self,
+        instrument: &Instrument,
+        grid: &[ParamSet],
+        objective: F,
+    ) -> Result<WalkForwardReport, WalkForwardError>
+    where
+        S: Strategy + FromParams,
+        F: Fn(&BacktestResults) -> f64,
+    {
+        let bars = instrument.data.len();
+        let needed = self.in_sample + 1;
+        if self.in_sample == 0 || bars < needed {
+            return Err(WalkForwardError::NotEnoughData {
+                needed,
+                available: bars,
+            });
+        }
+        if grid.is_empty() {
+            return Err(WalkForwardError::EmptyGrid);
+        }
+        let grid = grid
+            .iter()
+            .map(|p| S::schema().validate(p))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let initial = self.engine.config().initial_capital;
+        let mut windows = Vec::new();
+        let mut equity_curve: Vec<EquityPoint> = Vec::new();
+        let mut balance = initial;
+        let mut peak = initial;
+
+        for (is_range, oos_range) in self.windows(bars) {
+            let is_data = slice(instrument, is_range.clone());
+
+            let mut best: Option<(f64, f64, &ParamSet)> = None;
+            for params in &grid {
+                let mut strategy = S::from_validated(params);
+                let results = self.engine.run(&mut strategy, &is_data);
+                let score = objective(&results);
+                if best.is_none_or(|(s, _, _)| score > s) {
+                    best = Some((score, results.total_return(), params));
+                }
+            }
+            let (is_score, is_return, params) = best.expect("grid is not empty");
+
+            // The OOS run sees the IS bars as warm-up but trades only the OOS bars
+            let warmup = oos_range.start - is_range.start;
+            let oos_data = slice(instrument, is_range.start..oos_range.end);
+            let mut strategy = S::from_validated(params);
+            let results = self
+                .engine
+                .run_with_warmup(&mut strategy, &oos_data, warmup);
+
+            for point in &results.equity_curve {
+                let stitched = balance * point.balance / results.initial_balance;
+                peak = peak.max(stitched);
+                equity_curve.push(EquityPoint {
+                    timestamp: point.timestamp,
+                    balance: stitched,
+                    drawdown: (peak - stitched) / peak * 100.0,
+                });
+            }
+            balance *= results.final_balance / results.initial_balance;
+
+            windows.push(WalkForwardWindow {
+                in_sample: is_range,
+                out_of_sample: oos_range,
+                params: params.clone(),
+                is_score,
+                oos_score: objective(&results),
+                is_return,
+                oos_return: results.total_return(),
+                oos_trades: results.trades.len(),
+            });
+        }
+
+        Ok(WalkForwardReport {
+            mode: self.mode,
+            efficiency: efficiency(&windows),
+            stability: stability(&windows),
+            windows,
+            equity_curve,
+            initial_balance: initial,
+        })
+    }
+}
+
+fn slice(instrument: &Instrument, range: Range<usize>) -> Instrument {
+    Instrument::new(&instrument.symbol, instrument.data[range].to_vec())
+}
+
+/// Pardo's walk-forward efficiency, on returns per bar
+fn efficiency(windows: &[WalkForwardWindow]) -> f64 {
+    let per_bar = |ret: f64, range: &Range<usize>| ret / range.len().max(1) as f64;
+    let is_rate = stats::mean(
+        &windows
+            .iter()
+            .map(|w| per_bar(w.is_return, &w.in_sample))
+            .collect::<Vec<_>>(),
+    );
+    let oos_rate = stats::mean(
+        &windows
+            .iter()
+            .map(|w| per_bar(w.oos_return, &w.out_of_sample))
+            .collect::<Vec<_>>(),
+    );
+    if is_rate != 0.0 {
+        oos_rate / is_rate
+    } else {
+        0.0
+    }
+}
+
+fn stability(windows: &[WalkForwardWindow]) -> Vec<ParamStability> {
+    let Some(first) = windows.first() else {
+        return Vec::new();
+    };
+    first
+        .params
+        .iter()
+        .filter(|(_, value)| value.as_f64().is_some())
+        .map(|(name, _)| {
+            let values: Vec<f64> = windows
+                .iter()
+                .filter_map(|w| w.params.get(name).and_then(|v| v.as_f64()))
+                .collect();
+            let mean = stats::mean(&values);
+            let std_dev = stats::std_dev(&values);
+            let mut distinct = values.clone();
+            distinct.sort_by(f64::total_cmp);
+            distinct.dedup();
+            ParamStability {
+                name: name.to_string(),
+                mean,
+                std_dev,
+                coefficient_of_variation: if mean != 0.0 {
+                    std_dev / mean.abs()
+                } else {
+                    0.0
+                },
+                distinct_values: distinct.len(),
+            }
+        })
+        .collect()
+}
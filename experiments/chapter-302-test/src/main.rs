@@ -1,5 +1,7 @@
 use std::collections::HashMap;
 
+use ch352_test::{Metrics, MetricsConfig};
+
 #[derive(Debug, Clone)]
 struct Trade {
     entry_time: u64,
@@ -27,109 +29,72 @@ struct StrategyMetrics {
     avg_trade: f64,
     avg_win: f64,
     avg_loss: f64,
+    /// Longest time under water, in days
+    max_drawdown_duration: usize,
+    /// Days from the deepest trough back to the prior peak; `None` if never recovered
+    time_to_recovery: Option<usize>,
+    ulcer_index: f64,
+    ulcer_performance: f64,
+    omega_ratio: f64,
+    tail_ratio: f64,
+    skewness: f64,
+    kurtosis: f64,
+    /// One-day losses at 95% confidence, historical and normal
+    var: f64,
+    cvar: f64,
+    parametric_var: f64,
+    parametric_cvar: f64,
 }
 
 impl StrategyMetrics {
+    /// Metrics of `trades` over `days` calendar days, under the conventions
+    /// of the shared metrics module: daily returns of the equity marked with
+    /// the trades closed by the end of each day, 365 days a year and a 2%
+    /// risk-free rate
     fn new(name: &str, trades: &[Trade], initial_capital: f64, days: usize) -> Self {
-        let total_trades = trades.len();
-
-        let total_pnl: f64 = trades.iter().map(|t| t.pnl).sum();
-        let total_return = total_pnl / initial_capital;
-        let annual_return = total_return * (365.0 / days as f64);
-
-        let returns: Vec<f64> = trades.iter().map(|t| t.pnl / initial_capital).collect();
-        let avg_return = returns.iter().sum::<f64>() / returns.len() as f64;
-
-        let variance = returns.iter()
-            .map(|r| (r - avg_return).powi(2))
-            .sum::<f64>() / returns.len() as f64;
-        let volatility = variance.sqrt() * (252.0_f64).sqrt();
-
+        let start = trades.first().map_or(0, |t| t.entry_time);
+        let mut closed = trades.iter().peekable();
         let mut equity = initial_capital;
-        let mut peak = initial_capital;
-        let mut max_dd = 0.0;
-
-        for trade in trades {
-            equity += trade.pnl;
-            if equity > peak {
-                peak = equity;
-            }
-            let drawdown = (peak - equity) / peak;
-            if drawdown > max_dd {
-                max_dd = drawdown;
-            }
-        }
-
-        let risk_free_rate = 0.02;
-        let sharpe_ratio = if volatility > 0.0 {
-            (annual_return - risk_free_rate) / volatility
-        } else {
-            0.0
-        };
-
-        let downside_returns: Vec<f64> = returns.iter()
-            .filter(|&&r| r < 0.0)
-            .copied()
+        let curve: Vec<(i64, f64)> = (1..=days as u64)
+            .map(|day| {
+                let end = start + day * 86_400;
+                while let Some(trade) = closed.next_if(|t| t.exit_time <= end) {
+                    equity += trade.pnl;
+                }
+                (end as i64, equity)
+            })
             .collect();
-        let downside_variance = if !downside_returns.is_empty() {
-            downside_returns.iter()
-                .map(|r| r.powi(2))
-                .sum::<f64>() / downside_returns.len() as f64
-        } else {
-            0.0
-        };
-        let downside_deviation = downside_variance.sqrt() * (252.0_f64).sqrt();
-        let sortino_ratio = if downside_deviation > 0.0 {
-            (annual_return - risk_free_rate) / downside_deviation
-        } else {
-            0.0
-        };
-
-        let calmar_ratio = if max_dd > 0.0 {
-            annual_return / max_dd
-        } else {
-            0.0
-        };
-
-        let winning_trades: Vec<&Trade> = trades.iter().filter(|t| t.pnl > 0.0).collect();
-        let losing_trades: Vec<&Trade> = trades.iter().filter(|t| t.pnl < 0.0).collect();
-
-        let win_rate = winning_trades.len() as f64 / total_trades as f64;
-        let avg_win = if !winning_trades.is_empty() {
-            winning_trades.iter().map(|t| t.pnl).sum::<f64>() / winning_trades.len() as f64
-        } else {
-            0.0
-        };
-        let avg_loss = if !losing_trades.is_empty() {
-            losing_trades.iter().map(|t| t.pnl).sum::<f64>() / losing_trades.len() as f64
-        } else {
-            0.0
-        };
-        let avg_trade = total_pnl / total_trades as f64;
-
-        let total_wins: f64 = winning_trades.iter().map(|t| t.pnl).sum();
-        let total_losses: f64 = losing_trades.iter().map(|t| t.pnl.abs()).sum();
-        let profit_factor = if total_losses > 0.0 {
-            total_wins / total_losses
-        } else {
-            0.0
-        };
+        let pnls: Vec<f64> = trades.iter().map(|t| t.pnl).collect();
+        let config = MetricsConfig::per_bar(365.0).with_risk_free_rate(0.02);
+        let metrics = Metrics::compute(initial_capital, &curve, &pnls, &config);
 
         StrategyMetrics {
             name: name.to_string(),
-            total_return,
-            annual_return,
-            volatility,
-            max_drawdown: max_dd,
-            sharpe_ratio,
-            sortino_ratio,
-            calmar_ratio,
-            total_trades,
-            win_rate,
-            profit_factor,
-            avg_trade,
-            avg_win,
-            avg_loss,
+            total_return: metrics.total_return,
+            annual_return: metrics.annual_return,
+            volatility: metrics.volatility,
+            max_drawdown: metrics.max_drawdown,
+            sharpe_ratio: metrics.sharpe,
+            sortino_ratio: metrics.sortino,
+            calmar_ratio: metrics.calmar,
+            total_trades: metrics.trades,
+            win_rate: metrics.win_rate,
+            profit_factor: metrics.profit_factor,
+            avg_trade: metrics.avg_trade,
+            avg_win: metrics.avg_win,
+            avg_loss: metrics.avg_loss,
+            max_drawdown_duration: metrics.max_drawdown_duration,
+            time_to_recovery: metrics.time_to_recovery,
+            ulcer_index: metrics.ulcer_index,
+            ulcer_performance: metrics.ulcer_performance,
+            omega_ratio: metrics.omega,
+            tail_ratio: metrics.tail_ratio,
+            skewness: metrics.skewness,
+            kurtosis: metrics.kurtosis,
+            var: metrics.var,
+            cvar: metrics.cvar,
+            parametric_var: metrics.parametric_var,
+            parametric_cvar: metrics.parametric_cvar,
         }
     }
 
@@ -141,10 +106,23 @@ impl StrategyMetrics {
         println!("\nRisk:");
         println!("  Volatility: {:.2}%", self.volatility * 100.0);
         println!("  Max drawdown: {:.2}%", self.max_drawdown * 100.0);
+        println!("  Max drawdown duration: {} days", self.max_drawdown_duration);
+        match self.time_to_recovery {
+            Some(days) => println!("  Time to recovery: {} days", days),
+            None => println!("  Time to recovery: not recovered"),
+        }
+        println!("  Ulcer index: {:.2}%", self.ulcer_index * 100.0);
+        println!("  Skewness: {:.2}", self.skewness);
+        println!("  Kurtosis: {:.2}", self.kurtosis);
+        println!("  VaR 95% (historical / normal): {:.2}% / {:.2}%", self.var * 100.0, self.parametric_var * 100.0);
+        println!("  CVaR 95% (historical / normal): {:.2}% / {:.2}%", self.cvar * 100.0, self.parametric_cvar * 100.0);
         println!("\nEfficiency:");
         println!("  Sharpe Ratio: {:.2}", self.sharpe_ratio);
         println!("  Sortino Ratio: {:.2}", self.sortino_ratio);
         println!("  Calmar Ratio: {:.2}", self.calmar_ratio);
+        println!("  Ulcer Performance Index: {:.2}", self.ulcer_performance);
+        println!("  Omega Ratio: {:.2}", self.omega_ratio);
+        println!("  Tail Ratio: {:.2}", self.tail_ratio);
         println!("\nTrading:");
         println!("  Total trades: {}", self.total_trades);
         println!("  Win Rate: {:.2}%", self.win_rate * 100.0);